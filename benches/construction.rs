@@ -0,0 +1,68 @@
+//! Sequential against parallel tree construction.
+//!
+//! ```text
+//! cargo bench --bench construction [-- LEAVES [LEAF_BYTES]]
+//! cargo bench --bench construction --features rayon
+//! ```
+//!
+//! Each constructor builds the same tree; the best of a few runs is
+//! reported, with the speedup over `from_leaves`.
+
+use merkle::MerkleTree;
+use std::env;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const RUNS: usize = 3;
+
+fn best<F: FnMut() -> [u8; 32]>(mut build: F) -> (Duration, [u8; 32]) {
+    let mut best = Duration::MAX;
+    let mut root = [0; 32];
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        root = build();
+        best = best.min(start.elapsed());
+    }
+    (best, root)
+}
+
+fn main() {
+    // `cargo bench` passes `--bench` along; the sizes are the numbers.
+    let mut args = env::args()
+        .skip(1)
+        .filter_map(|arg| arg.parse::<usize>().ok());
+    let leaves = args.next().unwrap_or(1 << 18);
+    let leaf_bytes = args.next().unwrap_or(256);
+    let items: Vec<Vec<u8>> = (0..leaves)
+        .map(|i| (0..leaf_bytes).map(|b| (i ^ b) as u8).collect())
+        .collect();
+    println!(
+        "{} leaves of {} bytes, on {}",
+        leaves,
+        leaf_bytes,
+        if cfg!(feature = "rayon") {
+            "the rayon pool"
+        } else {
+            "scoped threads"
+        }
+    );
+
+    let (sequential, root) = best(|| MerkleTree::from_leaves(&items).unwrap().root());
+    println!("{:>12} {:>10.1?}", "from_leaves", sequential);
+
+    let cores = thread::available_parallelism().map_or(1, |n| n.get());
+    let mut threads = 1;
+    while threads <= cores.max(2) {
+        let (elapsed, par_root) =
+            best(|| MerkleTree::par_from_leaves(&items, threads).unwrap().root());
+        assert_eq!(par_root, root, "parallel build disagrees");
+        println!(
+            "{:>9} {:>2} {:>10.1?} {:>5.2}x",
+            "threads",
+            threads,
+            elapsed,
+            sequential.as_secs_f64() / elapsed.as_secs_f64()
+        );
+        threads *= 2;
+    }
+}
@@ -0,0 +1,149 @@
+//! Generates the root and per-claim proofs for a token airdrop claimed on
+//! chain with OpenZeppelin's `MerkleProof.verify`.
+//!
+//! Each claim is an `(address, uint256)` pair, hashed the way
+//! `StandardMerkleTree.of(claims, ["address", "uint256"])` hashes it, so the
+//! root and proofs match that library's and a contract checks a claim with
+//!
+//! ```solidity
+//! bytes32 leaf = keccak256(bytes.concat(keccak256(abi.encode(account, amount))));
+//! require(MerkleProof.verify(proof, root, leaf), "not in the airdrop");
+//! ```
+//!
+//! Reads `address,amount` lines (decimal amounts below 2^128) from the
+//! file given, or uses a generated list, and prints the result as JSON.
+//!
+//! ```text
+//! cargo run --example airdrop [CLAIMS.csv]
+//! ```
+
+use merkle::openzeppelin::{standard_leaf, verify_sorted};
+use merkle::OzTree;
+use std::env;
+use std::fs;
+use std::process;
+
+fn hex(bytes: &[u8]) -> String {
+    let digits: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("0x{}", digits)
+}
+
+fn parse_address(text: &str) -> Option<[u8; 20]> {
+    let digits = text.strip_prefix("0x")?;
+    if digits.len() != 40 {
+        return None;
+    }
+    let mut address = [0; 20];
+    for (i, byte) in address.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(digits.get(2 * i..2 * i + 2)?, 16).ok()?;
+    }
+    Some(address)
+}
+
+/// `abi.encode(account, amount)`: two 32-byte words, big-endian.
+fn encode(account: &[u8; 20], amount: u128) -> [u8; 64] {
+    let mut encoded = [0; 64];
+    encoded[12..32].copy_from_slice(account);
+    encoded[48..].copy_from_slice(&amount.to_be_bytes());
+    encoded
+}
+
+fn read_claims(path: &str) -> Result<Vec<Claim>, String> {
+    let text = fs::read_to_string(path).map_err(|err| format!("{}: {}", path, err))?;
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(number, line)| {
+            let claim = line.split_once(',').and_then(|(account, amount)| {
+                Some((parse_address(account.trim())?, amount.trim().parse().ok()?))
+            });
+            claim.ok_or_else(|| format!("{}:{}: expected address,amount", path, number + 1))
+        })
+        .collect()
+}
+
+fn generated_claims() -> Vec<Claim> {
+    (1..=10u8)
+        .map(|i| ([i; 20], u128::from(i) * 1_000_000_000_000_000_000))
+        .collect()
+}
+
+type Claim = ([u8; 20], u128);
+type ClaimProof = Vec<[u8; 32]>;
+
+/// The root and each claim's proof, or `None` if there are no claims.
+fn airdrop(claims: &[Claim]) -> Option<([u8; 32], Vec<ClaimProof>)> {
+    let leaves: Vec<[u8; 32]> = claims
+        .iter()
+        .map(|(account, amount)| standard_leaf(&encode(account, *amount)))
+        .collect();
+    let tree = OzTree::sorted(leaves.clone())?;
+    let proofs = leaves
+        .iter()
+        .map(|leaf| {
+            // Sorting moved the leaves, so look each one up by hash.
+            let idx = (0..tree.leaves())
+                .find(|&idx| tree.leaf(idx) == Some(*leaf))
+                .unwrap();
+            let proof = tree.proof(idx).unwrap();
+            assert!(verify_sorted(*leaf, tree.root(), &proof));
+            proof
+        })
+        .collect();
+    Some((tree.root(), proofs))
+}
+
+fn main() {
+    let claims = match env::args().nth(1) {
+        Some(path) => read_claims(&path).unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            process::exit(1);
+        }),
+        None => generated_claims(),
+    };
+
+    let (root, proofs) = match airdrop(&claims) {
+        Some(airdrop) => airdrop,
+        None => {
+            eprintln!("no claims");
+            process::exit(1);
+        }
+    };
+
+    println!("{{");
+    println!("  \"root\": \"{}\",", hex(&root));
+    println!("  \"claims\": [");
+    for (n, ((account, amount), proof)) in claims.iter().zip(&proofs).enumerate() {
+        let proof: Vec<String> = proof
+            .iter()
+            .map(|hash| format!("\"{}\"", hex(hash)))
+            .collect();
+        println!(
+            "    {{ \"account\": \"{}\", \"amount\": \"{}\", \"proof\": [{}] }}{}",
+            hex(account),
+            amount,
+            proof.join(", "),
+            if n + 1 < claims.len() { "," } else { "" }
+        );
+    }
+    println!("  ]");
+    println!("}}");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn claims_verify() {
+        let claims = generated_claims();
+        let (root, proofs) = airdrop(&claims).unwrap();
+        for ((account, amount), proof) in claims.iter().zip(&proofs) {
+            let leaf = standard_leaf(&encode(account, *amount));
+            assert!(verify_sorted(leaf, root, proof));
+            let inflated = standard_leaf(&encode(account, amount + 1));
+            assert!(!verify_sorted(inflated, root, proof));
+        }
+        assert!(airdrop(&[]).is_none());
+    }
+}
@@ -0,0 +1,177 @@
+//! A CosmWasm contract that pays out token claims against a Merkle root.
+//! The entry points take plain Rust types where a real contract would take
+//! `cosmwasm_std`'s `Deps`, `Env` and `MessageInfo`; the checks are the
+//! same. Proof checking goes through `verify_proof_bounded`, which refuses
+//! proofs deeper than the contract's limit before hashing, so a claim costs
+//! at most `max_depth + 1` hashes whatever the caller sends.
+//!
+//! ```text
+//! cargo run --example cosmwasm
+//! ```
+
+use merkle::bounded::MAX_DEPTH;
+use merkle::{verify_proof_bounded, LimitExceeded, MerkleTree, Path};
+use std::collections::BTreeMap;
+
+struct InstantiateMsg {
+    root: [u8; 32],
+    /// Deepest proof accepted, at most `MAX_DEPTH`. Defaults to it.
+    max_depth: Option<usize>,
+}
+
+enum ExecuteMsg {
+    Claim {
+        address: String,
+        amount: u128,
+        proof: Path,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ContractError {
+    ProofTooDeep(LimitExceeded),
+    InvalidProof,
+    AlreadyClaimed,
+}
+
+/// The contract's storage.
+struct Contract {
+    root: [u8; 32],
+    max_depth: usize,
+    paid: BTreeMap<String, u128>,
+}
+
+/// The leaf item of a claim: `address:amount`.
+fn claim_item(address: &str, amount: u128) -> Vec<u8> {
+    format!("{}:{}", address, amount).into_bytes()
+}
+
+fn instantiate(msg: InstantiateMsg) -> Contract {
+    Contract {
+        root: msg.root,
+        max_depth: msg.max_depth.unwrap_or(MAX_DEPTH).min(MAX_DEPTH),
+        paid: BTreeMap::new(),
+    }
+}
+
+impl Contract {
+    /// Returns the amount to send. Claims are keyed by address, which the
+    /// proven leaf commits to, so one allocation cannot be paid twice.
+    fn execute(&mut self, msg: ExecuteMsg) -> Result<u128, ContractError> {
+        let ExecuteMsg::Claim {
+            address,
+            amount,
+            proof,
+        } = msg;
+        if self.paid.contains_key(&address) {
+            return Err(ContractError::AlreadyClaimed);
+        }
+        let item = claim_item(&address, amount);
+        match verify_proof_bounded(&item, self.root, &proof, self.max_depth) {
+            Ok(true) => {}
+            Ok(false) => return Err(ContractError::InvalidProof),
+            Err(limit) => return Err(ContractError::ProofTooDeep(limit)),
+        }
+        self.paid.insert(address, amount);
+        Ok(amount)
+    }
+
+    fn query_paid(&self, address: &str) -> Option<u128> {
+        self.paid.get(address).copied()
+    }
+}
+
+fn allocations() -> Vec<(String, u128)> {
+    (0..6u32)
+        .map(|i| (format!("cosmos1holder{}", i), 1_000 * (i as u128 + 1)))
+        .collect()
+}
+
+fn main() {
+    let allocations = allocations();
+    let items: Vec<Vec<u8>> = allocations
+        .iter()
+        .map(|(address, amount)| claim_item(address, *amount))
+        .collect();
+    let tree = MerkleTree::from_leaves(&items).expect("there are allocations");
+    let mut contract = instantiate(InstantiateMsg {
+        root: tree.root(),
+        max_depth: Some(16),
+    });
+    for (idx, (address, amount)) in allocations.iter().enumerate() {
+        let msg = ExecuteMsg::Claim {
+            address: address.clone(),
+            amount: *amount,
+            proof: tree.path(idx).expect("every allocation has a leaf"),
+        };
+        println!("claim by {}: {:?}", address, contract.execute(msg));
+    }
+    println!(
+        "paid to {}: {:?}",
+        allocations[0].0,
+        contract.query_paid(&allocations[0].0)
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pays_each_claim_once() {
+        let allocations = allocations();
+        let items: Vec<Vec<u8>> = allocations
+            .iter()
+            .map(|(address, amount)| claim_item(address, *amount))
+            .collect();
+        let tree = MerkleTree::from_leaves(&items).unwrap();
+        let mut contract = instantiate(InstantiateMsg {
+            root: tree.root(),
+            max_depth: None,
+        });
+        let claim = |idx: usize, amount: u128| ExecuteMsg::Claim {
+            address: allocations[idx].0.clone(),
+            amount,
+            proof: tree.path(idx).unwrap(),
+        };
+
+        assert_eq!(
+            contract.execute(claim(2, 30_000)),
+            Err(ContractError::InvalidProof)
+        );
+        assert_eq!(contract.execute(claim(2, 3_000)), Ok(3_000));
+        assert_eq!(
+            contract.execute(claim(2, 3_000)),
+            Err(ContractError::AlreadyClaimed)
+        );
+        assert_eq!(contract.query_paid("cosmos1holder2"), Some(3_000));
+        assert_eq!(contract.query_paid("cosmos1holder3"), None);
+    }
+
+    #[test]
+    fn refuses_deep_proofs() {
+        let contract = || {
+            instantiate(InstantiateMsg {
+                root: [0; 32],
+                max_depth: Some(4),
+            })
+        };
+        let deep = ExecuteMsg::Claim {
+            address: "cosmos1holder0".to_string(),
+            amount: 1_000,
+            proof: vec![([0; 32], false); 5],
+        };
+        assert_eq!(
+            contract().execute(deep),
+            Err(ContractError::ProofTooDeep(LimitExceeded {
+                limit: 4,
+                depth: 5
+            }))
+        );
+        let capped = instantiate(InstantiateMsg {
+            root: [0; 32],
+            max_depth: Some(1 << 20),
+        });
+        assert_eq!(capped.max_depth, MAX_DEPTH);
+    }
+}
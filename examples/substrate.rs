@@ -0,0 +1,143 @@
+//! The claim check of a Substrate pallet, without the FRAME scaffolding.
+//! An off-chain service SCALE-encodes a `Claim`, the item and its
+//! `Proof`, as a call's argument. The runtime decodes it and checks it
+//! against the `TreeHead` it stores. The runtime's side only uses what the
+//! crate offers without `std`, so it builds for the wasm runtime with
+//!
+//! ```text
+//! cargo build --target wasm32-unknown-unknown --no-default-features --features scale
+//! ```
+//!
+//! Run the round trip on the host with
+//!
+//! ```text
+//! cargo run --example substrate --features scale
+//! ```
+
+use merkle::{IndexPolicy, MerkleTree, Padding, Proof, TreeHead};
+use parity_scale_codec::{Decode, DecodeAll, Encode};
+use std::collections::BTreeSet;
+
+/// The argument of the pallet's `claim` call.
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+struct Claim {
+    item: Vec<u8>,
+    proof: Proof,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PalletError {
+    NoHead,
+    Undecodable,
+    BadProof,
+    AlreadyClaimed,
+}
+
+/// The pallet's storage: the head set by governance and the leaves
+/// claimed so far.
+#[derive(Default)]
+struct Pallet {
+    head: Option<TreeHead>,
+    claimed: BTreeSet<u64>,
+}
+
+impl Pallet {
+    fn set_head(&mut self, encoded: &[u8]) -> Result<(), PalletError> {
+        let head = TreeHead::decode_all(&mut &encoded[..]).map_err(|_| PalletError::Undecodable)?;
+        self.head = Some(head);
+        self.claimed.clear();
+        Ok(())
+    }
+
+    /// Checks the claim and marks its leaf spent. The index is read from
+    /// the proof's directions, so a proof cannot be replayed under another
+    /// index to claim twice.
+    fn claim(&mut self, encoded: &[u8]) -> Result<u64, PalletError> {
+        let claim = Claim::decode_all(&mut &encoded[..]).map_err(|_| PalletError::Undecodable)?;
+        let head = self.head.ok_or(PalletError::NoHead)?;
+        let index = IndexPolicy::new(head.size, Padding::default())
+            .verify(&claim.item, head.root, &claim.proof)
+            .map_err(|_| PalletError::BadProof)?;
+        if !self.claimed.insert(index) {
+            return Err(PalletError::AlreadyClaimed);
+        }
+        Ok(index)
+    }
+}
+
+fn allowlist() -> Vec<Vec<u8>> {
+    (0..5u8)
+        .map(|i| format!("account-{}:{}", i, 100 * (i as u32 + 1)).into_bytes())
+        .collect()
+}
+
+fn main() {
+    let items = allowlist();
+    let tree = MerkleTree::from_leaves(&items).expect("the allowlist is not empty");
+    let mut pallet = Pallet::default();
+    let head = tree.head().encode();
+    println!("set_head({} bytes)", head.len());
+    pallet
+        .set_head(&head)
+        .expect("a freshly encoded head decodes");
+
+    for (idx, item) in items.iter().enumerate() {
+        let claim = Claim {
+            item: item.clone(),
+            proof: tree.leaf_proof(idx).expect("every item has a leaf"),
+        }
+        .encode();
+        let outcome = pallet.claim(&claim);
+        println!(
+            "claim({} bytes) for {}: {:?}",
+            claim.len(),
+            String::from_utf8_lossy(item),
+            outcome
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn claims_once() {
+        let items = allowlist();
+        let tree = MerkleTree::from_leaves(&items).unwrap();
+        let mut pallet = Pallet::default();
+        let claim = |idx: usize, item: &[u8]| {
+            Claim {
+                item: item.to_vec(),
+                proof: tree.leaf_proof(idx).unwrap(),
+            }
+            .encode()
+        };
+        assert_eq!(pallet.claim(&claim(0, &items[0])), Err(PalletError::NoHead));
+        pallet.set_head(&tree.head().encode()).unwrap();
+
+        assert_eq!(pallet.claim(&claim(4, &items[4])), Ok(4));
+        assert_eq!(
+            pallet.claim(&claim(4, &items[4])),
+            Err(PalletError::AlreadyClaimed)
+        );
+        assert_eq!(
+            pallet.claim(&claim(3, &items[4])),
+            Err(PalletError::BadProof)
+        );
+        let replayed = Claim {
+            item: items[2].clone(),
+            proof: Proof::new(1, tree.leaf_proof(2).unwrap().into_path()),
+        };
+        assert_eq!(pallet.claim(&replayed.encode()), Err(PalletError::BadProof));
+
+        let mut trailing = claim(1, &items[1]);
+        trailing.push(0);
+        assert_eq!(pallet.claim(&trailing), Err(PalletError::Undecodable));
+        assert_eq!(pallet.claim(&claim(1, &items[1])), Ok(1));
+        assert_eq!(
+            TreeHead::decode(&mut &tree.head().encode()[..]),
+            Ok(tree.head())
+        );
+    }
+}
@@ -0,0 +1,282 @@
+//! A transparency log served over HTTP, and an auditor that follows it.
+//!
+//! The server keeps a `MerkleLog` and answers plain HTTP/1.1:
+//!
+//! ```text
+//! POST /add                      body is the entry; answers its index
+//! GET  /head                     "<size> <hex root>"
+//! GET  /entry?index=I            the entry, as posted
+//! GET  /inclusion?index=I&size=N hex hashes, one per line
+//! GET  /consistency?old=M&new=N  hex hashes, one per line
+//! ```
+//!
+//! The auditor remembers the last head it accepted. Each round it fetches
+//! the new head, checks that the log only grew since, and checks every new
+//! entry's inclusion proof, so a log that rewrites history is caught.
+//!
+//! ```text
+//! cargo run --example transparency_log
+//! ```
+
+use merkle::{verify_consistency, verify_inclusion, MerkleLog};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn unhex(text: &str) -> Option<[u8; 32]> {
+    if text.len() != 64 {
+        return None;
+    }
+    let mut out = [0; 32];
+    for (i, byte) in out.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(text.get(2 * i..2 * i + 2)?, 16).ok()?;
+    }
+    Some(out)
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+#[derive(Default)]
+struct Log {
+    tree: MerkleLog,
+    entries: Vec<Vec<u8>>,
+}
+
+fn param(query: &str, name: &str) -> Option<u64> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|&(key, _)| key == name)
+        .and_then(|(_, value)| value.parse().ok())
+}
+
+fn hashes(proof: Vec<[u8; 32]>) -> Vec<u8> {
+    proof
+        .iter()
+        .map(|hash| hex(hash) + "\n")
+        .collect::<String>()
+        .into_bytes()
+}
+
+/// The status and body answering `method target` with `body`.
+fn answer(log: &Mutex<Log>, method: &str, target: &str, body: Vec<u8>) -> (u16, Vec<u8>) {
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let mut log = log.lock().unwrap();
+    let found = match (method, path) {
+        ("POST", "/add") => {
+            let index = log.tree.append(&body);
+            log.entries.push(body);
+            Some(index.to_string().into_bytes())
+        }
+        ("GET", "/head") => {
+            Some(format!("{} {}", log.tree.len(), hex(&log.tree.root())).into_bytes())
+        }
+        ("GET", "/entry") => {
+            param(query, "index").and_then(|index| log.entries.get(index as usize).cloned())
+        }
+        ("GET", "/inclusion") => param(query, "index")
+            .zip(param(query, "size"))
+            .and_then(|(index, size)| log.tree.inclusion_proof(index, size))
+            .map(hashes),
+        ("GET", "/consistency") => param(query, "old")
+            .zip(param(query, "new"))
+            .and_then(|(old, new)| log.tree.consistency_proof(old, new))
+            .map(hashes),
+        _ => None,
+    };
+    match found {
+        Some(body) => (200, body),
+        None => (404, b"not found\n".to_vec()),
+    }
+}
+
+fn handle(log: &Mutex<Log>, stream: TcpStream) -> io::Result<()> {
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let (method, target) = match (parts.next(), parts.next()) {
+        (Some(method), Some(target)) => (method.to_string(), target.to_string()),
+        _ => return Err(invalid("bad request line")),
+    };
+    let mut length = 0;
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse().map_err(|_| invalid("bad length"))?;
+            }
+        }
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+
+    let (status, body) = answer(log, &method, &target, body);
+    let mut stream = reader.into_inner();
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        if status == 200 { "OK" } else { "Not Found" },
+        body.len()
+    )?;
+    stream.write_all(&body)
+}
+
+fn serve(log: Arc<Mutex<Log>>) -> io::Result<SocketAddr> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?;
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            if let Err(err) = handle(&log, stream) {
+                eprintln!("server: {}", err);
+            }
+        }
+    });
+    Ok(addr)
+}
+
+fn request(addr: SocketAddr, method: &str, target: &str, body: &[u8]) -> io::Result<Vec<u8>> {
+    let mut stream = TcpStream::connect(addr)?;
+    write!(
+        stream,
+        "{} {} HTTP/1.1\r\nHost: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        method,
+        target,
+        addr,
+        body.len()
+    )?;
+    stream.write_all(body)?;
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response)?;
+    let split = response
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .ok_or_else(|| invalid("truncated response"))?;
+    let head = String::from_utf8_lossy(&response[..split]);
+    if head.split(' ').nth(1) != Some("200") {
+        return Err(io::Error::other(format!("{} {} failed", method, target)));
+    }
+    Ok(response[split + 4..].to_vec())
+}
+
+fn get_hashes(addr: SocketAddr, target: &str) -> io::Result<Vec<[u8; 32]>> {
+    let body = String::from_utf8(request(addr, "GET", target, b"")?)
+        .map_err(|_| invalid("proof is not text"))?;
+    body.lines()
+        .map(|line| unhex(line).ok_or_else(|| invalid("proof hash is not hex")))
+        .collect()
+}
+
+/// Follows a log from its empty head.
+struct Auditor {
+    addr: SocketAddr,
+    size: u64,
+    root: [u8; 32],
+}
+
+impl Auditor {
+    fn new(addr: SocketAddr) -> Self {
+        Auditor {
+            addr,
+            size: 0,
+            root: merkle::log::empty_root(),
+        }
+    }
+
+    /// Checks the log's current head against the last one accepted and
+    /// returns the entries added since.
+    fn audit(&mut self) -> io::Result<Vec<Vec<u8>>> {
+        let head = String::from_utf8(request(self.addr, "GET", "/head", b"")?)
+            .map_err(|_| invalid("head is not text"))?;
+        let (size, root) = head
+            .split_once(' ')
+            .and_then(|(size, root)| Some((size.parse().ok()?, unhex(root)?)))
+            .ok_or_else(|| invalid("bad head"))?;
+
+        let target = format!("/consistency?old={}&new={}", self.size, size);
+        let proof = get_hashes(self.addr, &target)?;
+        if !verify_consistency(self.size, size, self.root, root, &proof) {
+            return Err(invalid("log is not an extension of the last head"));
+        }
+
+        let mut entries = Vec::new();
+        for index in self.size..size {
+            let entry = request(self.addr, "GET", &format!("/entry?index={}", index), b"")?;
+            let target = format!("/inclusion?index={}&size={}", index, size);
+            let proof = get_hashes(self.addr, &target)?;
+            if !verify_inclusion(&entry, index, size, root, &proof) {
+                return Err(invalid("entry is not in the log"));
+            }
+            entries.push(entry);
+        }
+        self.size = size;
+        self.root = root;
+        Ok(entries)
+    }
+}
+
+/// Appends and audits three rounds of entries, then rewrites an old entry
+/// and checks that the auditor refuses the next head.
+fn demo() -> io::Result<()> {
+    let log = Arc::new(Mutex::new(Log::default()));
+    let addr = serve(log.clone())?;
+    println!("log listening on http://{}", addr);
+
+    let mut auditor = Auditor::new(addr);
+    for round in 0..3 {
+        for i in 0..5 {
+            let entry = format!("certificate {}-{}", round, i);
+            request(addr, "POST", "/add", entry.as_bytes())?;
+        }
+        let entries = auditor.audit()?;
+        println!(
+            "round {}: {} new entries, head {} {}",
+            round,
+            entries.len(),
+            auditor.size,
+            hex(&auditor.root)
+        );
+        assert_eq!(entries.len(), 5);
+    }
+
+    // Rewrite history: the log's next head no longer extends the audited one.
+    {
+        let mut log = log.lock().unwrap();
+        log.entries[3] = b"forged".to_vec();
+        let mut rewritten = MerkleLog::new();
+        for entry in &log.entries {
+            rewritten.append(entry);
+        }
+        log.tree = rewritten;
+    }
+    request(addr, "POST", "/add", b"certificate 3-0")?;
+    let refused = auditor.audit().unwrap_err();
+    println!("after rewrite: {}", refused);
+    assert_eq!(refused.kind(), io::ErrorKind::InvalidData);
+    Ok(())
+}
+
+fn main() -> io::Result<()> {
+    demo()
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn demo_runs() {
+        super::demo().unwrap();
+    }
+}
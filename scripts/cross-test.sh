@@ -0,0 +1,9 @@
+#!/bin/sh
+# Runs the test suite, including the golden vectors in src/conformance.rs,
+# on a big-endian and a 32-bit target under QEMU. Requires `cross`
+# (https://github.com/cross-rs/cross) and a container runtime.
+set -eu
+
+for target in s390x-unknown-linux-gnu powerpc-unknown-linux-gnu i686-unknown-linux-gnu; do
+    cross test --target "$target" --features borsh
+done
@@ -0,0 +1,215 @@
+use crate::memory::{vec_bytes, HeapSize};
+use crate::{hash_pair, TreeHead};
+
+/// An append-only Merkle mountain range over successive tree heads.
+///
+/// Each perfect subtree is hashed in position order. The log root bags the
+/// peaks from right to left, so `root = H(p0 || H(p1 || ... pn))`.
+#[derive(Debug, Clone, Default)]
+pub struct HeadLog {
+    levels: Vec<Vec<[u8; 32]>>,
+}
+
+/// Proof that a head was published at `position` in a log of `size` heads.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeadProof {
+    pub position: u64,
+    pub size: u64,
+    /// Siblings from the head up to the root of its peak.
+    pub siblings: Vec<[u8; 32]>,
+    /// Peaks to the left of the head's peak, highest first.
+    pub left_peaks: Vec<[u8; 32]>,
+    /// The bagged peaks to the right of the head's peak.
+    pub right: Option<[u8; 32]>,
+}
+
+fn bag(peaks: &[[u8; 32]]) -> Option<[u8; 32]> {
+    let (last, rest) = peaks.split_last()?;
+    Some(
+        rest.iter()
+            .rev()
+            .fold(*last, |acc, peak| hash_pair(peak, &acc)),
+    )
+}
+
+/// Locates `position` among the peaks of a log of `size` heads, returning
+/// the peak's ordinal, its height and the position's offset within it.
+fn locate(position: u64, size: u64) -> Option<(usize, u32, u64)> {
+    if position >= size {
+        return None;
+    }
+    let mut start = 0u64;
+    let mut ordinal = 0;
+    for height in (0..64).rev() {
+        if size >> height & 1 == 0 {
+            continue;
+        }
+        let width = 1u64 << height;
+        if position - start < width {
+            return Some((ordinal, height, position - start));
+        }
+        start += width;
+        ordinal += 1;
+    }
+    None
+}
+
+impl HeadLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> u64 {
+        self.levels.first().map_or(0, |heads| heads.len() as u64)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Appends a head and returns its position.
+    pub fn append(&mut self, head: &TreeHead) -> u64 {
+        let position = self.len();
+        let mut node = head.hash();
+        let mut height = 0;
+        loop {
+            if self.levels.len() == height {
+                self.levels.push(Vec::new());
+            }
+            let level = &mut self.levels[height];
+            level.push(node);
+            if level.len() & 1 == 1 {
+                break;
+            }
+            node = hash_pair(&level[level.len() - 2], &level[level.len() - 1]);
+            height += 1;
+        }
+        position
+    }
+
+    /// Peaks from highest to lowest.
+    fn peaks(&self) -> Vec<[u8; 32]> {
+        self.levels
+            .iter()
+            .rev()
+            .filter(|level| level.len() & 1 == 1)
+            .map(|level| level[level.len() - 1])
+            .collect()
+    }
+
+    /// The log root, or `None` while the log is empty.
+    pub fn root(&self) -> Option<[u8; 32]> {
+        bag(&self.peaks())
+    }
+
+    pub fn prove(&self, position: u64) -> Option<HeadProof> {
+        let (ordinal, height, _) = locate(position, self.len())?;
+        let siblings = (0..height as usize)
+            .map(|level| self.levels[level][(position >> level) as usize ^ 1])
+            .collect();
+        let peaks = self.peaks();
+        Some(HeadProof {
+            position,
+            size: self.len(),
+            siblings,
+            left_peaks: peaks[..ordinal].to_vec(),
+            right: bag(&peaks[ordinal + 1..]),
+        })
+    }
+}
+
+impl HeadProof {
+    pub fn verify(&self, head: &TreeHead, root: [u8; 32]) -> bool {
+        let (ordinal, height, _) = match locate(self.position, self.size) {
+            Some(found) => found,
+            None => return false,
+        };
+        let has_right = self.size & ((1u64 << height) - 1) != 0;
+        if self.siblings.len() != height as usize
+            || self.left_peaks.len() != ordinal
+            || self.right.is_some() != has_right
+        {
+            return false;
+        }
+
+        let mut node = head.hash();
+        for (level, sibling) in self.siblings.iter().enumerate() {
+            node = if self.position >> level & 1 == 1 {
+                hash_pair(sibling, &node)
+            } else {
+                hash_pair(&node, sibling)
+            };
+        }
+        if let Some(right) = &self.right {
+            node = hash_pair(&node, right);
+        }
+        let computed = self
+            .left_peaks
+            .iter()
+            .rev()
+            .fold(node, |acc, peak| hash_pair(peak, &acc));
+        computed == root
+    }
+}
+
+impl HeapSize for HeadLog {
+    fn heap_size_bytes(&self) -> usize {
+        vec_bytes(&self.levels) + self.levels.iter().map(vec_bytes).sum::<usize>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{hash_data, MerkleTree};
+
+    fn head(i: u64) -> TreeHead {
+        TreeHead {
+            size: i + 1,
+            root: hash_data(&i.to_le_bytes()),
+        }
+    }
+
+    #[test]
+    fn proves_every_position() {
+        let mut log = HeadLog::new();
+        assert_eq!(log.root(), None);
+        for size in 1..=13u64 {
+            assert_eq!(log.append(&head(size - 1)), size - 1);
+            let root = log.root().unwrap();
+            for position in 0..size {
+                let proof = log.prove(position).unwrap();
+                assert!(proof.verify(&head(position), root));
+                assert!(!proof.verify(&head(position + 1), root));
+            }
+            assert!(log.prove(size).is_none());
+        }
+    }
+
+    #[test]
+    fn rejects_moved_positions() {
+        let mut log = HeadLog::new();
+        for i in 0..7 {
+            log.append(&head(i));
+        }
+        let root = log.root().unwrap();
+        let mut proof = log.prove(5).unwrap();
+        proof.position = 4;
+        assert!(!proof.verify(&head(5), root));
+        proof.position = 5;
+        proof.size = 8;
+        assert!(!proof.verify(&head(5), root));
+    }
+
+    #[test]
+    fn anchors_tree_heads() {
+        let tree = MerkleTree::new(b"asdfjkln12345678", 4);
+        let mut log = HeadLog::new();
+        let position = log.append(&tree.head());
+        assert_eq!(tree.head().size, 4);
+        assert!(log
+            .prove(position)
+            .unwrap()
+            .verify(&tree.head(), log.root().unwrap()));
+    }
+}
@@ -0,0 +1,390 @@
+//! Timestamping tree heads on an external chain. An [`Anchor`] submits a
+//! digest and later reports whether it was confirmed; [`PendingAnchor`]
+//! drives one head through that and yields an [`AnchorReceipt`]. The
+//! digest anchored for a head is `TreeHead::hash`.
+//!
+//! What a receipt proves depends on the chain, so checking one takes a
+//! [`ReceiptVerifier`]. [`SignedReceipts`] accepts receipts whose
+//! attestation is a signature over [`AnchorReceipt::message`], for
+//! services that vouch for their own confirmations.
+//!
+//! With the `anchor-client` feature, [`JsonRpcAnchor`] talks to a generic
+//! JSON-RPC 2.0 endpoint over plain HTTP:
+//!
+//! ```text
+//! submit: {"method": <submit>, "params": ["<hex digest>"]}
+//!         -> "<transaction id>"
+//! status: {"method": <status>, "params": ["<transaction id>"]}
+//!         -> null while pending, or
+//!            {"block": N, "timestamp": T, "attestation": "<hex>"}
+//! ```
+
+use crate::{hash_data, HeadVerifier, TreeHead};
+
+/// Where a digest was recorded, as reported by the chain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Confirmation {
+    pub block: u64,
+    /// Seconds since the Unix epoch, as the chain reports them.
+    pub timestamp: u64,
+    /// Chain-specific evidence, for a [`ReceiptVerifier`].
+    pub attestation: Vec<u8>,
+}
+
+pub trait Anchor {
+    type Error;
+
+    /// Submits `digest` and returns an id to ask about it by.
+    fn submit(&mut self, digest: [u8; 32]) -> Result<String, Self::Error>;
+
+    /// The confirmation for submission `id`, or `None` while it is pending.
+    fn confirmation(&mut self, id: &str) -> Result<Option<Confirmation>, Self::Error>;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AnchorReceipt {
+    pub head: TreeHead,
+    /// The chain or service that confirmed it, as named by the caller.
+    pub chain: String,
+    pub transaction: String,
+    pub confirmation: Confirmation,
+}
+
+impl AnchorReceipt {
+    /// `H(head hash || block_le || timestamp_le || transaction)`, which a
+    /// signed attestation covers.
+    pub fn message(&self) -> [u8; 32] {
+        let mut bytes = self.head.hash().to_vec();
+        bytes.extend_from_slice(&self.confirmation.block.to_le_bytes());
+        bytes.extend_from_slice(&self.confirmation.timestamp.to_le_bytes());
+        bytes.extend_from_slice(self.transaction.as_bytes());
+        hash_data(&bytes)
+    }
+
+    /// Checks that this receipt is for `head` and that `verifier` accepts
+    /// its attestation.
+    pub fn verify<V: ReceiptVerifier>(&self, head: &TreeHead, verifier: &V) -> bool {
+        self.head == *head && verifier.verify(self)
+    }
+}
+
+pub trait ReceiptVerifier {
+    fn verify(&self, receipt: &AnchorReceipt) -> bool;
+}
+
+/// Accepts receipts whose attestation is a signature over their message,
+/// checked with the service's key.
+pub struct SignedReceipts<V>(pub V);
+
+impl<V: HeadVerifier> ReceiptVerifier for SignedReceipts<V> {
+    fn verify(&self, receipt: &AnchorReceipt) -> bool {
+        self.0
+            .verify(&receipt.message(), &receipt.confirmation.attestation)
+    }
+}
+
+/// A head submitted for anchoring and not yet confirmed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingAnchor {
+    pub head: TreeHead,
+    pub chain: String,
+    pub transaction: String,
+}
+
+impl PendingAnchor {
+    pub fn submit<A: Anchor>(
+        anchor: &mut A,
+        chain: &str,
+        head: TreeHead,
+    ) -> Result<Self, A::Error> {
+        Ok(PendingAnchor {
+            head,
+            chain: chain.to_string(),
+            transaction: anchor.submit(head.hash())?,
+        })
+    }
+
+    /// The receipt once the anchor reports a confirmation.
+    pub fn poll<A: Anchor>(&self, anchor: &mut A) -> Result<Option<AnchorReceipt>, A::Error> {
+        Ok(anchor
+            .confirmation(&self.transaction)?
+            .map(|confirmation| AnchorReceipt {
+                head: self.head,
+                chain: self.chain.clone(),
+                transaction: self.transaction.clone(),
+                confirmation,
+            }))
+    }
+}
+
+#[cfg(feature = "anchor-client")]
+pub use client::JsonRpcAnchor;
+
+#[cfg(feature = "anchor-client")]
+mod client {
+    use super::{Anchor, Confirmation};
+    use crate::http;
+    use crate::json::{self, Value};
+    use std::io;
+    use std::time::Duration;
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    fn unhex(text: &str) -> Option<Vec<u8>> {
+        if text.len() & 1 == 1 {
+            return None;
+        }
+        (0..text.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+            .collect()
+    }
+
+    fn invalid(msg: &str) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+    }
+
+    pub struct JsonRpcAnchor {
+        /// `http://host:port/path`.
+        url: String,
+        submit_method: String,
+        status_method: String,
+        timeout: Duration,
+        next_id: u64,
+    }
+
+    impl JsonRpcAnchor {
+        pub fn new(url: &str, submit_method: &str, status_method: &str) -> Self {
+            JsonRpcAnchor {
+                url: url.to_string(),
+                submit_method: submit_method.to_string(),
+                status_method: status_method.to_string(),
+                timeout: Duration::from_secs(10),
+                next_id: 1,
+            }
+        }
+
+        /// Connect, read and write timeout for each call; 10 seconds by
+        /// default.
+        pub fn set_timeout(&mut self, timeout: Duration) {
+            self.timeout = timeout;
+        }
+
+        /// Calls `method` with one string parameter and returns the result.
+        fn call(&mut self, method: &str, param: &str) -> io::Result<Value> {
+            let id = self.next_id;
+            self.next_id += 1;
+            let body = format!(
+                "{{\"jsonrpc\":\"2.0\",\"id\":{},\"method\":{},\"params\":[{}]}}",
+                id,
+                json::quote(method),
+                json::quote(param)
+            );
+            let response = http::post(&self.url, "application/json", &body, self.timeout)?;
+            let response = json::parse(&response).ok_or_else(|| invalid("response is not JSON"))?;
+            if response.get("id").and_then(Value::as_u64) != Some(id) {
+                return Err(invalid("response is for another request"));
+            }
+            if let Some(error) = response.get("error") {
+                let message = error.get("message").and_then(Value::as_str);
+                return Err(io::Error::other(format!(
+                    "{} failed: {}",
+                    method,
+                    message.unwrap_or("no message")
+                )));
+            }
+            response
+                .get("result")
+                .cloned()
+                .ok_or_else(|| invalid("response has no result"))
+        }
+    }
+
+    impl Anchor for JsonRpcAnchor {
+        type Error = io::Error;
+
+        fn submit(&mut self, digest: [u8; 32]) -> io::Result<String> {
+            let method = self.submit_method.clone();
+            let result = self.call(&method, &hex(&digest))?;
+            result
+                .as_str()
+                .map(str::to_string)
+                .ok_or_else(|| invalid("transaction id is not a string"))
+        }
+
+        fn confirmation(&mut self, id: &str) -> io::Result<Option<Confirmation>> {
+            let method = self.status_method.clone();
+            let result = self.call(&method, id)?;
+            if result == Value::Null {
+                return Ok(None);
+            }
+            let field = |name: &str| result.get(name).and_then(Value::as_u64);
+            let attestation = match result.get("attestation") {
+                None => Vec::new(),
+                Some(value) => value
+                    .as_str()
+                    .and_then(unhex)
+                    .ok_or_else(|| invalid("attestation is not hex"))?,
+            };
+            match (field("block"), field("timestamp")) {
+                (Some(block), Some(timestamp)) => Ok(Some(Confirmation {
+                    block,
+                    timestamp,
+                    attestation,
+                })),
+                _ => Err(invalid("confirmation needs a block and a timestamp")),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    struct Keyed([u8; 32]);
+
+    impl Keyed {
+        fn sign(&self, message: &[u8; 32]) -> Vec<u8> {
+            hash_data(&[self.0, *message].concat()).to_vec()
+        }
+    }
+
+    impl HeadVerifier for Keyed {
+        fn verify(&self, message: &[u8; 32], signature: &[u8]) -> bool {
+            self.sign(message) == signature
+        }
+    }
+
+    /// Confirms each digest on the second poll, in a block of its own.
+    #[derive(Default)]
+    struct FakeChain {
+        polls: HashMap<String, u32>,
+        digests: Vec<[u8; 32]>,
+    }
+
+    impl Anchor for FakeChain {
+        type Error = ();
+
+        fn submit(&mut self, digest: [u8; 32]) -> Result<String, ()> {
+            self.digests.push(digest);
+            Ok(format!("tx{}", self.digests.len() - 1))
+        }
+
+        fn confirmation(&mut self, id: &str) -> Result<Option<Confirmation>, ()> {
+            let block: u64 = id.strip_prefix("tx").ok_or(())?.parse().map_err(|_| ())?;
+            let polls = self.polls.entry(id.to_string()).or_insert(0);
+            *polls += 1;
+            if *polls < 2 {
+                return Ok(None);
+            }
+            // Signs `AnchorReceipt::message`, knowing the head by its hash.
+            let timestamp = 1_700_000_000 + block;
+            let mut bytes = self.digests[block as usize].to_vec();
+            bytes.extend_from_slice(&block.to_le_bytes());
+            bytes.extend_from_slice(&timestamp.to_le_bytes());
+            bytes.extend_from_slice(id.as_bytes());
+            Ok(Some(Confirmation {
+                block,
+                timestamp,
+                attestation: Keyed([7; 32]).sign(&hash_data(&bytes)),
+            }))
+        }
+    }
+
+    #[test]
+    fn anchors_and_verifies_heads() {
+        let mut chain = FakeChain::default();
+        let head = TreeHead {
+            size: 12,
+            root: [0xab; 32],
+        };
+        let pending = PendingAnchor::submit(&mut chain, "fake", head).unwrap();
+        assert_eq!(chain.digests, vec![head.hash()]);
+        assert_eq!(pending.poll(&mut chain), Ok(None));
+        let receipt = pending.poll(&mut chain).unwrap().unwrap();
+        assert_eq!(
+            (receipt.chain.as_str(), receipt.confirmation.block),
+            ("fake", 0)
+        );
+
+        let verifier = SignedReceipts(Keyed([7; 32]));
+        assert!(receipt.verify(&head, &verifier));
+        assert!(!receipt.verify(&head, &SignedReceipts(Keyed([8; 32]))));
+        let other = TreeHead { size: 13, ..head };
+        assert!(!receipt.verify(&other, &verifier));
+        let mut moved = receipt.clone();
+        moved.confirmation.timestamp += 1;
+        assert!(!moved.verify(&head, &verifier));
+    }
+
+    #[cfg(feature = "anchor-client")]
+    #[test]
+    fn json_rpc_round_trip() {
+        use std::io::{BufRead, BufReader, Read, Write};
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/rpc", listener.local_addr().unwrap());
+        let requests = std::thread::spawn(move || {
+            let answers = [
+                r#""0xfeed""#,
+                "null",
+                r#"{"block": 812, "timestamp": 1700000123, "attestation": "beef"}"#,
+            ];
+            let mut seen = Vec::new();
+            for (n, answer) in answers.iter().enumerate() {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if let Some(value) = line.strip_prefix("Content-Length: ") {
+                        length = value.trim().parse().unwrap();
+                    }
+                    if line == "\r\n" {
+                        break;
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                seen.push(String::from_utf8(body).unwrap());
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\n\r\n{{\"jsonrpc\":\"2.0\",\"id\":{},\"result\":{}}}",
+                    n + 1,
+                    answer
+                );
+                (&stream).write_all(response.as_bytes()).unwrap();
+            }
+            seen
+        });
+
+        let mut anchor = JsonRpcAnchor::new(&url, "anchor_submit", "anchor_status");
+        let head = TreeHead {
+            size: 3,
+            root: [1; 32],
+        };
+        let pending = PendingAnchor::submit(&mut anchor, "rpc", head).unwrap();
+        assert_eq!(pending.transaction, "0xfeed");
+        assert_eq!(pending.poll(&mut anchor).unwrap(), None);
+        let receipt = pending.poll(&mut anchor).unwrap().unwrap();
+        assert_eq!(
+            receipt.confirmation,
+            Confirmation {
+                block: 812,
+                timestamp: 1_700_000_123,
+                attestation: vec![0xbe, 0xef],
+            }
+        );
+
+        let seen = requests.join().unwrap();
+        let digest: String = head.hash().iter().map(|b| format!("{:02x}", b)).collect();
+        assert!(seen[0].contains("\"method\":\"anchor_submit\""));
+        assert!(seen[0].contains(&digest));
+        assert!(seen[1].contains("\"params\":[\"0xfeed\"]"));
+    }
+}
@@ -0,0 +1,105 @@
+//! Growing a tree by more leaves. Nodes are kept leaves first, so every
+//! internal node moves when leaves are added and appending rebuilds the
+//! levels above the leaves; for a tree that only grows, `MerkleLog` or the
+//! streaming builder avoid that. Labels, leaf ids and the content index
+//! carry over, and each new leaf gets the next id.
+
+use crate::{MerkleHasher, MerkleTree};
+use alloc::vec::Vec;
+use core::ops::Range;
+
+impl<H: MerkleHasher> MerkleTree<H> {
+    /// Adds `item` as the last leaf and returns its id.
+    pub fn append<T: AsRef<[u8]>>(&mut self, item: T) -> u64 {
+        self.append_leaves([item]).start
+    }
+
+    /// Adds `items` after the last leaf, in order, rebuilding the levels
+    /// once, and returns the ids they were given.
+    pub fn append_leaves<I>(&mut self, items: I) -> Range<u64>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        let start = self.leaves;
+        let mut ctx = H::Context::default();
+        let mut hashes: Vec<[u8; 32]> = self.nodes[..start].iter().map(|node| node.value).collect();
+        hashes.extend(
+            items
+                .into_iter()
+                .map(|item| H::hash_data_with(&mut ctx, item.as_ref())),
+        );
+        let end = hashes.len();
+        if end == start {
+            return start as u64..start as u64;
+        }
+        let grown = MerkleTree::<H>::from_leaf_hashes_in(&mut ctx, hashes, self.padding);
+        self.nodes = grown.nodes;
+        self.leaves = grown.leaves;
+        self.layout = grown.layout;
+        for idx in start..end {
+            // The new ids are the new positions.
+            if !self.ids.is_empty() {
+                self.ids.push(idx as u64);
+                self.id_positions.push(idx);
+            }
+            if let Some(positions) = &mut self.positions {
+                positions
+                    .entry(self.nodes[idx].value)
+                    .or_insert_with(Vec::new)
+                    .push(idx);
+            }
+        }
+        start as u64..end as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{verify_proof, MerkleTree, Padding};
+
+    #[test]
+    fn matches_rebuild() {
+        let items: Vec<[u8; 1]> = (0..9u8).map(|i| [i]).collect();
+        for &padding in [Padding::Promote, Padding::DuplicateLast, Padding::Zero].iter() {
+            let mut tree = MerkleTree::from_leaves_padded(&items[..1], padding).unwrap();
+            tree.index_leaves();
+            assert!(tree.set_label(0, "first"));
+            for (len, item) in items.iter().enumerate().skip(1) {
+                assert_eq!(tree.append(item), len as u64);
+                let rebuilt = MerkleTree::from_leaves_padded(&items[..=len], padding).unwrap();
+                assert_eq!(tree.root(), rebuilt.root());
+                assert_eq!(tree.leaf_index(item), Some(len));
+            }
+            assert_eq!(tree.leaf_by_label("first"), Some(0));
+            assert_eq!(tree.append_leaves(Vec::<&[u8]>::new()), 9..9);
+            assert_eq!(tree.append_leaves(["x", "y"]), 9..11);
+            let path = tree.proof_by_id(10).unwrap();
+            assert!(verify_proof(b"y", tree.root(), &path).is_valid());
+        }
+    }
+
+    #[test]
+    fn ids_survive_sorting_and_appends() {
+        let items = ["pear", "fig", "apple"];
+        let (mut tree, order) = MerkleTree::from_leaves_sorted(&items).unwrap();
+        assert_ne!(order, [0, 1, 2]);
+        for (id, item) in items.iter().enumerate() {
+            let idx = tree.leaf_by_id(id as u64).unwrap();
+            assert_eq!(order[idx], id);
+            assert_eq!(tree.leaf_id(idx), Some(id as u64));
+            let path = tree.proof_by_id(id as u64).unwrap();
+            assert!(verify_proof(item.as_bytes(), tree.root(), &path).is_valid());
+        }
+
+        let root = tree.root();
+        assert_eq!(tree.append("kiwi"), 3);
+        assert_ne!(tree.root(), root);
+        for (id, item) in items.iter().chain(["kiwi"].iter()).enumerate() {
+            let path = tree.proof_by_id(id as u64).unwrap();
+            assert!(verify_proof(item.as_bytes(), tree.root(), &path).is_valid());
+        }
+        assert_eq!((tree.leaf_by_id(3), tree.next_id()), (Some(3), 4));
+        assert!(tree.proof_by_id(4).is_none());
+    }
+}
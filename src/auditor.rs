@@ -0,0 +1,272 @@
+//! Third-party spot checks of a remote [`MerkleLog`](crate::MerkleLog). [`sample`] fetches
+//! the log's head, picks entries at random, checks each against the head
+//! with its inclusion proof, and signs an [`AuditReport`] of the results.
+//!
+//! The sampled indices are drawn from `H("merkle-audit" || seed ||
+//! head hash || counter_le)`, so anyone holding the report can recompute
+//! them and see that the auditor did not pick the entries to suit the
+//! log. The seed should be unknown to the log until its head is fixed,
+//! say a fresh random value or a recent beacon output.
+
+use crate::{hash_data, log, verify_inclusion, HeadSigner, HeadVerifier, TreeHead};
+use sha2::{Digest, Sha256};
+use std::convert::TryInto;
+
+/// How an auditor reaches a log.
+pub trait LogTransport {
+    type Error;
+
+    fn head(&mut self) -> Result<TreeHead, Self::Error>;
+
+    fn entry(&mut self, index: u64) -> Result<Vec<u8>, Self::Error>;
+
+    /// Inclusion proof for entry `index` in the log of `size` entries.
+    fn inclusion_proof(&mut self, index: u64, size: u64) -> Result<Vec<[u8; 32]>, Self::Error>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleOutcome {
+    /// The entry, whose RFC 6962 leaf hash is `leaf`, is in the log.
+    Verified { leaf: [u8; 32] },
+    /// The entry or its proof was returned but does not match the head.
+    Invalid,
+    /// The log did not answer for this entry.
+    Unavailable,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sample {
+    pub index: u64,
+    pub outcome: SampleOutcome,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditReport {
+    pub head: TreeHead,
+    pub seed: [u8; 32],
+    /// Seconds since the Unix epoch at which the auditor signed.
+    pub issued_at: u64,
+    pub samples: Vec<Sample>,
+    pub signature: Vec<u8>,
+}
+
+/// Indices of the entries to sample: `count` distinct ones, or every entry
+/// if the log has no more than that.
+pub fn sample_indices(head: &TreeHead, seed: &[u8; 32], count: usize) -> Vec<u64> {
+    if count as u64 >= head.size {
+        return (0..head.size).collect();
+    }
+    let mut indices = Vec::with_capacity(count);
+    let mut counter = 0u64;
+    while indices.len() < count {
+        let mut hasher = Sha256::new();
+        hasher.update(b"merkle-audit");
+        hasher.update(seed);
+        hasher.update(head.hash());
+        hasher.update(counter.to_le_bytes());
+        counter += 1;
+        let digest: [u8; 32] = hasher.finalize().into();
+        let index = u64::from_le_bytes(digest[..8].try_into().unwrap()) % head.size;
+        if !indices.contains(&index) {
+            indices.push(index);
+        }
+    }
+    indices
+}
+
+/// Audits `count` entries of the log behind `transport`. Only a failure to
+/// fetch the head is an error; entries that cannot be fetched or checked
+/// are reported as such.
+pub fn sample<T: LogTransport, S: HeadSigner>(
+    transport: &mut T,
+    count: usize,
+    seed: [u8; 32],
+    issued_at: u64,
+    signer: &S,
+) -> Result<AuditReport, T::Error> {
+    let head = transport.head()?;
+    let samples = sample_indices(&head, &seed, count)
+        .into_iter()
+        .map(|index| {
+            let fetched = transport.entry(index).and_then(|entry| {
+                let proof = transport.inclusion_proof(index, head.size)?;
+                Ok((entry, proof))
+            });
+            let outcome = match fetched {
+                Err(_) => SampleOutcome::Unavailable,
+                Ok((entry, proof))
+                    if verify_inclusion(&entry, index, head.size, head.root, &proof) =>
+                {
+                    SampleOutcome::Verified {
+                        leaf: log::leaf_hash(&entry),
+                    }
+                }
+                Ok(_) => SampleOutcome::Invalid,
+            };
+            Sample { index, outcome }
+        })
+        .collect();
+    let mut report = AuditReport {
+        head,
+        seed,
+        issued_at,
+        samples,
+        signature: Vec::new(),
+    };
+    report.signature = signer.sign(&report.message());
+    Ok(report)
+}
+
+impl AuditReport {
+    /// Whether every sampled entry verified.
+    pub fn passed(&self) -> bool {
+        self.samples
+            .iter()
+            .all(|sample| matches!(sample.outcome, SampleOutcome::Verified { .. }))
+    }
+
+    /// The hash the auditor signs: the head, seed and time, then each
+    /// sample's index, outcome tag and, when verified, leaf hash.
+    pub fn message(&self) -> [u8; 32] {
+        let mut bytes = b"merkle-audit-report".to_vec();
+        bytes.extend_from_slice(&self.head.hash());
+        bytes.extend_from_slice(&self.seed);
+        bytes.extend_from_slice(&self.issued_at.to_le_bytes());
+        for sample in &self.samples {
+            bytes.extend_from_slice(&sample.index.to_le_bytes());
+            match sample.outcome {
+                SampleOutcome::Verified { leaf } => {
+                    bytes.push(0);
+                    bytes.extend_from_slice(&leaf);
+                }
+                SampleOutcome::Invalid => bytes.push(1),
+                SampleOutcome::Unavailable => bytes.push(2),
+            }
+        }
+        hash_data(&bytes)
+    }
+
+    /// Checks the auditor's signature and that the samples are the ones the
+    /// seed picks.
+    pub fn verify<V: HeadVerifier>(&self, verifier: &V) -> bool {
+        let picked = sample_indices(&self.head, &self.seed, self.samples.len());
+        picked.len() == self.samples.len()
+            && picked
+                .iter()
+                .zip(&self.samples)
+                .all(|(&index, sample)| sample.index == index)
+            && verifier.verify(&self.message(), &self.signature)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MerkleLog;
+
+    struct Keyed([u8; 32]);
+
+    impl HeadSigner for Keyed {
+        fn sign(&self, message: &[u8; 32]) -> Vec<u8> {
+            hash_data(&[self.0, *message].concat()).to_vec()
+        }
+    }
+
+    impl HeadVerifier for Keyed {
+        fn verify(&self, message: &[u8; 32], signature: &[u8]) -> bool {
+            self.sign(message) == signature
+        }
+    }
+
+    /// A log that serves its entries, lying about the ones in `forged` and
+    /// refusing the ones in `missing`.
+    struct Remote {
+        log: MerkleLog,
+        entries: Vec<Vec<u8>>,
+        forged: Vec<u64>,
+        missing: Vec<u64>,
+    }
+
+    impl Remote {
+        fn new(size: u8) -> Self {
+            let mut log = MerkleLog::new();
+            let entries: Vec<Vec<u8>> = (0..size).map(|i| vec![i; 3]).collect();
+            for entry in &entries {
+                log.append(entry);
+            }
+            Remote {
+                log,
+                entries,
+                forged: Vec::new(),
+                missing: Vec::new(),
+            }
+        }
+    }
+
+    impl LogTransport for Remote {
+        type Error = String;
+
+        fn head(&mut self) -> Result<TreeHead, String> {
+            Ok(TreeHead {
+                size: self.log.len(),
+                root: self.log.root(),
+            })
+        }
+
+        fn entry(&mut self, index: u64) -> Result<Vec<u8>, String> {
+            if self.missing.contains(&index) {
+                return Err("unavailable".to_string());
+            }
+            let entry = self.entries.get(index as usize).ok_or("no entry")?;
+            if self.forged.contains(&index) {
+                return Ok(b"forged".to_vec());
+            }
+            Ok(entry.clone())
+        }
+
+        fn inclusion_proof(&mut self, index: u64, size: u64) -> Result<Vec<[u8; 32]>, String> {
+            self.log
+                .inclusion_proof(index, size)
+                .ok_or_else(|| "no proof".to_string())
+        }
+    }
+
+    #[test]
+    fn samples_and_signs() {
+        let mut remote = Remote::new(40);
+        let key = Keyed([3; 32]);
+        let report = sample(&mut remote, 8, [9; 32], 1_700_000_000, &key).unwrap();
+        assert_eq!(report.samples.len(), 8);
+        assert!(report.passed());
+        assert!(report.verify(&key));
+        assert!(!report.verify(&Keyed([4; 32])));
+
+        // Samples the auditor chose itself do not match the seed.
+        let mut cherry_picked = report.clone();
+        cherry_picked.samples[0].index = (report.samples[0].index + 1) % 40;
+        cherry_picked.signature = key.sign(&cherry_picked.message());
+        assert!(!cherry_picked.verify(&key));
+
+        let picked = sample_indices(&report.head, &[9; 32], 8);
+        remote.forged.push(picked[2]);
+        remote.missing.push(picked[5]);
+        let report = sample(&mut remote, 8, [9; 32], 1_700_000_001, &key).unwrap();
+        assert!(!report.passed());
+        assert_eq!(report.samples[2].outcome, SampleOutcome::Invalid);
+        assert_eq!(report.samples[5].outcome, SampleOutcome::Unavailable);
+        assert!(report.verify(&key));
+    }
+
+    #[test]
+    fn small_logs_are_sampled_whole() {
+        let head = TreeHead {
+            size: 5,
+            root: [0; 32],
+        };
+        assert_eq!(sample_indices(&head, &[1; 32], 9), vec![0, 1, 2, 3, 4]);
+        let mut indices = sample_indices(&head, &[1; 32], 4);
+        indices.sort_unstable();
+        indices.dedup();
+        assert_eq!(indices.len(), 4);
+    }
+}
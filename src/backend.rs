@@ -0,0 +1,37 @@
+/// Name of the SHA-256 kernel `sha2` dispatches to on this machine.
+///
+/// On x86 the SHA-NI path is picked at runtime; otherwise the portable
+/// implementation is used (or the `asm` one with our `asm` feature). On
+/// aarch64 the ARMv8 crypto extensions are only used with the `asm` feature.
+pub fn backend() -> &'static str {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    {
+        if is_x86_feature_detected!("sha")
+            && is_x86_feature_detected!("sse2")
+            && is_x86_feature_detected!("ssse3")
+            && is_x86_feature_detected!("sse4.1")
+        {
+            return "sha-ni";
+        }
+        if cfg!(feature = "asm") {
+            return "asm";
+        }
+    }
+    #[cfg(all(target_arch = "aarch64", feature = "asm"))]
+    {
+        if std::arch::is_aarch64_feature_detected!("sha2") {
+            return "armv8-crypto";
+        }
+    }
+    "soft"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_known_backend() {
+        assert!(["sha-ni", "asm", "armv8-crypto", "soft"].contains(&backend()));
+    }
+}
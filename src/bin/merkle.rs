@@ -0,0 +1,386 @@
+//! Command-line roots and proofs over files and directories, for attesting
+//! build artifacts without a wrapper program.
+//!
+//! ```text
+//! merkle root <path> [--chunk-size N]
+//! merkle prove <file> --chunk I [--chunk-size N] -o <proof.bin>
+//! merkle prove <dir> --entry <relative path> -o <proof.bin>
+//! merkle verify <proof.bin> --root <hex> --leaves N
+//! ```
+//!
+//! A file's leaves are its chunks of `--chunk-size` bytes (4096 unless
+//! given), the last of which may be short. A directory's leaves are its
+//! files, found recursively and sorted by `/`-separated relative path, one
+//! leaf per file: the path, a zero byte, then the SHA-256 of the contents.
+//! Symbolic links are skipped. Trees use SHA-256 and the default padding.
+//!
+//! A proof file holds everything `verify` needs: after the magic `MRKP`,
+//! extension fields (as in the library's formats) carry the encoded
+//! `Proof` and the leaf item it proves. `verify` also takes the tree's leaf
+//! count, published with the root, and refuses a proof whose directions
+//! are not those of the leaf it names.
+
+use merkle::{ext, MerkleTree, Proof, TreeHead, VerificationOutcome};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use std::process;
+
+const DEFAULT_CHUNK_SIZE: usize = 4096;
+const MAGIC: &[u8] = b"MRKP";
+const PROOF_TAG: u16 = 1;
+const ITEM_TAG: u16 = 2;
+
+const USAGE: &str = "usage:
+  merkle root <path> [--chunk-size N]
+  merkle prove <file> --chunk I [--chunk-size N] -o <proof.bin>
+  merkle prove <dir> --entry <relative path> -o <proof.bin>
+  merkle verify <proof.bin> --root <hex> --leaves N";
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn unhex(text: &str) -> Option<[u8; 32]> {
+    if text.len() != 64 {
+        return None;
+    }
+    let mut out = [0; 32];
+    for (i, byte) in out.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(text.get(2 * i..2 * i + 2)?, 16).ok()?;
+    }
+    Some(out)
+}
+
+/// A command line split into positional arguments and `--flag value` pairs.
+struct Args {
+    positional: Vec<String>,
+    flags: Vec<(String, String)>,
+}
+
+impl Args {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut parsed = Args {
+            positional: Vec::new(),
+            flags: Vec::new(),
+        };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if arg.starts_with('-') && arg.len() > 1 {
+                let value = args
+                    .next()
+                    .ok_or_else(|| format!("{} needs a value", arg))?;
+                parsed.flags.push((arg.clone(), value.clone()));
+            } else {
+                parsed.positional.push(arg.clone());
+            }
+        }
+        Ok(parsed)
+    }
+
+    fn flag(&self, names: &[&str]) -> Option<&str> {
+        self.flags
+            .iter()
+            .find(|(name, _)| names.contains(&name.as_str()))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Fails on flags outside `known` and on a positional count other than
+    /// `count`.
+    fn expect(&self, count: usize, known: &[&str]) -> Result<(), String> {
+        if let Some((name, _)) = self
+            .flags
+            .iter()
+            .find(|(name, _)| !known.contains(&name.as_str()))
+        {
+            return Err(format!("unknown option {}", name));
+        }
+        if self.positional.len() != count {
+            return Err(USAGE.to_string());
+        }
+        Ok(())
+    }
+
+    fn number(&self, names: &[&str]) -> Result<Option<usize>, String> {
+        self.flag(names)
+            .map(|value| {
+                value
+                    .parse()
+                    .map_err(|_| format!("{} is not a number: {}", names[0], value))
+            })
+            .transpose()
+    }
+}
+
+fn read(path: &Path) -> Result<Vec<u8>, String> {
+    fs::read(path).map_err(|err| format!("{}: {}", path.display(), err))
+}
+
+fn file_items(path: &Path, chunk_size: usize) -> Result<Vec<Vec<u8>>, String> {
+    if chunk_size == 0 {
+        return Err("--chunk-size must be positive".to_string());
+    }
+    let bytes = read(path)?;
+    if bytes.is_empty() {
+        return Err(format!("{}: file is empty", path.display()));
+    }
+    Ok(bytes.chunks(chunk_size).map(<[u8]>::to_vec).collect())
+}
+
+fn content_hash(path: &Path) -> Result<[u8; 32], String> {
+    let mut file = fs::File::open(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0; 1 << 16];
+    loop {
+        let read = file
+            .read(&mut buf)
+            .map_err(|err| format!("{}: {}", path.display(), err))?;
+        if read == 0 {
+            return Ok(hasher.finalize().into());
+        }
+        hasher.update(&buf[..read]);
+    }
+}
+
+/// Relative paths of the files under `root`, sorted.
+fn walk(root: &Path) -> Result<Vec<String>, String> {
+    let mut found = Vec::new();
+    let mut pending = vec![(root.to_path_buf(), String::new())];
+    while let Some((dir, prefix)) = pending.pop() {
+        let entries = fs::read_dir(&dir).map_err(|err| format!("{}: {}", dir.display(), err))?;
+        for entry in entries {
+            let entry = entry.map_err(|err| format!("{}: {}", dir.display(), err))?;
+            let name = entry
+                .file_name()
+                .into_string()
+                .map_err(|name| format!("{}: name is not UTF-8", name.to_string_lossy()))?;
+            let relative = format!("{}{}", prefix, name);
+            let kind = entry
+                .file_type()
+                .map_err(|err| format!("{}: {}", relative, err))?;
+            if kind.is_dir() {
+                pending.push((entry.path(), relative + "/"));
+            } else if kind.is_file() {
+                found.push(relative);
+            }
+        }
+    }
+    found.sort_unstable();
+    Ok(found)
+}
+
+/// The directory's leaf items, with the relative path of each.
+fn dir_items(root: &Path) -> Result<Vec<(String, Vec<u8>)>, String> {
+    let files = walk(root)?;
+    if files.is_empty() {
+        return Err(format!("{}: no files", root.display()));
+    }
+    files
+        .into_iter()
+        .map(|relative| {
+            let mut item = relative.clone().into_bytes();
+            item.push(0);
+            item.extend_from_slice(&content_hash(&root.join(&relative))?);
+            Ok((relative, item))
+        })
+        .collect()
+}
+
+fn root(args: &Args) -> Result<String, String> {
+    args.expect(1, &["--chunk-size"])?;
+    let path = Path::new(&args.positional[0]);
+    let items = if path.is_dir() {
+        if args.flag(&["--chunk-size"]).is_some() {
+            return Err("--chunk-size is for files".to_string());
+        }
+        dir_items(path)?.into_iter().map(|(_, item)| item).collect()
+    } else {
+        let chunk_size = args
+            .number(&["--chunk-size"])?
+            .unwrap_or(DEFAULT_CHUNK_SIZE);
+        file_items(path, chunk_size)?
+    };
+    Ok(hex(&MerkleTree::from_leaves(&items).unwrap().root()))
+}
+
+fn prove(args: &Args) -> Result<String, String> {
+    args.expect(1, &["--chunk", "--chunk-size", "--entry", "-o", "--output"])?;
+    let path = Path::new(&args.positional[0]);
+    let output = args
+        .flag(&["-o", "--output"])
+        .ok_or("prove needs -o <proof.bin>")?;
+    let (items, idx) = if path.is_dir() {
+        let entry = args
+            .flag(&["--entry"])
+            .ok_or("directories are proven by --entry <relative path>")?;
+        let items = dir_items(path)?;
+        let idx = items
+            .iter()
+            .position(|(relative, _)| relative == entry)
+            .ok_or_else(|| format!("{}: no such file in {}", entry, path.display()))?;
+        (items.into_iter().map(|(_, item)| item).collect(), idx)
+    } else {
+        let chunk_size = args
+            .number(&["--chunk-size"])?
+            .unwrap_or(DEFAULT_CHUNK_SIZE);
+        let idx = args
+            .number(&["--chunk"])?
+            .ok_or("files are proven by --chunk <index>")?;
+        (file_items(path, chunk_size)?, idx)
+    };
+    let tree = MerkleTree::from_leaves(&items).unwrap();
+    let proof = tree
+        .leaf_proof(idx)
+        .map_err(|_| format!("chunk {} is past the last of {}", idx, items.len()))?;
+
+    let mut out = MAGIC.to_vec();
+    let encoded = proof.to_bytes().ok_or("proof is too deep to encode")?;
+    ext::write_field(&mut out, PROOF_TAG, &encoded).unwrap();
+    ext::write_field(&mut out, ITEM_TAG, &items[idx]).ok_or("chunk is too large")?;
+    fs::write(output, out).map_err(|err| format!("{}: {}", output, err))?;
+    Ok(hex(&tree.root()))
+}
+
+fn verify(args: &Args) -> Result<String, String> {
+    args.expect(1, &["--root", "--leaves"])?;
+    let root = args.flag(&["--root"]).ok_or("verify needs --root <hex>")?;
+    let root = unhex(root).ok_or("--root must be 64 hex digits")?;
+    let leaves = args
+        .number(&["--leaves"])?
+        .ok_or("verify needs --leaves N")?;
+    let head = TreeHead {
+        size: leaves as u64,
+        root,
+    };
+    let bytes = read(Path::new(&args.positional[0]))?;
+    let fields = bytes
+        .strip_prefix(MAGIC)
+        .filter(|fields| ext::check(fields, &[]))
+        .ok_or("not a proof file")?;
+    let proof = ext::find(fields, PROOF_TAG)
+        .and_then(Proof::from_bytes)
+        .ok_or("proof file has no valid proof")?;
+    let item = ext::find(fields, ITEM_TAG).ok_or("proof file has no item")?;
+    match proof.verify(item, &head) {
+        VerificationOutcome::Valid => Ok(format!("valid: leaf {}", proof.index())),
+        VerificationOutcome::Invalid(failure) => Err(format!("invalid: {}", failure)),
+    }
+}
+
+fn run(args: &[String]) -> Result<String, String> {
+    let (command, rest) = args.split_first().ok_or(USAGE)?;
+    let rest = Args::parse(rest)?;
+    match command.as_str() {
+        "root" => root(&rest),
+        "prove" => prove(&rest),
+        "verify" => verify(&rest),
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match run(&args) {
+        Ok(output) => println!("{}", output),
+        Err(message) => {
+            eprintln!("{}", message);
+            process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run_with(args: &[&str]) -> Result<String, String> {
+        run(&args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn files_and_directories() {
+        let dir = std::env::temp_dir().join(format!("merkle-cli-{}", process::id()));
+        let artifacts = dir.join("artifacts");
+        fs::create_dir_all(artifacts.join("lib")).unwrap();
+        let data: Vec<u8> = (0..10_000u32).map(|i| i as u8).collect();
+        fs::write(artifacts.join("app"), &data).unwrap();
+        fs::write(artifacts.join("lib").join("core.so"), b"core").unwrap();
+        let (app, out) = (artifacts.join("app"), dir.join("proof.bin"));
+        let (app, out) = (app.to_str().unwrap(), out.to_str().unwrap());
+
+        let root = run_with(&["root", app, "--chunk-size", "1000"]).unwrap();
+        let chunks: Vec<&[u8]> = data.chunks(1000).collect();
+        assert_eq!(root, hex(&MerkleTree::from_leaves(chunks).unwrap().root()));
+        assert_eq!(run_with(&["root", app]).unwrap().len(), 64);
+        let proven = run_with(&[
+            "prove",
+            app,
+            "--chunk",
+            "7",
+            "--chunk-size",
+            "1000",
+            "-o",
+            out,
+        ]);
+        assert_eq!(proven.unwrap(), root);
+        assert_eq!(
+            run_with(&["verify", out, "--root", &root, "--leaves", "10"]).unwrap(),
+            "valid: leaf 7"
+        );
+        let other = hex(&[0; 32]);
+        assert!(
+            run_with(&["verify", out, "--root", &other, "--leaves", "10"])
+                .unwrap_err()
+                .starts_with("invalid")
+        );
+        assert!(run_with(&["verify", out, "--root", &root, "--leaves", "4"])
+            .unwrap_err()
+            .starts_with("invalid: bad index"));
+        assert_eq!(
+            run_with(&["verify", out, "--root", &root]),
+            Err("verify needs --leaves N".to_string())
+        );
+        assert!(run_with(&[
+            "prove",
+            app,
+            "--chunk",
+            "10",
+            "--chunk-size",
+            "1000",
+            "-o",
+            out
+        ])
+        .is_err());
+
+        let dir_root = run_with(&["root", artifacts.to_str().unwrap()]).unwrap();
+        let entry = [
+            "prove",
+            artifacts.to_str().unwrap(),
+            "--entry",
+            "lib/core.so",
+            "-o",
+            out,
+        ];
+        assert_eq!(run_with(&entry).unwrap(), dir_root);
+        assert_eq!(
+            run_with(&["verify", out, "--root", &dir_root, "--leaves", "2"]).unwrap(),
+            "valid: leaf 1"
+        );
+        fs::write(artifacts.join("lib").join("core.so"), b"tampered").unwrap();
+        let changed = run_with(&["root", artifacts.to_str().unwrap()]).unwrap();
+        assert_ne!(changed, dir_root);
+        assert!(run_with(&["verify", out, "--root", &changed, "--leaves", "2"]).is_err());
+
+        fs::write(out, b"not a proof").unwrap();
+        assert_eq!(
+            run_with(&["verify", out, "--root", &root, "--leaves", "10"]),
+            Err("not a proof file".to_string())
+        );
+        assert_eq!(
+            run_with(&["root", app, "--bogus", "1"]),
+            Err("unknown option --bogus".to_string())
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
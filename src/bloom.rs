@@ -0,0 +1,224 @@
+use crate::format::{Version, CURRENT};
+use crate::memory::{vec_bytes, HeapSize};
+use crate::{hash_data, hash_pair, MerkleTree, Path};
+use std::convert::TryInto;
+
+/// Most hash functions a filter may use. Past a handful, more hashes only
+/// raise the false positive rate, and each one costs every lookup a probe.
+pub const MAX_HASHES: u32 = 32;
+
+/// Bloom filter over leaf hashes. Leaf hashes are already uniform, so bit
+/// positions are taken straight from them by double hashing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "borsh", derive(borsh::BorshSerialize))]
+pub struct BloomFilter {
+    bits: Vec<u8>,
+    hashes: u32,
+}
+
+impl BloomFilter {
+    /// `hashes` is clamped to `1..=MAX_HASHES`.
+    pub fn new(bits: usize, hashes: u32) -> Self {
+        BloomFilter {
+            bits: vec![0; bits.max(8).div_ceil(8)],
+            hashes: hashes.clamp(1, MAX_HASHES),
+        }
+    }
+
+    pub fn from_tree(tree: &MerkleTree, bits_per_leaf: usize, hashes: u32) -> Self {
+        let mut filter = BloomFilter::new(tree.leaves() * bits_per_leaf, hashes);
+        for idx in 0..tree.leaves() {
+            filter.insert_hash(&tree.nodes[idx].value);
+        }
+        filter
+    }
+
+    pub fn insert_hash(&mut self, leaf_hash: &[u8; 32]) {
+        for bit in positions(self.hashes, self.bits.len(), leaf_hash) {
+            self.bits[bit / 8] |= 1 << (bit % 8);
+        }
+    }
+
+    pub fn might_contain_hash(&self, leaf_hash: &[u8; 32]) -> bool {
+        positions(self.hashes, self.bits.len(), leaf_hash)
+            .all(|bit| self.bits[bit / 8] & (1 << (bit % 8)) != 0)
+    }
+
+    pub fn might_contain(&self, item: &[u8]) -> bool {
+        self.might_contain_hash(&hash_data(item))
+    }
+
+    /// Encoding committed to by `FilteredTree`: format version, hash count
+    /// (u32 LE), bit length in bytes (u64 LE), then the bits.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(13 + self.bits.len());
+        out.push(CURRENT.byte());
+        out.extend_from_slice(&self.hashes.to_le_bytes());
+        out.extend_from_slice(&(self.bits.len() as u64).to_le_bytes());
+        out.extend_from_slice(&self.bits);
+        out
+    }
+
+    /// Rejects hash counts outside `1..=MAX_HASHES`, so that an untrusted
+    /// filter cannot make lookups arbitrarily slow.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 13 || Version::from_byte(bytes[0]) != Some(Version::V1) {
+            return None;
+        }
+        let bytes = &bytes[1..];
+        let hashes = u32::from_le_bytes(bytes[0..4].try_into().unwrap());
+        let len = u64::from_le_bytes(bytes[4..12].try_into().unwrap());
+        if !(1..=MAX_HASHES).contains(&hashes) || len == 0 || len != (bytes.len() - 12) as u64 {
+            return None;
+        }
+        Some(BloomFilter {
+            bits: bytes[12..].to_vec(),
+            hashes,
+        })
+    }
+
+    pub fn commitment(&self) -> [u8; 32] {
+        hash_data(&self.to_bytes())
+    }
+}
+
+#[cfg(feature = "borsh")]
+impl borsh::BorshDeserialize for BloomFilter {
+    fn deserialize_reader<R: borsh::io::Read>(reader: &mut R) -> borsh::io::Result<Self> {
+        let bits = Vec::<u8>::deserialize_reader(reader)?;
+        let hashes = u32::deserialize_reader(reader)?;
+        if bits.is_empty() || !(1..=MAX_HASHES).contains(&hashes) {
+            return Err(borsh::io::Error::new(
+                borsh::io::ErrorKind::InvalidData,
+                "invalid bloom filter",
+            ));
+        }
+        Ok(BloomFilter { bits, hashes })
+    }
+}
+
+fn positions(hashes: u32, bytes: usize, leaf_hash: &[u8; 32]) -> impl Iterator<Item = usize> {
+    let h1 = u64::from_le_bytes(leaf_hash[0..8].try_into().unwrap());
+    let h2 = u64::from_le_bytes(leaf_hash[8..16].try_into().unwrap()) | 1;
+    let m = bytes as u64 * 8;
+    (0..hashes as u64).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % m) as usize)
+}
+
+/// A tree whose published root also commits to a Bloom filter of its leaves.
+/// The filter is an extra leaf paired with the data tree root:
+/// `root = hash_pair(tree_root, hash_data(filter))`.
+pub struct FilteredTree {
+    tree: MerkleTree,
+    filter: BloomFilter,
+}
+
+impl FilteredTree {
+    pub fn new(tree: MerkleTree, bits_per_leaf: usize, hashes: u32) -> Self {
+        let filter = BloomFilter::from_tree(&tree, bits_per_leaf, hashes);
+        FilteredTree { tree, filter }
+    }
+
+    pub fn tree(&self) -> &MerkleTree {
+        &self.tree
+    }
+
+    pub fn filter(&self) -> &BloomFilter {
+        &self.filter
+    }
+
+    pub fn root(&self) -> [u8; 32] {
+        hash_pair(&self.tree.root(), &self.filter.commitment())
+    }
+
+    /// A `false` answer proves absence without consulting the tree.
+    pub fn might_contain(&self, item: &[u8]) -> bool {
+        self.filter.might_contain(item)
+    }
+
+    pub fn proof(&self, item: &[u8], idx: usize) -> Option<Path> {
+        let mut proof = self.tree.proof(item, idx)?;
+        proof.push((self.filter.commitment(), true));
+        Some(proof)
+    }
+
+    /// Proof for `filter.to_bytes()` against `root()`.
+    pub fn filter_proof(&self) -> Path {
+        vec![(self.tree.root(), false)]
+    }
+}
+
+impl HeapSize for BloomFilter {
+    fn heap_size_bytes(&self) -> usize {
+        vec_bytes(&self.bits)
+    }
+}
+
+impl HeapSize for FilteredTree {
+    fn heap_size_bytes(&self) -> usize {
+        self.tree.heap_size_bytes() + self.filter.heap_size_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::verify_proof;
+
+    #[test]
+    fn no_false_negatives() {
+        let data = b"asdfjkln12345678";
+        let tree = MerkleTree::new(data, 4);
+        let filtered = FilteredTree::new(tree, 16, 4);
+
+        for chunk in data.chunks(4) {
+            assert!(filtered.might_contain(chunk));
+        }
+        assert!(!filtered.might_contain(b"definitely absent"));
+    }
+
+    #[test]
+    fn filter_is_committed() {
+        let data = b"asdfjkln12345678";
+        let filtered = FilteredTree::new(MerkleTree::new(data, 4), 16, 4);
+        let root = filtered.root();
+
+        let item = data.chunks(4).nth(1).unwrap();
+        assert!(verify_proof(item, root, &filtered.proof(item, 1).unwrap()).is_valid());
+
+        let bytes = filtered.filter().to_bytes();
+        assert!(verify_proof(&bytes, root, &filtered.filter_proof()).is_valid());
+        assert_eq!(
+            BloomFilter::from_bytes(&bytes).as_ref(),
+            Some(filtered.filter())
+        );
+
+        let mut tampered = bytes.clone();
+        tampered[13] ^= 1;
+        assert!(!verify_proof(&tampered, root, &filtered.filter_proof()).is_valid());
+    }
+
+    #[test]
+    fn bounds_hash_count() {
+        let filter = BloomFilter::new(64, 1000);
+        let bytes = filter.to_bytes();
+        assert_eq!(&bytes[1..5], &MAX_HASHES.to_le_bytes());
+        assert_eq!(BloomFilter::from_bytes(&bytes), Some(filter));
+
+        let mut costly = bytes;
+        costly[1..5].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(BloomFilter::from_bytes(&costly).is_none());
+        costly[1..5].copy_from_slice(&(MAX_HASHES + 1).to_le_bytes());
+        assert!(BloomFilter::from_bytes(&costly).is_none());
+    }
+
+    #[test]
+    #[cfg(feature = "borsh")]
+    fn borsh_bounds_hash_count() {
+        let filter = BloomFilter::new(64, 4);
+        let mut bytes = borsh::to_vec(&filter).unwrap();
+        assert_eq!(borsh::from_slice::<BloomFilter>(&bytes).unwrap(), filter);
+        let at = bytes.len() - 4;
+        bytes[at..].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(borsh::from_slice::<BloomFilter>(&bytes).is_err());
+    }
+}
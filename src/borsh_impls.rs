@@ -0,0 +1,161 @@
+use crate::format::{Version, CURRENT};
+use crate::{MerkleHasher, MerkleTree, Padding};
+use borsh::io::{Error, ErrorKind, Read, Result, Write};
+use borsh::{BorshDeserialize, BorshSerialize};
+use std::convert::TryFrom;
+
+// Layout (sizes and positions are u64 on every platform): format version,
+// leaves, node hashes, (position, label) pairs sorted by position, leaf ids
+// by position (empty when every id is its position), and the padding byte
+// if the leaf count is not a power of two (other trees read the same under
+// every padding). Deserialization
+// rehashes internal nodes so a corrupted account cannot yield a tree whose
+// root disagrees with its leaves.
+impl<H: MerkleHasher> BorshSerialize for MerkleTree<H> {
+    fn serialize<W: Write>(&self, writer: &mut W) -> Result<()> {
+        CURRENT.byte().serialize(writer)?;
+        (self.leaves as u64).serialize(writer)?;
+        let nodes: Vec<[u8; 32]> = self.nodes.iter().map(|node| node.value).collect();
+        nodes.serialize(writer)?;
+        let mut labels: Vec<(u64, &Vec<u8>)> = self
+            .leaf_labels
+            .iter()
+            .map(|(&idx, label)| (idx as u64, label))
+            .collect();
+        labels.sort_unstable();
+        labels.serialize(writer)?;
+        self.ids.serialize(writer)?;
+        if !self.leaves.is_power_of_two() {
+            self.padding.byte().serialize(writer)?;
+        }
+        Ok(())
+    }
+}
+
+impl<H: MerkleHasher> BorshDeserialize for MerkleTree<H> {
+    fn deserialize_reader<R: Read>(reader: &mut R) -> Result<Self> {
+        let invalid = |msg| Error::new(ErrorKind::InvalidData, msg);
+        if Version::from_byte(u8::deserialize_reader(reader)?) != Some(Version::V1) {
+            return Err(invalid("unsupported format version"));
+        }
+        let too_large = || invalid("tree too large for this platform");
+        let leaves = usize::try_from(u64::deserialize_reader(reader)?).map_err(|_| too_large())?;
+        let nodes = Vec::<[u8; 32]>::deserialize_reader(reader)?;
+        let labels = Vec::<(u64, Vec<u8>)>::deserialize_reader(reader)?;
+        let ids = Vec::<u64>::deserialize_reader(reader)?;
+        let padding = if leaves.is_power_of_two() {
+            Padding::default()
+        } else {
+            Padding::from_byte(u8::deserialize_reader(reader)?)
+                .ok_or_else(|| invalid("unknown padding"))?
+        };
+
+        if leaves == 0 || nodes.len() < leaves {
+            return Err(invalid("inconsistent tree shape"));
+        }
+        let tree = MerkleTree::from_leaf_hashes(nodes[..leaves].to_vec(), padding);
+        if tree.nodes.len() != nodes.len() {
+            return Err(invalid("inconsistent tree shape"));
+        }
+        if tree
+            .nodes
+            .iter()
+            .zip(&nodes)
+            .any(|(node, &value)| node.value != value)
+        {
+            return Err(invalid("internal node does not match its children"));
+        }
+        let mut tree = if ids.is_empty() {
+            tree
+        } else {
+            tree.with_ids(ids)
+                .ok_or_else(|| invalid("invalid leaf ids"))?
+        };
+        for (idx, label) in labels {
+            let idx = usize::try_from(idx).map_err(|_| too_large())?;
+            if !tree.set_label(idx, label) {
+                return Err(invalid("invalid label"));
+            }
+        }
+        Ok(tree)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{verify_proof, WeightedTree};
+
+    #[test]
+    fn tree_round_trip() {
+        let data = b"asdfjkln12345678";
+        let tree = MerkleTree::with_labels(data, 4, ["a", "b"]).unwrap();
+        let bytes = borsh::to_vec(&tree).unwrap();
+        let decoded: MerkleTree = borsh::from_slice(&bytes).unwrap();
+
+        assert_eq!(decoded.root(), tree.root());
+        assert_eq!(decoded.leaf_by_label("b"), Some(1));
+        assert_eq!(decoded.leaf_id(3), Some(3));
+        assert!(
+            verify_proof(b"jkln", decoded.root(), &decoded.proof(b"jkln", 1).unwrap()).is_valid()
+        );
+        assert_eq!(borsh::to_vec(&decoded).unwrap(), bytes);
+    }
+
+    #[test]
+    fn ids_round_trip() {
+        let (tree, order) =
+            MerkleTree::from_leaves_sorted(&["pear", "fig", "apple", "kiwi"]).unwrap();
+        let bytes = borsh::to_vec(&tree).unwrap();
+        let decoded: MerkleTree = borsh::from_slice(&bytes).unwrap();
+        for (idx, &pos) in order.iter().enumerate() {
+            assert_eq!(decoded.leaf_id(idx), Some(pos as u64));
+        }
+
+        // Ids equal to positions are left out, and repeated ids refused.
+        let plain = borsh::to_vec(&MerkleTree::from_leaves(["a", "b"]).unwrap()).unwrap();
+        assert_eq!(plain.len(), 1 + 8 + 4 + 3 * 32 + 4 + 4);
+        let ids_at = bytes.len() - 4 * 8;
+        let mut repeated = bytes.clone();
+        repeated.copy_within(ids_at..ids_at + 8, ids_at + 8);
+        assert!(borsh::from_slice::<MerkleTree>(&repeated).is_err());
+    }
+
+    #[test]
+    fn rejects_corrupted_tree() {
+        let tree = MerkleTree::new(b"asdfjkln12345678", 4);
+        let mut bytes = borsh::to_vec(&tree).unwrap();
+        bytes[1 + 8 + 4] ^= 1;
+        assert!(borsh::from_slice::<MerkleTree>(&bytes).is_err());
+    }
+
+    #[test]
+    fn odd_tree_round_trip() {
+        let tree = MerkleTree::from_leaves_padded(["a", "b", "c"], Padding::DuplicateLast).unwrap();
+        let bytes = borsh::to_vec(&tree).unwrap();
+        let decoded: MerkleTree = borsh::from_slice(&bytes).unwrap();
+        assert_eq!(
+            (decoded.root(), decoded.padding()),
+            (tree.root(), Padding::DuplicateLast)
+        );
+
+        let mut other = bytes.clone();
+        *other.last_mut().unwrap() = Padding::Zero.byte();
+        assert!(borsh::from_slice::<MerkleTree>(&other).is_err());
+        assert!(borsh::from_slice::<MerkleTree>(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn proof_round_trip() {
+        let tree = WeightedTree::new(vec![("a", 1), ("b", 2)]).unwrap();
+        let proof = tree.proof(1).unwrap();
+        let decoded = borsh::from_slice(&borsh::to_vec(&proof).unwrap()).unwrap();
+        assert_eq!(proof, decoded);
+
+        let path = MerkleTree::new(b"asdfjkln12345678", 4).path(2).unwrap();
+        assert_eq!(
+            path,
+            borsh::from_slice::<crate::Path>(&borsh::to_vec(&path).unwrap()).unwrap()
+        );
+    }
+}
@@ -0,0 +1,66 @@
+//! Verification with an explicit iteration cap for metered runtimes such as
+//! CosmWasm contracts. Nothing here allocates or depends on the platform,
+//! so gas use is a fixed function of the proof length.
+
+use crate::{hash_data, hash_pair};
+
+/// Deepest proof the bounded verifier will walk by default: trees beyond
+/// 2^64 leaves cannot be addressed anyway.
+pub const MAX_DEPTH: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LimitExceeded {
+    pub limit: usize,
+    pub depth: usize,
+}
+
+/// Like `verify_proof`, but refuses proofs longer than `max_depth` before
+/// hashing anything, so callers can bound the cost of untrusted input.
+pub fn verify_proof_bounded(
+    item: &[u8],
+    root: [u8; 32],
+    proof: &[([u8; 32], bool)],
+    max_depth: usize,
+) -> Result<bool, LimitExceeded> {
+    if proof.len() > max_depth {
+        return Err(LimitExceeded {
+            limit: max_depth,
+            depth: proof.len(),
+        });
+    }
+    let mut candidate = hash_data(item);
+    for (hash, odd) in proof {
+        candidate = if *odd {
+            hash_pair(&candidate, hash)
+        } else {
+            hash_pair(hash, &candidate)
+        };
+    }
+    Ok(candidate == root)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MerkleTree;
+
+    #[test]
+    fn enforces_limit() {
+        let data = b"asdfasdfasdfasdfasdfasdfasdfasdf";
+        let tree = MerkleTree::new(data, 8);
+        let proof = tree.path(3).unwrap();
+
+        assert_eq!(
+            verify_proof_bounded(b"asdf", tree.root(), &proof, MAX_DEPTH),
+            Ok(true)
+        );
+        assert_eq!(
+            verify_proof_bounded(b"nope", tree.root(), &proof, 3),
+            Ok(false)
+        );
+        assert_eq!(
+            verify_proof_bounded(b"asdf", tree.root(), &proof, 2),
+            Err(LimitExceeded { limit: 2, depth: 3 })
+        );
+    }
+}
@@ -0,0 +1,192 @@
+//! Verification within a caller's budget, for endpoints that check proofs
+//! from untrusted clients. The work a proof asks for is counted from its
+//! shape before anything is hashed, and proofs over budget are refused with
+//! [`BudgetExceeded`] instead of being verified.
+//!
+//! Hashes are counted per invocation: one per item, one per node computed
+//! on the way up, and one for a composed proof's shard root taken as the
+//! outer item. Promoted nodes are counted although they cost nothing, so
+//! the count is an upper bound under `Padding::Promote`.
+
+use crate::{
+    verify_multiproof, verify_proof, ComposedProof, MultiProof, TreeHead, VerificationOutcome,
+};
+use alloc::vec::Vec;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VerifyBudget {
+    pub max_hashes: usize,
+    /// Tree levels walked, added up over the proofs of a composition.
+    pub max_depth: usize,
+}
+
+/// A limit that a proof would pass. Multiproof hash counts stop at the
+/// first level past the limit, so `needed` may fall short of the total.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BudgetExceeded {
+    Hashes { limit: usize, needed: usize },
+    Depth { limit: usize, depth: usize },
+}
+
+impl VerifyBudget {
+    fn check(&self, hashes: usize, depth: usize) -> Result<(), BudgetExceeded> {
+        if depth > self.max_depth {
+            return Err(BudgetExceeded::Depth {
+                limit: self.max_depth,
+                depth,
+            });
+        }
+        if hashes > self.max_hashes {
+            return Err(BudgetExceeded::Hashes {
+                limit: self.max_hashes,
+                needed: hashes,
+            });
+        }
+        Ok(())
+    }
+}
+
+pub fn verify_proof_budgeted(
+    item: &[u8],
+    root: [u8; 32],
+    proof: &[([u8; 32], bool)],
+    budget: &VerifyBudget,
+) -> Result<VerificationOutcome, BudgetExceeded> {
+    budget.check(proof.len().saturating_add(1), proof.len())?;
+    Ok(verify_proof(item, root, proof))
+}
+
+pub fn verify_composed_budgeted(
+    item: &[u8],
+    shard_size: u64,
+    head: &TreeHead,
+    proof: &ComposedProof,
+    budget: &VerifyBudget,
+) -> Result<VerificationOutcome, BudgetExceeded> {
+    let depth = proof.inner().len().saturating_add(proof.outer().len());
+    budget.check(depth.saturating_add(2), depth)?;
+    Ok(proof.verify(item, shard_size, head))
+}
+
+/// Levels above the leaves of a tree of `leaves` leaves.
+fn levels(mut leaves: u64) -> usize {
+    let mut levels = 0;
+    while leaves > 1 {
+        leaves = leaves.div_ceil(2);
+        levels += 1;
+    }
+    levels
+}
+
+pub fn verify_multiproof_budgeted<T: AsRef<[u8]>>(
+    items: &[T],
+    head: &TreeHead,
+    proof: &MultiProof,
+    budget: &VerifyBudget,
+) -> Result<bool, BudgetExceeded> {
+    let depth = levels(head.size);
+    budget.check(0, depth)?;
+    // One hash per item and per distinct ancestor of the proven leaves.
+    let mut known: Vec<u64> = proof.indices().to_vec();
+    let mut hashes = items.len();
+    for _ in 0..depth {
+        known.iter_mut().for_each(|offset| *offset /= 2);
+        known.dedup();
+        hashes = hashes.saturating_add(known.len());
+        if hashes > budget.max_hashes {
+            break;
+        }
+    }
+    budget.check(hashes, depth)?;
+    Ok(verify_multiproof(items, head, proof))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MerkleTree, Proof};
+
+    #[test]
+    fn refuses_over_budget() {
+        let data = b"asdfjkln12345678zxcvqwer09876543";
+        let tree = MerkleTree::new(data, 8);
+        let budget = VerifyBudget {
+            max_hashes: 4,
+            max_depth: 3,
+        };
+        let path = tree.path(1).unwrap();
+        assert_eq!(
+            verify_proof_budgeted(b"jkln", tree.root(), &path, &budget),
+            Ok(VerificationOutcome::Valid)
+        );
+        let long = [path.clone(), path].concat();
+        assert_eq!(
+            verify_proof_budgeted(b"jkln", tree.root(), &long, &budget),
+            Err(BudgetExceeded::Depth { limit: 3, depth: 6 })
+        );
+        let tight = VerifyBudget {
+            max_hashes: 3,
+            ..budget
+        };
+        assert_eq!(
+            verify_proof_budgeted(b"jkln", tree.root(), &long[..3], &tight),
+            Err(BudgetExceeded::Hashes {
+                limit: 3,
+                needed: 4
+            })
+        );
+
+        let roots = [tree.root(), MerkleTree::new(b"qwertyui", 2).root()];
+        let master = MerkleTree::from_leaves(roots).unwrap();
+        let composed = Proof::compose(tree.leaf_proof(1).unwrap(), master.leaf_proof(0).unwrap());
+        let roomy = VerifyBudget {
+            max_hashes: 6,
+            max_depth: 4,
+        };
+        assert!(
+            verify_composed_budgeted(b"jkln", 8, &master.head(), &composed, &roomy)
+                .unwrap()
+                .is_valid()
+        );
+        assert_eq!(
+            verify_composed_budgeted(b"jkln", 8, &master.head(), &composed, &budget),
+            Err(BudgetExceeded::Depth { limit: 3, depth: 4 })
+        );
+    }
+
+    #[test]
+    fn counts_multiproof_work() {
+        let data = b"asdfjkln12345678zxcvqwer09876543";
+        let tree = MerkleTree::new(data, 8);
+        let item = |idx: usize| &data[4 * idx..4 * idx + 4];
+        let proof = tree.multiproof(&[0, 1, 6]).unwrap();
+        let items = [item(0), item(1), item(6)];
+        // Three leaves, parents 0 and 3, then 0 and 1, then the root.
+        let exact = VerifyBudget {
+            max_hashes: 8,
+            max_depth: 3,
+        };
+        assert_eq!(
+            verify_multiproof_budgeted(&items, &tree.head(), &proof, &exact),
+            Ok(true)
+        );
+        let short = VerifyBudget {
+            max_hashes: 7,
+            ..exact
+        };
+        assert_eq!(
+            verify_multiproof_budgeted(&items, &tree.head(), &proof, &short),
+            Err(BudgetExceeded::Hashes {
+                limit: 7,
+                needed: 8
+            })
+        );
+
+        let big = MerkleTree::from_leaves((0..100u8).map(|i| [i])).unwrap();
+        let proof = big.multiproof(&[3]).unwrap();
+        assert_eq!(
+            verify_multiproof_budgeted(&[[3u8]], &big.head(), &proof, &exact),
+            Err(BudgetExceeded::Depth { limit: 3, depth: 7 })
+        );
+    }
+}
@@ -0,0 +1,174 @@
+//! A canonical text dump of a tree for audits: two trees dump to the same
+//! text exactly when their shape, padding and node hashes agree, so they can
+//! be compared with `diff`. Trees over a power of two leaves read the same
+//! under every padding and always dump the default one. Labels and leaf ids
+//! are not included.
+//!
+//! ```text
+//! merkle-canonical 1
+//! leaves 3
+//! padding promote
+//! 0 0 <hex hash>
+//! 0 1 <hex hash>
+//! ...
+//! ```
+//!
+//! After the header comes one `level offset hash` line per node, leaves
+//! first, each level in offset order, ending with the root.
+
+use crate::{Layout, MerkleHasher, MerkleTree, Padding};
+use std::io::{BufRead, Error, ErrorKind, Result, Write};
+
+const HEADER: &str = "merkle-canonical 1";
+
+fn padding_name(padding: Padding) -> &'static str {
+    match padding {
+        Padding::Promote => "promote",
+        Padding::DuplicateLast => "duplicate-last",
+        Padding::Zero => "zero",
+    }
+}
+
+pub(crate) fn parse_hash(hex: &str) -> Option<[u8; 32]> {
+    if hex.len() != 64 || !hex.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f')) {
+        return None;
+    }
+    let mut hash = [0; 32];
+    for (byte, pair) in hash.iter_mut().zip(hex.as_bytes().chunks(2)) {
+        *byte = u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok()?;
+    }
+    Some(hash)
+}
+
+impl<H: MerkleHasher> MerkleTree<H> {
+    pub fn dump_canonical<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writeln!(writer, "{}", HEADER)?;
+        writeln!(writer, "leaves {}", self.leaves())?;
+        let padding = if self.leaves().is_power_of_two() {
+            Padding::default()
+        } else {
+            self.padding()
+        };
+        writeln!(writer, "padding {}", padding_name(padding))?;
+        for (idx, node) in self.nodes.iter().enumerate() {
+            let (level, offset) = self.layout.level_offset(idx).expect("node is in range");
+            write!(writer, "{} {} ", level, offset)?;
+            for byte in &node.value {
+                write!(writer, "{:02x}", byte)?;
+            }
+            writeln!(writer)?;
+        }
+        Ok(())
+    }
+
+    /// Reads a dump back, rehashing the leaves with `H`; every internal
+    /// hash in the dump must match.
+    pub fn load_canonical<R: BufRead>(reader: R) -> Result<Self> {
+        let invalid = |msg| Error::new(ErrorKind::InvalidData, msg);
+        let mut lines = reader.lines();
+        let mut next_line = || {
+            lines
+                .next()
+                .unwrap_or_else(|| Err(invalid("truncated dump")))
+        };
+
+        if next_line()? != HEADER {
+            return Err(invalid("not a canonical dump of a supported version"));
+        }
+        let leaves = next_line()?
+            .strip_prefix("leaves ")
+            .and_then(|count| count.parse::<usize>().ok())
+            .filter(|&leaves| leaves > 0)
+            .ok_or_else(|| invalid("bad leaf count"))?;
+        let padding = match next_line()?.strip_prefix("padding ") {
+            Some("promote") => Padding::Promote,
+            Some("duplicate-last") => Padding::DuplicateLast,
+            Some("zero") => Padding::Zero,
+            _ => return Err(invalid("bad padding")),
+        };
+
+        let layout = Layout::new(leaves).expect("leaves is non-zero");
+        let mut nodes = Vec::with_capacity(layout.nodes());
+        for idx in 0..layout.nodes() {
+            let line = next_line()?;
+            let (level, offset) = layout.level_offset(idx).expect("node is in range");
+            let hash = match line.split(' ').collect::<Vec<_>>()[..] {
+                [l, o, hash] if l == level.to_string() && o == offset.to_string() => {
+                    parse_hash(hash)
+                }
+                _ => None,
+            };
+            nodes.push(hash.ok_or_else(|| invalid("bad or out-of-order node line"))?);
+        }
+        if next_line().is_ok() {
+            return Err(invalid("trailing lines"));
+        }
+
+        let tree = MerkleTree::from_leaf_hashes(nodes[..leaves].to_vec(), padding);
+        if tree
+            .nodes
+            .iter()
+            .zip(&nodes)
+            .any(|(node, &hash)| node.value != hash)
+        {
+            return Err(invalid("internal node does not match its children"));
+        }
+        Ok(tree)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let tree = MerkleTree::from_leaves_padded(["a", "b", "c"], Padding::Zero).unwrap();
+        let mut dump = Vec::new();
+        tree.dump_canonical(&mut dump).unwrap();
+        let text = String::from_utf8(dump.clone()).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(
+            lines[..3],
+            ["merkle-canonical 1", "leaves 3", "padding zero"]
+        );
+        assert_eq!(lines.len(), 3 + tree.size());
+        assert!(lines[5].starts_with("0 2 "));
+        assert!(lines[8].starts_with("2 0 "));
+
+        let loaded: MerkleTree = MerkleTree::load_canonical(&dump[..]).unwrap();
+        assert_eq!(
+            (loaded.root(), loaded.padding()),
+            (tree.root(), Padding::Zero)
+        );
+        let mut again = Vec::new();
+        loaded.dump_canonical(&mut again).unwrap();
+        assert_eq!(again, dump);
+    }
+
+    #[test]
+    fn rejects_edited_dumps() {
+        let tree = MerkleTree::new(b"asdfjkln12345678", 4);
+        let mut dump = Vec::new();
+        tree.dump_canonical(&mut dump).unwrap();
+        let text = String::from_utf8(dump).unwrap();
+
+        let load =
+            |text: String| -> Result<MerkleTree> { MerkleTree::load_canonical(text.as_bytes()) };
+        let mut lines: Vec<String> = text.lines().map(String::from).collect();
+        assert!(load(lines[..lines.len() - 1].join("\n")).is_err());
+        lines.swap(3, 4);
+        assert!(load(lines.join("\n")).is_err());
+        assert!(load(text.replace("padding promote", "padding zero")).is_ok());
+        assert!(load(text.replace("leaves 4", "leaves 3")).is_err());
+        assert!(load(format!("{}extra\n", text)).is_err());
+
+        let leaf = text.lines().nth(3).unwrap();
+        let flipped = format!(
+            "{}{}",
+            &leaf[..leaf.len() - 1],
+            if leaf.ends_with('0') { '1' } else { '0' }
+        );
+        assert!(load(text.replace(leaf, &flipped)).is_err());
+    }
+}
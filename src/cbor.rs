@@ -0,0 +1,381 @@
+//! CBOR encodings of proofs and signed heads, for CBOR-based formats such
+//! as COSE (see `cose`):
+//!
+//! ```text
+//! proof       = [index: uint, path: [* [sibling: bstr .size 32, odd: bool]]]
+//! signed-head = [size: uint, root: bstr .size 32, issued-at: uint,
+//!                signature: bstr]
+//! timed-proof = [signed-head, proof]
+//! ```
+//!
+//! `odd` is set where the running hash is hashed first. Encoding is the
+//! deterministic encoding of RFC 8949, section 4.2, and decoding is strict:
+//! non-minimal heads, indefinite lengths, other simple values and trailing
+//! bytes are all rejected.
+
+use crate::{Proof, SignedHead, TimedProof, TreeHead};
+use std::convert::{TryFrom, TryInto};
+
+pub(crate) const UINT: u8 = 0;
+pub(crate) const NINT: u8 = 1;
+pub(crate) const BYTES: u8 = 2;
+pub(crate) const TEXT: u8 = 3;
+pub(crate) const ARRAY: u8 = 4;
+pub(crate) const MAP: u8 = 5;
+pub(crate) const TAG: u8 = 6;
+const SIMPLE: u8 = 7;
+
+const FALSE: u64 = 20;
+const TRUE: u64 = 21;
+
+/// Deepest nesting `skip` follows.
+const MAX_DEPTH: usize = 16;
+
+/// Longest path a decoded proof may have, as in `der`.
+const MAX_PATH: u64 = 256;
+
+/// Writes a head of major type `major` with argument `value`, in as few
+/// bytes as the value allows.
+pub(crate) fn write_head(out: &mut Vec<u8>, major: u8, value: u64) {
+    let major = major << 5;
+    if value < 24 {
+        out.push(major | value as u8);
+    } else if value <= u8::MAX as u64 {
+        out.extend_from_slice(&[major | 24, value as u8]);
+    } else if value <= u16::MAX as u64 {
+        out.push(major | 25);
+        out.extend_from_slice(&(value as u16).to_be_bytes());
+    } else if value <= u32::MAX as u64 {
+        out.push(major | 26);
+        out.extend_from_slice(&(value as u32).to_be_bytes());
+    } else {
+        out.push(major | 27);
+        out.extend_from_slice(&value.to_be_bytes());
+    }
+}
+
+pub(crate) fn write_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    write_head(out, BYTES, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+pub(crate) fn write_text(out: &mut Vec<u8>, text: &str) {
+    write_head(out, TEXT, text.len() as u64);
+    out.extend_from_slice(text.as_bytes());
+}
+
+pub(crate) fn write_int(out: &mut Vec<u8>, value: i64) {
+    if value < 0 {
+        write_head(out, NINT, !(value as u64));
+    } else {
+        write_head(out, UINT, value as u64);
+    }
+}
+
+fn write_bool(out: &mut Vec<u8>, value: bool) {
+    write_head(out, SIMPLE, if value { TRUE } else { FALSE });
+}
+
+/// Reads one head, returning its major type, argument and the rest. Only
+/// minimal, definite-length heads are accepted.
+pub(crate) fn read_head(input: &[u8]) -> Option<(u8, u64, &[u8])> {
+    let (&first, rest) = input.split_first()?;
+    let (major, info) = (first >> 5, first & 0x1f);
+    let (value, rest, least) = match info {
+        0..=23 => return Some((major, info as u64, rest)),
+        24 => (*rest.first()? as u64, rest.get(1..)?, 24),
+        25 => (
+            u16::from_be_bytes(rest.get(..2)?.try_into().unwrap()) as u64,
+            &rest[2..],
+            0x100,
+        ),
+        26 => (
+            u32::from_be_bytes(rest.get(..4)?.try_into().unwrap()) as u64,
+            &rest[4..],
+            0x1_0000,
+        ),
+        27 => (
+            u64::from_be_bytes(rest.get(..8)?.try_into().unwrap()),
+            &rest[8..],
+            0x1_0000_0000,
+        ),
+        _ => return None,
+    };
+    // Simple values 24 to 31 are reserved or floats, which nothing here
+    // uses.
+    if value < least || major == SIMPLE {
+        return None;
+    }
+    Some((major, value, rest))
+}
+
+/// Reads a head of major type `major`, returning its argument and the rest.
+pub(crate) fn read_expected(input: &[u8], major: u8) -> Option<(u64, &[u8])> {
+    match read_head(input)? {
+        (actual, value, rest) if actual == major => Some((value, rest)),
+        _ => None,
+    }
+}
+
+pub(crate) fn read_uint(input: &[u8]) -> Option<(u64, &[u8])> {
+    read_expected(input, UINT)
+}
+
+pub(crate) fn read_int(input: &[u8]) -> Option<(i64, &[u8])> {
+    match read_head(input)? {
+        (UINT, value, rest) => Some((value.try_into().ok()?, rest)),
+        (NINT, value, rest) => Some((!i64::try_from(value).ok()?, rest)),
+        _ => None,
+    }
+}
+
+pub(crate) fn read_bytes(input: &[u8]) -> Option<(&[u8], &[u8])> {
+    let (len, rest) = read_expected(input, BYTES)?;
+    let len = usize::try_from(len).ok()?;
+    if rest.len() < len {
+        return None;
+    }
+    Some(rest.split_at(len))
+}
+
+fn read_hash(input: &[u8]) -> Option<([u8; 32], &[u8])> {
+    let (hash, rest) = read_bytes(input)?;
+    Some((hash.try_into().ok()?, rest))
+}
+
+fn read_bool(input: &[u8]) -> Option<(bool, &[u8])> {
+    match input.split_first()? {
+        (&0xf4, rest) => Some((false, rest)),
+        (&0xf5, rest) => Some((true, rest)),
+        _ => None,
+    }
+}
+
+/// Reads an array head of exactly `len` items, returning the rest.
+pub(crate) fn read_array(input: &[u8], len: u64) -> Option<&[u8]> {
+    match read_expected(input, ARRAY)? {
+        (actual, rest) if actual == len => Some(rest),
+        _ => None,
+    }
+}
+
+/// Steps over one well-formed item of any type.
+pub(crate) fn skip(input: &[u8]) -> Option<&[u8]> {
+    skip_nested(input, 0)
+}
+
+fn skip_nested(input: &[u8], depth: usize) -> Option<&[u8]> {
+    if depth > MAX_DEPTH {
+        return None;
+    }
+    if let Some((_, rest)) = read_bool(input) {
+        return Some(rest);
+    }
+    if input.first() == Some(&0xf6) {
+        return Some(&input[1..]);
+    }
+    let (major, value, mut rest) = read_head(input)?;
+    match major {
+        UINT | NINT => Some(rest),
+        BYTES | TEXT => rest.get(usize::try_from(value).ok()?..),
+        ARRAY | MAP => {
+            let items = if major == MAP {
+                value.checked_mul(2)?
+            } else {
+                value
+            };
+            for _ in 0..items {
+                rest = skip_nested(rest, depth + 1)?;
+            }
+            Some(rest)
+        }
+        TAG => skip_nested(rest, depth + 1),
+        _ => None,
+    }
+}
+
+pub(crate) fn write_proof(out: &mut Vec<u8>, proof: &Proof) {
+    write_head(out, ARRAY, 2);
+    write_head(out, UINT, proof.index());
+    write_head(out, ARRAY, proof.len() as u64);
+    for (sibling, odd) in proof.path() {
+        write_head(out, ARRAY, 2);
+        write_bytes(out, sibling);
+        write_bool(out, *odd);
+    }
+}
+
+pub(crate) fn read_proof(input: &[u8]) -> Option<(Proof, &[u8])> {
+    let input = read_array(input, 2)?;
+    let (index, input) = read_uint(input)?;
+    let (len, mut input) = read_expected(input, ARRAY)?;
+    if len > MAX_PATH {
+        return None;
+    }
+    let mut path = Vec::with_capacity(len as usize);
+    for _ in 0..len {
+        let (sibling, rest) = read_hash(read_array(input, 2)?)?;
+        let (odd, rest) = read_bool(rest)?;
+        path.push((sibling, odd));
+        input = rest;
+    }
+    Some((Proof::new(index, path), input))
+}
+
+pub(crate) fn write_signed_head(out: &mut Vec<u8>, head: &SignedHead) {
+    write_head(out, ARRAY, 4);
+    write_head(out, UINT, head.head.size);
+    write_bytes(out, &head.head.root);
+    write_head(out, UINT, head.issued_at);
+    write_bytes(out, &head.signature);
+}
+
+pub(crate) fn read_signed_head(input: &[u8]) -> Option<(SignedHead, &[u8])> {
+    let input = read_array(input, 4)?;
+    let (size, input) = read_uint(input)?;
+    let (root, input) = read_hash(input)?;
+    let (issued_at, input) = read_uint(input)?;
+    let (signature, input) = read_bytes(input)?;
+    let head = SignedHead {
+        head: TreeHead { size, root },
+        issued_at,
+        signature: signature.to_vec(),
+    };
+    Some((head, input))
+}
+
+pub(crate) fn write_timed_proof(out: &mut Vec<u8>, timed: &TimedProof) {
+    write_head(out, ARRAY, 2);
+    write_signed_head(out, &timed.head);
+    write_proof(out, &timed.proof);
+}
+
+pub(crate) fn read_timed_proof(input: &[u8]) -> Option<(TimedProof, &[u8])> {
+    let input = read_array(input, 2)?;
+    let (head, input) = read_signed_head(input)?;
+    let (proof, input) = read_proof(input)?;
+    Some((TimedProof { head, proof }, input))
+}
+
+/// Reads one item from the front of its input, returning it and the rest.
+type Read<T> = fn(&[u8]) -> Option<(T, &[u8])>;
+
+/// Decodes one item with `read`, refusing trailing bytes.
+fn whole<T>(bytes: &[u8], read: Read<T>) -> Option<T> {
+    match read(bytes)? {
+        (value, []) => Some(value),
+        _ => None,
+    }
+}
+
+pub fn encode_proof(proof: &Proof) -> Vec<u8> {
+    let mut out = Vec::with_capacity(4 + proof.len() * 36);
+    write_proof(&mut out, proof);
+    out
+}
+
+pub fn decode_proof(cbor: &[u8]) -> Option<Proof> {
+    whole(cbor, read_proof)
+}
+
+pub fn encode_signed_head(head: &SignedHead) -> Vec<u8> {
+    let mut out = Vec::with_capacity(56 + head.signature.len());
+    write_signed_head(&mut out, head);
+    out
+}
+
+pub fn decode_signed_head(cbor: &[u8]) -> Option<SignedHead> {
+    whole(cbor, read_signed_head)
+}
+
+pub fn encode_timed_proof(timed: &TimedProof) -> Vec<u8> {
+    let mut out = Vec::new();
+    write_timed_proof(&mut out, timed);
+    out
+}
+
+pub fn decode_timed_proof(cbor: &[u8]) -> Option<TimedProof> {
+    whole(cbor, read_timed_proof)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MerkleTree;
+
+    #[test]
+    fn proof_round_trip() {
+        let tree = MerkleTree::new(b"asdfjkln12345678", 4);
+        let proof = tree.leaf_proof(2).unwrap();
+        let cbor = encode_proof(&proof);
+        // [2, [[h'..', odd], [h'..', odd]]]
+        assert_eq!(&cbor[..6], &[0x82, 0x02, 0x82, 0x82, 0x58, 0x20]);
+        assert_eq!(decode_proof(&cbor), Some(proof));
+
+        let mut trailing = cbor.clone();
+        trailing.push(0);
+        assert_eq!(decode_proof(&trailing), None);
+        // A non-minimal index.
+        let mut padded = vec![0x82, 0x18, 0x02];
+        padded.extend_from_slice(&cbor[2..]);
+        assert_eq!(decode_proof(&padded), None);
+        // An indefinite-length path.
+        let mut indefinite = cbor[..2].to_vec();
+        indefinite.push(0x9f);
+        indefinite.extend_from_slice(&cbor[3..]);
+        indefinite.push(0xff);
+        assert_eq!(decode_proof(&indefinite), None);
+    }
+
+    #[test]
+    fn timed_proof_round_trip() {
+        let tree = MerkleTree::new(b"asdfjkln12345678", 4);
+        let timed = TimedProof {
+            head: SignedHead {
+                head: tree.head(),
+                issued_at: 1_700_000_000,
+                signature: vec![9; 64],
+            },
+            proof: tree.leaf_proof(1).unwrap(),
+        };
+        let cbor = encode_timed_proof(&timed);
+        assert_eq!(decode_timed_proof(&cbor), Some(timed.clone()));
+        assert_eq!(
+            decode_signed_head(&encode_signed_head(&timed.head)),
+            Some(timed.head)
+        );
+        assert_eq!(skip(&cbor), Some(&[][..]));
+        assert_eq!(decode_timed_proof(&cbor[..cbor.len() - 1]), None);
+    }
+
+    #[test]
+    fn heads() {
+        for &value in [
+            0,
+            23,
+            24,
+            255,
+            256,
+            65_535,
+            65_536,
+            u32::MAX as u64,
+            u64::MAX,
+        ]
+        .iter()
+        {
+            let mut out = Vec::new();
+            write_head(&mut out, UINT, value);
+            assert_eq!(read_uint(&out), Some((value, &[][..])));
+        }
+        for &value in [-1, -24, -25, i64::MIN, 7].iter() {
+            let mut out = Vec::new();
+            write_int(&mut out, value);
+            assert_eq!(read_int(&out), Some((value, &[][..])));
+        }
+        let mut out = Vec::new();
+        write_int(&mut out, -7);
+        assert_eq!(out, [0x26]);
+        assert_eq!(read_head(&[0x19, 0x00, 0xff]), None);
+        assert_eq!(read_head(&[0xf9, 0x3c, 0x00]), None);
+    }
+}
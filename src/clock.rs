@@ -0,0 +1,201 @@
+//! A Merkle clock: a DAG of events, each naming the hashes of the events it
+//! causally follows. The current heads summarise the whole history, so two
+//! replicas compare heads to find out whether they have diverged and
+//! exchange only the events the other side is missing.
+
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    pub parents: Vec<[u8; 32]>,
+    pub payload: Vec<u8>,
+}
+
+impl Event {
+    /// `H(count_le || parents || payload)`, with the parents sorted.
+    pub fn hash(&self) -> [u8; 32] {
+        let mut parents = self.parents.clone();
+        parents.sort_unstable();
+        let mut hasher = Sha256::new();
+        hasher.update((parents.len() as u32).to_le_bytes());
+        for parent in &parents {
+            hasher.update(parent);
+        }
+        hasher.update(&self.payload);
+        hasher.finalize().into()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MissingParent(pub [u8; 32]);
+
+#[derive(Debug, Clone, Default)]
+pub struct MerkleClock {
+    events: HashMap<[u8; 32], Event>,
+    heads: BTreeSet<[u8; 32]>,
+}
+
+impl MerkleClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    pub fn contains(&self, hash: &[u8; 32]) -> bool {
+        self.events.contains_key(hash)
+    }
+
+    pub fn event(&self, hash: &[u8; 32]) -> Option<&Event> {
+        self.events.get(hash)
+    }
+
+    /// Events no other event follows, in hash order.
+    pub fn heads(&self) -> Vec<[u8; 32]> {
+        self.heads.iter().copied().collect()
+    }
+
+    /// Commitment to the whole history: the hash of the sorted heads.
+    pub fn root(&self) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        for head in &self.heads {
+            hasher.update(head);
+        }
+        hasher.finalize().into()
+    }
+
+    /// Records a local event following every current head.
+    pub fn append(&mut self, payload: Vec<u8>) -> [u8; 32] {
+        let event = Event {
+            parents: self.heads(),
+            payload,
+        };
+        self.insert(event).expect("heads are always present")
+    }
+
+    /// Adds an event whose parents are all known. Inserting a known event
+    /// is a no-op.
+    pub fn insert(&mut self, event: Event) -> Result<[u8; 32], MissingParent> {
+        if let Some(parent) = event.parents.iter().find(|parent| !self.contains(parent)) {
+            return Err(MissingParent(*parent));
+        }
+        let hash = event.hash();
+        if self.contains(&hash) {
+            return Ok(hash);
+        }
+        for parent in &event.parents {
+            self.heads.remove(parent);
+        }
+        self.heads.insert(hash);
+        self.events.insert(hash, event);
+        Ok(hash)
+    }
+
+    /// Adds events sent by a peer, which must come parents first as
+    /// produced by [`MerkleClock::events_since`].
+    pub fn merge<I: IntoIterator<Item = Event>>(&mut self, events: I) -> Result<(), MissingParent> {
+        for event in events {
+            self.insert(event)?;
+        }
+        Ok(())
+    }
+
+    /// The peer's heads that this clock has not seen.
+    pub fn missing(&self, remote_heads: &[[u8; 32]]) -> Vec<[u8; 32]> {
+        remote_heads
+            .iter()
+            .filter(|head| !self.contains(head))
+            .copied()
+            .collect()
+    }
+
+    fn ancestors(&self, from: &[[u8; 32]]) -> HashSet<[u8; 32]> {
+        let mut seen = HashSet::new();
+        let mut stack: Vec<[u8; 32]> = from
+            .iter()
+            .filter(|hash| self.contains(hash))
+            .copied()
+            .collect();
+        while let Some(hash) = stack.pop() {
+            if seen.insert(hash) {
+                stack.extend(self.events[&hash].parents.iter().copied());
+            }
+        }
+        seen
+    }
+
+    /// Events a peer with `remote_heads` lacks, parents before children.
+    /// Remote heads this clock does not know are ignored.
+    pub fn events_since(&self, remote_heads: &[[u8; 32]]) -> Vec<Event> {
+        let mut done = self.ancestors(remote_heads);
+        let mut out = Vec::new();
+        let mut stack: Vec<([u8; 32], bool)> =
+            self.heads.iter().map(|&head| (head, false)).collect();
+        while let Some((hash, expanded)) = stack.pop() {
+            if expanded {
+                out.push(self.events[&hash].clone());
+                continue;
+            }
+            if !done.insert(hash) {
+                continue;
+            }
+            stack.push((hash, true));
+            for parent in &self.events[&hash].parents {
+                if !done.contains(parent) {
+                    stack.push((*parent, false));
+                }
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tracks_heads() {
+        let mut clock = MerkleClock::new();
+        let a = clock.append(b"a".to_vec());
+        let b = clock.append(b"b".to_vec());
+        assert_eq!(clock.heads(), vec![b]);
+        assert_eq!(clock.event(&b).unwrap().parents, vec![a]);
+
+        let orphan = Event {
+            parents: vec![[7; 32]],
+            payload: vec![],
+        };
+        assert_eq!(clock.insert(orphan), Err(MissingParent([7; 32])));
+    }
+
+    #[test]
+    fn syncs_diverged_replicas() {
+        let mut left = MerkleClock::new();
+        left.append(b"base".to_vec());
+        let mut right = left.clone();
+        for i in 0..3u8 {
+            left.append(vec![b'l', i]);
+        }
+        right.append(b"r".to_vec());
+        assert_ne!(left.root(), right.root());
+        assert_eq!(right.missing(&left.heads()).len(), 1);
+
+        right.merge(left.events_since(&right.heads())).unwrap();
+        left.merge(right.events_since(&left.heads())).unwrap();
+        assert_eq!(left.root(), right.root());
+        assert_eq!((left.len(), left.heads().len()), (5, 2));
+
+        let merged = left.append(b"merge".to_vec());
+        right.merge(left.events_since(&right.heads())).unwrap();
+        assert_eq!(right.heads(), vec![merged]);
+        assert!(left.events_since(&right.heads()).is_empty());
+    }
+}
@@ -0,0 +1,195 @@
+use crate::select::path_index;
+use crate::{hash_data, verify_proof, MerkleTree, Padding, Path};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+
+/// Commits to a set of salted values under one root. Each sealed value is
+/// `H(context || salt || value)`, so a reveal cannot be replayed against a
+/// commitment made for a different context (auction, round, game). Odd
+/// levels are closed with `Padding::Zero`, so every path has an entry per
+/// level and spells out its index.
+pub struct CommitReveal {
+    context: Vec<u8>,
+    entries: Vec<([u8; 32], Vec<u8>)>,
+    tree: MerkleTree,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "borsh",
+    derive(borsh::BorshSerialize, borsh::BorshDeserialize)
+)]
+pub struct Reveal {
+    pub index: usize,
+    pub salt: [u8; 32],
+    pub value: Vec<u8>,
+    pub proof: Path,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RevealError {
+    AlreadyRevealed,
+    BadProof,
+}
+
+pub fn seal(context: &[u8], salt: &[u8; 32], value: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(hash_data(context));
+    hasher.update(salt);
+    hasher.update(value);
+    hasher.finalize().into()
+}
+
+impl CommitReveal {
+    /// `entries` are `(salt, value)` pairs; salts must be secret and unique
+    /// for the commitment to hide the values. Returns `None` if there are
+    /// no entries.
+    pub fn new<I, V>(context: &[u8], entries: I) -> Option<Self>
+    where
+        I: IntoIterator<Item = ([u8; 32], V)>,
+        V: AsRef<[u8]>,
+    {
+        let entries: Vec<([u8; 32], Vec<u8>)> = entries
+            .into_iter()
+            .map(|(salt, value)| (salt, value.as_ref().to_vec()))
+            .collect();
+        let sealed = entries
+            .iter()
+            .map(|(salt, value)| seal(context, salt, value));
+        let tree = MerkleTree::from_leaves_padded(sealed, Padding::Zero)?;
+        Some(CommitReveal {
+            context: context.to_vec(),
+            entries,
+            tree,
+        })
+    }
+
+    pub fn root(&self) -> [u8; 32] {
+        self.tree.root()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn context(&self) -> &[u8] {
+        &self.context
+    }
+
+    pub fn reveal(&self, index: usize) -> Option<Reveal> {
+        let (salt, value) = self.entries.get(index)?;
+        Some(Reveal {
+            index,
+            salt: *salt,
+            value: value.clone(),
+            proof: self.tree.path(index).ok()?,
+        })
+    }
+}
+
+/// Verifier side: checks reveals against a published root and refuses to
+/// accept the same position twice.
+pub struct RevealVerifier {
+    context: Vec<u8>,
+    root: [u8; 32],
+    revealed: HashSet<usize>,
+}
+
+impl RevealVerifier {
+    pub fn new(context: &[u8], root: [u8; 32]) -> Self {
+        RevealVerifier {
+            context: context.to_vec(),
+            root,
+            revealed: HashSet::new(),
+        }
+    }
+
+    pub fn accept(&mut self, reveal: &Reveal) -> Result<(), RevealError> {
+        let sealed = seal(&self.context, &reveal.salt, &reveal.value);
+        if path_index(&reveal.proof) != Some(reveal.index)
+            || !verify_proof(&sealed, self.root, &reveal.proof).is_valid()
+        {
+            return Err(RevealError::BadProof);
+        }
+        if !self.revealed.insert(reveal.index) {
+            return Err(RevealError::AlreadyRevealed);
+        }
+        Ok(())
+    }
+
+    pub fn is_revealed(&self, index: usize) -> bool {
+        self.revealed.contains(&index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bids() -> CommitReveal {
+        CommitReveal::new(
+            b"auction-7",
+            vec![
+                ([1; 32], "100"),
+                ([2; 32], "250"),
+                ([3; 32], "75"),
+                ([4; 32], "300"),
+            ],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn reveal_subset() {
+        let bids = bids();
+        let mut verifier = RevealVerifier::new(b"auction-7", bids.root());
+
+        let reveal = bids.reveal(1).unwrap();
+        assert_eq!(reveal.value, b"250");
+        assert_eq!(verifier.accept(&reveal), Ok(()));
+        assert!(verifier.is_revealed(1));
+        assert!(!verifier.is_revealed(2));
+        assert_eq!(verifier.accept(&reveal), Err(RevealError::AlreadyRevealed));
+    }
+
+    #[test]
+    fn any_entry_count() {
+        for len in [3u8, 5] {
+            let entries = (0..len).map(|i| ([i; 32], [i]));
+            let bids = CommitReveal::new(b"round-2", entries).unwrap();
+            let mut verifier = RevealVerifier::new(b"round-2", bids.root());
+            for idx in 0..len as usize {
+                assert_eq!(verifier.accept(&bids.reveal(idx).unwrap()), Ok(()));
+            }
+            let mut moved = bids.reveal(len as usize - 1).unwrap();
+            moved.index -= 1;
+            assert_eq!(verifier.accept(&moved), Err(RevealError::BadProof));
+        }
+        let none: Vec<([u8; 32], &[u8])> = Vec::new();
+        assert!(CommitReveal::new(b"round-2", none).is_none());
+    }
+
+    #[test]
+    fn rejects_tampering() {
+        let bids = bids();
+        let mut verifier = RevealVerifier::new(b"auction-7", bids.root());
+
+        let mut reveal = bids.reveal(2).unwrap();
+        reveal.value = b"999".to_vec();
+        assert_eq!(verifier.accept(&reveal), Err(RevealError::BadProof));
+
+        let mut moved = bids.reveal(2).unwrap();
+        moved.index = 3;
+        assert_eq!(verifier.accept(&moved), Err(RevealError::BadProof));
+
+        let mut other = RevealVerifier::new(b"auction-8", bids.root());
+        assert_eq!(
+            other.accept(&bids.reveal(0).unwrap()),
+            Err(RevealError::BadProof)
+        );
+    }
+}
@@ -0,0 +1,95 @@
+//! Compact binary encoding of a path: format version (1 byte), depth (1
+//! byte), the direction bits packed least-significant bit first, then the
+//! sibling hashes. Version 0 is the same layout without the version byte.
+
+use crate::format::{Version, CURRENT};
+use crate::Path;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::convert::TryInto;
+
+pub fn encoded_len(depth: usize) -> usize {
+    2 + depth.div_ceil(8) + 32 * depth
+}
+
+/// Returns `None` for paths deeper than 255.
+pub fn encode_path(path: &[([u8; 32], bool)]) -> Option<Vec<u8>> {
+    let depth: u8 = path.len().try_into().ok()?;
+    let mut out = vec![0; encoded_len(path.len())];
+    out[0] = CURRENT.byte();
+    out[1] = depth;
+    let bitmap_len = path.len().div_ceil(8);
+    for (level, (sibling, odd)) in path.iter().enumerate() {
+        if *odd {
+            out[2 + level / 8] |= 1 << (level % 8);
+        }
+        let start = 2 + bitmap_len + 32 * level;
+        out[start..start + 32].copy_from_slice(sibling);
+    }
+    Some(out)
+}
+
+/// Decodes a path, returning it with the unread remainder of `bytes`. Unused
+/// bitmap bits must be zero.
+pub fn decode_path(bytes: &[u8]) -> Option<(Path, &[u8])> {
+    match Version::from_byte(*bytes.first()?)? {
+        Version::V1 => decode_path_v0(&bytes[1..]),
+        Version::V0 => None,
+    }
+}
+
+pub(crate) fn decode_path_v0(bytes: &[u8]) -> Option<(Path, &[u8])> {
+    let depth = *bytes.first()? as usize;
+    let len = encoded_len(depth) - 1;
+    if bytes.len() < len {
+        return None;
+    }
+    let bitmap_len = depth.div_ceil(8);
+    let bitmap = &bytes[1..1 + bitmap_len];
+    if depth & 7 != 0 && bitmap[bitmap_len - 1] >> (depth & 7) != 0 {
+        return None;
+    }
+    let path = bytes[1 + bitmap_len..len]
+        .chunks(32)
+        .enumerate()
+        .map(|(level, sibling)| {
+            (
+                sibling.try_into().unwrap(),
+                bitmap[level / 8] & (1 << (level % 8)) != 0,
+            )
+        })
+        .collect();
+    Some((path, &bytes[len..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MerkleTree;
+
+    #[test]
+    fn round_trip() {
+        let data = b"asdfasdfasdfasdfasdfasdfasdfasdf";
+        let tree = MerkleTree::new(data, 8);
+        for idx in 0..8 {
+            let path = tree.path(idx).unwrap();
+            let bytes = encode_path(&path).unwrap();
+            assert_eq!(bytes.len(), 2 + 1 + 96);
+            assert_eq!(bytes[2] as usize, idx);
+            assert_eq!(decode_path(&bytes), Some((path, &[][..])));
+        }
+    }
+
+    #[test]
+    fn rejects_malformed() {
+        let bytes = encode_path(&[([1; 32], true)]).unwrap();
+        assert!(decode_path(&bytes[..bytes.len() - 1]).is_none());
+        let mut stray = bytes.clone();
+        stray[2] |= 0b10;
+        assert!(decode_path(&stray).is_none());
+        let mut future = bytes.clone();
+        future[0] = 2;
+        assert!(decode_path(&future).is_none());
+        assert!(encode_path(&vec![([0; 32], false); 256]).is_none());
+    }
+}
@@ -0,0 +1,256 @@
+//! zstd and lz4 compression of exports, with the `zstd` and `lz4` features.
+//! Node hashes barely compress, but leaf payloads carried next to them,
+//! such as bundled items, often do.
+//!
+//! Output is a plain zstd or lz4 frame, so the command-line tools read it
+//! too. [`Decoder`] tells the two apart by the frame's magic number and
+//! decodes as it reads: a [`MultiProof`] is read a field at a time straight
+//! out of the stream, while snapshots and proof stores, which are parsed
+//! in place, are decoded into one buffer with [`decompress`].
+
+use crate::{MerkleHasher, MerkleTree, MultiProof};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+
+#[cfg(feature = "zstd")]
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+#[cfg(feature = "lz4")]
+const LZ4_MAGIC: [u8; 4] = [0x04, 0x22, 0x4d, 0x18];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// zstd at a level from 1 to 22, or 0 for zstd's default.
+    #[cfg(feature = "zstd")]
+    Zstd(i32),
+    #[cfg(feature = "lz4")]
+    Lz4,
+}
+
+/// Compresses everything written to it into one frame, which `finish`
+/// closes.
+pub struct Encoder<W: Write> {
+    inner: EncoderKind<W>,
+}
+
+enum EncoderKind<W: Write> {
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::stream::write::Encoder<'static, W>),
+    #[cfg(feature = "lz4")]
+    Lz4(lz4_flex::frame::FrameEncoder<W>),
+}
+
+impl<W: Write> Encoder<W> {
+    pub fn new(writer: W, compression: Compression) -> io::Result<Self> {
+        let inner = match compression {
+            #[cfg(feature = "zstd")]
+            Compression::Zstd(level) => {
+                EncoderKind::Zstd(zstd::stream::write::Encoder::new(writer, level)?)
+            }
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => EncoderKind::Lz4(lz4_flex::frame::FrameEncoder::new(writer)),
+        };
+        Ok(Encoder { inner })
+    }
+
+    /// Ends the frame and returns the writer. Dropping an encoder instead
+    /// leaves the frame unfinished.
+    pub fn finish(self) -> io::Result<W> {
+        match self.inner {
+            #[cfg(feature = "zstd")]
+            EncoderKind::Zstd(encoder) => encoder.finish(),
+            #[cfg(feature = "lz4")]
+            EncoderKind::Lz4(encoder) => encoder.finish().map_err(io::Error::from),
+        }
+    }
+}
+
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut self.inner {
+            #[cfg(feature = "zstd")]
+            EncoderKind::Zstd(encoder) => encoder.write(buf),
+            #[cfg(feature = "lz4")]
+            EncoderKind::Lz4(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.inner {
+            #[cfg(feature = "zstd")]
+            EncoderKind::Zstd(encoder) => encoder.flush(),
+            #[cfg(feature = "lz4")]
+            EncoderKind::Lz4(encoder) => encoder.flush(),
+        }
+    }
+}
+
+/// Decompresses one zstd or lz4 frame as it is read.
+pub struct Decoder<R: BufRead> {
+    inner: DecoderKind<R>,
+}
+
+enum DecoderKind<R: BufRead> {
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::stream::read::Decoder<'static, R>),
+    #[cfg(feature = "lz4")]
+    Lz4(lz4_flex::frame::FrameDecoder<R>),
+}
+
+impl<R: BufRead> Decoder<R> {
+    /// Picks the codec from the frame's magic number. Fails with
+    /// `InvalidData` if it names neither enabled codec.
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let mut magic = [0; 4];
+        if let Some(start) = reader.fill_buf()?.get(..4) {
+            magic.copy_from_slice(start);
+        }
+        let inner = match magic {
+            #[cfg(feature = "zstd")]
+            ZSTD_MAGIC => DecoderKind::Zstd(zstd::stream::read::Decoder::with_buffer(reader)?),
+            #[cfg(feature = "lz4")]
+            LZ4_MAGIC => DecoderKind::Lz4(lz4_flex::frame::FrameDecoder::new(reader)),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "not a zstd or lz4 frame",
+                ))
+            }
+        };
+        Ok(Decoder { inner })
+    }
+}
+
+impl<R: BufRead> Read for Decoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match &mut self.inner {
+            #[cfg(feature = "zstd")]
+            DecoderKind::Zstd(decoder) => decoder.read(buf),
+            #[cfg(feature = "lz4")]
+            DecoderKind::Lz4(decoder) => decoder.read(buf),
+        }
+    }
+}
+
+pub fn compress(bytes: &[u8], compression: Compression) -> io::Result<Vec<u8>> {
+    let mut encoder = Encoder::new(Vec::new(), compression)?;
+    encoder.write_all(bytes)?;
+    encoder.finish()
+}
+
+/// Decompresses a frame of at most `limit` bytes, failing with
+/// `InvalidData` on a larger one rather than decoding it all.
+pub fn decompress(bytes: &[u8], limit: u64) -> io::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    Decoder::new(bytes)?
+        .take(limit.saturating_add(1))
+        .read_to_end(&mut out)?;
+    if out.len() as u64 > limit {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "decompressed size over the limit",
+        ));
+    }
+    Ok(out)
+}
+
+impl<H: MerkleHasher> MerkleTree<H> {
+    /// Like `save`, compressing the snapshot.
+    pub fn save_compressed<P: AsRef<std::path::Path>>(
+        &self,
+        path: P,
+        compression: Compression,
+    ) -> io::Result<()> {
+        let file = BufWriter::new(File::create(path)?);
+        let mut encoder = Encoder::new(file, compression)?;
+        encoder.write_all(&self.snapshot())?;
+        encoder.finish()?.flush()
+    }
+
+    /// Reloads a snapshot written by `save_compressed`, decoding the file
+    /// as it is read.
+    pub fn load_compressed<P: AsRef<std::path::Path>>(path: P) -> io::Result<Self> {
+        let mut bytes = Vec::new();
+        Decoder::new(BufReader::new(File::open(path)?))?.read_to_end(&mut bytes)?;
+        MerkleTree::from_snapshot(&bytes)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "not a valid tree snapshot"))
+    }
+}
+
+impl MultiProof {
+    /// `write_to` through an encoder, returning `writer` once the frame is
+    /// finished.
+    pub fn write_compressed<W: Write>(&self, writer: W, compression: Compression) -> io::Result<W> {
+        let mut encoder = Encoder::new(writer, compression)?;
+        self.write_to(&mut encoder)?;
+        encoder.finish()
+    }
+
+    /// Reads a proof written by `write_compressed` without buffering the
+    /// decoded stream.
+    pub fn read_compressed<R: BufRead>(reader: R) -> io::Result<Self> {
+        MultiProof::read_from(Decoder::new(reader)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{verify_multiproof, ProofStore};
+
+    fn codecs() -> Vec<Compression> {
+        vec![
+            #[cfg(feature = "zstd")]
+            Compression::Zstd(0),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd(19),
+            #[cfg(feature = "lz4")]
+            Compression::Lz4,
+        ]
+    }
+
+    #[test]
+    fn round_trips() {
+        let payload: Vec<u8> = b"leaf payload "
+            .iter()
+            .copied()
+            .cycle()
+            .take(10_000)
+            .collect();
+        for compression in codecs() {
+            let compressed = compress(&payload, compression).unwrap();
+            assert!(compressed.len() < payload.len() / 10);
+            assert_eq!(decompress(&compressed, 10_000).unwrap(), payload);
+            assert!(decompress(&compressed, 9_999).is_err());
+        }
+        assert!(decompress(&payload, 10_000).is_err());
+        assert!(decompress(&[], 10_000).is_err());
+    }
+
+    #[test]
+    fn compresses_exports() {
+        let items: Vec<[u8; 4]> = (0..1000u32).map(u32::to_le_bytes).collect();
+        let tree = MerkleTree::from_leaves(&items).unwrap();
+        let indices: Vec<usize> = (0..1000).step_by(3).collect();
+        let proof = tree.multiproof(&indices).unwrap();
+        let proven: Vec<[u8; 4]> = indices.iter().map(|&idx| items[idx]).collect();
+        for (n, compression) in codecs().into_iter().enumerate() {
+            let path = std::env::temp_dir().join(format!(
+                "merkle-compressed-{}-{}",
+                std::process::id(),
+                n
+            ));
+            tree.save_compressed(&path, compression).unwrap();
+            let loaded: MerkleTree = MerkleTree::load_compressed(&path).unwrap();
+            assert_eq!(loaded.root(), tree.root());
+            let bytes = decompress(&std::fs::read(&path).unwrap(), 1 << 20).unwrap();
+            let store = ProofStore::from_bytes(&bytes).unwrap();
+            assert_eq!(store.path(999), tree.path(999).ok());
+            std::fs::remove_file(&path).unwrap();
+
+            let framed = proof.write_compressed(Vec::new(), compression).unwrap();
+            let read = MultiProof::read_compressed(&framed[..]).unwrap();
+            assert!(verify_multiproof(&proven, &tree.head(), &read));
+            assert!(MultiProof::read_compressed(&framed[..framed.len() / 2]).is_err());
+        }
+    }
+}
@@ -0,0 +1,198 @@
+//! Golden vectors for every root and serialized format. They are computed
+//! on x86_64 and must match bit for bit on every target, including
+//! big-endian and 32-bit ones (see `scripts/cross-test.sh`).
+
+use crate::*;
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn tree() -> MerkleTree {
+    MerkleTree::new(b"asdfjkln12345678", 4)
+}
+
+const ROOT: &str = "a888b417134c48465f89d63825a02e850a18a5cf181c0f637c543aef57c05a1c";
+
+#[test]
+fn tree_root() {
+    assert_eq!(hex(&tree().root()), ROOT);
+}
+
+#[test]
+fn bloom_encoding() {
+    let filter = BloomFilter::from_tree(&tree(), 4, 3);
+    assert_eq!(hex(&filter.to_bytes()), "010300000002000000000000006f1d");
+}
+
+#[test]
+fn compact_path() {
+    let bytes = compact::encode_path(&tree().path(2).unwrap()).unwrap();
+    assert_eq!(
+        hex(&bytes),
+        "010202f8638b979b2f4f793ddb6dbd197e0ee25a7a6ea32b0ae22f5e3c5d119d839e75\
+         26f229e317719a2951d0f3e2da86ffe9e25c4142a9de8fbbbdb4746086dff522"
+    );
+}
+
+#[test]
+fn der_tree_head() {
+    let der = der::encode_tree_head(300, &tree().root());
+    assert_eq!(hex(&der), format!("30260202012c0420{}", ROOT));
+}
+
+#[test]
+fn selection() {
+    assert_eq!(
+        select_indices(b"seed", 1000, 5),
+        vec![47, 375, 546, 446, 133]
+    );
+}
+
+#[test]
+fn weighted_root() {
+    let tree = WeightedTree::new(vec![("a", 1), ("b", 2)]).unwrap();
+    assert_eq!(
+        hex(&tree.root()),
+        "ae26fab21334ee098753ef33d145723f690b2d5578ca735eba0ea7357c6d6950"
+    );
+}
+
+#[test]
+fn sealed_value() {
+    assert_eq!(
+        hex(&commit_reveal::seal(b"ctx", &[1; 32], b"v")),
+        "568a5394490955858bdb4c97ccf82fc049f82c8e778d9139c4e9403ffe599d49"
+    );
+}
+
+#[cfg(feature = "borsh")]
+#[test]
+fn borsh_tree() {
+    let bytes = borsh::to_vec(&tree()).unwrap();
+    assert_eq!(&bytes[..13], &[1, 4, 0, 0, 0, 0, 0, 0, 0, 7, 0, 0, 0]);
+    assert_eq!(hex(&bytes[13 + 6 * 32..13 + 7 * 32]), ROOT);
+}
+
+/// Every way of building a tree, checked against `MerkleTree::new`. Any new
+/// construction path (parallel ones in particular) belongs in this list.
+fn build_paths(data: &[u8], leaves: usize) -> Vec<(&'static str, [u8; 32])> {
+    let mut incremental = IncrementalBuild::new(data, leaves).unwrap();
+    while incremental.build_step(Budget::Nodes(3)) != BuildStatus::Done {}
+    let items: Vec<&[u8]> = data.chunks(data.len() / leaves).take(leaves).collect();
+    let mut paths = vec![
+        ("new", MerkleTree::new(data, leaves).root()),
+        (
+            "batch_hasher",
+            MerkleTree::new_with_batch_hasher(data, leaves, &mut Local::<sha2::Sha256>::default())
+                .unwrap()
+                .root(),
+        ),
+        (
+            "progress",
+            MerkleTree::new_with_progress(data, leaves, |_| {}, &CancelToken::new())
+                .unwrap()
+                .root(),
+        ),
+        ("incremental", incremental.finish().unwrap().root()),
+    ];
+
+    let mut stream = MerkleTreeBuilder::new(items[0].len());
+    for item in &items {
+        stream.write(item);
+    }
+    paths.push(("stream", stream.finalize().unwrap().root));
+
+    // Three producers, each filling its own shard with a run of leaves.
+    let sharded = ShardedBuilder::new(3);
+    std::thread::scope(|scope| {
+        for (shard, run) in items.chunks(leaves.div_ceil(3)).enumerate() {
+            let sharded = &sharded;
+            scope.spawn(move || sharded.append_all(shard, run).unwrap());
+        }
+    });
+    paths.push(("sharded", sharded.finalize().unwrap().root()));
+
+    let (legacy, current) = DualTree::<sha2::Sha256, sha2::Sha256>::new(data, leaves).roots();
+    paths.push(("dual/legacy", legacy));
+    paths.push(("dual/current", current));
+
+    for &(name, threads) in [
+        ("par_from_leaves/1", 1),
+        ("par_from_leaves/2", 2),
+        ("par_from_leaves/3", 3),
+        ("par_from_leaves/8", 8),
+    ]
+    .iter()
+    {
+        paths.push((
+            name,
+            MerkleTree::par_from_leaves(&items, threads).unwrap().root(),
+        ));
+    }
+    for &(name, threads) in [
+        ("par_build_from_leaves_padded/1", 1),
+        ("par_build_from_leaves_padded/2", 2),
+        ("par_build_from_leaves_padded/3", 3),
+        ("par_build_from_leaves_padded/8", 8),
+    ]
+    .iter()
+    {
+        let tree: MerkleTree =
+            MerkleTree::par_build_from_leaves_padded(&items, Padding::default(), threads).unwrap();
+        paths.push((name, tree.root()));
+    }
+    paths
+}
+
+#[test]
+fn construction_matrix() {
+    // (leaves, chunk size, root) over bytes 0, 1, 2, ... mod 251, computed
+    // with an independent Python implementation of the node layout.
+    let matrix = [
+        (
+            1,
+            1,
+            "6e340b9cffb37a989ca544e6bb780a2c78901d3fb33738768511a30617afa01d",
+        ),
+        (
+            2,
+            7,
+            "15655b9359769254d8ecbcbf69736ee790178ced6946722e7f8e986582ced0d2",
+        ),
+        (
+            16,
+            32,
+            "9c8f2f7ce1cb371e3979a9676788eeff490b30b6a95e72057c579246f79a81c6",
+        ),
+        (
+            256,
+            3,
+            "c87fd0917b8b0819e8d0d980713532cc5e11eff2c92a8aeacc04d8bcc0ad0b21",
+        ),
+        (
+            2048,
+            64,
+            "ca6cfb02eb9c29d7803675a4462f43351eb7fe4592930d8e69741f4a8353a340",
+        ),
+    ];
+    for &(leaves, chunk, root) in matrix.iter() {
+        let data: Vec<u8> = (0..leaves * chunk).map(|i| (i % 251) as u8).collect();
+        for (path, computed) in build_paths(&data, leaves) {
+            assert_eq!(hex(&computed), root, "{} with {} leaves", path, leaves);
+        }
+    }
+}
+
+#[test]
+fn odd_leaf_counts() {
+    let data: Vec<u8> = (0..7 * 5).map(|i| i as u8).collect();
+    for leaves in [3, 5, 6, 7] {
+        let chunk = data.len() / leaves;
+        let items = data.chunks(chunk).take(leaves);
+        let expected = MerkleTree::from_leaves(items).unwrap().root();
+        for (path, computed) in build_paths(&data, leaves) {
+            assert_eq!(computed, expected, "{} with {} leaves", path, leaves);
+        }
+    }
+}
@@ -0,0 +1,204 @@
+//! Hashes fixed by the hasher alone, evaluated at compile time so they can
+//! sit in `const` items downstream, such as a contract's genesis state.
+//! [`sha256`] is a `const fn` SHA-256 for this; it is far slower than the
+//! `sha2` one and meant only for constants.
+//!
+//! [`ConstHasher`] gives, for each hasher built on SHA-256, the prefixes it
+//! hashes before leaves and pairs and its zero hashes: `ZERO_HASHES[h]` is
+//! the root of a perfect subtree of height `h` whose leaves are all 32 zero
+//! bytes, the empty subtrees of a sparse tree.
+
+use crate::{MerkleHasher, Rfc6962};
+use sha2::Sha256;
+
+/// Heights with a zero hash, the depth of the sparse tree's keyspace.
+pub const ZERO_DEPTH: usize = 256;
+
+/// Root of the empty RFC 6962 log, `SHA-256("")`.
+pub const EMPTY_ROOT: [u8; 32] = sha256(&[b""]);
+
+pub trait ConstHasher: MerkleHasher {
+    /// Hashed before a leaf's data.
+    const LEAF_TAG: &'static [u8];
+    /// Hashed before the two halves of a pair.
+    const NODE_TAG: &'static [u8];
+    const ZERO_HASHES: [[u8; 32]; ZERO_DEPTH + 1];
+}
+
+impl ConstHasher for Sha256 {
+    const LEAF_TAG: &'static [u8] = b"";
+    const NODE_TAG: &'static [u8] = b"";
+    // Some 500 compressions, past the point where rustc starts to warn.
+    #[allow(long_running_const_eval)]
+    const ZERO_HASHES: [[u8; 32]; ZERO_DEPTH + 1] = zero_hashes(Self::NODE_TAG);
+}
+
+impl ConstHasher for Rfc6962 {
+    const LEAF_TAG: &'static [u8] = &[0];
+    const NODE_TAG: &'static [u8] = &[1];
+    // Some 500 compressions, past the point where rustc starts to warn.
+    #[allow(long_running_const_eval)]
+    const ZERO_HASHES: [[u8; 32]; ZERO_DEPTH + 1] = zero_hashes(Self::NODE_TAG);
+}
+
+const fn zero_hashes(tag: &[u8]) -> [[u8; 32]; ZERO_DEPTH + 1] {
+    let mut hashes = [[0; 32]; ZERO_DEPTH + 1];
+    let mut height = 0;
+    while height < ZERO_DEPTH {
+        let below = hashes[height];
+        hashes[height + 1] = sha256(&[tag, &below, &below]);
+        height += 1;
+    }
+    hashes
+}
+
+const K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+const fn compress(state: &mut [u32; 8], block: &[u8; 64]) {
+    let mut w = [0u32; 64];
+    let mut i = 0;
+    while i < 16 {
+        w[i] = u32::from_be_bytes([
+            block[4 * i],
+            block[4 * i + 1],
+            block[4 * i + 2],
+            block[4 * i + 3],
+        ]);
+        i += 1;
+    }
+    while i < 64 {
+        let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+        let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+        w[i] = w[i - 16]
+            .wrapping_add(s0)
+            .wrapping_add(w[i - 7])
+            .wrapping_add(s1);
+        i += 1;
+    }
+
+    let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = *state;
+    i = 0;
+    while i < 64 {
+        let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+        let choice = (e & f) ^ (!e & g);
+        let t1 = h
+            .wrapping_add(s1)
+            .wrapping_add(choice)
+            .wrapping_add(K[i])
+            .wrapping_add(w[i]);
+        let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+        let majority = (a & b) ^ (a & c) ^ (b & c);
+        let t2 = s0.wrapping_add(majority);
+        h = g;
+        g = f;
+        f = e;
+        e = d.wrapping_add(t1);
+        d = c;
+        c = b;
+        b = a;
+        a = t1.wrapping_add(t2);
+        i += 1;
+    }
+    let worked = [a, b, c, d, e, f, g, h];
+    i = 0;
+    while i < 8 {
+        state[i] = state[i].wrapping_add(worked[i]);
+        i += 1;
+    }
+}
+
+/// SHA-256 of `parts` concatenated.
+pub const fn sha256(parts: &[&[u8]]) -> [u8; 32] {
+    let mut state: [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+        0x5be0cd19,
+    ];
+    let mut block = [0u8; 64];
+    let mut filled = 0;
+    let mut total: u64 = 0;
+    let mut part = 0;
+    while part < parts.len() {
+        let bytes = parts[part];
+        let mut i = 0;
+        while i < bytes.len() {
+            block[filled] = bytes[i];
+            filled += 1;
+            if filled == 64 {
+                compress(&mut state, &block);
+                filled = 0;
+            }
+            i += 1;
+        }
+        total += bytes.len() as u64;
+        part += 1;
+    }
+
+    block[filled] = 0x80;
+    filled += 1;
+    if filled > 56 {
+        while filled < 64 {
+            block[filled] = 0;
+            filled += 1;
+        }
+        compress(&mut state, &block);
+        filled = 0;
+    }
+    while filled < 56 {
+        block[filled] = 0;
+        filled += 1;
+    }
+    let bits = (total * 8).to_be_bytes();
+    let mut i = 0;
+    while i < 8 {
+        block[56 + i] = bits[i];
+        i += 1;
+    }
+    compress(&mut state, &block);
+
+    let mut out = [0u8; 32];
+    i = 0;
+    while i < 8 {
+        let word = state[i].to_be_bytes();
+        out[4 * i] = word[0];
+        out[4 * i + 1] = word[1];
+        out[4 * i + 2] = word[2];
+        out[4 * i + 3] = word[3];
+        i += 1;
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_runtime_hashes() {
+        assert_eq!(EMPTY_ROOT, Sha256::hash_data(b""));
+        for len in [0, 3, 55, 56, 63, 64, 65, 200] {
+            let data: alloc::vec::Vec<u8> = (0..len as u8).collect();
+            let (head, tail) = data.split_at(len / 3);
+            assert_eq!(sha256(&[head, tail]), Sha256::hash_data(&data));
+        }
+
+        const ROOT: [u8; 32] = <Sha256 as ConstHasher>::ZERO_HASHES[ZERO_DEPTH];
+        let mut zero = [0u8; 32];
+        for _ in 0..ZERO_DEPTH {
+            zero = Sha256::hash_pair(&zero, &zero);
+        }
+        assert_eq!(ROOT, zero);
+        let zeros = <Rfc6962 as ConstHasher>::ZERO_HASHES;
+        assert_eq!(zeros[0], [0; 32]);
+        assert_eq!(zeros[5], Rfc6962::hash_pair(&zeros[4], &zeros[4]));
+        assert_eq!(sha256(&[Rfc6962::LEAF_TAG, b"x"]), Rfc6962::hash_data(b"x"));
+    }
+}
@@ -0,0 +1,362 @@
+//! Proof bundles in COSE_Sign1 envelopes (RFC 9052), for CBOR-based
+//! ecosystems such as IoT attestation and ISO mDL. A [`ProofBundle`] is an
+//! item with its [`TimedProof`], so it can be checked with nothing but the
+//! log's key; the envelope adds the signature of whoever passes it on.
+//!
+//! A bundle may also carry the leaf's label (see `MerkleTree::set_label`),
+//! so the receiver can file the item under the name the sender uses. The
+//! root does not commit to labels; in an envelope the label is covered by
+//! the envelope's signature only.
+//!
+//! ```text
+//! bundle     = {1: bstr, 2: timed-proof, ? 3: bstr}    ; item, proof, label
+//! COSE_Sign1 = #6.18([protected: bstr .cbor {1: int},    ; algorithm
+//!                     unprotected: {* label => any},
+//!                     payload: bstr .cbor bundle,
+//!                     signature: bstr])
+//! ```
+//!
+//! `timed-proof` is as in `cbor`. The signature is over the
+//! `Sig_structure` `["Signature1", protected, h'', payload]`, so any COSE
+//! library can check an envelope made here. Envelopes are written tagged;
+//! untagged ones, and unprotected headers, are accepted when opened.
+
+use crate::cbor::{
+    read_array, read_bytes, read_expected, read_head, read_int, read_timed_proof, read_uint, skip,
+    write_bytes, write_head, write_int, write_text, write_timed_proof, ARRAY, BYTES, MAP, TAG,
+    UINT,
+};
+use crate::{FreshnessPolicy, HeadVerifier, MerkleTree, SignedHead, TimedProof, TimedProofError};
+
+/// CBOR tag of a COSE_Sign1 message.
+const SIGN1_TAG: u64 = 18;
+
+/// Header label of the signature algorithm.
+const ALG: i64 = 1;
+
+/// Signs envelopes with a COSE algorithm, such as -7 for ES256 or -8 for
+/// EdDSA.
+pub trait CoseSigner {
+    fn algorithm(&self) -> i64;
+
+    fn sign(&self, to_be_signed: &[u8]) -> Vec<u8>;
+}
+
+pub trait CoseVerifier {
+    /// Whether `signature` is one over `to_be_signed` by `algorithm`.
+    /// Algorithms the verifier does not expect should fail.
+    fn verify(&self, algorithm: i64, to_be_signed: &[u8], signature: &[u8]) -> bool;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CoseError {
+    /// Not a COSE_Sign1 carrying a bundle.
+    Malformed,
+    BadSignature,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProofBundle {
+    pub item: Vec<u8>,
+    pub proof: TimedProof,
+    pub label: Option<Vec<u8>>,
+}
+
+impl ProofBundle {
+    pub fn new(item: Vec<u8>, proof: TimedProof) -> Self {
+        ProofBundle {
+            item,
+            proof,
+            label: None,
+        }
+    }
+
+    /// Checks the bundled proof for the bundled item, as
+    /// `TimedProof::verify` does.
+    pub fn verify<V: HeadVerifier>(
+        &self,
+        verifier: &V,
+        policy: &FreshnessPolicy,
+        now: u64,
+    ) -> Result<(), TimedProofError> {
+        self.proof.verify(&self.item, verifier, policy, now)
+    }
+
+    pub fn to_cbor(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.item.len() + 512);
+        write_head(&mut out, MAP, 2 + self.label.is_some() as u64);
+        write_head(&mut out, UINT, 1);
+        write_bytes(&mut out, &self.item);
+        write_head(&mut out, UINT, 2);
+        write_timed_proof(&mut out, &self.proof);
+        if let Some(label) = &self.label {
+            write_head(&mut out, UINT, 3);
+            write_bytes(&mut out, label);
+        }
+        out
+    }
+
+    /// Rejects maps with other keys, or keys out of order.
+    pub fn from_cbor(cbor: &[u8]) -> Option<Self> {
+        let (entries, input) = read_expected(cbor, MAP)?;
+        if !(2..=3).contains(&entries) {
+            return None;
+        }
+        let (item, input) = read_bytes(key(input, 1)?)?;
+        let (proof, mut input) = read_timed_proof(key(input, 2)?)?;
+        let mut label = None;
+        if entries == 3 {
+            let (bytes, rest) = read_bytes(key(input, 3)?)?;
+            label = Some(bytes.to_vec());
+            input = rest;
+        }
+        if !input.is_empty() {
+            return None;
+        }
+        Some(ProofBundle {
+            item: item.to_vec(),
+            proof,
+            label,
+        })
+    }
+
+    /// The bundle in a tagged COSE_Sign1 signed by `signer`.
+    pub fn sign<S: CoseSigner>(&self, signer: &S) -> Vec<u8> {
+        let mut protected = Vec::with_capacity(4);
+        write_head(&mut protected, MAP, 1);
+        write_int(&mut protected, ALG);
+        write_int(&mut protected, signer.algorithm());
+        let payload = self.to_cbor();
+        let signature = signer.sign(&to_be_signed(&protected, &payload));
+
+        let mut out = Vec::with_capacity(payload.len() + signature.len() + 16);
+        write_head(&mut out, TAG, SIGN1_TAG);
+        write_head(&mut out, ARRAY, 4);
+        write_bytes(&mut out, &protected);
+        write_head(&mut out, MAP, 0);
+        write_bytes(&mut out, &payload);
+        write_bytes(&mut out, &signature);
+        out
+    }
+
+    /// The bundle in `cose`, if `verifier` accepts its signature. The
+    /// bundled proof itself is not checked; see `verify`.
+    pub fn open<V: CoseVerifier>(cose: &[u8], verifier: &V) -> Result<Self, CoseError> {
+        let (protected, payload, signature) = split(cose).ok_or(CoseError::Malformed)?;
+        let algorithm = algorithm(protected).ok_or(CoseError::Malformed)?;
+        if !verifier.verify(algorithm, &to_be_signed(protected, payload), signature) {
+            return Err(CoseError::BadSignature);
+        }
+        ProofBundle::from_cbor(payload).ok_or(CoseError::Malformed)
+    }
+}
+
+impl MerkleTree {
+    /// Bundle for `item`, the leaf labelled `label`, against `head`, which
+    /// should be signed over this tree's head. Returns `None` if no leaf
+    /// has the label.
+    pub fn bundle_by_label<L: AsRef<[u8]>>(
+        &self,
+        label: L,
+        item: Vec<u8>,
+        head: SignedHead,
+    ) -> Option<ProofBundle> {
+        let idx = self.leaf_by_label(&label)?;
+        let proof = self.leaf_proof(idx).ok()?;
+        Some(ProofBundle {
+            item,
+            proof: TimedProof { head, proof },
+            label: Some(label.as_ref().to_vec()),
+        })
+    }
+}
+
+/// The rest of `input` after the map key `expected`.
+fn key(input: &[u8], expected: u64) -> Option<&[u8]> {
+    match read_uint(input)? {
+        (actual, rest) if actual == expected => Some(rest),
+        _ => None,
+    }
+}
+
+/// The protected header, payload and signature of a COSE_Sign1.
+fn split(cose: &[u8]) -> Option<(&[u8], &[u8], &[u8])> {
+    let input = match read_head(cose)? {
+        (TAG, SIGN1_TAG, rest) => rest,
+        (ARRAY, ..) => cose,
+        _ => return None,
+    };
+    let input = read_array(input, 4)?;
+    let (protected, input) = read_bytes(input)?;
+    read_expected(input, MAP)?;
+    let input = skip(input)?;
+    let (payload, input) = read_bytes(input)?;
+    let (signature, input) = read_bytes(input)?;
+    if !input.is_empty() {
+        return None;
+    }
+    Some((protected, payload, signature))
+}
+
+/// The algorithm named in a protected header.
+fn algorithm(protected: &[u8]) -> Option<i64> {
+    let (entries, mut input) = read_expected(protected, MAP)?;
+    let mut algorithm = None;
+    for _ in 0..entries {
+        match read_int(input) {
+            Some((ALG, rest)) => {
+                let (alg, rest) = read_int(rest)?;
+                algorithm = Some(alg);
+                input = rest;
+            }
+            _ => input = skip(skip(input)?)?,
+        }
+    }
+    if !input.is_empty() {
+        return None;
+    }
+    algorithm
+}
+
+fn to_be_signed(protected: &[u8], payload: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(protected.len() + payload.len() + 24);
+    write_head(&mut out, ARRAY, 4);
+    write_text(&mut out, "Signature1");
+    write_bytes(&mut out, protected);
+    write_head(&mut out, BYTES, 0);
+    write_bytes(&mut out, payload);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{hash_data, HeadSigner, MerkleTree, SignedHead};
+    use std::time::Duration;
+
+    /// Keyed hash standing in for a real signature scheme.
+    struct Keyed([u8; 32]);
+
+    impl HeadSigner for Keyed {
+        fn sign(&self, message: &[u8; 32]) -> Vec<u8> {
+            hash_data(&[self.0, *message].concat()).to_vec()
+        }
+    }
+
+    impl HeadVerifier for Keyed {
+        fn verify(&self, message: &[u8; 32], signature: &[u8]) -> bool {
+            HeadSigner::sign(self, message) == signature
+        }
+    }
+
+    impl CoseSigner for Keyed {
+        fn algorithm(&self) -> i64 {
+            -7
+        }
+
+        fn sign(&self, to_be_signed: &[u8]) -> Vec<u8> {
+            HeadSigner::sign(self, &hash_data(to_be_signed))
+        }
+    }
+
+    impl CoseVerifier for Keyed {
+        fn verify(&self, algorithm: i64, to_be_signed: &[u8], signature: &[u8]) -> bool {
+            algorithm == -7 && CoseSigner::sign(self, to_be_signed) == signature
+        }
+    }
+
+    fn bundle(log: &Keyed) -> ProofBundle {
+        let tree = MerkleTree::new(b"asdfjkln12345678", 4);
+        ProofBundle::new(
+            b"jkln".to_vec(),
+            TimedProof {
+                head: SignedHead::sign(tree.head(), 1_000, log),
+                proof: tree.leaf_proof(1).unwrap(),
+            },
+        )
+    }
+
+    #[test]
+    fn bundle_round_trip() {
+        let log = Keyed([7; 32]);
+        let bundle = bundle(&log);
+        let cbor = bundle.to_cbor();
+        assert_eq!(&cbor[..7], &[0xa2, 0x01, 0x44, b'j', b'k', b'l', b'n']);
+        assert_eq!(ProofBundle::from_cbor(&cbor), Some(bundle.clone()));
+        assert_eq!(ProofBundle::from_cbor(&cbor[..cbor.len() - 1]), None);
+
+        let policy = FreshnessPolicy {
+            max_age: Duration::from_secs(60),
+            max_skew: Duration::from_secs(5),
+        };
+        assert_eq!(bundle.verify(&log, &policy, 1_010), Ok(()));
+        let mut wrong = bundle;
+        wrong.item = b"asdf".to_vec();
+        assert_eq!(
+            wrong.verify(&log, &policy, 1_010),
+            Err(TimedProofError::BadProof)
+        );
+    }
+
+    #[test]
+    fn labelled_bundles() {
+        let log = Keyed([7; 32]);
+        let mut tree = MerkleTree::new(b"asdfjkln12345678", 4);
+        assert!(tree.set_label(2, "invoice-2024-001"));
+        let head = SignedHead::sign(tree.head(), 1_000, &log);
+        let bundle = tree
+            .bundle_by_label("invoice-2024-001", b"1234".to_vec(), head.clone())
+            .unwrap();
+        assert_eq!(bundle.proof.proof.index(), 2);
+        assert_eq!(bundle.label.as_deref(), Some(&b"invoice-2024-001"[..]));
+        let policy = FreshnessPolicy {
+            max_age: Duration::from_secs(60),
+            max_skew: Duration::from_secs(5),
+        };
+        assert_eq!(bundle.verify(&log, &policy, 1_000), Ok(()));
+        assert!(tree
+            .bundle_by_label("missing", b"1234".to_vec(), head)
+            .is_none());
+
+        let cbor = bundle.to_cbor();
+        assert_eq!(cbor[0], 0xa3);
+        assert_eq!(ProofBundle::from_cbor(&cbor), Some(bundle.clone()));
+        let holder = Keyed([8; 32]);
+        assert_eq!(
+            ProofBundle::open(&bundle.sign(&holder), &holder),
+            Ok(bundle)
+        );
+
+        // A label key without a label entry.
+        let mut short = cbor;
+        short[0] = 0xa2;
+        assert_eq!(ProofBundle::from_cbor(&short), None);
+    }
+
+    #[test]
+    fn sign1_envelopes() {
+        let (log, holder) = (Keyed([7; 32]), Keyed([8; 32]));
+        let bundle = bundle(&log);
+        let cose = bundle.sign(&holder);
+        // Tag 18, four items, then the protected header {1: -7}.
+        assert_eq!(&cose[..6], &[0xd2, 0x84, 0x43, 0xa1, 0x01, 0x26]);
+        assert_eq!(ProofBundle::open(&cose, &holder), Ok(bundle.clone()));
+        assert_eq!(ProofBundle::open(&cose, &log), Err(CoseError::BadSignature));
+        assert_eq!(
+            ProofBundle::open(&cose[..cose.len() - 1], &holder),
+            Err(CoseError::Malformed)
+        );
+
+        // Untagged, with a key id in the unprotected header.
+        let mut untagged = cose[1..6].to_vec();
+        untagged.extend_from_slice(&[0xa1, 0x04, 0x41, 0x01]);
+        untagged.extend_from_slice(&cose[7..]);
+        assert_eq!(ProofBundle::open(&untagged, &holder), Ok(bundle));
+
+        // Changing the payload breaks the signature.
+        let mut tampered = cose;
+        let at = tampered.len() - 40;
+        tampered[at] ^= 1;
+        assert!(ProofBundle::open(&tampered, &holder).is_err());
+    }
+}
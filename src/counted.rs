@@ -0,0 +1,250 @@
+//! Trees whose nodes commit to the number of leaves beneath them, so that
+//! the size of any subtree can be proven without its leaves. A node is
+//! committed as `H(hash || le_u256(count))` and a parent hashes its
+//! children's commitments in leaf order; a lone node at the end of a level
+//! is promoted with its count. The root is the top node's commitment, so
+//! it binds the total.
+//!
+//! A [`SizeProof`] runs from a subtree to the root. Besides the hashes, the
+//! verifier checks every count against the tree's shape: a node at `level`
+//! and `offset` of a tree of `leaves` leaves covers the leaves from
+//! `offset << level`, at most `1 << level` of them, and a proof whose counts
+//! disagree is refused even if its hashes were made to match.
+
+#[cfg(feature = "std")]
+use crate::memory::{vec_bytes, HeapSize};
+use crate::{bounded, MerkleHasher};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use sha2::Sha256;
+
+pub struct CountedTree<H = Sha256> {
+    /// `(hash, count)` per node, leaves first.
+    levels: Vec<Vec<([u8; 32], u64)>>,
+    hasher: core::marker::PhantomData<H>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "borsh",
+    derive(borsh::BorshSerialize, borsh::BorshDeserialize)
+)]
+pub struct SizeProof {
+    pub level: u32,
+    pub offset: u64,
+    /// Leaves in the whole tree.
+    pub leaves: u64,
+    /// Leaves under the proven subtree.
+    pub count: u64,
+    /// Sibling hashes and counts from the subtree up, skipping levels where
+    /// the subtree's ancestor was promoted.
+    pub siblings: Vec<([u8; 32], u64)>,
+}
+
+/// `H(hash || le_u256(count))`.
+pub fn commit<H: MerkleHasher>(hash: &[u8; 32], count: u64) -> [u8; 32] {
+    let mut chunk = [0u8; 32];
+    chunk[..8].copy_from_slice(&count.to_le_bytes());
+    H::hash_pair(hash, &chunk)
+}
+
+fn parent<H: MerkleHasher>(
+    left: &([u8; 32], u64),
+    right: &([u8; 32], u64),
+) -> Option<([u8; 32], u64)> {
+    let hash = H::hash_pair(
+        &commit::<H>(&left.0, left.1),
+        &commit::<H>(&right.0, right.1),
+    );
+    Some((hash, left.1.checked_add(right.1)?))
+}
+
+/// Leaves under the node at `level` and `offset` of a tree of `leaves`
+/// leaves, or `None` if there is no such node.
+pub fn leaves_under(leaves: u64, level: u32, offset: u64) -> Option<u64> {
+    let start = offset.checked_mul(1u64.checked_shl(level)?)?;
+    if start >= leaves || (level > 0 && leaves <= 1u64 << (level - 1)) {
+        return None;
+    }
+    Some((leaves - start).min(1 << level))
+}
+
+impl CountedTree {
+    /// Returns `None` if there are no items.
+    pub fn from_leaves<I>(items: I) -> Option<Self>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        CountedTree::build(items)
+    }
+}
+
+impl<H: MerkleHasher> CountedTree<H> {
+    /// Like `from_leaves`, for a tree hashed with `H`.
+    pub fn build<I>(items: I) -> Option<Self>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        let leaves: Vec<([u8; 32], u64)> = items
+            .into_iter()
+            .map(|item| (H::hash_data(item.as_ref()), 1))
+            .collect();
+        if leaves.is_empty() {
+            return None;
+        }
+        let mut levels = vec![leaves];
+        while levels[levels.len() - 1].len() > 1 {
+            let next = levels[levels.len() - 1]
+                .chunks(2)
+                .map(|pair| match pair {
+                    [left, right] => parent::<H>(left, right),
+                    [lone] => Some(*lone),
+                    _ => unreachable!(),
+                })
+                .collect::<Option<Vec<_>>>()?;
+            levels.push(next);
+        }
+        Some(CountedTree {
+            levels,
+            hasher: core::marker::PhantomData,
+        })
+    }
+
+    pub fn leaves(&self) -> u64 {
+        self.levels[0].len() as u64
+    }
+
+    pub fn root(&self) -> [u8; 32] {
+        let (hash, count) = self.levels[self.levels.len() - 1][0];
+        commit::<H>(&hash, count)
+    }
+
+    /// Hash and leaf count of the node at `level` and `offset`.
+    pub fn subtree(&self, level: u32, offset: u64) -> Option<([u8; 32], u64)> {
+        let level = self.levels.get(level as usize)?;
+        level.get(usize::try_from(offset).ok()?).copied()
+    }
+
+    pub fn size_proof(&self, level: u32, offset: u64) -> Option<SizeProof> {
+        let (_, count) = self.subtree(level, offset)?;
+        let mut siblings = Vec::new();
+        let mut at = offset as usize;
+        for nodes in &self.levels[level as usize..self.levels.len() - 1] {
+            if let Some(sibling) = nodes.get(at ^ 1) {
+                siblings.push(*sibling);
+            }
+            at /= 2;
+        }
+        Some(SizeProof {
+            level,
+            offset,
+            leaves: self.leaves(),
+            count,
+            siblings,
+        })
+    }
+}
+
+impl SizeProof {
+    /// Checks that `subtree` is the node at the proof's position under
+    /// `root`, covering `count` of the tree's `leaves` leaves.
+    pub fn verify(&self, subtree: [u8; 32], root: [u8; 32]) -> bool {
+        self.verify_with::<Sha256>(subtree, root)
+    }
+
+    pub fn verify_with<H: MerkleHasher>(&self, subtree: [u8; 32], root: [u8; 32]) -> bool {
+        if self.siblings.len() > bounded::MAX_DEPTH
+            || leaves_under(self.leaves, self.level, self.offset) != Some(self.count)
+        {
+            return false;
+        }
+        let mut node = (subtree, self.count);
+        let mut siblings = self.siblings.iter();
+        let (mut level, mut offset) = (self.level, self.offset);
+        // Walk up until the node covers every leaf.
+        while node.1 < self.leaves {
+            let sibling_offset = offset ^ 1;
+            if let Some(expected) = leaves_under(self.leaves, level, sibling_offset) {
+                let sibling = match siblings.next() {
+                    Some(sibling) if sibling.1 == expected => sibling,
+                    _ => return false,
+                };
+                let pair = if offset & 1 == 1 {
+                    parent::<H>(sibling, &node)
+                } else {
+                    parent::<H>(&node, sibling)
+                };
+                node = match pair {
+                    Some(pair) => pair,
+                    None => return false,
+                };
+            }
+            level += 1;
+            offset /= 2;
+        }
+        siblings.next().is_none() && node.1 == self.leaves && commit::<H>(&node.0, node.1) == root
+    }
+}
+
+#[cfg(feature = "std")]
+impl<H> HeapSize for CountedTree<H> {
+    fn heap_size_bytes(&self) -> usize {
+        vec_bytes(&self.levels) + self.levels.iter().map(vec_bytes).sum::<usize>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn proves_subtree_sizes() {
+        let items: Vec<[u8; 1]> = (0..11u8).map(|i| [i]).collect();
+        let tree = CountedTree::from_leaves(&items).unwrap();
+        assert_eq!(tree.leaves(), 11);
+        // Levels of 11, 6, 3, 2 and 1 nodes.
+        for (level, len) in [(0, 11), (1, 6), (2, 3), (3, 2), (4, 1)] {
+            for offset in 0..len {
+                let (hash, count) = tree.subtree(level, offset).unwrap();
+                assert_eq!(leaves_under(11, level, offset), Some(count));
+                let proof = tree.size_proof(level, offset).unwrap();
+                assert!(proof.verify(hash, tree.root()), "{} {}", level, offset);
+                let mut wrong = proof.clone();
+                wrong.count += 1;
+                assert!(!wrong.verify(hash, tree.root()));
+            }
+        }
+        // The last node of level 2 covers leaves 8 to 10.
+        assert_eq!(tree.size_proof(2, 2).unwrap().count, 3);
+        assert!(tree.subtree(2, 3).is_none());
+
+        let proof = tree.size_proof(1, 2).unwrap();
+        let (other, _) = tree.subtree(1, 3).unwrap();
+        assert!(!proof.verify(other, tree.root()));
+        let claimed = SizeProof {
+            leaves: 12,
+            ..proof.clone()
+        };
+        let (hash, _) = tree.subtree(1, 2).unwrap();
+        assert!(!claimed.verify(hash, tree.root()));
+        let single = CountedTree::from_leaves(&items[..1]).unwrap();
+        let (leaf, _) = single.subtree(0, 0).unwrap();
+        assert!(single.size_proof(0, 0).unwrap().verify(leaf, single.root()));
+        assert!(CountedTree::from_leaves(Vec::<&[u8]>::new()).is_none());
+    }
+
+    #[test]
+    fn shape_counts() {
+        assert_eq!(leaves_under(11, 3, 1), Some(3));
+        assert_eq!(leaves_under(11, 4, 0), Some(11));
+        assert_eq!(leaves_under(11, 5, 0), None);
+        assert_eq!(leaves_under(11, 0, 11), None);
+        assert_eq!(leaves_under(1, 0, 0), Some(1));
+        assert_eq!(leaves_under(u64::MAX, 63, 1), Some((1 << 63) - 1));
+        assert_eq!(leaves_under(u64::MAX, 64, 0), None);
+    }
+}
@@ -0,0 +1,221 @@
+//! Cuckoo filter over 32-byte hashes. Like a Bloom filter it answers "maybe
+//! present" or "certainly absent", but it keeps one short fingerprint per
+//! item in one of two buckets, so items can also be removed.
+//!
+//! The two buckets of an item are its first bucket and that bucket xor a
+//! hash of the fingerprint, so either can be found from the other without
+//! the item. When both are full a resident is moved to its other bucket to
+//! make room; if that keeps failing the filter is full, and `insert_hash`
+//! says so instead of dropping anything, as a dropped fingerprint would be
+//! a false "certainly absent".
+
+use crate::memory::{vec_bytes, HeapSize};
+use std::convert::TryInto;
+
+/// Fingerprints per bucket.
+const SLOTS: usize = 4;
+
+/// Moves tried before an insert gives up.
+const MAX_KICKS: usize = 500;
+
+/// Unused slot; fingerprints are never zero.
+const FREE: u16 = 0;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CuckooFilter {
+    /// A power of two of buckets.
+    buckets: Vec<[u16; SLOTS]>,
+    len: usize,
+    /// Fingerprint and bucket that found no slot, once the filter is full.
+    victim: Option<(usize, u16)>,
+}
+
+impl Default for CuckooFilter {
+    fn default() -> Self {
+        CuckooFilter::with_capacity(0)
+    }
+}
+
+impl CuckooFilter {
+    /// Room for about `items` items before `insert_hash` fails.
+    pub fn with_capacity(items: usize) -> Self {
+        // Four-slot buckets fill to about 95% before inserts start failing.
+        let buckets = (items * 20 / 19).div_ceil(SLOTS).max(1).next_power_of_two();
+        CuckooFilter {
+            buckets: vec![[FREE; SLOTS]; buckets],
+            len: 0,
+            victim: None,
+        }
+    }
+
+    /// Items inserted and not removed.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Adds `hash`, or returns `false` with the filter unchanged if it is
+    /// full. `hash` should be uniform, such as a `hash_data` output.
+    pub fn insert_hash(&mut self, hash: &[u8; 32]) -> bool {
+        if self.victim.is_some() {
+            return false;
+        }
+        let (first, mut fingerprint) = self.locate(hash);
+        let second = self.alternate(first, fingerprint);
+        if self.place(first, fingerprint) || self.place(second, fingerprint) {
+            self.len += 1;
+            return true;
+        }
+        let mut bucket = first;
+        for kick in 0..MAX_KICKS {
+            let slot = &mut self.buckets[bucket][kick % SLOTS];
+            fingerprint = std::mem::replace(slot, fingerprint);
+            bucket = self.alternate(bucket, fingerprint);
+            if self.place(bucket, fingerprint) {
+                self.len += 1;
+                return true;
+            }
+        }
+        // Every fingerprint is still held, counting the victim.
+        self.victim = Some((bucket, fingerprint));
+        self.len += 1;
+        true
+    }
+
+    /// `false` only if `hash` was never inserted, or was removed since.
+    pub fn might_contain_hash(&self, hash: &[u8; 32]) -> bool {
+        let (first, fingerprint) = self.locate(hash);
+        let second = self.alternate(first, fingerprint);
+        self.victim
+            .is_some_and(|victim| victim == (first, fingerprint) || victim == (second, fingerprint))
+            || self.buckets[first].contains(&fingerprint)
+            || self.buckets[second].contains(&fingerprint)
+    }
+
+    /// Removes one copy of `hash`, which must have been inserted: removing
+    /// an item that was not can remove another with the same fingerprint.
+    pub fn remove_hash(&mut self, hash: &[u8; 32]) -> bool {
+        let (first, fingerprint) = self.locate(hash);
+        let second = self.alternate(first, fingerprint);
+        if let Some(victim) = self.victim {
+            if victim == (first, fingerprint) || victim == (second, fingerprint) {
+                self.victim = None;
+                self.len -= 1;
+                return true;
+            }
+        }
+        for bucket in [first, second] {
+            if let Some(slot) = self.buckets[bucket]
+                .iter_mut()
+                .find(|slot| **slot == fingerprint)
+            {
+                *slot = FREE;
+                self.len -= 1;
+                // The freed slot may be one of the victim's buckets.
+                if let Some((bucket, fingerprint)) = self.victim {
+                    let other = self.alternate(bucket, fingerprint);
+                    if self.place(bucket, fingerprint) || self.place(other, fingerprint) {
+                        self.victim = None;
+                    }
+                }
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Whether an insert has found no slot, so that the next one fails.
+    pub fn is_full(&self) -> bool {
+        self.victim.is_some()
+    }
+
+    fn locate(&self, hash: &[u8; 32]) -> (usize, u16) {
+        let index = u64::from_le_bytes(hash[0..8].try_into().unwrap());
+        let fingerprint = u16::from_le_bytes(hash[8..10].try_into().unwrap()).max(1);
+        (self.bucket(index), fingerprint)
+    }
+
+    fn alternate(&self, bucket: usize, fingerprint: u16) -> usize {
+        self.bucket(bucket as u64 ^ u64::from(fingerprint).wrapping_mul(0x5bd1_e995))
+    }
+
+    fn bucket(&self, index: u64) -> usize {
+        (index & (self.buckets.len() as u64 - 1)) as usize
+    }
+
+    fn place(&mut self, bucket: usize, fingerprint: u16) -> bool {
+        match self.buckets[bucket].iter_mut().find(|slot| **slot == FREE) {
+            Some(slot) => {
+                *slot = fingerprint;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl HeapSize for CuckooFilter {
+    fn heap_size_bytes(&self) -> usize {
+        vec_bytes(&self.buckets)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash_data;
+
+    #[test]
+    fn no_false_negatives() {
+        let hashes: Vec<[u8; 32]> = (0..1000u32).map(|i| hash_data(&i.to_le_bytes())).collect();
+        let mut filter = CuckooFilter::with_capacity(1000);
+        for hash in &hashes {
+            assert!(filter.insert_hash(hash));
+        }
+        assert_eq!(filter.len(), 1000);
+        assert!(hashes.iter().all(|hash| filter.might_contain_hash(hash)));
+        let false_positives = (1000..11000u32)
+            .filter(|i| filter.might_contain_hash(&hash_data(&i.to_le_bytes())))
+            .count();
+        // About 2 * SLOTS / 2^16 of absent items.
+        assert!(false_positives < 50, "{}", false_positives);
+
+        for hash in &hashes[..500] {
+            assert!(filter.remove_hash(hash));
+        }
+        assert_eq!(filter.len(), 500);
+        assert!(hashes[500..]
+            .iter()
+            .all(|hash| filter.might_contain_hash(hash)));
+    }
+
+    #[test]
+    fn reports_when_full() {
+        let mut filter = CuckooFilter::with_capacity(8);
+        let mut inserted = Vec::new();
+        for i in 0u32.. {
+            let hash = hash_data(&i.to_le_bytes());
+            if !filter.insert_hash(&hash) {
+                break;
+            }
+            inserted.push(hash);
+        }
+        assert!(filter.is_full());
+        assert_eq!(filter.len(), inserted.len());
+        assert!(inserted.iter().all(|hash| filter.might_contain_hash(hash)));
+
+        // Removing items makes room for the one that found no slot.
+        let mut removed = 0;
+        while filter.is_full() {
+            assert!(filter.remove_hash(&inserted[removed]));
+            removed += 1;
+        }
+        assert_eq!(filter.len(), inserted.len() - removed);
+        assert!(inserted[removed..]
+            .iter()
+            .all(|hash| filter.might_contain_hash(hash)));
+    }
+}
@@ -0,0 +1,146 @@
+//! Resumable iteration over every leaf with its proof, for exports too
+//! long to finish in one run. A [`Cursor`] computes each proof as it is
+//! reached, so memory stays at one path however large the tree, and its
+//! [`CursorCheckpoint`] records where it stopped and which root it was
+//! reading, so an interrupted job resumes at the next leaf and refuses to
+//! resume against a tree that has since changed.
+//!
+//! Checkpoint layout: format version (1 byte), the number of leaves done
+//! (u64 LE), then the root.
+
+use crate::format::{Version, CURRENT};
+use crate::{MerkleHasher, MerkleTree, NodeStore, Path};
+use alloc::vec::Vec;
+use core::convert::TryInto;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CursorCheckpoint {
+    /// Leaves already yielded; the next one has this index.
+    pub done: u64,
+    pub root: [u8; 32],
+}
+
+impl CursorCheckpoint {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(41);
+        out.push(CURRENT.byte());
+        out.extend_from_slice(&self.done.to_le_bytes());
+        out.extend_from_slice(&self.root);
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != 41 || Version::from_byte(bytes[0]) != Some(Version::V1) {
+            return None;
+        }
+        Some(CursorCheckpoint {
+            done: u64::from_le_bytes(bytes[1..9].try_into().unwrap()),
+            root: bytes[9..].try_into().unwrap(),
+        })
+    }
+}
+
+/// Yields `(index, leaf hash, path)` for each leaf from where it started.
+pub struct Cursor<'a, H, S> {
+    tree: &'a MerkleTree<H, S>,
+    next: usize,
+}
+
+impl<H: MerkleHasher, S: NodeStore> MerkleTree<H, S> {
+    /// A cursor over the leaves after `after`, or over all of them for
+    /// `None`.
+    pub fn cursor(&self, after: Option<usize>) -> Cursor<'_, H, S> {
+        let next = after.map_or(0, |idx| idx.saturating_add(1));
+        Cursor {
+            tree: self,
+            next: next.min(self.leaves()),
+        }
+    }
+
+    /// Resumes a cursor from `checkpoint`, or returns `None` if it was
+    /// taken on a tree with another root or more leaves.
+    pub fn resume(&self, checkpoint: &CursorCheckpoint) -> Option<Cursor<'_, H, S>> {
+        let done: usize = checkpoint.done.try_into().ok()?;
+        if checkpoint.root != self.root() || done > self.leaves() {
+            return None;
+        }
+        Some(Cursor {
+            tree: self,
+            next: done,
+        })
+    }
+}
+
+impl<H: MerkleHasher, S: NodeStore> Cursor<'_, H, S> {
+    /// Index of the next leaf to be yielded.
+    pub fn position(&self) -> usize {
+        self.next
+    }
+
+    /// Where to resume after the leaves yielded so far.
+    pub fn checkpoint(&self) -> CursorCheckpoint {
+        CursorCheckpoint {
+            done: self.next as u64,
+            root: self.tree.root(),
+        }
+    }
+}
+
+impl<H: MerkleHasher, S: NodeStore> Iterator for Cursor<'_, H, S> {
+    type Item = (usize, [u8; 32], Path);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let idx = self.next;
+        let path = self.tree.path(idx).ok()?;
+        self.next += 1;
+        Some((idx, self.tree.node(idx).ok()?, path))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let left = self.tree.leaves() - self.next;
+        (left, Some(left))
+    }
+}
+
+impl<H: MerkleHasher, S: NodeStore> ExactSizeIterator for Cursor<'_, H, S> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::verify_proof;
+
+    #[test]
+    fn resumes_after_interruption() {
+        let items: Vec<[u8; 1]> = (0..11u8).map(|i| [i]).collect();
+        let tree = MerkleTree::from_leaves(&items).unwrap();
+
+        let mut cursor = tree.cursor(None);
+        let first: Vec<usize> = cursor.by_ref().take(4).map(|(idx, _, _)| idx).collect();
+        assert_eq!(first, vec![0, 1, 2, 3]);
+        let saved = cursor.checkpoint().to_bytes();
+
+        let checkpoint = CursorCheckpoint::from_bytes(&saved).unwrap();
+        let resumed = tree.resume(&checkpoint).unwrap();
+        assert_eq!((resumed.position(), resumed.len()), (4, 7));
+        let mut seen = first;
+        for (idx, leaf, path) in resumed {
+            assert_eq!(tree.node(idx), Ok(leaf));
+            assert!(verify_proof(&items[idx], tree.root(), &path).is_valid());
+            seen.push(idx);
+        }
+        assert_eq!(seen, (0..11).collect::<Vec<_>>());
+
+        assert_eq!(tree.cursor(Some(8)).next().map(|(idx, _, _)| idx), Some(9));
+        assert_eq!(tree.cursor(Some(10)).next(), None);
+        assert_eq!(tree.cursor(Some(usize::MAX)).len(), 0);
+
+        let other = MerkleTree::from_leaves(&items[..10]).unwrap();
+        assert!(other.resume(&checkpoint).is_none());
+        let past = CursorCheckpoint {
+            done: 12,
+            ..checkpoint
+        };
+        assert!(tree.resume(&past).is_none());
+        assert!(CursorCheckpoint::from_bytes(&saved[..40]).is_none());
+    }
+}
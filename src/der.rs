@@ -0,0 +1,226 @@
+//! DER profile for proofs and tree heads, for PKI tooling and HSM-based
+//! verifiers that only consume DER:
+//!
+//! ```text
+//! MerkleProofs DEFINITIONS EXPLICIT TAGS ::= BEGIN
+//!     PathEntry ::= SEQUENCE {
+//!         sibling  OCTET STRING (SIZE (32)),
+//!         odd      BOOLEAN   -- running hash is hashed first
+//!     }
+//!     MerkleProof ::= SEQUENCE SIZE (0..256) OF PathEntry
+//!     TreeHead ::= SEQUENCE {
+//!         treeSize INTEGER (0..18446744073709551615),
+//!         rootHash OCTET STRING (SIZE (32))
+//!     }
+//! END
+//! ```
+//!
+//! Decoding is strict: non-minimal lengths or integers, booleans other than
+//! `00`/`FF` and trailing bytes are all rejected.
+
+use crate::Path;
+use std::convert::TryInto;
+
+pub(crate) const BOOLEAN: u8 = 0x01;
+const INTEGER: u8 = 0x02;
+pub(crate) const OCTET_STRING: u8 = 0x04;
+pub(crate) const SEQUENCE: u8 = 0x30;
+
+pub(crate) fn write_tlv(out: &mut Vec<u8>, tag: u8, value: &[u8]) {
+    out.push(tag);
+    let len = value.len();
+    if len < 0x80 {
+        out.push(len as u8);
+    } else {
+        let bytes = len.to_be_bytes();
+        let skip = bytes.iter().take_while(|&&b| b == 0).count();
+        out.push(0x80 | (bytes.len() - skip) as u8);
+        out.extend_from_slice(&bytes[skip..]);
+    }
+    out.extend_from_slice(value);
+}
+
+/// Reads one TLV with the expected tag, returning its value and the rest.
+pub(crate) fn read_tlv(input: &[u8], tag: u8) -> Option<(&[u8], &[u8])> {
+    match read_any(input)? {
+        (actual, value, rest) if actual == tag => Some((value, rest)),
+        _ => None,
+    }
+}
+
+/// Reads one TLV of any single-byte tag.
+pub(crate) fn read_any(input: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, rest) = input.split_first()?;
+    let (&first, rest) = rest.split_first()?;
+    if tag & 0x1f == 0x1f {
+        return None;
+    }
+    let (len, rest) = if first < 0x80 {
+        (first as usize, rest)
+    } else {
+        let n = (first & 0x7f) as usize;
+        if n == 0 || n > std::mem::size_of::<usize>() || rest.len() < n || rest[0] == 0 {
+            return None;
+        }
+        let len = rest[..n]
+            .iter()
+            .fold(0usize, |acc, &b| acc << 8 | b as usize);
+        if len < 0x80 {
+            return None;
+        }
+        (len, &rest[n..])
+    };
+    if rest.len() < len {
+        return None;
+    }
+    let (value, rest) = rest.split_at(len);
+    Some((tag, value, rest))
+}
+
+fn write_u64(out: &mut Vec<u8>, value: u64) {
+    let bytes = value.to_be_bytes();
+    let skip = bytes.iter().take_while(|&&b| b == 0).count().min(7);
+    let mut int = Vec::with_capacity(9);
+    if bytes[skip] & 0x80 != 0 {
+        int.push(0);
+    }
+    int.extend_from_slice(&bytes[skip..]);
+    write_tlv(out, INTEGER, &int);
+}
+
+fn read_u64(input: &[u8]) -> Option<(u64, &[u8])> {
+    let (int, rest) = read_tlv(input, INTEGER)?;
+    let minimal = match int {
+        [] => false,
+        [0, next, ..] => next & 0x80 != 0,
+        [first, ..] => first & 0x80 == 0,
+    };
+    let digits = if int[0] == 0 && int.len() > 1 {
+        &int[1..]
+    } else {
+        int
+    };
+    if !minimal || digits.len() > 8 {
+        return None;
+    }
+    Some((
+        digits.iter().fold(0u64, |acc, &b| acc << 8 | b as u64),
+        rest,
+    ))
+}
+
+fn read_hash(input: &[u8]) -> Option<([u8; 32], &[u8])> {
+    let (hash, rest) = read_tlv(input, OCTET_STRING)?;
+    Some((hash.try_into().ok()?, rest))
+}
+
+pub fn encode_proof(path: &[([u8; 32], bool)]) -> Vec<u8> {
+    let mut entries = Vec::with_capacity(path.len() * 39);
+    for (sibling, odd) in path {
+        let mut entry = Vec::with_capacity(37);
+        write_tlv(&mut entry, OCTET_STRING, sibling);
+        write_tlv(&mut entry, BOOLEAN, &[if *odd { 0xff } else { 0x00 }]);
+        write_tlv(&mut entries, SEQUENCE, &entry);
+    }
+    let mut out = Vec::with_capacity(entries.len() + 4);
+    write_tlv(&mut out, SEQUENCE, &entries);
+    out
+}
+
+pub fn decode_proof(der: &[u8]) -> Option<Path> {
+    let (mut entries, rest) = read_tlv(der, SEQUENCE)?;
+    if !rest.is_empty() {
+        return None;
+    }
+    let mut path = Vec::new();
+    while !entries.is_empty() {
+        let (entry, next) = read_tlv(entries, SEQUENCE)?;
+        let (sibling, entry) = read_hash(entry)?;
+        let odd = match read_tlv(entry, BOOLEAN)? {
+            ([0xff], []) => true,
+            ([0x00], []) => false,
+            _ => return None,
+        };
+        path.push((sibling, odd));
+        entries = next;
+    }
+    if path.len() > 256 {
+        return None;
+    }
+    Some(path)
+}
+
+pub fn encode_tree_head(size: u64, root: &[u8; 32]) -> Vec<u8> {
+    let mut head = Vec::with_capacity(45);
+    write_u64(&mut head, size);
+    write_tlv(&mut head, OCTET_STRING, root);
+    let mut out = Vec::with_capacity(47);
+    write_tlv(&mut out, SEQUENCE, &head);
+    out
+}
+
+pub fn decode_tree_head(der: &[u8]) -> Option<(u64, [u8; 32])> {
+    let (head, rest) = read_tlv(der, SEQUENCE)?;
+    if !rest.is_empty() {
+        return None;
+    }
+    let (size, head) = read_u64(head)?;
+    let (root, head) = read_hash(head)?;
+    if !head.is_empty() {
+        return None;
+    }
+    Some((size, root))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MerkleTree;
+
+    #[test]
+    fn proof_round_trip() {
+        let data = b"asdfasdfasdfasdfasdfasdfasdfasdf";
+        let tree = MerkleTree::new(data, 8);
+        let path = tree.path(6).unwrap();
+        let der = encode_proof(&path);
+        assert_eq!(&der[..2], &[0x30, 0x75]);
+        assert_eq!(&der[2..6], &[0x30, 0x25, 0x04, 0x20]);
+        assert_eq!(decode_proof(&der), Some(path));
+        assert_eq!(decode_proof(&encode_proof(&[])), Some(vec![]));
+    }
+
+    #[test]
+    fn long_form_lengths() {
+        let path = vec![([7; 32], true); 5];
+        let der = encode_proof(&path);
+        assert_eq!(&der[..3], &[0x30, 0x81, 195]);
+        assert_eq!(decode_proof(&der), Some(path));
+    }
+
+    #[test]
+    fn tree_head_round_trip() {
+        for size in [0, 1, 127, 128, 255, 256, u64::MAX] {
+            let der = encode_tree_head(size, &[9; 32]);
+            assert_eq!(decode_tree_head(&der), Some((size, [9; 32])));
+        }
+        assert_eq!(
+            &encode_tree_head(128, &[0; 32])[2..6],
+            &[0x02, 0x02, 0x00, 0x80]
+        );
+    }
+
+    #[test]
+    fn rejects_non_der() {
+        let mut der = encode_proof(&[([1; 32], true)]);
+        let last = der.len() - 1;
+        der[last] = 0x01;
+        assert!(decode_proof(&der).is_none());
+
+        let mut trailing = encode_proof(&[]);
+        trailing.push(0);
+        assert!(decode_proof(&trailing).is_none());
+
+        assert!(decode_proof(&[0x30, 0x81, 0x00]).is_none());
+        assert!(decode_tree_head(&[0x30, 0x25, 0x02, 0x02, 0x00, 0x01]).is_none());
+    }
+}
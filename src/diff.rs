@@ -0,0 +1,106 @@
+//! Finding the leaves two trees disagree on by walking both from the root
+//! and descending only where node hashes differ, so equal subtrees are
+//! skipped whole. `node_at` exposes the same nodes one at a time, for peers
+//! that run this walk against a remote tree over request and response.
+//!
+//! A node's hash only stands for its leaves when it covers the same leaves
+//! in both trees. Under trees of different leaf counts or padding, nodes
+//! reaching past the shorter tree's last leaf are always descended into,
+//! and leaves only one tree has count as differing.
+
+use crate::{MerkleHasher, MerkleTree};
+use alloc::vec;
+use alloc::vec::Vec;
+
+impl<H: MerkleHasher> MerkleTree<H> {
+    /// Indices, ascending, of the leaves that differ from `other`'s or that
+    /// only one of the trees has.
+    pub fn diff(&self, other: &MerkleTree<H>) -> Vec<usize> {
+        let common = self.leaves().min(other.leaves());
+        let total = self.leaves().max(other.leaves());
+        let same_shape = self.leaves() == other.leaves() && self.padding() == other.padding();
+        let top = self.layout.levels().max(other.layout.levels()) - 1;
+
+        let mut differing = Vec::new();
+        let mut pending: Vec<(usize, usize)> = vec![(top, 0)];
+        while let Some((level, offset)) = pending.pop() {
+            let span = 1usize.checked_shl(level as u32).unwrap_or(usize::MAX);
+            let first = offset.saturating_mul(span);
+            if first >= total {
+                continue;
+            }
+            let last = first.saturating_add(span);
+            let aligned = same_shape || last <= common;
+            if aligned && self.node_at(level, offset) == other.node_at(level, offset) {
+                continue;
+            }
+            if level == 0 {
+                differing.push(offset);
+            } else {
+                // Right first, so that leaves come off the stack in order.
+                pending.push((level - 1, 2 * offset + 1));
+                pending.push((level - 1, 2 * offset));
+            }
+        }
+        differing
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{MerkleTree, Padding};
+
+    fn tree(items: &[&str], padding: Padding) -> MerkleTree {
+        MerkleTree::from_leaves_padded(items, padding).unwrap()
+    }
+
+    #[test]
+    fn finds_changed_leaves() {
+        let base = ["a", "b", "c", "d", "e", "f", "g"];
+        let changed = ["a", "B", "c", "d", "e", "f", "G"];
+        let padding = Padding::default();
+        assert_eq!(tree(&base, padding).diff(&tree(&base, padding)), vec![]);
+        assert_eq!(
+            tree(&base, padding).diff(&tree(&changed, padding)),
+            vec![1, 6]
+        );
+        assert_eq!(
+            tree(&changed, padding).diff(&tree(&base, padding)),
+            vec![1, 6]
+        );
+    }
+
+    #[test]
+    fn compares_unequal_trees() {
+        // Duplicating the last leaf gives the same node as a tree that
+        // padded by duplicating it, which must not hide the extra leaf.
+        let short = tree(&["a", "b", "c"], Padding::DuplicateLast);
+        let long = tree(&["a", "b", "c", "c"], Padding::DuplicateLast);
+        assert_eq!(short.root(), long.root());
+        assert_eq!(short.diff(&long), vec![3]);
+        assert_eq!(long.diff(&short), vec![3]);
+
+        let grown = tree(&["a", "x", "c", "d", "e", "f"], Padding::Promote);
+        assert_eq!(short.diff(&grown), vec![1, 3, 4, 5]);
+        let zero = tree(&["a", "b", "c"], Padding::Zero);
+        assert_eq!(short.diff(&zero), vec![]);
+    }
+
+    #[test]
+    fn remote_walk() {
+        let local = tree(&["a", "b", "c", "d", "e"], Padding::Zero);
+        let remote = tree(&["a", "b", "c", "D", "e"], Padding::Zero);
+        assert_eq!(local.node_at(0, 3), Some(crate::hash_data(b"d")));
+        assert_eq!(local.node_at(0, 5), None);
+        assert_eq!(local.node_at(3, 0), Some(local.root()));
+        assert_eq!(local.node_at(4, 0), None);
+        // Above the changed leaf, only its ancestors differ.
+        let differ: Vec<(usize, usize)> = (0..4)
+            .flat_map(|level| (0..5).map(move |offset| (level, offset)))
+            .filter(|&(level, offset)| {
+                local.node_at(level, offset) != remote.node_at(level, offset)
+            })
+            .collect();
+        assert_eq!(differ, vec![(0, 3), (1, 1), (2, 0), (3, 0)]);
+    }
+}
@@ -0,0 +1,223 @@
+//! Bookkeeping for downloads verified against a known root. A
+//! [`DownloadState`] records which blocks have arrived with a valid proof,
+//! says which to ask for next, and persists between runs, so an
+//! interrupted download resumes without fetching or rechecking blocks it
+//! already has. The blocks themselves are the caller's to store.
+//!
+//! Proofs are checked against the block's position as well as the root:
+//! the directions must be the ones leaf `index` of a tree of `leaves`
+//! leaves under `padding` takes, so a valid proof for one block cannot be
+//! passed off as another's.
+//!
+//! Persisted layout: format version (1 byte), padding (1 byte), leaves
+//! (u64 LE), the root, then one bit per block, least-significant first.
+
+use crate::format::{Version, CURRENT};
+use crate::{verify_proof_with, MerkleHasher, Padding, Proof};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::convert::TryInto;
+use core::marker::PhantomData;
+use sha2::Sha256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RejectedBlock {
+    /// The proof's index is not a block of this download.
+    NotABlock(u64),
+    /// The proof is for another position or does not reach the root.
+    BadProof(u64),
+}
+
+#[derive(Debug, Clone)]
+pub struct DownloadState<H = Sha256> {
+    root: [u8; 32],
+    leaves: u64,
+    padding: Padding,
+    received: Vec<u8>,
+    count: u64,
+    hasher: PhantomData<H>,
+}
+
+impl DownloadState {
+    pub fn new(root: [u8; 32], leaves: u64, padding: Padding) -> Option<Self> {
+        DownloadState::build(root, leaves, padding)
+    }
+}
+
+impl<H: MerkleHasher> DownloadState<H> {
+    /// Like `new`, for a tree hashed with `H`. Returns `None` for an empty
+    /// tree or one too large to track in memory.
+    pub fn build(root: [u8; 32], leaves: u64, padding: Padding) -> Option<Self> {
+        if leaves == 0 {
+            return None;
+        }
+        let bytes: usize = leaves.div_ceil(8).try_into().ok()?;
+        Some(DownloadState {
+            root,
+            leaves,
+            padding,
+            received: vec![0; bytes],
+            count: 0,
+            hasher: PhantomData,
+        })
+    }
+
+    pub fn root(&self) -> [u8; 32] {
+        self.root
+    }
+
+    pub fn leaves(&self) -> u64 {
+        self.leaves
+    }
+
+    pub fn received(&self) -> u64 {
+        self.count
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.count == self.leaves
+    }
+
+    pub fn has(&self, index: u64) -> bool {
+        index < self.leaves && self.received[(index / 8) as usize] >> (index % 8) & 1 == 1
+    }
+
+    /// The first block not yet received.
+    pub fn next_needed(&self) -> Option<u64> {
+        self.next_needed_from(0)
+    }
+
+    /// The first block not yet received at or after `start`, so that
+    /// several requests can be kept in flight.
+    pub fn next_needed_from(&self, start: u64) -> Option<u64> {
+        let mut index = start;
+        while index < self.leaves {
+            // Skip whole bytes of received blocks.
+            if index & 7 == 0 && self.received[(index / 8) as usize] == 0xff {
+                index += 8;
+            } else if self.has(index) {
+                index += 1;
+            } else {
+                return Some(index);
+            }
+        }
+        None
+    }
+
+    /// Records `block` if `proof` shows it is block `proof.index()` under
+    /// the root. Returns whether it was new.
+    pub fn accept(&mut self, block: &[u8], proof: &Proof) -> Result<bool, RejectedBlock> {
+        let index = proof.index();
+        let expected = self
+            .padding
+            .directions(self.leaves, index)
+            .ok_or(RejectedBlock::NotABlock(index))?;
+        let placed = proof.path().iter().map(|&(_, odd)| odd).eq(expected);
+        if !placed || !verify_proof_with::<H>(block, self.root, proof.path()).is_valid() {
+            return Err(RejectedBlock::BadProof(index));
+        }
+        if self.has(index) {
+            return Ok(false);
+        }
+        self.received[(index / 8) as usize] |= 1 << (index % 8);
+        self.count += 1;
+        Ok(true)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(42 + self.received.len());
+        out.push(CURRENT.byte());
+        out.push(self.padding.byte());
+        out.extend_from_slice(&self.leaves.to_le_bytes());
+        out.extend_from_slice(&self.root);
+        out.extend_from_slice(&self.received);
+        out
+    }
+
+    /// Rejects lengths that do not match the block count and set bits past
+    /// the last block.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 42 || Version::from_byte(bytes[0]) != Some(Version::V1) {
+            return None;
+        }
+        let padding = Padding::from_byte(bytes[1])?;
+        let leaves = u64::from_le_bytes(bytes[2..10].try_into().unwrap());
+        let root = bytes[10..42].try_into().unwrap();
+        let mut state = DownloadState::build(root, leaves, padding)?;
+        let received = &bytes[42..];
+        if received.len() != state.received.len() {
+            return None;
+        }
+        let tail = leaves % 8;
+        if tail != 0 && received[received.len() - 1] >> tail != 0 {
+            return None;
+        }
+        state.received.copy_from_slice(received);
+        state.count = received.iter().map(|bits| bits.count_ones() as u64).sum();
+        Some(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MerkleTree;
+
+    #[test]
+    fn resumes_verified_downloads() {
+        let blocks: Vec<Vec<u8>> = (0..21u8).map(|i| vec![i; 10]).collect();
+        for &padding in [Padding::Promote, Padding::DuplicateLast, Padding::Zero].iter() {
+            let tree = MerkleTree::from_leaves_padded(&blocks, padding).unwrap();
+            let mut state = DownloadState::new(tree.root(), 21, padding).unwrap();
+            for index in [0, 1, 2, 5, 20] {
+                let proof = tree.leaf_proof(index).unwrap();
+                assert_eq!(state.accept(&blocks[index], &proof), Ok(true));
+            }
+            assert_eq!(state.next_needed(), Some(3));
+            assert_eq!(state.next_needed_from(4), Some(4));
+            assert_eq!(state.next_needed_from(5), Some(6));
+
+            let mut resumed = DownloadState::<Sha256>::from_bytes(&state.to_bytes()).unwrap();
+            assert_eq!(resumed.to_bytes(), state.to_bytes());
+            assert_eq!(resumed.received(), 5);
+            let proof = tree.leaf_proof(5).unwrap();
+            assert_eq!(resumed.accept(&blocks[5], &proof), Ok(false));
+            while let Some(index) = resumed.next_needed() {
+                let proof = tree.leaf_proof(index as usize).unwrap();
+                assert_eq!(resumed.accept(&blocks[index as usize], &proof), Ok(true));
+            }
+            assert!(resumed.is_complete());
+            assert_eq!(resumed.received(), 21);
+        }
+    }
+
+    #[test]
+    fn rejects_misplaced_blocks() {
+        let blocks: Vec<Vec<u8>> = (0..6u8).map(|i| vec![i]).collect();
+        let tree = MerkleTree::from_leaves(&blocks).unwrap();
+        let mut state = DownloadState::new(tree.root(), 6, Padding::Promote).unwrap();
+        let proof = tree.leaf_proof(4).unwrap();
+        assert_eq!(
+            state.accept(&blocks[3], &proof),
+            Err(RejectedBlock::BadProof(4))
+        );
+        // A valid proof relabelled with another index is refused.
+        let relabelled = Proof::new(5, proof.path().clone());
+        assert_eq!(
+            state.accept(&blocks[4], &relabelled),
+            Err(RejectedBlock::BadProof(5))
+        );
+        let past = Proof::new(6, proof.path().clone());
+        assert_eq!(
+            state.accept(&blocks[4], &past),
+            Err(RejectedBlock::NotABlock(6))
+        );
+        assert_eq!(state.received(), 0);
+
+        let mut bytes = state.to_bytes();
+        *bytes.last_mut().unwrap() |= 0x80;
+        assert!(DownloadState::<Sha256>::from_bytes(&bytes).is_none());
+        assert!(DownloadState::<Sha256>::from_bytes(&bytes[..42]).is_none());
+        assert!(DownloadState::new([0; 32], 0, Padding::Zero).is_none());
+    }
+}
@@ -0,0 +1,179 @@
+//! Trees committed under two hash functions at once, for migrations that
+//! publish the legacy root beside the new one until every verifier has
+//! moved over. [`DualTree`] reads each item once and walks the levels once,
+//! hashing every node with both functions. The two trees have the same
+//! shape, so a leaf's paths in each have the same directions and differ
+//! only in their hashes.
+
+#[cfg(feature = "std")]
+use crate::memory::HeapSize;
+use crate::{Error, Layout, MerkleHasher, MerkleTree, Node, Padding, Path};
+use alloc::vec::Vec;
+
+pub struct DualTree<L: MerkleHasher, N: MerkleHasher> {
+    legacy: MerkleTree<L>,
+    current: MerkleTree<N>,
+}
+
+impl<L: MerkleHasher, N: MerkleHasher> DualTree<L, N> {
+    /// Chunks `data` as `MerkleTree::new` does.
+    pub fn new(data: &[u8], leaves: usize) -> Self {
+        let chunk_size = data.len() / leaves;
+        let (mut legacy, mut current) = (L::Context::default(), N::Context::default());
+        let hashes = data
+            .chunks(chunk_size)
+            .take(leaves)
+            .map(|chunk| {
+                (
+                    L::hash_data_with(&mut legacy, chunk),
+                    N::hash_data_with(&mut current, chunk),
+                )
+            })
+            .collect();
+        DualTree::from_leaf_hashes(hashes, Padding::default())
+    }
+
+    pub fn from_leaves<I>(items: I) -> Option<Self>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        DualTree::from_leaves_padded(items, Padding::default())
+    }
+
+    /// Returns `None` if there are no items.
+    pub fn from_leaves_padded<I>(items: I, padding: Padding) -> Option<Self>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        let (mut legacy, mut current) = (L::Context::default(), N::Context::default());
+        let hashes: Vec<([u8; 32], [u8; 32])> = items
+            .into_iter()
+            .map(|item| {
+                (
+                    L::hash_data_with(&mut legacy, item.as_ref()),
+                    N::hash_data_with(&mut current, item.as_ref()),
+                )
+            })
+            .collect();
+        if hashes.is_empty() {
+            return None;
+        }
+        Some(DualTree::from_leaf_hashes(hashes, padding))
+    }
+
+    fn from_leaf_hashes(hashes: Vec<([u8; 32], [u8; 32])>, padding: Padding) -> Self {
+        let leaves = hashes.len();
+        let size = Layout::new(leaves)
+            .expect("trees have at least one leaf")
+            .nodes();
+        let mut legacy: Vec<Node> = Vec::with_capacity(size);
+        let mut current: Vec<Node> = Vec::with_capacity(size);
+        for (old, new) in hashes {
+            legacy.push(Node::new(old));
+            current.push(Node::new(new));
+        }
+        let (mut legacy_ctx, mut current_ctx) = (L::Context::default(), N::Context::default());
+        let mut start = 0;
+        let mut len = leaves;
+        while len > 1 {
+            for idx in 0..len / 2 {
+                let even = start + 2 * idx;
+                let old = L::hash_pair_with(
+                    &mut legacy_ctx,
+                    &legacy[even + 1].value,
+                    &legacy[even].value,
+                );
+                let new = N::hash_pair_with(
+                    &mut current_ctx,
+                    &current[even + 1].value,
+                    &current[even].value,
+                );
+                legacy.push(Node::new(old));
+                current.push(Node::new(new));
+            }
+            if len & 1 == 1 {
+                let lone = start + len - 1;
+                let old = padding.parent::<L>(&mut legacy_ctx, &legacy[lone].value);
+                let new = padding.parent::<N>(&mut current_ctx, &current[lone].value);
+                legacy.push(Node::new(old));
+                current.push(Node::new(new));
+            }
+            start += len;
+            len = len.div_ceil(2);
+        }
+        DualTree {
+            legacy: MerkleTree::from_nodes_padded(legacy, leaves, padding),
+            current: MerkleTree::from_nodes_padded(current, leaves, padding),
+        }
+    }
+
+    pub fn legacy(&self) -> &MerkleTree<L> {
+        &self.legacy
+    }
+
+    pub fn current(&self) -> &MerkleTree<N> {
+        &self.current
+    }
+
+    /// The legacy root, then the current one.
+    pub fn roots(&self) -> ([u8; 32], [u8; 32]) {
+        (self.legacy.root(), self.current.root())
+    }
+
+    /// Leaf `idx`'s legacy path, then its current one.
+    pub fn paths(&self, idx: usize) -> Result<(Path, Path), Error> {
+        Ok((self.legacy.path(idx)?, self.current.path(idx)?))
+    }
+
+    pub fn into_trees(self) -> (MerkleTree<L>, MerkleTree<N>) {
+        (self.legacy, self.current)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<L: MerkleHasher, N: MerkleHasher> HeapSize for DualTree<L, N> {
+    fn heap_size_bytes(&self) -> usize {
+        self.legacy.heap_size_bytes() + self.current.heap_size_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{verify_proof_with, Rfc6962};
+    use sha2::{Sha256, Sha512Trunc256};
+
+    #[test]
+    fn matches_separate_builds() {
+        let items: Vec<[u8; 2]> = (0..13u8).map(|i| [i, 13 - i]).collect();
+        for &padding in [Padding::Promote, Padding::DuplicateLast, Padding::Zero].iter() {
+            for len in [1, 2, 5, 8, 13] {
+                let dual: DualTree<Sha256, Sha512Trunc256> =
+                    DualTree::from_leaves_padded(&items[..len], padding).unwrap();
+                let old = MerkleTree::<Sha256>::from_leaves_padded(&items[..len], padding).unwrap();
+                let new =
+                    MerkleTree::<Sha512Trunc256>::build_from_leaves_padded(&items[..len], padding)
+                        .unwrap();
+                assert_eq!(dual.roots(), (old.root(), new.root()));
+                let (legacy, current) = dual.paths(len - 1).unwrap();
+                assert_eq!(legacy, old.path(len - 1).unwrap());
+                assert!(
+                    verify_proof_with::<Sha512Trunc256>(&items[len - 1], new.root(), &current)
+                        .is_valid()
+                );
+            }
+        }
+
+        let data = b"asdfjkln12345678zxcvqwer09876543";
+        let dual: DualTree<Sha256, Rfc6962> = DualTree::new(data, 8);
+        assert_eq!(dual.legacy().root(), MerkleTree::new(data, 8).root());
+        assert_eq!(
+            dual.current().root(),
+            MerkleTree::<Rfc6962>::build(data, 8).root()
+        );
+        assert_eq!(dual.paths(8), Err(Error::NotALeaf(8)));
+        assert!(DualTree::<Sha256, Rfc6962>::from_leaves(Vec::<&[u8]>::new()).is_none());
+    }
+}
@@ -0,0 +1,264 @@
+//! Rotating trees by epoch. Items are appended to an open epoch, which is
+//! sealed into a tree once it reaches a size threshold or outlives its
+//! schedule. Sealed epochs keep their tree and head, and every head is
+//! anchored in a [`HeadLog`] at the position of its epoch number.
+
+use crate::{hash_data, verify_proof, HeadLog, HeadProof, MerkleTree, Padding, Path, TreeHead};
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EpochPolicy {
+    /// Seal once the open epoch holds this many items.
+    pub max_leaves: usize,
+    /// Seal a non-empty epoch once it has been open this long.
+    pub max_age: Option<Duration>,
+}
+
+/// A sealed epoch; `items` is its tree's leaf count.
+pub struct Epoch {
+    pub number: u64,
+    pub items: usize,
+    pub tree: MerkleTree,
+    pub head: TreeHead,
+}
+
+/// Proof that an item was sealed into some epoch within a range: its path
+/// in that epoch's tree, plus the anchoring of the epoch's head in the
+/// head log. The proof names the epoch it found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EpochProof {
+    pub epoch: u64,
+    pub head: TreeHead,
+    pub path: Path,
+    pub anchor: HeadProof,
+}
+
+impl EpochProof {
+    /// Checks the proof against the head log root for an epoch in
+    /// `from..=to`.
+    pub fn verify(&self, item: &[u8], from: u64, to: u64, log_root: [u8; 32]) -> bool {
+        from <= self.epoch
+            && self.epoch <= to
+            && self.anchor.position == self.epoch
+            && self.anchor.verify(&self.head, log_root)
+            && verify_proof(item, self.head.root, &self.path).is_valid()
+    }
+}
+
+pub struct EpochManager {
+    policy: EpochPolicy,
+    current: u64,
+    opened: Instant,
+    pending: Vec<[u8; 32]>,
+    sealed: BTreeMap<u64, Epoch>,
+    heads: HeadLog,
+}
+
+impl EpochManager {
+    pub fn new(policy: EpochPolicy) -> Self {
+        EpochManager {
+            policy: EpochPolicy {
+                max_leaves: policy.max_leaves.max(1),
+                ..policy
+            },
+            current: 0,
+            opened: Instant::now(),
+            pending: Vec::new(),
+            sealed: BTreeMap::new(),
+            heads: HeadLog::new(),
+        }
+    }
+
+    /// Number of the open epoch.
+    pub fn current(&self) -> u64 {
+        self.current
+    }
+
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Appends an item to the open epoch and returns the epoch and leaf
+    /// index it will be proven under, sealing the epoch if it is now full.
+    pub fn append(&mut self, item: &[u8]) -> (u64, usize) {
+        let at = (self.current, self.pending.len());
+        self.pending.push(hash_data(item));
+        if self.pending.len() >= self.policy.max_leaves {
+            self.seal();
+        }
+        at
+    }
+
+    /// Seals the open epoch if it is older than the policy allows. Returns
+    /// the number of the epoch sealed, if any.
+    pub fn tick(&mut self, now: Instant) -> Option<u64> {
+        let max_age = self.policy.max_age?;
+        if now.saturating_duration_since(self.opened) < max_age {
+            return None;
+        }
+        self.seal()
+    }
+
+    /// Seals the open epoch now, unless it is empty.
+    pub fn seal(&mut self) -> Option<u64> {
+        if self.pending.is_empty() {
+            return None;
+        }
+        let hashes = std::mem::take(&mut self.pending);
+        let items = hashes.len();
+        let tree = MerkleTree::from_leaf_hashes(hashes, Padding::default());
+        let head = tree.head();
+        let number = self.current;
+        self.heads.append(&head);
+        self.sealed.insert(
+            number,
+            Epoch {
+                number,
+                items,
+                tree,
+                head,
+            },
+        );
+        self.current += 1;
+        self.opened = Instant::now();
+        Some(number)
+    }
+
+    pub fn epoch(&self, number: u64) -> Option<&Epoch> {
+        self.sealed.get(&number)
+    }
+
+    pub fn head(&self, number: u64) -> Option<TreeHead> {
+        self.epoch(number).map(|epoch| epoch.head)
+    }
+
+    /// Proof for an item in a sealed epoch. Items in the open epoch have no
+    /// proof until it is sealed.
+    pub fn proof(&self, number: u64, index: usize) -> Option<Path> {
+        let epoch = self.epoch(number)?;
+        if index >= epoch.items {
+            return None;
+        }
+        epoch.tree.path(index).ok()
+    }
+
+    /// Finds the earliest retained epoch in `from..=to` holding `item` and
+    /// proves its inclusion there against the current head log root.
+    pub fn prove_in_range(&self, item: &[u8], from: u64, to: u64) -> Option<EpochProof> {
+        if from > to {
+            return None;
+        }
+        let leaf = hash_data(item);
+        self.sealed.range(from..=to).find_map(|(&number, epoch)| {
+            let index = epoch.tree.nodes[..epoch.items]
+                .iter()
+                .position(|node| node.value == leaf)?;
+            Some(EpochProof {
+                epoch: number,
+                head: epoch.head,
+                path: epoch.tree.path(index).ok()?,
+                anchor: self.heads.prove(number)?,
+            })
+        })
+    }
+
+    /// Log of every sealed head, indexed by epoch number. Pruned epochs stay
+    /// anchored here.
+    pub fn heads(&self) -> &HeadLog {
+        &self.heads
+    }
+
+    /// Drops the trees of epochs before `number`.
+    pub fn prune_before(&mut self, number: u64) {
+        self.sealed = self.sealed.split_off(&number);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::verify_proof;
+
+    fn manager(max_leaves: usize, max_age: Option<Duration>) -> EpochManager {
+        EpochManager::new(EpochPolicy {
+            max_leaves,
+            max_age,
+        })
+    }
+
+    #[test]
+    fn rotates_on_size() {
+        let mut epochs = manager(4, None);
+        for i in 0..10u8 {
+            assert_eq!(epochs.append(&[i]), (u64::from(i / 4), usize::from(i % 4)));
+        }
+        assert_eq!(epochs.current(), 2);
+        assert_eq!(epochs.pending(), 2);
+        assert!(epochs.proof(2, 0).is_none());
+
+        let epoch = epochs.epoch(1).unwrap();
+        let proof = epochs.proof(1, 2).unwrap();
+        assert!(verify_proof(&[6], epoch.head.root, &proof).is_valid());
+        assert!(epochs.proof(1, 4).is_none());
+    }
+
+    #[test]
+    fn rotates_on_schedule() {
+        let mut epochs = manager(100, Some(Duration::from_secs(60)));
+        assert_eq!(epochs.tick(Instant::now() + Duration::from_secs(61)), None);
+        epochs.append(b"a");
+        epochs.append(b"b");
+        epochs.append(b"c");
+        assert_eq!(epochs.tick(Instant::now()), None);
+        assert_eq!(
+            epochs.tick(Instant::now() + Duration::from_secs(61)),
+            Some(0)
+        );
+
+        let epoch = epochs.epoch(0).unwrap();
+        assert_eq!((epoch.items, epoch.tree.leaves()), (3, 3));
+        assert!(verify_proof(b"c", epoch.head.root, &epochs.proof(0, 2).unwrap()).is_valid());
+        assert!(epochs.proof(0, 3).is_none());
+    }
+
+    #[test]
+    fn proves_presence_in_range() {
+        let mut epochs = manager(2, None);
+        for item in [b"a", b"b", b"c", b"d", b"e", b"c"].iter() {
+            epochs.append(*item);
+        }
+        let root = epochs.heads().root().unwrap();
+
+        let proof = epochs.prove_in_range(b"c", 0, 2).unwrap();
+        assert_eq!(proof.epoch, 1);
+        assert!(proof.verify(b"c", 0, 2, root));
+        assert!(proof.verify(b"c", 1, 1, root));
+        assert!(!proof.verify(b"c", 2, 2, root));
+        assert!(!proof.verify(b"d", 0, 2, root));
+
+        assert_eq!(epochs.prove_in_range(b"c", 2, 5).unwrap().epoch, 2);
+        assert!(epochs.prove_in_range(b"a", 1, 2).is_none());
+        assert!(epochs.prove_in_range(b"a", 1, 0).is_none());
+
+        let mut moved = proof;
+        moved.epoch = 2;
+        assert!(!moved.verify(b"c", 0, 2, root));
+    }
+
+    #[test]
+    fn pruned_heads_stay_anchored() {
+        let mut epochs = manager(2, None);
+        for i in 0..6u8 {
+            epochs.append(&[i]);
+        }
+        let head = epochs.head(0).unwrap();
+        epochs.prune_before(2);
+        assert!(epochs.epoch(1).is_none());
+        assert!(epochs.epoch(2).is_some());
+
+        let log = epochs.heads();
+        assert_eq!(log.len(), 3);
+        assert!(log.prove(0).unwrap().verify(&head, log.root().unwrap()));
+    }
+}
@@ -0,0 +1,42 @@
+use core::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The index is past the last node of the tree.
+    IndexOutOfRange { index: usize, size: usize },
+    /// The operation needs a leaf but was given an internal node.
+    NotALeaf(usize),
+    /// The operation needs an internal node but was given a leaf.
+    NotAnInternalNode(usize),
+    /// The root has no parent or sibling.
+    Root(usize),
+    /// The node is the last of an odd level and has no sibling.
+    NoSibling(usize),
+    /// The node is withheld in a `ThresholdTree` and was not recovered.
+    Sealed(usize),
+    /// A leaf range holds no leaves.
+    EmptyRange { start: usize, end: usize },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::IndexOutOfRange { index, size } => {
+                write!(
+                    f,
+                    "node index {} out of range for a tree of {} nodes",
+                    index, size
+                )
+            }
+            Error::NotALeaf(index) => write!(f, "node {} is not a leaf", index),
+            Error::NotAnInternalNode(index) => write!(f, "node {} is not an internal node", index),
+            Error::Root(index) => write!(f, "node {} is the root", index),
+            Error::NoSibling(index) => write!(f, "node {} has no sibling", index),
+            Error::Sealed(index) => write!(f, "node {} is sealed", index),
+            Error::EmptyRange { start, end } => write!(f, "leaf range {}..{} is empty", start, end),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
@@ -0,0 +1,96 @@
+//! Proof sizes computed from tree shape alone, counted in 32-byte hashes, so
+//! services can budget requests before building anything.
+
+use alloc::collections::BTreeSet;
+
+/// Sibling hashes in an inclusion proof for a tree of `leaves` leaves. Under
+/// `Padding::Promote` this is an upper bound; promoted levels add none.
+pub fn proof_hashes(leaves: usize) -> usize {
+    leaves.next_power_of_two().trailing_zeros() as usize
+}
+
+/// Hashes a deduplicated multiproof for `indices` needs: the siblings of
+/// every proven path that cannot be recomputed from the proven leaves. A
+/// lone node at the end of a level has no sibling in the proof under any
+/// padding, so this is exact for every tree. Returns `None` if an index is
+/// out of range.
+pub fn multiproof_hashes(indices: &[usize], leaves: usize) -> Option<usize> {
+    if indices.iter().any(|&idx| idx >= leaves) {
+        return None;
+    }
+    let mut level: BTreeSet<usize> = indices.iter().copied().collect();
+    let (mut hashes, mut len) = (0, leaves);
+    while len > 1 {
+        hashes += level
+            .iter()
+            .filter(|&&off| off ^ 1 < len && !level.contains(&(off ^ 1)))
+            .count();
+        level = level.iter().map(|off| off / 2).collect();
+        len = len.div_ceil(2);
+    }
+    Some(hashes)
+}
+
+/// Hashes in an RFC 6962 consistency proof between tree sizes `old` and
+/// `new`. Returns `None` if `old > new`.
+pub fn consistency_proof_hashes(old: usize, new: usize) -> Option<usize> {
+    fn subproof(m: usize, n: usize, complete: bool) -> usize {
+        if m == n {
+            return if complete { 0 } else { 1 };
+        }
+        let k = split_point(n);
+        if m <= k {
+            subproof(m, k, complete) + 1
+        } else {
+            subproof(m - k, n - k, false) + 1
+        }
+    }
+    match old {
+        _ if old > new => None,
+        0 => Some(0),
+        _ if old == new => Some(0),
+        _ => Some(subproof(old, new, true)),
+    }
+}
+
+/// Largest power of two strictly less than `n`, for `n > 1`.
+pub(crate) fn split_point(n: usize) -> usize {
+    1 << (usize::BITS - 1 - (n - 1).leading_zeros())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_proofs() {
+        assert_eq!(proof_hashes(1), 0);
+        assert_eq!(proof_hashes(8), 3);
+        assert_eq!(proof_hashes(5), 3);
+    }
+
+    #[test]
+    fn multiproofs_share_nodes() {
+        assert_eq!(multiproof_hashes(&[2], 8), Some(3));
+        assert_eq!(multiproof_hashes(&[2, 3], 8), Some(2));
+        assert_eq!(multiproof_hashes(&[0, 7], 8), Some(4));
+        assert_eq!(multiproof_hashes(&(0..8).collect::<Vec<_>>(), 8), Some(0));
+        assert_eq!(multiproof_hashes(&[8], 8), None);
+
+        // Levels of 5, 3, 2 and 1: leaf 4 is promoted twice.
+        assert_eq!(multiproof_hashes(&[4], 5), Some(1));
+        assert_eq!(multiproof_hashes(&[0], 5), Some(3));
+        assert_eq!(multiproof_hashes(&[3, 4], 5), Some(2));
+        assert_eq!(multiproof_hashes(&[5, 6], 7), Some(2));
+    }
+
+    #[test]
+    fn consistency_proofs() {
+        // Sizes from the RFC 6962 examples: 3 -> 7, 4 -> 7 and 6 -> 7.
+        assert_eq!(consistency_proof_hashes(3, 7), Some(4));
+        assert_eq!(consistency_proof_hashes(4, 7), Some(1));
+        assert_eq!(consistency_proof_hashes(6, 7), Some(3));
+        assert_eq!(consistency_proof_hashes(7, 7), Some(0));
+        assert_eq!(consistency_proof_hashes(8, 7), None);
+    }
+}
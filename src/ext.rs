@@ -0,0 +1,82 @@
+//! Extension fields for the crate's binary formats. A format that allows
+//! extensions ends with a run of fields, each a tag (u16 LE), a length
+//! (u32 LE) and that many bytes of value. Decoders skip tags they do not
+//! know, so producers can add fields without breaking deployed verifiers;
+//! a tag with the [`CRITICAL`] bit set must instead be understood, and an
+//! unknown critical tag rejects the whole input.
+
+use std::convert::TryInto;
+
+pub const CRITICAL: u16 = 0x8000;
+
+pub fn write_field(out: &mut Vec<u8>, tag: u16, value: &[u8]) -> Option<()> {
+    let len: u32 = value.len().try_into().ok()?;
+    out.extend_from_slice(&tag.to_le_bytes());
+    out.extend_from_slice(&len.to_le_bytes());
+    out.extend_from_slice(value);
+    Some(())
+}
+
+/// Iterates over the fields in `bytes`, yielding `None` once if the framing
+/// is truncated.
+pub fn fields(bytes: &[u8]) -> impl Iterator<Item = Option<(u16, &[u8])>> {
+    let mut rest = Some(bytes);
+    std::iter::from_fn(move || {
+        let bytes = rest.filter(|bytes| !bytes.is_empty())?;
+        let field = (|| {
+            let tag = u16::from_le_bytes(bytes.get(..2)?.try_into().unwrap());
+            let len = u32::from_le_bytes(bytes.get(2..6)?.try_into().unwrap());
+            let end = 6usize.checked_add(len.try_into().ok()?)?;
+            Some((tag, bytes.get(6..end)?, &bytes[end..]))
+        })();
+        rest = field.map(|(_, _, rest)| rest);
+        Some(field.map(|(tag, value, _)| (tag, value)))
+    })
+}
+
+/// Checks the framing, and that every critical tag is in `known`.
+pub fn check(bytes: &[u8], known: &[u16]) -> bool {
+    fields(bytes)
+        .all(|field| field.is_some_and(|(tag, _)| tag & CRITICAL == 0 || known.contains(&tag)))
+}
+
+/// The value of the first field tagged `tag`.
+pub fn find(bytes: &[u8], tag: u16) -> Option<&[u8]> {
+    fields(bytes)
+        .map_while(|field| field)
+        .find(|&(found, _)| found == tag)
+        .map(|(_, value)| value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skips_unknown_fields() {
+        let mut bytes = Vec::new();
+        write_field(&mut bytes, 7, b"seven").unwrap();
+        write_field(&mut bytes, 9, b"").unwrap();
+        assert!(check(&bytes, &[]));
+        assert_eq!(find(&bytes, 7), Some(&b"seven"[..]));
+        assert_eq!(find(&bytes, 9), Some(&b""[..]));
+        assert_eq!(find(&bytes, 8), None);
+        assert!(check(&[], &[]));
+    }
+
+    #[test]
+    fn rejects_unknown_critical_fields() {
+        let mut bytes = Vec::new();
+        write_field(&mut bytes, CRITICAL | 1, b"must understand").unwrap();
+        assert!(!check(&bytes, &[]));
+        assert!(check(&bytes, &[CRITICAL | 1]));
+    }
+
+    #[test]
+    fn rejects_truncated_fields() {
+        let mut bytes = Vec::new();
+        write_field(&mut bytes, 7, b"seven").unwrap();
+        assert!(!check(&bytes[..bytes.len() - 1], &[]));
+        assert!(!check(&bytes[..3], &[]));
+    }
+}
@@ -0,0 +1,199 @@
+//! Post-processing applied to a tree root before it is published. The
+//! built-in steps each pair the root with one extra chunk, so a proof
+//! against the tree root extends to the finalized root by one path entry.
+//! [`Finalizers`] chains steps in order, and [`verify_finalized`] replays
+//! the same chain when checking a proof.
+
+use crate::{hash_data, hash_pair, MerkleTree};
+
+pub trait Finalizer {
+    fn finalize(&self, root: [u8; 32]) -> [u8; 32];
+}
+
+impl<F: Fn([u8; 32]) -> [u8; 32]> Finalizer for F {
+    fn finalize(&self, root: [u8; 32]) -> [u8; 32] {
+        self(root)
+    }
+}
+
+/// `le_u256(len)`: the length as a little-endian 256-bit integer.
+pub fn length_chunk(len: u64) -> [u8; 32] {
+    let mut chunk = [0u8; 32];
+    chunk[..8].copy_from_slice(&len.to_le_bytes());
+    chunk
+}
+
+/// SSZ `mix_in_length`: `H(root || le_u256(len))`. Binding the element count
+/// keeps a list distinct from the same list with padding appended.
+pub fn mix_in_length(root: &[u8; 32], len: u64) -> [u8; 32] {
+    hash_pair(root, &length_chunk(len))
+}
+
+/// Path entry extending a proof against the tree root to the mixed-in root.
+pub fn length_entry(len: u64) -> ([u8; 32], bool) {
+    (length_chunk(len), true)
+}
+
+/// Mixes in the element count, as [`mix_in_length`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MixInLength(pub u64);
+
+impl Finalizer for MixInLength {
+    fn finalize(&self, root: [u8; 32]) -> [u8; 32] {
+        mix_in_length(&root, self.0)
+    }
+}
+
+/// `H(H(tag) || root)`: separates roots of different protocols.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DomainTag([u8; 32]);
+
+impl DomainTag {
+    pub fn new(tag: &[u8]) -> Self {
+        DomainTag(hash_data(tag))
+    }
+}
+
+impl Finalizer for DomainTag {
+    fn finalize(&self, root: [u8; 32]) -> [u8; 32] {
+        hash_pair(&self.0, &root)
+    }
+}
+
+/// `H(root || H(metadata))`: binds application metadata to the root.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BindMetadata([u8; 32]);
+
+impl BindMetadata {
+    pub fn new(metadata: &[u8]) -> Self {
+        BindMetadata(hash_data(metadata))
+    }
+}
+
+impl Finalizer for BindMetadata {
+    fn finalize(&self, root: [u8; 32]) -> [u8; 32] {
+        hash_pair(&root, &self.0)
+    }
+}
+
+/// An ordered chain of finalizers, applied first to last.
+#[derive(Default)]
+pub struct Finalizers {
+    steps: Vec<Box<dyn Finalizer + Send + Sync>>,
+}
+
+impl Finalizers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn then<F: Finalizer + Send + Sync + 'static>(mut self, step: F) -> Self {
+        self.steps.push(Box::new(step));
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+}
+
+impl Finalizer for Finalizers {
+    fn finalize(&self, root: [u8; 32]) -> [u8; 32] {
+        self.steps
+            .iter()
+            .fold(root, |root, step| step.finalize(root))
+    }
+}
+
+impl MerkleTree {
+    pub fn finalized_root<F: Finalizer + ?Sized>(&self, finalizer: &F) -> [u8; 32] {
+        finalizer.finalize(self.root())
+    }
+}
+
+/// Like `verify_proof`, with `root` a finalized root.
+pub fn verify_finalized<F: Finalizer + ?Sized>(
+    item: &[u8],
+    root: [u8; 32],
+    proof: &[([u8; 32], bool)],
+    finalizer: &F,
+) -> bool {
+    let mut candidate = hash_data(item);
+    for (hash, odd) in proof {
+        candidate = if *odd {
+            hash_pair(&candidate, hash)
+        } else {
+            hash_pair(hash, &candidate)
+        };
+    }
+    finalizer.finalize(candidate) == root
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{verify_proof, MerkleTree};
+
+    #[test]
+    fn distinguishes_padding() {
+        let tree = MerkleTree::new(b"AB\0\0", 4);
+        assert_ne!(
+            mix_in_length(&tree.root(), 2),
+            mix_in_length(&tree.root(), 3)
+        );
+        assert_eq!(length_chunk(0x0102), {
+            let mut chunk = [0u8; 32];
+            chunk[0] = 2;
+            chunk[1] = 1;
+            chunk
+        });
+    }
+
+    #[test]
+    fn extends_proofs() {
+        let tree = MerkleTree::new(b"AB\0\0", 4);
+        let mut path = tree.path(1).unwrap();
+        path.push(length_entry(2));
+        assert!(verify_proof(b"B", mix_in_length(&tree.root(), 2), &path).is_valid());
+        assert!(!verify_proof(b"B", mix_in_length(&tree.root(), 3), &path).is_valid());
+    }
+
+    #[test]
+    fn chains_in_order() {
+        let tree = MerkleTree::new(b"AB\0\0", 4);
+        let finalizers = Finalizers::new()
+            .then(MixInLength(2))
+            .then(DomainTag::new(b"example/v1"))
+            .then(BindMetadata::new(b"epoch=7"));
+        assert_eq!(finalizers.len(), 3);
+
+        let expected = hash_pair(
+            &hash_pair(&hash_data(b"example/v1"), &mix_in_length(&tree.root(), 2)),
+            &hash_data(b"epoch=7"),
+        );
+        let root = tree.finalized_root(&finalizers);
+        assert_eq!(root, expected);
+
+        let path = tree.path(0).unwrap();
+        assert!(verify_finalized(b"A", root, &path, &finalizers));
+        assert!(!verify_finalized(b"A", tree.root(), &path, &finalizers));
+
+        let reordered = Finalizers::new()
+            .then(DomainTag::new(b"example/v1"))
+            .then(MixInLength(2))
+            .then(BindMetadata::new(b"epoch=7"));
+        assert_ne!(tree.finalized_root(&reordered), root);
+    }
+
+    #[test]
+    fn accepts_closures() {
+        let tree = MerkleTree::new(b"AB\0\0", 4);
+        let finalizers = Finalizers::new().then(|root: [u8; 32]| hash_data(&root));
+        assert_eq!(tree.finalized_root(&finalizers), hash_data(&tree.root()));
+        assert_eq!(tree.finalized_root(&Finalizers::new()), tree.root());
+    }
+}
@@ -0,0 +1,115 @@
+//! Version numbers for the crate's own binary formats (compact paths, Bloom
+//! filter encodings, Borsh trees). Every artifact starts with its version
+//! byte; decoders reject versions they do not know instead of guessing.
+//! The DER profile is standard ASN.1 and evolves through its module instead.
+//! Formats with room for growth carry optional fields as described in
+//! [`crate::ext`], so adding one does not need a new version.
+//!
+//! Artifacts written before versioning was introduced are version 0. Their
+//! first byte is data, so they cannot be detected and callers must say
+//! which version they stored.
+//!
+//! With the `strict` feature the build reads and negotiates only
+//! [`CURRENT`]: legacy inputs are rejected rather than upgraded, so
+//! consensus code cannot come to depend on which versions a peer offers.
+
+use alloc::vec::Vec;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Version {
+    /// Unversioned layouts from before format versioning.
+    V0 = 0,
+    V1 = 1,
+}
+
+pub const CURRENT: Version = Version::V1;
+
+/// Versions this build can read, oldest first.
+#[cfg(not(feature = "strict"))]
+pub const SUPPORTED: &[Version] = &[Version::V0, Version::V1];
+#[cfg(feature = "strict")]
+pub const SUPPORTED: &[Version] = &[CURRENT];
+
+impl Version {
+    pub fn from_byte(byte: u8) -> Option<Version> {
+        match byte {
+            0 => Some(Version::V0),
+            1 => Some(Version::V1),
+            _ => None,
+        }
+    }
+
+    pub fn byte(self) -> u8 {
+        self as u8
+    }
+}
+
+/// Highest version both sides can write and read, given the version bytes a
+/// peer advertises. Version 0 has no header and is never negotiated.
+pub fn negotiate(peer: &[u8]) -> Option<Version> {
+    peer.iter()
+        .filter_map(|&byte| Version::from_byte(byte))
+        .filter(|&version| version != Version::V0 && SUPPORTED.contains(&version))
+        .max()
+}
+
+/// Re-encodes a compact proof written in version `from` in the current
+/// format. Returns `None` if the input does not parse as that version.
+pub fn upgrade_proof(bytes: &[u8], from: Version) -> Option<Vec<u8>> {
+    if !SUPPORTED.contains(&from) {
+        return None;
+    }
+    let path = match from {
+        Version::V0 => crate::compact::decode_path_v0(bytes)?,
+        Version::V1 => crate::compact::decode_path(bytes)?,
+    };
+    match path {
+        (path, []) => crate::compact::encode_path(&path),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compact, MerkleTree};
+
+    #[test]
+    fn negotiation() {
+        assert_eq!(negotiate(&[1, 7]), Some(Version::V1));
+        assert_eq!(negotiate(&[0]), None);
+        assert_eq!(negotiate(&[9]), None);
+    }
+
+    #[test]
+    #[cfg(not(feature = "strict"))]
+    fn upgrades_legacy_proofs() {
+        let tree = MerkleTree::new(b"asdfjkln12345678", 4);
+        let path = tree.path(3).unwrap();
+        let mut legacy = vec![2u8, 0b11];
+        legacy.extend(path.iter().flat_map(|(hash, _)| hash.to_vec()));
+
+        let upgraded = upgrade_proof(&legacy, Version::V0).unwrap();
+        assert_eq!(upgraded[0], CURRENT.byte());
+        assert_eq!(compact::decode_path(&upgraded), Some((path, &[][..])));
+        assert_eq!(
+            upgrade_proof(&upgraded, Version::V1),
+            Some(upgraded.clone())
+        );
+        assert!(upgrade_proof(&upgraded, Version::V0).is_none());
+    }
+
+    #[test]
+    #[cfg(feature = "strict")]
+    fn strict_rejects_legacy_proofs() {
+        let tree = MerkleTree::new(b"asdfjkln12345678", 4);
+        let path = tree.path(3).unwrap();
+        let mut legacy = vec![2u8, 0b11];
+        legacy.extend(path.iter().flat_map(|(hash, _)| hash.to_vec()));
+
+        assert_eq!(SUPPORTED, &[CURRENT]);
+        assert!(upgrade_proof(&legacy, Version::V0).is_none());
+        let current = compact::encode_path(&path).unwrap();
+        assert_eq!(upgrade_proof(&current, CURRENT), Some(current.clone()));
+    }
+}
@@ -0,0 +1,526 @@
+#[cfg(feature = "std")]
+use crate::{verify_proof_in, VerificationOutcome};
+use crate::{Layout, MerkleTree, Node, Padding};
+use alloc::vec::Vec;
+use core::fmt;
+use core::marker::PhantomData;
+use sha2::digest::consts::U32;
+use sha2::digest::generic_array::typenum::{IsGreaterOrEqual, True};
+use sha2::{Digest, Sha256};
+#[cfg(feature = "std")]
+use std::sync::Mutex;
+
+/// Hash function used for leaves and internal nodes. Implemented for every
+/// `Digest` with a 32-byte output; wider digests can be used through
+/// `Truncated`.
+///
+/// The `_with` methods hash through a caller-held `Context`, which is reset
+/// rather than rebuilt after each hash; tree construction and proof
+/// verification keep one context for the whole job.
+pub trait MerkleHasher {
+    type Context: Default;
+
+    fn hash_data_with(ctx: &mut Self::Context, data: &[u8]) -> [u8; 32];
+    fn hash_pair_with(ctx: &mut Self::Context, left: &[u8; 32], right: &[u8; 32]) -> [u8; 32];
+
+    fn hash_data(data: &[u8]) -> [u8; 32] {
+        Self::hash_data_with(&mut Self::Context::default(), data)
+    }
+
+    fn hash_pair(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+        Self::hash_pair_with(&mut Self::Context::default(), left, right)
+    }
+}
+
+impl<D: Digest<OutputSize = U32> + Default> MerkleHasher for D {
+    type Context = D;
+
+    fn hash_data_with(ctx: &mut D, data: &[u8]) -> [u8; 32] {
+        ctx.update(data);
+        ctx.finalize_reset().into()
+    }
+
+    fn hash_pair_with(ctx: &mut D, left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+        ctx.update(left);
+        ctx.update(right);
+        ctx.finalize_reset().into()
+    }
+}
+
+/// Adapter keeping the first 32 bytes of a wider digest, e.g.
+/// `Truncated<Sha512>`.
+pub struct Truncated<D>(PhantomData<D>);
+
+fn truncate(digest: &[u8]) -> [u8; 32] {
+    let mut out = [0; 32];
+    out.copy_from_slice(&digest[..32]);
+    out
+}
+
+impl<D> MerkleHasher for Truncated<D>
+where
+    D: Digest + Default,
+    D::OutputSize: IsGreaterOrEqual<U32, Output = True>,
+{
+    type Context = D;
+
+    fn hash_data_with(ctx: &mut D, data: &[u8]) -> [u8; 32] {
+        ctx.update(data);
+        truncate(&ctx.finalize_reset())
+    }
+
+    fn hash_pair_with(ctx: &mut D, left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+        ctx.update(left);
+        ctx.update(right);
+        truncate(&ctx.finalize_reset())
+    }
+}
+
+/// Leaves commit to `H(H(data))`; internal nodes hash as with `H`. A tree
+/// built with this hasher over raw items equals a `MerkleTree<H>` built over
+/// their first hashes, so a party holding only `H(data)` can build the tree
+/// and serve proofs (checked with `verify_proof_with::<H>` against the
+/// digest) while end users verify the raw data with
+/// `verify_proof_with::<DoubleHashed<H>>`.
+pub struct DoubleHashed<H>(PhantomData<H>);
+
+impl<H: MerkleHasher> MerkleHasher for DoubleHashed<H> {
+    type Context = H::Context;
+
+    fn hash_data_with(ctx: &mut H::Context, data: &[u8]) -> [u8; 32] {
+        let first = H::hash_data_with(ctx, data);
+        H::hash_data_with(ctx, &first)
+    }
+
+    fn hash_pair_with(ctx: &mut H::Context, left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+        H::hash_pair_with(ctx, left, right)
+    }
+}
+
+/// Domain-separated SHA-256 as in RFC 6962: leaves are `H(0x00 || data)`
+/// and pairs `H(0x01 || left || right)`.
+///
+/// With plain `Sha256` a leaf and a node hash the same way, so the 64 bytes
+/// under any internal node pass as a leaf and prove with the node's path.
+/// `MerkleTree<Rfc6962>` rules that out; `Sha256` stays the default so
+/// existing roots are reproduced unchanged.
+pub struct Rfc6962;
+
+impl MerkleHasher for Rfc6962 {
+    type Context = Sha256;
+
+    fn hash_data_with(ctx: &mut Sha256, data: &[u8]) -> [u8; 32] {
+        ctx.update([0]);
+        ctx.update(data);
+        ctx.finalize_reset().into()
+    }
+
+    fn hash_pair_with(ctx: &mut Sha256, left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+        ctx.update([1]);
+        ctx.update(left);
+        ctx.update(right);
+        ctx.finalize_reset().into()
+    }
+}
+
+/// Leaves hashed with `L` and internal nodes (padding included) with `N`,
+/// e.g. a fast leaf hash under internals that a contract can recompute.
+pub struct Hybrid<L, N>(PhantomData<(L, N)>);
+
+impl<L: MerkleHasher, N: MerkleHasher> MerkleHasher for Hybrid<L, N> {
+    type Context = (L::Context, N::Context);
+
+    fn hash_data_with(ctx: &mut Self::Context, data: &[u8]) -> [u8; 32] {
+        L::hash_data_with(&mut ctx.0, data)
+    }
+
+    fn hash_pair_with(ctx: &mut Self::Context, left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+        N::hash_pair_with(&mut ctx.1, left, right)
+    }
+}
+
+/// Idle contexts shared between threads, for services hashing on many
+/// threads at once. A context is taken for the length of one `with` call,
+/// or set up if none is idle, and handed back afterwards.
+#[cfg(feature = "std")]
+pub struct ContextPool<H: MerkleHasher> {
+    idle: Mutex<Vec<H::Context>>,
+}
+
+#[cfg(feature = "std")]
+impl<H: MerkleHasher> ContextPool<H> {
+    pub fn new() -> Self {
+        ContextPool {
+            idle: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Contexts currently idle in the pool.
+    pub fn idle(&self) -> usize {
+        self.idle.lock().unwrap().len()
+    }
+
+    pub fn with<R, F: FnOnce(&mut H::Context) -> R>(&self, f: F) -> R {
+        let popped = self.idle.lock().unwrap().pop();
+        let mut ctx = popped.unwrap_or_default();
+        let result = f(&mut ctx);
+        self.idle.lock().unwrap().push(ctx);
+        result
+    }
+
+    /// `verify_proof_with::<H>` on a pooled context.
+    pub fn verify_proof(
+        &self,
+        item: &[u8],
+        root: [u8; 32],
+        proof: &[([u8; 32], bool)],
+    ) -> VerificationOutcome {
+        self.with(|ctx| verify_proof_in::<H>(ctx, item, root, proof))
+    }
+}
+
+#[cfg(feature = "std")]
+impl<H: MerkleHasher> Default for ContextPool<H> {
+    fn default() -> Self {
+        ContextPool::new()
+    }
+}
+
+impl<H: MerkleHasher> MerkleTree<DoubleHashed<H>> {
+    /// Like `proof`, given the item's first hash instead of the item.
+    pub fn proof_by_digest(&self, digest: &[u8; 32], idx: usize) -> Option<crate::Path> {
+        if self.node(idx).ok()? != H::hash_data(digest) {
+            return None;
+        }
+        self.path(idx).ok()
+    }
+}
+
+/// Hasher living outside the process, such as an HSM reached over PKCS#11.
+/// Requests are batched per tree level and may fail. Implementations must
+/// compute the same function as `MerkleHasher::hash_data`/`hash_pair` for
+/// the tree's hasher, or proofs will not verify.
+pub trait BatchHasher {
+    type Error;
+
+    fn hash_leaves(&mut self, items: &[&[u8]]) -> Result<Vec<[u8; 32]>, Self::Error>;
+
+    /// Hashes each `(first, second)` pair in order.
+    fn hash_pairs(&mut self, pairs: &[([u8; 32], [u8; 32])]) -> Result<Vec<[u8; 32]>, Self::Error>;
+}
+
+/// Runs a `MerkleHasher` in-process behind the `BatchHasher` interface.
+pub struct Local<H>(PhantomData<H>);
+
+impl<H> Default for Local<H> {
+    fn default() -> Self {
+        Local(PhantomData)
+    }
+}
+
+impl<H: MerkleHasher> BatchHasher for Local<H> {
+    type Error = core::convert::Infallible;
+
+    fn hash_leaves(&mut self, items: &[&[u8]]) -> Result<Vec<[u8; 32]>, Self::Error> {
+        let mut ctx = H::Context::default();
+        Ok(items
+            .iter()
+            .map(|item| H::hash_data_with(&mut ctx, item))
+            .collect())
+    }
+
+    fn hash_pairs(&mut self, pairs: &[([u8; 32], [u8; 32])]) -> Result<Vec<[u8; 32]>, Self::Error> {
+        let mut ctx = H::Context::default();
+        Ok(pairs
+            .iter()
+            .map(|(first, second)| H::hash_pair_with(&mut ctx, first, second))
+            .collect())
+    }
+}
+
+/// Why `MerkleTree::new_with_batch_hasher` failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BatchError<E> {
+    /// The batch hasher returned an error.
+    Hasher(E),
+    /// `leaves` is zero or more than `data` has bytes.
+    NoLeaves,
+    /// A request for `expected` hashes was answered with `got`.
+    WrongCount { expected: usize, got: usize },
+}
+
+impl<E: fmt::Display> fmt::Display for BatchError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BatchError::Hasher(err) => write!(f, "batch hasher failed: {}", err),
+            BatchError::NoLeaves => write!(f, "no leaves to hash"),
+            BatchError::WrongCount { expected, got } => {
+                write!(f, "asked for {} hashes, got {}", expected, got)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<E: fmt::Debug + fmt::Display> std::error::Error for BatchError<E> {}
+
+fn batch<E>(
+    reply: Result<Vec<[u8; 32]>, E>,
+    expected: usize,
+) -> Result<Vec<[u8; 32]>, BatchError<E>> {
+    let hashes = reply.map_err(BatchError::Hasher)?;
+    if hashes.len() != expected {
+        return Err(BatchError::WrongCount {
+            expected,
+            got: hashes.len(),
+        });
+    }
+    Ok(hashes)
+}
+
+impl MerkleTree {
+    /// Same tree as `MerkleTree::new`, with one hashing request for the
+    /// leaves and one per level of internal nodes. A lone node's padded
+    /// pair goes in its level's request.
+    pub fn new_with_batch_hasher<B: BatchHasher>(
+        data: &[u8],
+        leaves: usize,
+        hasher: &mut B,
+    ) -> Result<Self, BatchError<B::Error>> {
+        let chunk_size = match data.len().checked_div(leaves) {
+            Some(size) if size > 0 => size,
+            _ => return Err(BatchError::NoLeaves),
+        };
+        let layout = Layout::new(leaves).ok_or(BatchError::NoLeaves)?;
+        let padding = Padding::default();
+        let chunks: Vec<&[u8]> = data.chunks(chunk_size).take(leaves).collect();
+        let mut nodes: Vec<Node> = Vec::with_capacity(layout.nodes());
+        nodes.extend(
+            batch(hasher.hash_leaves(&chunks), leaves)?
+                .into_iter()
+                .map(Node::new),
+        );
+        for level in 0..layout.levels() - 1 {
+            let start = layout.node_index(level, 0).expect("level is in the tree");
+            let len = layout.level_size(level).expect("level is in the tree");
+            let mut pairs: Vec<([u8; 32], [u8; 32])> = (0..len / 2)
+                .map(|i| {
+                    let even = start + 2 * i;
+                    (nodes[even + 1].value, nodes[even].value)
+                })
+                .collect();
+            let mut promoted = None;
+            if len & 1 == 1 {
+                let lone = nodes[start + len - 1].value;
+                match padding.sibling(&lone) {
+                    Some(sibling) => pairs.push((sibling, lone)),
+                    None => promoted = Some(lone),
+                }
+            }
+            let parents = batch(hasher.hash_pairs(&pairs), pairs.len())?;
+            nodes.extend(parents.into_iter().chain(promoted).map(Node::new));
+        }
+        Ok(MerkleTree::from_nodes_padded(nodes, leaves, padding))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sha2::{Sha256, Sha512, Sha512Trunc256};
+
+    #[test]
+    fn digest_hashers() {
+        assert_eq!(Sha256::hash_data(b"abc"), crate::hash_data(b"abc"));
+        assert_eq!(
+            Sha256::hash_pair(&[1; 32], &[2; 32]),
+            crate::hash_pair(&[1; 32], &[2; 32])
+        );
+        assert_ne!(Sha512Trunc256::hash_data(b"abc"), Sha256::hash_data(b"abc"));
+    }
+
+    #[test]
+    fn domain_separation() {
+        fn forge<H: MerkleHasher>() -> bool {
+            let tree = MerkleTree::<H>::build(b"asdfjkln12345678", 4);
+            let (first, second) = (tree.node(0).unwrap(), tree.node(1).unwrap());
+            let fake_leaf = [second, first].concat();
+            let path = vec![(tree.node(5).unwrap(), false)];
+            crate::verify_proof_with::<H>(&fake_leaf, tree.root(), &path).is_valid()
+        }
+        assert!(forge::<Sha256>());
+        assert!(!forge::<Rfc6962>());
+        assert_eq!(
+            Rfc6962::hash_pair(&[1; 32], &[2; 32]),
+            crate::log::node_hash(&[1; 32], &[2; 32])
+        );
+    }
+
+    #[test]
+    fn reused_contexts() {
+        let mut ctx = Sha256::default();
+        for _ in 0..3 {
+            assert_eq!(
+                Sha256::hash_data_with(&mut ctx, b"abc"),
+                Sha256::hash_data(b"abc")
+            );
+            assert_eq!(
+                Sha256::hash_pair_with(&mut ctx, &[1; 32], &[2; 32]),
+                Sha256::hash_pair(&[1; 32], &[2; 32])
+            );
+        }
+        let mut wide = Sha512::default();
+        let _ = Truncated::<Sha512>::hash_data_with(&mut wide, b"x");
+        assert_eq!(
+            Truncated::<Sha512>::hash_data_with(&mut wide, b"abc"),
+            Truncated::<Sha512>::hash_data(b"abc")
+        );
+
+        let tree = MerkleTree::new(b"asdfjkln12345678", 4);
+        let pool = ContextPool::<Sha256>::new();
+        std::thread::scope(|scope| {
+            for idx in 0..4 {
+                let (pool, tree) = (&pool, &tree);
+                scope.spawn(move || {
+                    let item = &b"asdfjkln12345678"[4 * idx..4 * idx + 4];
+                    let path = tree.path(idx).unwrap();
+                    assert!(pool.verify_proof(item, tree.root(), &path).is_valid());
+                    assert!(!pool.verify_proof(b"nope", tree.root(), &path).is_valid());
+                });
+            }
+        });
+        assert!((1..=4).contains(&pool.idle()));
+    }
+
+    struct CountingHsm {
+        requests: usize,
+        fail_after: usize,
+    }
+
+    impl BatchHasher for CountingHsm {
+        type Error = &'static str;
+
+        fn hash_leaves(&mut self, items: &[&[u8]]) -> Result<Vec<[u8; 32]>, Self::Error> {
+            self.requests += 1;
+            Local::<Sha256>::default()
+                .hash_leaves(items)
+                .map_err(|_| unreachable!())
+        }
+
+        fn hash_pairs(
+            &mut self,
+            pairs: &[([u8; 32], [u8; 32])],
+        ) -> Result<Vec<[u8; 32]>, Self::Error> {
+            self.requests += 1;
+            if self.requests > self.fail_after {
+                return Err("session closed");
+            }
+            Local::<Sha256>::default()
+                .hash_pairs(pairs)
+                .map_err(|_| unreachable!())
+        }
+    }
+
+    #[test]
+    fn batch_hasher_builds_same_tree() {
+        let data = b"asdfasdfasdfasdfasdfasdfasdfasdf";
+        let mut hsm = CountingHsm {
+            requests: 0,
+            fail_after: usize::MAX,
+        };
+        let tree = MerkleTree::new_with_batch_hasher(data, 8, &mut hsm).unwrap();
+        assert_eq!(tree.root(), MerkleTree::new(data, 8).root());
+        assert_eq!(tree.size(), 15);
+        assert_eq!(hsm.requests, 4);
+
+        let mut failing = CountingHsm {
+            requests: 0,
+            fail_after: 2,
+        };
+        assert_eq!(
+            MerkleTree::new_with_batch_hasher(data, 8, &mut failing).err(),
+            Some(BatchError::Hasher("session closed"))
+        );
+    }
+
+    #[test]
+    fn batch_hasher_any_leaf_count() {
+        // The trailing "ij" is dropped, as `MerkleTree::new` drops it.
+        let data = b"abcdefghij";
+        for leaves in [3, 4, 5] {
+            let tree =
+                MerkleTree::new_with_batch_hasher(data, leaves, &mut Local::<Sha256>::default())
+                    .unwrap();
+            let expected = MerkleTree::new(data, leaves);
+            assert_eq!(tree.size(), expected.size());
+            assert_eq!(tree.root(), expected.root());
+            let chunk = data.len() / leaves;
+            let path = tree.path(leaves - 1).unwrap();
+            let item = &data[(leaves - 1) * chunk..leaves * chunk];
+            assert!(crate::verify_proof(item, tree.root(), &path).is_valid());
+        }
+
+        struct Short;
+        impl BatchHasher for Short {
+            type Error = core::convert::Infallible;
+
+            fn hash_leaves(&mut self, items: &[&[u8]]) -> Result<Vec<[u8; 32]>, Self::Error> {
+                Local::<Sha256>::default().hash_leaves(items)
+            }
+
+            fn hash_pairs(
+                &mut self,
+                pairs: &[([u8; 32], [u8; 32])],
+            ) -> Result<Vec<[u8; 32]>, Self::Error> {
+                Ok(Local::<Sha256>::default().hash_pairs(pairs)?[1..].to_vec())
+            }
+        }
+        assert_eq!(
+            MerkleTree::new_with_batch_hasher(data, 4, &mut Short).err(),
+            Some(BatchError::WrongCount {
+                expected: 2,
+                got: 1
+            })
+        );
+        let mut local = Local::<Sha256>::default();
+        for leaves in [0, 11] {
+            assert_eq!(
+                MerkleTree::new_with_batch_hasher(data, leaves, &mut local).err(),
+                Some(BatchError::NoLeaves)
+            );
+        }
+    }
+
+    #[test]
+    fn truncated_hashers() {
+        let full = Sha512::digest(b"abc");
+        assert_eq!(Truncated::<Sha512>::hash_data(b"abc")[..], full[..32]);
+        assert_ne!(
+            Truncated::<Sha512>::hash_data(b"abc"),
+            Sha512Trunc256::hash_data(b"abc")
+        );
+        assert_eq!(
+            Truncated::<Sha256>::hash_data(b"abc"),
+            Sha256::hash_data(b"abc")
+        );
+    }
+
+    #[test]
+    fn double_hashed_leaves() {
+        use crate::{verify_proof, verify_proof_with};
+
+        let items = [&b"alice"[..], b"bob", b"carol", b"dave"];
+        let digests: Vec<[u8; 32]> = items.iter().map(|item| Sha256::hash_data(item)).collect();
+        let tree = MerkleTree::<DoubleHashed<Sha256>>::build_from_leaves(items.iter()).unwrap();
+        let intermediary = MerkleTree::from_leaves(&digests).unwrap();
+        assert_eq!(tree.root(), intermediary.root());
+
+        let path = intermediary.proof(&digests[2], 2).unwrap();
+        assert!(verify_proof(&digests[2], tree.root(), &path).is_valid());
+        assert!(verify_proof_with::<DoubleHashed<Sha256>>(b"carol", tree.root(), &path).is_valid());
+        assert!(!verify_proof(b"carol", tree.root(), &path).is_valid());
+        assert_eq!(tree.proof(b"carol", 2), Some(path.clone()));
+        assert_eq!(tree.proof_by_digest(&digests[2], 2), Some(path));
+        assert_eq!(tree.proof_by_digest(&digests[1], 2), None);
+    }
+}
@@ -0,0 +1,35 @@
+//! A tree's published summary, its leaf count and root. Heads travel on
+//! their own, in signed checkpoints and on chain, so they are available
+//! without `std`.
+
+use crate::{hash_data, MerkleTree};
+
+/// A published tree head: the leaf count and the root at that size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "scale",
+    derive(parity_scale_codec::Encode, parity_scale_codec::Decode)
+)]
+pub struct TreeHead {
+    pub size: u64,
+    pub root: [u8; 32],
+}
+
+impl TreeHead {
+    /// `H(size_le || root)`, the leaf committed to a `HeadLog`.
+    pub fn hash(&self) -> [u8; 32] {
+        let mut bytes = [0u8; 40];
+        bytes[..8].copy_from_slice(&self.size.to_le_bytes());
+        bytes[8..].copy_from_slice(&self.root);
+        hash_data(&bytes)
+    }
+}
+
+impl MerkleTree {
+    pub fn head(&self) -> TreeHead {
+        TreeHead {
+            size: self.leaves() as u64,
+            root: self.root(),
+        }
+    }
+}
@@ -0,0 +1,251 @@
+//! Commitments to aggregate counts, for publishing statistics that can be
+//! checked bucket by bucket without releasing the records behind them.
+//! Each leaf is one bucket, its label and count, and leaves are in label
+//! order, so the same counts always give the same root.
+//!
+//! A [`Noise`] hook sees each count before it is committed and returns the
+//! count to release, which is where a differentially private mechanism
+//! adds its noise. Only the released counts are committed: proofs show
+//! what was published, not the true counts, and the noise is not
+//! reproducible from the commitment.
+
+use crate::memory::{vec_bytes, HeapSize};
+use crate::{MerkleHasher, MerkleTree, Padding, Proof, TreeHead};
+use sha2::Sha256;
+use std::collections::BTreeMap;
+
+/// Perturbs a bucket's count before it is committed.
+pub trait Noise {
+    /// The count to release for `label` in place of `count`. Mechanisms
+    /// whose noise can go below zero clamp or offset it here.
+    fn perturb(&mut self, label: &[u8], count: u64) -> u64;
+}
+
+impl<F: FnMut(&[u8], u64) -> u64> Noise for F {
+    fn perturb(&mut self, label: &[u8], count: u64) -> u64 {
+        self(label, count)
+    }
+}
+
+/// Releases counts unchanged.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Exact;
+
+impl Noise for Exact {
+    fn perturb(&mut self, _label: &[u8], count: u64) -> u64 {
+        count
+    }
+}
+
+/// `"merkle-bucket" || u32 LE label length || label || u64 LE count`, the
+/// item hashed into a bucket's leaf.
+pub fn bucket_item(label: &[u8], count: u64) -> Vec<u8> {
+    let mut item = Vec::with_capacity(25 + label.len());
+    item.extend_from_slice(b"merkle-bucket");
+    item.extend_from_slice(&(label.len() as u32).to_le_bytes());
+    item.extend_from_slice(label);
+    item.extend_from_slice(&count.to_le_bytes());
+    item
+}
+
+pub struct Histogram<H = Sha256> {
+    labels: Vec<Vec<u8>>,
+    counts: Vec<u64>,
+    tree: MerkleTree<H>,
+}
+
+/// A bucket's released count and the proof that it was committed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BucketProof {
+    pub label: Vec<u8>,
+    pub count: u64,
+    pub proof: Proof,
+}
+
+impl BucketProof {
+    /// Checks the bucket against the histogram's `head`, whose size is its
+    /// number of buckets.
+    pub fn verify(&self, head: &TreeHead) -> bool {
+        self.verify_with::<Sha256>(head)
+    }
+
+    pub fn verify_with<H: MerkleHasher>(&self, head: &TreeHead) -> bool {
+        self.proof
+            .verify_with::<H>(
+                &bucket_item(&self.label, self.count),
+                head,
+                Padding::default(),
+            )
+            .is_valid()
+    }
+}
+
+impl Histogram {
+    /// Commits to `counts` as given. Counts for a repeated label are added
+    /// up. Returns `None` if there are no buckets.
+    pub fn from_counts<L, I>(counts: I) -> Option<Self>
+    where
+        L: AsRef<[u8]>,
+        I: IntoIterator<Item = (L, u64)>,
+    {
+        Histogram::build(counts, &mut Exact)
+    }
+
+    /// Counts `records` into the buckets `bucket` assigns them to.
+    pub fn from_records<R, L, I, F>(
+        records: I,
+        mut bucket: F,
+        noise: &mut dyn Noise,
+    ) -> Option<Self>
+    where
+        L: AsRef<[u8]>,
+        I: IntoIterator<Item = R>,
+        F: FnMut(&R) -> L,
+    {
+        let counts = records.into_iter().map(|record| (bucket(&record), 1));
+        Histogram::build(counts, noise)
+    }
+}
+
+impl<H: MerkleHasher> Histogram<H> {
+    /// Like `from_counts`, passing each bucket's total through `noise`
+    /// before it is committed.
+    pub fn build<L, I, N>(counts: I, noise: &mut N) -> Option<Self>
+    where
+        L: AsRef<[u8]>,
+        I: IntoIterator<Item = (L, u64)>,
+        N: Noise + ?Sized,
+    {
+        let mut totals: BTreeMap<Vec<u8>, u64> = BTreeMap::new();
+        for (label, count) in counts {
+            let total = totals.entry(label.as_ref().to_vec()).or_insert(0);
+            *total = total.saturating_add(count);
+        }
+        let (labels, counts): (Vec<_>, Vec<_>) = totals
+            .into_iter()
+            .map(|(label, count)| {
+                let released = noise.perturb(&label, count);
+                (label, released)
+            })
+            .unzip();
+        let items = labels
+            .iter()
+            .zip(counts.iter())
+            .map(|(label, &count)| bucket_item(label, count));
+        let tree = MerkleTree::build_from_leaves_padded(items, Padding::default())?;
+        Some(Histogram {
+            labels,
+            counts,
+            tree,
+        })
+    }
+
+    pub fn root(&self) -> [u8; 32] {
+        self.tree.root()
+    }
+
+    pub fn head(&self) -> TreeHead {
+        TreeHead {
+            size: self.labels.len() as u64,
+            root: self.root(),
+        }
+    }
+
+    pub fn tree(&self) -> &MerkleTree<H> {
+        &self.tree
+    }
+
+    /// Buckets in label order with their released counts.
+    pub fn buckets(&self) -> impl Iterator<Item = (&[u8], u64)> + '_ {
+        self.labels
+            .iter()
+            .map(Vec::as_slice)
+            .zip(self.counts.iter().copied())
+    }
+
+    /// Sum of the released counts.
+    pub fn total(&self) -> u64 {
+        self.counts
+            .iter()
+            .fold(0u64, |total, &count| total.saturating_add(count))
+    }
+
+    fn position(&self, label: &[u8]) -> Option<usize> {
+        self.labels
+            .binary_search_by(|probe| probe.as_slice().cmp(label))
+            .ok()
+    }
+
+    pub fn count(&self, label: &[u8]) -> Option<u64> {
+        self.position(label).map(|idx| self.counts[idx])
+    }
+
+    pub fn bucket_proof(&self, label: &[u8]) -> Option<BucketProof> {
+        let idx = self.position(label)?;
+        Some(BucketProof {
+            label: label.to_vec(),
+            count: self.counts[idx],
+            proof: self.tree.leaf_proof(idx).ok()?,
+        })
+    }
+}
+
+impl<H> HeapSize for Histogram<H> {
+    fn heap_size_bytes(&self) -> usize {
+        let labels: usize = self.labels.iter().map(Vec::capacity).sum();
+        vec_bytes(&self.labels) + labels + vec_bytes(&self.counts) + self.tree.heap_size_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn proves_buckets() {
+        let ages = [34u32, 29, 41, 38, 23, 67, 45, 31];
+        let decade = |age: &u32| format!("{}0s", age / 10);
+        let hist = Histogram::from_records(ages.iter().copied(), decade, &mut Exact).unwrap();
+        let buckets: Vec<_> = hist.buckets().map(|(l, c)| (l.to_vec(), c)).collect();
+        assert_eq!(buckets[0], (b"20s".to_vec(), 2));
+        assert_eq!(hist.count(b"30s"), Some(3));
+        assert_eq!(hist.total(), 8);
+        assert!(hist.count(b"50s").is_none());
+
+        let proof = hist.bucket_proof(b"40s").unwrap();
+        assert_eq!(proof.count, 2);
+        assert!(proof.verify(&hist.head()));
+        let inflated = BucketProof {
+            count: 3,
+            ..proof.clone()
+        };
+        assert!(!inflated.verify(&hist.head()));
+        let relabelled = BucketProof {
+            label: b"60s".to_vec(),
+            ..proof
+        };
+        assert!(!relabelled.verify(&hist.head()));
+
+        // Counts in any order, with repeats, commit the same.
+        let counts = [("30s", 1), ("20s", 2), ("40s", 2), ("60s", 1), ("30s", 2)];
+        assert_eq!(Histogram::from_counts(counts).unwrap().root(), hist.root());
+        assert!(Histogram::from_counts(Vec::<(&str, u64)>::new()).is_none());
+    }
+
+    #[test]
+    fn commits_released_counts() {
+        let counts = [("a", 10u64), ("b", 0), ("c", 5)];
+        let mut seen = Vec::new();
+        let mut noise = |label: &[u8], count: u64| {
+            seen.push(label.to_vec());
+            count.saturating_add(label[0] as u64 % 3).saturating_sub(1)
+        };
+        let hist: Histogram = Histogram::build(counts, &mut noise).unwrap();
+        assert_eq!(seen, [b"a".to_vec(), b"b".to_vec(), b"c".to_vec()]);
+        assert_eq!(hist.count(b"a"), Some(10));
+        assert_eq!(hist.count(b"b"), Some(1));
+        assert_eq!(hist.count(b"c"), Some(4));
+        assert!(hist.bucket_proof(b"c").unwrap().verify(&hist.head()));
+        assert_ne!(hist.root(), Histogram::from_counts(counts).unwrap().root());
+    }
+}
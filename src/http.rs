@@ -0,0 +1,54 @@
+//! The plain HTTP/1.1 client shared by the network clients: one `POST` per
+//! connection, no TLS, and only `200` counts as success.
+
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Posts `body` to `url` (`http://host:port/path`) and returns the body of
+/// a `200` response. `timeout` bounds the connect and each read and write.
+pub(crate) fn post(
+    url: &str,
+    content_type: &str,
+    body: &str,
+    timeout: Duration,
+) -> io::Result<String> {
+    let rest = url
+        .strip_prefix("http://")
+        .ok_or_else(|| invalid(format!("not an http URL: {}", url)))?;
+    let (host, path) = match rest.find('/') {
+        Some(slash) => rest.split_at(slash),
+        None => (rest, "/"),
+    };
+    let addr = std::net::ToSocketAddrs::to_socket_addrs(host)?
+        .next()
+        .ok_or_else(|| invalid(format!("no address for {}", host)))?;
+    let mut stream = TcpStream::connect_timeout(&addr, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    write!(
+        stream,
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        path,
+        host,
+        content_type,
+        body.len(),
+        body
+    )?;
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    let (head, body) = response
+        .split_once("\r\n\r\n")
+        .ok_or_else(|| invalid("truncated response".to_string()))?;
+    let status = head.lines().next().and_then(|line| line.split(' ').nth(1));
+    match status {
+        Some("200") => Ok(body.to_string()),
+        Some(status) => Err(io::Error::other(format!("{} answered {}", host, status))),
+        None => Err(invalid("bad status line".to_string())),
+    }
+}
@@ -0,0 +1,141 @@
+//! Tree heads for trees built with a [`Hybrid`] hasher. Proofs from such a
+//! tree only verify with the same two functions, so the head names them and
+//! [`HybridHead::verify`] picks them from there rather than from the
+//! verifier's configuration.
+//!
+//! The head hash is `H(size_le || root || leaf id || node id)`.
+
+use crate::{hash_data, verify_proof_with, Hybrid, MerkleHasher, MerkleTree, VerificationOutcome};
+use sha2::{Sha256, Sha512Trunc256};
+
+/// Hash functions a head can name, with their ids.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "borsh",
+    derive(borsh::BorshSerialize, borsh::BorshDeserialize)
+)]
+#[cfg_attr(feature = "borsh", borsh(use_discriminant = true))]
+pub enum HashFunction {
+    Sha256 = 0,
+    /// SHA-512/256, faster than SHA-256 on most 64-bit machines.
+    Sha512Trunc256 = 1,
+}
+
+impl HashFunction {
+    pub fn from_byte(byte: u8) -> Option<HashFunction> {
+        match byte {
+            0 => Some(HashFunction::Sha256),
+            1 => Some(HashFunction::Sha512Trunc256),
+            _ => None,
+        }
+    }
+
+    pub fn byte(self) -> u8 {
+        self as u8
+    }
+}
+
+/// A hasher with a [`HashFunction`] id.
+pub trait NamedHasher: MerkleHasher {
+    const FUNCTION: HashFunction;
+}
+
+impl NamedHasher for Sha256 {
+    const FUNCTION: HashFunction = HashFunction::Sha256;
+}
+
+impl NamedHasher for Sha512Trunc256 {
+    const FUNCTION: HashFunction = HashFunction::Sha512Trunc256;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HybridHead {
+    pub size: u64,
+    pub root: [u8; 32],
+    pub leaf: HashFunction,
+    pub node: HashFunction,
+}
+
+impl HybridHead {
+    pub fn hash(&self) -> [u8; 32] {
+        let mut bytes = [0u8; 42];
+        bytes[..8].copy_from_slice(&self.size.to_le_bytes());
+        bytes[8..40].copy_from_slice(&self.root);
+        bytes[40] = self.leaf.byte();
+        bytes[41] = self.node.byte();
+        hash_data(&bytes)
+    }
+
+    /// `verify_proof_with` against the root, with the functions named here.
+    pub fn verify(&self, item: &[u8], proof: &[([u8; 32], bool)]) -> VerificationOutcome {
+        type S256 = Sha256;
+        type S512 = Sha512Trunc256;
+        let root = self.root;
+        match (self.leaf, self.node) {
+            (HashFunction::Sha256, HashFunction::Sha256) => {
+                verify_proof_with::<S256>(item, root, proof)
+            }
+            (HashFunction::Sha256, HashFunction::Sha512Trunc256) => {
+                verify_proof_with::<Hybrid<S256, S512>>(item, root, proof)
+            }
+            (HashFunction::Sha512Trunc256, HashFunction::Sha256) => {
+                verify_proof_with::<Hybrid<S512, S256>>(item, root, proof)
+            }
+            (HashFunction::Sha512Trunc256, HashFunction::Sha512Trunc256) => {
+                verify_proof_with::<S512>(item, root, proof)
+            }
+        }
+    }
+}
+
+impl<L: NamedHasher, N: NamedHasher> MerkleTree<Hybrid<L, N>> {
+    pub fn hybrid_head(&self) -> HybridHead {
+        HybridHead {
+            size: self.leaves() as u64,
+            root: self.root(),
+            leaf: L::FUNCTION,
+            node: N::FUNCTION,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn head_selects_hashers() {
+        let items = ["a", "b", "c", "d", "e"];
+        let tree = MerkleTree::<Hybrid<Sha512Trunc256, Sha256>>::build_from_leaves(items).unwrap();
+        let head = tree.hybrid_head();
+        assert_eq!(
+            (head.leaf, head.node),
+            (HashFunction::Sha512Trunc256, HashFunction::Sha256)
+        );
+        assert_eq!(tree.node(0).unwrap(), Sha512Trunc256::hash_data(b"a"));
+        assert_eq!(
+            tree.node(5).unwrap(),
+            Sha256::hash_pair(&tree.node(1).unwrap(), &tree.node(0).unwrap())
+        );
+
+        let path = tree.path(2).unwrap();
+        assert!(head.verify(b"c", &path).is_valid());
+        assert!(!head.verify(b"d", &path).is_valid());
+        let swapped = HybridHead {
+            leaf: HashFunction::Sha256,
+            node: HashFunction::Sha512Trunc256,
+            ..head
+        };
+        assert!(!swapped.verify(b"c", &path).is_valid());
+        assert_ne!(swapped.hash(), head.hash());
+
+        for byte in 0..2 {
+            assert_eq!(
+                HashFunction::from_byte(byte).map(HashFunction::byte),
+                Some(byte)
+            );
+        }
+        assert_eq!(HashFunction::from_byte(2), None);
+    }
+}
@@ -0,0 +1,161 @@
+use crate::{Layout, MerkleTree, Node, Padding};
+use sha2::Sha256;
+use std::time::{Duration, Instant};
+
+/// How much work one `build_step` call may do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Budget {
+    Nodes(usize),
+    Time(Duration),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuildStatus {
+    Pending { done: usize, total: usize },
+    Done,
+}
+
+/// Nodes hashed between clock reads under a time budget.
+const CLOCK_INTERVAL: usize = 64;
+
+/// A `MerkleTree::new` build split into steps, so a single-threaded host can
+/// spread construction over several event-loop ticks.
+pub struct IncrementalBuild<'a> {
+    data: &'a [u8],
+    chunk_size: usize,
+    layout: Layout,
+    nodes: Vec<Node>,
+}
+
+impl<'a> IncrementalBuild<'a> {
+    /// Returns `None` if `leaves` is zero or more than `data` has bytes.
+    pub fn new(data: &'a [u8], leaves: usize) -> Option<Self> {
+        let chunk_size = data.len().checked_div(leaves).filter(|&size| size > 0)?;
+        let layout = Layout::new(leaves)?;
+        Some(IncrementalBuild {
+            data,
+            chunk_size,
+            nodes: Vec::with_capacity(layout.nodes()),
+            layout,
+        })
+    }
+
+    fn total(&self) -> usize {
+        self.layout.nodes()
+    }
+
+    fn push_next(&mut self) {
+        let k = self.nodes.len();
+        let node = match self.layout.children(k) {
+            None => Node::as_leaf(&self.data[k * self.chunk_size..(k + 1) * self.chunk_size]),
+            Some((even, Some(odd))) => Node::as_parent(&self.nodes[odd], &self.nodes[even]),
+            Some((lone, None)) => {
+                let parent = Padding::default()
+                    .parent::<Sha256>(&mut Sha256::default(), &self.nodes[lone].value);
+                Node::new(parent)
+            }
+        };
+        self.nodes.push(node);
+    }
+
+    pub fn build_step(&mut self, budget: Budget) -> BuildStatus {
+        let start = Instant::now();
+        let mut hashed = 0;
+        while self.nodes.len() < self.total() {
+            let exhausted = match budget {
+                Budget::Nodes(limit) => hashed >= limit,
+                Budget::Time(limit) => {
+                    hashed % CLOCK_INTERVAL == 0 && hashed > 0 && start.elapsed() >= limit
+                }
+            };
+            if exhausted {
+                break;
+            }
+            self.push_next();
+            hashed += 1;
+        }
+        self.status()
+    }
+
+    pub fn status(&self) -> BuildStatus {
+        if self.nodes.len() == self.total() {
+            BuildStatus::Done
+        } else {
+            BuildStatus::Pending {
+                done: self.nodes.len(),
+                total: self.total(),
+            }
+        }
+    }
+
+    /// The finished tree, or `None` if steps remain.
+    pub fn finish(self) -> Option<MerkleTree> {
+        if self.status() != BuildStatus::Done {
+            return None;
+        }
+        let leaves = self.layout.leaves();
+        Some(MerkleTree::from_nodes_padded(
+            self.nodes,
+            leaves,
+            Padding::default(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn node_budget() {
+        let data = b"asdfasdfasdfasdfasdfasdfasdfasdf";
+        let mut build = IncrementalBuild::new(data, 8).unwrap();
+        assert_eq!(
+            build.build_step(Budget::Nodes(6)),
+            BuildStatus::Pending { done: 6, total: 15 }
+        );
+        assert_eq!(
+            build.build_step(Budget::Nodes(6)),
+            BuildStatus::Pending {
+                done: 12,
+                total: 15
+            }
+        );
+        assert_eq!(build.build_step(Budget::Nodes(6)), BuildStatus::Done);
+        assert_eq!(
+            build.finish().unwrap().root(),
+            MerkleTree::new(data, 8).root()
+        );
+
+        assert!(IncrementalBuild::new(data, 8).unwrap().finish().is_none());
+
+        // Levels of 5, 3, 2 and 1 nodes.
+        let mut build = IncrementalBuild::new(data, 5).unwrap();
+        assert_eq!(
+            build.build_step(Budget::Nodes(7)),
+            BuildStatus::Pending { done: 7, total: 11 }
+        );
+        assert_eq!(build.build_step(Budget::Nodes(7)), BuildStatus::Done);
+        assert_eq!(
+            build.finish().unwrap().root(),
+            MerkleTree::new(data, 5).root()
+        );
+        assert!(IncrementalBuild::new(data, 0).is_none());
+        assert!(IncrementalBuild::new(data, 33).is_none());
+    }
+
+    #[test]
+    fn time_budget() {
+        let data = vec![3u8; 64 * 1024];
+        let mut build = IncrementalBuild::new(&data, 1024).unwrap();
+        let mut steps = 0;
+        while build.build_step(Budget::Time(Duration::from_micros(50))) != BuildStatus::Done {
+            steps += 1;
+        }
+        assert!(steps < 2048);
+        assert_eq!(
+            build.finish().unwrap().root(),
+            MerkleTree::new(&data, 1024).root()
+        );
+    }
+}
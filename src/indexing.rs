@@ -0,0 +1,227 @@
+//! Conversions between the index spaces used to address a tree:
+//!
+//! - leaf position: `0..leaves`, in data order;
+//! - node index: position in the flat node array, leaves first, then each
+//!   level in turn, ending with the root;
+//! - `(level, offset)`: level 0 holds the leaves, offsets count from the
+//!   start of the level;
+//! - generalized index: the root is 1 and the children of `g` are `2g` and
+//!   `2g + 1` (as in SSZ), so a node at distance `d` below the root with
+//!   offset `o` has gindex `2^d + o`.
+//!
+//! Each level holds half the level below it, rounded up, so the last node of
+//! an odd level has no sibling (see [`crate::padding`]). Every conversion is
+//! range checked and returns `None` outside the tree.
+
+#[cfg(feature = "std")]
+use crate::memory::{vec_bytes, HeapSize};
+use crate::{MerkleHasher, MerkleTree, NodeStore};
+use alloc::vec;
+use alloc::vec::Vec;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Layout {
+    level_sizes: Vec<usize>,
+    level_starts: Vec<usize>,
+}
+
+impl Layout {
+    /// Returns `None` for an empty tree.
+    pub fn new(leaves: usize) -> Option<Layout> {
+        if leaves == 0 {
+            return None;
+        }
+        let mut level_sizes = vec![leaves];
+        while level_sizes[level_sizes.len() - 1] > 1 {
+            let last = level_sizes[level_sizes.len() - 1];
+            level_sizes.push(last.div_ceil(2));
+        }
+        let level_starts = level_sizes
+            .iter()
+            .scan(0, |start, &size| {
+                let this = *start;
+                *start += size;
+                Some(this)
+            })
+            .collect();
+        Some(Layout {
+            level_sizes,
+            level_starts,
+        })
+    }
+
+    pub fn leaves(&self) -> usize {
+        self.level_sizes[0]
+    }
+
+    pub fn nodes(&self) -> usize {
+        self.level_starts[self.levels() - 1] + 1
+    }
+
+    pub fn levels(&self) -> usize {
+        self.level_sizes.len()
+    }
+
+    pub fn root(&self) -> usize {
+        self.nodes() - 1
+    }
+
+    /// Node count per level, leaves first.
+    pub fn level_sizes(&self) -> &[usize] {
+        &self.level_sizes
+    }
+
+    pub fn level_size(&self, level: usize) -> Option<usize> {
+        self.level_sizes.get(level).copied()
+    }
+
+    pub fn node_index(&self, level: usize, offset: usize) -> Option<usize> {
+        if offset >= self.level_size(level)? {
+            return None;
+        }
+        Some(self.level_starts[level] + offset)
+    }
+
+    pub fn level_offset(&self, node: usize) -> Option<(usize, usize)> {
+        if node >= self.nodes() {
+            return None;
+        }
+        let level = self.level_starts.partition_point(|&start| start <= node) - 1;
+        Some((level, node - self.level_starts[level]))
+    }
+
+    pub fn leaf_node(&self, position: usize) -> Option<usize> {
+        self.node_index(0, position)
+    }
+
+    pub fn node_leaf(&self, node: usize) -> Option<usize> {
+        match self.level_offset(node)? {
+            (0, position) => Some(position),
+            _ => None,
+        }
+    }
+
+    pub fn parent(&self, node: usize) -> Option<usize> {
+        let (level, offset) = self.level_offset(node)?;
+        self.node_index(level + 1, offset / 2)
+    }
+
+    pub fn sibling(&self, node: usize) -> Option<usize> {
+        let (level, offset) = self.level_offset(node)?;
+        self.node_index(level, offset ^ 1)
+    }
+
+    /// Children in offset order. Pairs are hashed odd offset first, so the
+    /// second child's hash leads; it is `None` for a lone child.
+    pub fn children(&self, node: usize) -> Option<(usize, Option<usize>)> {
+        let (level, offset) = self.level_offset(node)?;
+        let below = level.checked_sub(1)?;
+        Some((
+            self.node_index(below, 2 * offset)?,
+            self.node_index(below, 2 * offset + 1),
+        ))
+    }
+
+    pub fn gindex(&self, level: usize, offset: usize) -> Option<u64> {
+        self.node_index(level, offset)?;
+        let distance = (self.levels() - 1 - level) as u32;
+        1u64.checked_shl(distance)?.checked_add(offset as u64)
+    }
+
+    pub fn from_gindex(&self, gindex: u64) -> Option<(usize, usize)> {
+        if gindex == 0 {
+            return None;
+        }
+        let distance = (63 - gindex.leading_zeros()) as usize;
+        let level = (self.levels() - 1).checked_sub(distance)?;
+        let offset = (gindex - (1 << distance)) as usize;
+        self.node_index(level, offset)?;
+        Some((level, offset))
+    }
+}
+
+impl<H: MerkleHasher, S: NodeStore> MerkleTree<H, S> {
+    pub fn layout(&self) -> &Layout {
+        &self.layout
+    }
+
+    /// Hash of the node at `offset` on `level`, leaves being level 0.
+    pub fn node_at(&self, level: usize, offset: usize) -> Option<[u8; 32]> {
+        let idx = self.layout.node_index(level, offset)?;
+        Some(self.nodes.get(idx))
+    }
+}
+
+#[cfg(feature = "std")]
+impl HeapSize for Layout {
+    fn heap_size_bytes(&self) -> usize {
+        vec_bytes(&self.level_sizes) + vec_bytes(&self.level_starts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_tree_navigation() {
+        let tree = MerkleTree::new(b"asdfasdfasdfasdfasdfasdfasdfasdf", 8);
+        let layout = tree.layout();
+        assert_eq!(layout.nodes(), tree.size());
+        for node in 0..tree.size() - 1 {
+            assert_eq!(layout.parent(node), tree.parent_idx(node).ok());
+            assert_eq!(layout.sibling(node), tree.sibling_idx(node).ok());
+        }
+        for node in tree.leaves()..tree.size() {
+            assert_eq!(
+                layout.children(node),
+                Some((tree.right_idx(node).unwrap(), tree.left_idx(node).ok()))
+            );
+        }
+        assert_eq!(layout.parent(layout.root()), None);
+        assert_eq!(layout.children(0), None);
+    }
+
+    #[test]
+    fn conversions_round_trip() {
+        let layout = Layout::new(8).unwrap();
+        for node in 0..layout.nodes() {
+            let (level, offset) = layout.level_offset(node).unwrap();
+            assert_eq!(layout.node_index(level, offset), Some(node));
+            let gindex = layout.gindex(level, offset).unwrap();
+            assert_eq!(layout.from_gindex(gindex), Some((level, offset)));
+        }
+        assert_eq!(layout.gindex(3, 0), Some(1));
+        assert_eq!(layout.gindex(0, 5), Some(13));
+        assert_eq!(layout.level_offset(13), Some((2, 1)));
+        assert_eq!(layout.node_leaf(5), Some(5));
+        assert_eq!(layout.node_leaf(8), None);
+    }
+
+    #[test]
+    fn range_checks() {
+        let layout = Layout::new(4).unwrap();
+        assert_eq!(layout.node_index(0, 4), None);
+        assert_eq!(layout.node_index(3, 0), None);
+        assert_eq!(layout.level_offset(7), None);
+        assert_eq!(layout.from_gindex(0), None);
+        assert_eq!(layout.from_gindex(8), None);
+        assert_eq!(layout.leaf_node(4), None);
+        assert!(Layout::new(0).is_none());
+    }
+
+    #[test]
+    fn odd_levels() {
+        let layout = Layout::new(5).unwrap();
+        assert_eq!((layout.levels(), layout.nodes()), (4, 11));
+        assert_eq!(layout.sibling(4), None);
+        assert_eq!(layout.parent(4), Some(7));
+        assert_eq!(layout.children(7), Some((4, None)));
+        assert_eq!(layout.children(8), Some((5, Some(6))));
+        assert_eq!(layout.children(9), Some((7, None)));
+        assert_eq!(layout.sibling(7), None);
+        assert_eq!(layout.children(10), Some((8, Some(9))));
+        assert_eq!(layout.gindex(0, 4), Some(12));
+        assert_eq!(layout.from_gindex(13), None);
+    }
+}
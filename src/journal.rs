@@ -0,0 +1,195 @@
+//! An append-only journal of issued proofs, for proving later that a proof
+//! was handed out. Each entry records who asked, when, the root and the
+//! proof; entries are written to a sink as they are made and committed as
+//! leaves of a [`MerkleLog`], whose inclusion proofs then show that an
+//! entry was journaled, and whose root can be published or witnessed.
+//!
+//! Sink records are a u32 LE length followed by the entry: `issued_at`
+//! (u64 LE, seconds since the Unix epoch), the root, the requester (u32 LE
+//! length, bytes), then the proof as `Proof::to_bytes`. A journal reopens by
+//! replaying its records.
+
+use crate::{verify_inclusion, MerkleHasher, MerkleLog, MerkleTree, Proof};
+use std::convert::TryInto;
+use std::io::{self, Read, Write};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JournalEntry {
+    pub requester: Vec<u8>,
+    pub issued_at: u64,
+    pub root: [u8; 32],
+    pub proof: Proof,
+}
+
+impl JournalEntry {
+    /// Returns `None` for proofs too deep to encode.
+    pub fn encode(&self) -> Option<Vec<u8>> {
+        let proof = self.proof.to_bytes()?;
+        let mut out = Vec::with_capacity(44 + self.requester.len() + proof.len());
+        out.extend_from_slice(&self.issued_at.to_le_bytes());
+        out.extend_from_slice(&self.root);
+        out.extend_from_slice(&(self.requester.len() as u32).to_le_bytes());
+        out.extend_from_slice(&self.requester);
+        out.extend_from_slice(&proof);
+        Some(out)
+    }
+
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        let issued_at = u64::from_le_bytes(bytes.get(..8)?.try_into().unwrap());
+        let root = bytes.get(8..40)?.try_into().unwrap();
+        let len = u32::from_le_bytes(bytes.get(40..44)?.try_into().unwrap()) as usize;
+        let requester = bytes.get(44..44usize.checked_add(len)?)?.to_vec();
+        let proof = Proof::from_bytes(&bytes[44 + len..])?;
+        Some(JournalEntry {
+            requester,
+            issued_at,
+            root,
+            proof,
+        })
+    }
+}
+
+pub struct ProofJournal<W: Write> {
+    log: MerkleLog,
+    sink: W,
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+impl<W: Write> ProofJournal<W> {
+    pub fn new(sink: W) -> Self {
+        ProofJournal {
+            log: MerkleLog::new(),
+            sink,
+        }
+    }
+
+    /// Replays the records in `existing` and appends new ones to `sink`,
+    /// usually the same file opened for appending.
+    pub fn open<R: Read>(mut existing: R, sink: W) -> io::Result<Self> {
+        let mut journal = ProofJournal::new(sink);
+        let mut len = [0; 4];
+        loop {
+            match existing.read_exact(&mut len) {
+                Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => break,
+                result => result?,
+            }
+            let mut record = vec![0; u32::from_le_bytes(len) as usize];
+            existing.read_exact(&mut record)?;
+            JournalEntry::decode(&record).ok_or_else(|| invalid("bad journal entry"))?;
+            journal.log.append(&record);
+        }
+        Ok(journal)
+    }
+
+    pub fn len(&self) -> u64 {
+        self.log.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.log.is_empty()
+    }
+
+    pub fn root(&self) -> [u8; 32] {
+        self.log.root()
+    }
+
+    pub fn log(&self) -> &MerkleLog {
+        &self.log
+    }
+
+    /// Writes `entry` to the sink, then commits it. Returns its position.
+    pub fn record(&mut self, entry: &JournalEntry) -> io::Result<u64> {
+        let record = entry.encode().ok_or_else(|| invalid("proof too deep"))?;
+        self.sink.write_all(&(record.len() as u32).to_le_bytes())?;
+        self.sink.write_all(&record)?;
+        self.sink.flush()?;
+        Ok(self.log.append(&record))
+    }
+
+    /// Takes the proof for leaf `idx` of `tree` and journals it before
+    /// handing it out.
+    pub fn issue<H: MerkleHasher>(
+        &mut self,
+        tree: &MerkleTree<H>,
+        idx: usize,
+        requester: &[u8],
+        issued_at: u64,
+    ) -> io::Result<(Proof, u64)> {
+        let proof = tree
+            .leaf_proof(idx)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        let entry = JournalEntry {
+            requester: requester.to_vec(),
+            issued_at,
+            root: tree.root(),
+            proof,
+        };
+        let position = self.record(&entry)?;
+        Ok((entry.proof, position))
+    }
+
+    /// Evidence that the entry at `position` was journaled, against the
+    /// root of the journal at `size` entries.
+    pub fn prove(&self, position: u64, size: u64) -> Option<Vec<[u8; 32]>> {
+        self.log.inclusion_proof(position, size)
+    }
+}
+
+/// Checks that `entry` is at `position` in the journal of `size` entries
+/// with `root`.
+pub fn verify_journaled(
+    entry: &JournalEntry,
+    position: u64,
+    size: u64,
+    root: [u8; 32],
+    proof: &[[u8; 32]],
+) -> bool {
+    match entry.encode() {
+        Some(record) => verify_inclusion(&record, position, size, root, proof),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn proves_issuance() {
+        let tree = MerkleTree::new(b"asdfjkln12345678", 4);
+        let mut journal = ProofJournal::new(Vec::new());
+        for (idx, who) in [(1, &b"alice"[..]), (3, b"bob"), (1, b"carol")] {
+            journal.issue(&tree, idx, who, 1_000 + idx as u64).unwrap();
+        }
+        assert!(journal.issue(&tree, 4, b"mallory", 0).is_err());
+        assert_eq!(journal.len(), 3);
+
+        let entry = JournalEntry {
+            requester: b"bob".to_vec(),
+            issued_at: 1_003,
+            root: tree.root(),
+            proof: tree.leaf_proof(3).unwrap(),
+        };
+        let root = journal.root();
+        let evidence = journal.prove(1, 3).unwrap();
+        assert!(verify_journaled(&entry, 1, 3, root, &evidence));
+        let redated = JournalEntry {
+            issued_at: 1_004,
+            ..entry.clone()
+        };
+        assert!(!verify_journaled(&redated, 1, 3, root, &evidence));
+        assert!(!verify_journaled(&entry, 2, 3, root, &evidence));
+
+        // Reopening replays the records into the same log.
+        let ProofJournal { sink, .. } = journal;
+        let mut reopened = ProofJournal::open(&sink[..], Vec::new()).unwrap();
+        assert_eq!((reopened.len(), reopened.root()), (3, root));
+        reopened.record(&entry).unwrap();
+        assert!(reopened.root() != root);
+        assert!(ProofJournal::open(&sink[..sink.len() - 1], Vec::new()).is_err());
+        assert_eq!(JournalEntry::decode(&entry.encode().unwrap()), Some(entry));
+    }
+}
@@ -0,0 +1,243 @@
+//! Just enough JSON for the JSON-RPC client: a value parser and string
+//! escaping. Numbers are kept as their text, so integers of any size
+//! survive until the caller parses them.
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Value {
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    pub(crate) fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(fields) => fields
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_u64(&self) -> Option<u64> {
+        match self {
+            Value::Number(text) => text.parse().ok(),
+            _ => None,
+        }
+    }
+}
+
+/// `text` as a JSON string literal.
+pub(crate) fn quote(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() + 2);
+    out.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Parses one JSON value, allowing whitespace around it.
+pub(crate) fn parse(text: &str) -> Option<Value> {
+    let mut parser = Parser {
+        bytes: text.as_bytes(),
+        at: 0,
+        depth: 0,
+    };
+    let value = parser.value()?;
+    parser.skip_space();
+    (parser.at == parser.bytes.len()).then_some(value)
+}
+
+/// Nesting past this is refused rather than recursed into.
+const MAX_DEPTH: usize = 64;
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    at: usize,
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn skip_space(&mut self) {
+        while self
+            .bytes
+            .get(self.at)
+            .is_some_and(|b| b" \t\r\n".contains(b))
+        {
+            self.at += 1;
+        }
+    }
+
+    fn eat(&mut self, expected: &[u8]) -> Option<()> {
+        if self.bytes.get(self.at..self.at + expected.len())? == expected {
+            self.at += expected.len();
+            Some(())
+        } else {
+            None
+        }
+    }
+
+    fn value(&mut self) -> Option<Value> {
+        self.skip_space();
+        match *self.bytes.get(self.at)? {
+            b'n' => self.eat(b"null").map(|_| Value::Null),
+            b't' => self.eat(b"true").map(|_| Value::Bool(true)),
+            b'f' => self.eat(b"false").map(|_| Value::Bool(false)),
+            b'"' => self.string().map(Value::String),
+            b'[' => self.nested(|parser| parser.array()),
+            b'{' => self.nested(|parser| parser.object()),
+            _ => self.number(),
+        }
+    }
+
+    fn nested<F: FnOnce(&mut Self) -> Option<Value>>(&mut self, parse: F) -> Option<Value> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return None;
+        }
+        let value = parse(self);
+        self.depth -= 1;
+        value
+    }
+
+    fn number(&mut self) -> Option<Value> {
+        let start = self.at;
+        while self
+            .bytes
+            .get(self.at)
+            .is_some_and(|b| b"+-.eE0123456789".contains(b))
+        {
+            self.at += 1;
+        }
+        let text = core::str::from_utf8(&self.bytes[start..self.at]).ok()?;
+        text.parse::<f64>().ok()?;
+        Some(Value::Number(text.to_string()))
+    }
+
+    fn string(&mut self) -> Option<String> {
+        self.eat(b"\"")?;
+        let mut out = Vec::new();
+        loop {
+            let byte = *self.bytes.get(self.at)?;
+            self.at += 1;
+            match byte {
+                b'"' => return String::from_utf8(out).ok(),
+                b'\\' => {
+                    let escaped = *self.bytes.get(self.at)?;
+                    self.at += 1;
+                    let c = match escaped {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        // Surrogate pairs are not needed by the clients.
+                        b'u' => {
+                            let digits = self.bytes.get(self.at..self.at + 4)?;
+                            self.at += 4;
+                            let code = u32::from_str_radix(core::str::from_utf8(digits).ok()?, 16);
+                            char::from_u32(code.ok()?)?
+                        }
+                        _ => return None,
+                    };
+                    out.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                }
+                byte if byte < 0x20 => return None,
+                byte => out.push(byte),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Option<Value> {
+        self.eat(b"[")?;
+        let mut items = Vec::new();
+        self.skip_space();
+        if self.eat(b"]").is_some() {
+            return Some(Value::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            self.skip_space();
+            if self.eat(b"]").is_some() {
+                return Some(Value::Array(items));
+            }
+            self.eat(b",")?;
+        }
+    }
+
+    fn object(&mut self) -> Option<Value> {
+        self.eat(b"{")?;
+        let mut fields = Vec::new();
+        self.skip_space();
+        if self.eat(b"}").is_some() {
+            return Some(Value::Object(fields));
+        }
+        loop {
+            self.skip_space();
+            let key = self.string()?;
+            self.skip_space();
+            self.eat(b":")?;
+            fields.push((key, self.value()?));
+            self.skip_space();
+            if self.eat(b"}").is_some() {
+                return Some(Value::Object(fields));
+            }
+            self.eat(b",")?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_responses() {
+        let value =
+            parse(r#" {"id": 7, "result": {"ok": true, "tx": "a\"bA", "list": [1, null]}} "#)
+                .unwrap();
+        assert_eq!(value.get("id").and_then(Value::as_u64), Some(7));
+        let result = value.get("result").unwrap();
+        assert_eq!(result.get("ok"), Some(&Value::Bool(true)));
+        assert_eq!(result.get("tx").and_then(Value::as_str), Some("a\"bA"));
+        assert_eq!(
+            result.get("list"),
+            Some(&Value::Array(vec![
+                Value::Number("1".to_string()),
+                Value::Null
+            ]))
+        );
+        assert_eq!(
+            parse(&quote("tab\there \"quoted\"")),
+            Some(Value::String("tab\there \"quoted\"".to_string()))
+        );
+
+        assert!(parse("{\"a\": 1,}").is_none());
+        assert!(parse("[1] 2").is_none());
+        assert!(parse(&"[".repeat(MAX_DEPTH + 1)).is_none());
+    }
+}
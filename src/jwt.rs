@@ -0,0 +1,77 @@
+//! Membership assertions carried as a JWT claim. The claim value is the
+//! base64url encoding of `root || compact path`, so a token can carry the
+//! proof without any JSON structure of its own.
+
+use crate::{base64, compact, verify_proof, Path};
+use std::convert::TryInto;
+
+/// Suggested claim name.
+pub const CLAIM: &str = "mkp";
+
+/// Default cap on the claim value length, in characters. Fits a 32-level
+/// proof with room to spare while keeping tokens header-sized.
+pub const MAX_CLAIM_LEN: usize = 1536;
+
+/// Claim value for `path` under `root`, or `None` if it would exceed
+/// `max_len` characters.
+pub fn encode_claim(root: &[u8; 32], path: &[([u8; 32], bool)], max_len: usize) -> Option<String> {
+    if (4 * (32 + compact::encoded_len(path.len()))).div_ceil(3) > max_len {
+        return None;
+    }
+    let mut bytes = root.to_vec();
+    bytes.extend(compact::encode_path(path)?);
+    Some(base64::encode(&bytes))
+}
+
+/// Size is checked before decoding so oversized claims cost nothing.
+pub fn decode_claim(claim: &str, max_len: usize) -> Option<([u8; 32], Path)> {
+    if claim.len() > max_len {
+        return None;
+    }
+    let bytes = base64::decode(claim)?;
+    if bytes.len() < 32 {
+        return None;
+    }
+    let root = bytes[..32].try_into().unwrap();
+    match compact::decode_path(&bytes[32..])? {
+        (path, []) => Some((root, path)),
+        _ => None,
+    }
+}
+
+/// Token-validation hook: the claim must reference `expected_root` and prove
+/// membership of `item`.
+pub fn verify_claim(claim: &str, item: &[u8], expected_root: &[u8; 32], max_len: usize) -> bool {
+    match decode_claim(claim, max_len) {
+        Some((root, path)) => &root == expected_root && verify_proof(item, root, &path).is_valid(),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MerkleTree;
+
+    #[test]
+    fn claim_round_trip() {
+        let data = b"asdfjkln12345678";
+        let tree = MerkleTree::new(data, 4);
+        let claim = encode_claim(&tree.root(), &tree.path(2).unwrap(), MAX_CLAIM_LEN).unwrap();
+        assert!(claim
+            .bytes()
+            .all(|c| c.is_ascii_alphanumeric() || c == b'-' || c == b'_'));
+        assert!(verify_claim(&claim, b"1234", &tree.root(), MAX_CLAIM_LEN));
+        assert!(!verify_claim(&claim, b"5678", &tree.root(), MAX_CLAIM_LEN));
+        assert!(!verify_claim(&claim, b"1234", &[0; 32], MAX_CLAIM_LEN));
+    }
+
+    #[test]
+    fn bounded_size() {
+        let path = vec![([1; 32], true); 40];
+        assert!(encode_claim(&[0; 32], &path, MAX_CLAIM_LEN).is_none());
+        let claim = encode_claim(&[0; 32], &path, 4096).unwrap();
+        assert!(decode_claim(&claim, MAX_CLAIM_LEN).is_none());
+        assert_eq!(decode_claim(&claim, 4096), Some(([0; 32], path)));
+    }
+}
@@ -0,0 +1,87 @@
+//! SHA-256 fast path for 64-byte messages. Internal nodes always hash two
+//! digests, and so do the leaves of a tree over other trees' roots; such a
+//! message is exactly one block, and its padding block is a constant. Feeding
+//! both straight to the compression function skips the hasher's buffering
+//! and padding logic.
+
+use crate::{MerkleTree, Padding};
+
+const IV: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
+/// Padding block for a 64-byte message: the end marker, then the length in
+/// bits (512) as a big-endian u64.
+const PAD: [u8; 64] = {
+    let mut block = [0; 64];
+    block[0] = 0x80;
+    block[62] = 0x02;
+    block
+};
+
+/// SHA-256 of a 64-byte message; equal to `hash_data(block)`.
+pub fn hash64(block: &[u8; 64]) -> [u8; 32] {
+    let mut state = IV;
+    let blocks: [&_; 2] = [block[..].into(), PAD[..].into()];
+    sha2::compress256(&mut state, &[*blocks[0], *blocks[1]]);
+    let mut out = [0; 32];
+    for (chunk, word) in out.chunks_exact_mut(4).zip(&state) {
+        chunk.copy_from_slice(&word.to_be_bytes());
+    }
+    out
+}
+
+/// `hash_pair` through `hash64`.
+pub fn hash_pair64(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut block = [0; 64];
+    block[..32].copy_from_slice(left);
+    block[32..].copy_from_slice(right);
+    hash64(&block)
+}
+
+impl MerkleTree {
+    /// Same tree as `from_leaves` over the same bytes, hashing every leaf
+    /// and internal node with `hash64`. Returns `None` if there are no
+    /// leaves.
+    pub fn from_leaves64(leaves: &[[u8; 64]]) -> Option<Self> {
+        MerkleTree::from_leaves64_padded(leaves, Padding::default())
+    }
+
+    pub fn from_leaves64_padded(leaves: &[[u8; 64]], padding: Padding) -> Option<Self> {
+        if leaves.is_empty() {
+            return None;
+        }
+        let hashes = leaves.iter().map(hash64).collect();
+        Some(MerkleTree::from_leaf_hashes_by(
+            hashes,
+            padding,
+            hash_pair64,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{hash_data, hash_pair};
+
+    #[test]
+    fn matches_generic_hashing() {
+        let block: [u8; 64] = std::array::from_fn(|i| i as u8);
+        assert_eq!(hash64(&block), hash_data(&block));
+        assert_eq!(hash64(&[0; 64]), hash_data(&[0; 64]));
+        assert_eq!(
+            hash_pair64(&[1; 32], &[2; 32]),
+            hash_pair(&[1; 32], &[2; 32])
+        );
+
+        let leaves: Vec<[u8; 64]> = (0..5u8).map(|i| [i; 64]).collect();
+        for &padding in [Padding::Promote, Padding::DuplicateLast, Padding::Zero].iter() {
+            let fast = MerkleTree::from_leaves64_padded(&leaves, padding).unwrap();
+            let slow = MerkleTree::from_leaves_padded(&leaves, padding).unwrap();
+            assert_eq!(fast.root(), slow.root());
+            assert_eq!(fast.path(3), slow.path(3));
+        }
+        assert!(MerkleTree::from_leaves64(&[]).is_none());
+    }
+}
@@ -1,17 +1,304 @@
-use sha2::{Digest, Sha256};
+#![cfg_attr(not(feature = "std"), no_std)]
 
-struct Node {
+extern crate alloc;
+
+#[cfg(not(feature = "std"))]
+use alloc::collections::BTreeMap as Map;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use core::marker::PhantomData;
+use sha2::Sha256;
+#[cfg(feature = "std")]
+use std::collections::HashMap as Map;
+
+#[cfg(feature = "std")]
+pub mod anchor;
+#[cfg(feature = "std")]
+pub mod anchoring;
+pub mod append;
+#[cfg(feature = "std")]
+pub mod auditor;
+#[cfg(feature = "std")]
+pub mod backend;
+#[cfg(feature = "std")]
+mod base64;
+#[cfg(feature = "std")]
+pub mod bloom;
+#[cfg(all(feature = "borsh", feature = "std"))]
+mod borsh_impls;
+pub mod bounded;
+pub mod budget;
+#[cfg(feature = "std")]
+pub mod canonical;
+#[cfg(feature = "cose")]
+pub mod cbor;
+#[cfg(feature = "std")]
+pub mod clock;
+#[cfg(feature = "std")]
+pub mod commit_reveal;
+pub mod compact;
+#[cfg(any(feature = "zstd", feature = "lz4"))]
+pub mod compress;
+#[cfg(test)]
+mod conformance;
+pub mod consts;
+#[cfg(feature = "cose")]
+pub mod cose;
+pub mod counted;
+#[cfg(feature = "std")]
+pub mod cuckoo;
+pub mod cursor;
+#[cfg(feature = "std")]
+pub mod der;
+pub mod diff;
+pub mod download;
+pub mod dual;
+#[cfg(feature = "std")]
+pub mod epoch;
+mod error;
+pub mod estimate;
+#[cfg(feature = "std")]
+pub mod ext;
+#[cfg(feature = "std")]
+pub mod finalize;
+pub mod format;
+pub mod hasher;
+pub mod head;
+#[cfg(feature = "std")]
+pub mod histogram;
+#[cfg(any(feature = "witness-client", feature = "anchor-client"))]
+mod http;
+#[cfg(feature = "std")]
+pub mod hybrid;
+#[cfg(feature = "std")]
+pub mod incremental;
+pub mod indexing;
+#[cfg(feature = "std")]
+pub mod journal;
+#[cfg(feature = "anchor-client")]
+mod json;
+#[cfg(feature = "std")]
+pub mod jwt;
+#[cfg(feature = "std")]
+pub mod leaf64;
+#[cfg(feature = "std")]
+pub mod log;
+pub mod lookup;
+#[cfg(feature = "std")]
+pub mod memory;
+#[cfg(feature = "std")]
+pub mod migrate;
+#[cfg(feature = "std")]
+pub mod mining;
+pub mod multiproof;
+pub mod nodes;
+#[cfg(feature = "std")]
+pub mod openzeppelin;
+pub mod outcome;
+pub mod padding;
+#[cfg(feature = "std")]
+pub mod page;
+#[cfg(feature = "std")]
+pub mod parallel;
+pub mod perfect;
+pub mod policy;
+#[cfg(feature = "std")]
+pub mod progress;
+#[cfg(feature = "std")]
+pub mod prolly;
+pub mod proof;
+#[cfg(feature = "std")]
+pub mod proof_array;
+#[cfg(feature = "std")]
+pub mod reconcile;
+#[cfg(feature = "std")]
+pub mod redact;
+#[cfg(feature = "std")]
+pub mod rpc;
+#[cfg(feature = "std")]
+pub mod select;
+#[cfg(feature = "std")]
+pub mod set;
+#[cfg(feature = "std")]
+pub mod shape;
+#[cfg(feature = "std")]
+pub mod sharded;
+#[cfg(feature = "std")]
+pub mod solidity;
+#[cfg(feature = "std")]
+pub mod sorted;
+#[cfg(feature = "std")]
+pub mod sparse;
+#[cfg(feature = "std")]
+pub mod store;
+#[cfg(feature = "std")]
+pub mod stream;
+#[cfg(feature = "std")]
+pub mod tenant;
+#[cfg(feature = "testing")]
+pub mod testing;
+#[cfg(feature = "std")]
+pub mod threshold;
+#[cfg(feature = "std")]
+pub mod timed;
+pub mod tombstone;
+pub mod update;
+#[cfg(feature = "std")]
+pub mod weighted;
+#[cfg(feature = "witness-client")]
+pub mod witness;
+#[cfg(feature = "std")]
+pub mod x509;
+
+#[cfg(feature = "std")]
+pub use anchor::{HeadLog, HeadProof};
+#[cfg(feature = "anchor-client")]
+pub use anchoring::JsonRpcAnchor;
+#[cfg(feature = "std")]
+pub use anchoring::{
+    Anchor, AnchorReceipt, Confirmation, PendingAnchor, ReceiptVerifier, SignedReceipts,
+};
+#[cfg(feature = "std")]
+pub use auditor::{AuditReport, LogTransport, Sample, SampleOutcome};
+#[cfg(feature = "std")]
+pub use backend::backend;
+#[cfg(feature = "std")]
+pub use bloom::{BloomFilter, FilteredTree};
+pub use bounded::{verify_proof_bounded, LimitExceeded};
+pub use budget::{
+    verify_composed_budgeted, verify_multiproof_budgeted, verify_proof_budgeted, BudgetExceeded,
+    VerifyBudget,
+};
+#[cfg(feature = "std")]
+pub use clock::{Event, MerkleClock};
+#[cfg(feature = "std")]
+pub use commit_reveal::{CommitReveal, Reveal, RevealError, RevealVerifier};
+#[cfg(any(feature = "zstd", feature = "lz4"))]
+pub use compress::Compression;
+pub use consts::{ConstHasher, EMPTY_ROOT};
+#[cfg(feature = "cose")]
+pub use cose::{CoseError, CoseSigner, CoseVerifier, ProofBundle};
+pub use counted::{CountedTree, SizeProof};
+#[cfg(feature = "std")]
+pub use cuckoo::CuckooFilter;
+pub use cursor::{Cursor, CursorCheckpoint};
+pub use download::{DownloadState, RejectedBlock};
+pub use dual::DualTree;
+#[cfg(feature = "std")]
+pub use epoch::{Epoch, EpochManager, EpochPolicy, EpochProof};
+pub use error::Error;
+#[cfg(feature = "std")]
+pub use finalize::{verify_finalized, Finalizer, Finalizers};
+#[cfg(feature = "std")]
+pub use hasher::ContextPool;
+pub use hasher::{
+    BatchError, BatchHasher, DoubleHashed, Hybrid, Local, MerkleHasher, Rfc6962, Truncated,
+};
+pub use head::TreeHead;
+#[cfg(feature = "std")]
+pub use histogram::{BucketProof, Histogram, Noise};
+#[cfg(feature = "std")]
+pub use hybrid::{HashFunction, HybridHead, NamedHasher};
+#[cfg(feature = "std")]
+pub use incremental::{Budget, BuildStatus, IncrementalBuild};
+pub use indexing::Layout;
+#[cfg(feature = "std")]
+pub use journal::{verify_journaled, JournalEntry, ProofJournal};
+#[cfg(feature = "std")]
+pub use log::{verify_consistency, verify_inclusion, MerkleLog, StoredLog};
+#[cfg(feature = "std")]
+pub use memory::HeapSize;
+pub use multiproof::{
+    verify_multiproof, verify_multiproof_with, verify_range_proof, verify_range_proof_with,
+    MultiProof, RangeProof,
+};
+#[cfg(feature = "std")]
+pub use nodes::FileStore;
+pub use nodes::NodeStore;
+#[cfg(feature = "std")]
+pub use openzeppelin::OzTree;
+pub use outcome::{Failure, FailureKind, VerificationOutcome};
+pub use padding::Padding;
+#[cfg(feature = "std")]
+pub use page::Page;
+#[cfg(feature = "std")]
+pub use parallel::{par_verify_multiproof, par_verify_multiproof_with};
+pub use perfect::{PerfectMerkleTree, PerfectProof};
+pub use policy::{IndexPolicy, PolicyFailure, PolicyRule};
+#[cfg(feature = "std")]
+pub use progress::{CancelToken, Progress, ProgressError};
+#[cfg(feature = "std")]
+pub use prolly::{ChunkStore, ProllyProof, ProllyTree};
+pub use proof::{ComposedProof, Proof};
+#[cfg(feature = "std")]
+pub use proof_array::ProofArray;
+#[cfg(feature = "std")]
+pub use reconcile::{reconcile, Iblt, ReconcileSet};
+#[cfg(feature = "std")]
+pub use redact::{LeafRecord, RedactableTree};
+#[cfg(feature = "std")]
+pub use select::{select_indices, verify_selection};
+#[cfg(feature = "std")]
+pub use set::{
+    verify_count, verify_member, verify_non_member, CountProof, CountedEntry, MultisetCommitment,
+    Neighbour, NonMembershipProof, SetCommitment,
+};
+#[cfg(feature = "std")]
+pub use shape::Shape;
+#[cfg(feature = "std")]
+pub use sharded::{ShardPosition, ShardedBuilder};
+#[cfg(feature = "std")]
+pub use sparse::{SnapshotChunk, SnapshotError, SnapshotImport, SparseMerkleTree, SparseProof};
+#[cfg(all(feature = "borsh", feature = "std"))]
+pub use store::MerkleTreeView;
+#[cfg(feature = "std")]
+pub use store::ProofStore;
+#[cfg(feature = "std")]
+pub use stream::{Frontier, MerkleTreeBuilder};
+#[cfg(feature = "std")]
+pub use tenant::{QuotaExceeded, TenantProof, TenantRegistry};
+#[cfg(feature = "std")]
+pub use threshold::{Escrow, SecretSharing, Shamir, Share, ThresholdTree};
+#[cfg(feature = "std")]
+pub use timed::{
+    FreshnessPolicy, HeadSigner, HeadVerifier, SignedHead, TimedProof, TimedProofError,
+};
+pub use tombstone::DeletionProof;
+#[cfg(feature = "std")]
+pub use weighted::{WeightedProof, WeightedTree};
+#[cfg(feature = "witness-client")]
+pub use witness::{Checkpoint, Cosignature, NoQuorum, Witness, WitnessClient};
+
+pub type Path = Vec<([u8; 32], bool)>;
+
+/// A node hash held in memory, as stored by the default `Vec<Node>`.
+pub struct Node {
     value: [u8; 32],
 }
 
-pub struct MerkleTree {
-    nodes: Vec<Node>,
+/// A tree over fixed 32-byte hashes. `H` hashes the leaves and internal
+/// nodes; the default is SHA-256, and the constructors and verifiers that
+/// take no hasher use it. `S` holds the node hashes; see [`NodeStore`].
+pub struct MerkleTree<H = Sha256, S = Vec<Node>> {
+    nodes: S,
     leaves: usize,
+    layout: Layout,
+    padding: Padding,
+    labels: Map<Vec<u8>, usize>,
+    leaf_labels: Map<usize, Vec<u8>>,
+    /// Leaf ids by position and positions by id, both empty while every
+    /// leaf's id is its position.
+    ids: Vec<u64>,
+    id_positions: Vec<usize>,
+    /// Positions of each leaf hash, once `index_leaves` has been called.
+    positions: Option<Map<[u8; 32], Vec<usize>>>,
+    hasher: PhantomData<H>,
 }
 
+#[cfg_attr(not(feature = "std"), allow(dead_code))]
 impl Node {
     pub fn new(value: [u8; 32]) -> Self {
-        Node { value: value }
+        Node { value }
     }
 
     pub fn as_leaf(data: &[u8]) -> Self {
@@ -24,31 +311,160 @@ impl Node {
 }
 
 pub fn hash_data(data: &[u8]) -> [u8; 32] {
-    let mut hasher = Sha256::new();
-    hasher.update(&data);
-    hasher.finalize().into()
+    Sha256::hash_data(data)
 }
 
 pub fn hash_pair(left_data: &[u8; 32], right_data: &[u8; 32]) -> [u8; 32] {
-    let mut hasher = Sha256::new();
-    hasher.update(left_data);
-    hasher.update(right_data);
-    hasher.finalize().into()
+    Sha256::hash_pair(left_data, right_data)
 }
 
 impl MerkleTree {
+    /// Splits `data` into `leaves` chunks of `data.len() / leaves` bytes;
+    /// any remainder is ignored. Use `from_leaves` when the items are
+    /// separate or vary in length.
     pub fn new(data: &[u8], leaves: usize) -> Self {
+        MerkleTree::build(data, leaves)
+    }
+
+    /// One leaf per item, closing odd levels with `Padding::default()`.
+    /// Returns `None` if there are no items.
+    pub fn from_leaves<I>(items: I) -> Option<Self>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        MerkleTree::build_from_leaves(items)
+    }
+
+    pub fn from_leaves_padded<I>(items: I, padding: Padding) -> Option<Self>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        MerkleTree::build_from_leaves_padded(items, padding)
+    }
+
+    /// Like `new`, labelling leaves in order. Returns `None` if a label is
+    /// repeated or there are more labels than leaves.
+    pub fn with_labels<I, L>(data: &[u8], leaves: usize, labels: I) -> Option<Self>
+    where
+        I: IntoIterator<Item = L>,
+        L: AsRef<[u8]>,
+    {
+        let mut tree = MerkleTree::new(data, leaves);
+        for (idx, label) in labels.into_iter().enumerate() {
+            if !tree.set_label(idx, label) {
+                return None;
+            }
+        }
+        Some(tree)
+    }
+}
+
+impl<H: MerkleHasher> MerkleTree<H> {
+    /// Like `new`, hashing with `H`.
+    pub fn build(data: &[u8], leaves: usize) -> Self {
         let chunk_size = data.len() / leaves;
-        let mut nodes: Vec<Node> = data
+        let mut ctx = H::Context::default();
+        let hashes = data
             .chunks(chunk_size)
-            .map(|chunk| Node::as_leaf(chunk))
+            .take(leaves)
+            .map(|chunk| H::hash_data_with(&mut ctx, chunk))
             .collect();
-        for idx in 0..(leaves - 1) {
-            nodes.push(Node::as_parent(&nodes[2 * idx + 1], &nodes[2 * idx]));
+        MerkleTree::from_leaf_hashes_in(&mut ctx, hashes, Padding::default())
+    }
+
+    /// Like `from_leaves`, hashing with `H`.
+    pub fn build_from_leaves<I>(items: I) -> Option<Self>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        MerkleTree::build_from_leaves_padded(items, Padding::default())
+    }
+
+    pub fn build_from_leaves_padded<I>(items: I, padding: Padding) -> Option<Self>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        let mut ctx = H::Context::default();
+        let hashes: Vec<[u8; 32]> = items
+            .into_iter()
+            .map(|item| H::hash_data_with(&mut ctx, item.as_ref()))
+            .collect();
+        if hashes.is_empty() {
+            return None;
+        }
+        Some(MerkleTree::from_leaf_hashes_in(&mut ctx, hashes, padding))
+    }
+
+    /// Builds a tree over already-hashed leaves, of which there must be at
+    /// least one.
+    #[cfg_attr(not(feature = "std"), allow(dead_code))]
+    pub(crate) fn from_leaf_hashes(hashes: Vec<[u8; 32]>, padding: Padding) -> Self {
+        MerkleTree::from_leaf_hashes_in(&mut H::Context::default(), hashes, padding)
+    }
+
+    fn from_leaf_hashes_in(ctx: &mut H::Context, hashes: Vec<[u8; 32]>, padding: Padding) -> Self {
+        MerkleTree::from_leaf_hashes_by(hashes, padding, |first, second| {
+            H::hash_pair_with(ctx, first, second)
+        })
+    }
+
+    /// Builds the internal nodes with `pair`, which must compute
+    /// `H::hash_pair`.
+    fn from_leaf_hashes_by<F>(hashes: Vec<[u8; 32]>, padding: Padding, mut pair: F) -> Self
+    where
+        F: FnMut(&[u8; 32], &[u8; 32]) -> [u8; 32],
+    {
+        let leaves = hashes.len();
+        let size = Layout::new(leaves)
+            .expect("trees have at least one leaf")
+            .nodes();
+        let mut nodes: Vec<Node> = Vec::with_capacity(size);
+        nodes.extend(hashes.into_iter().map(Node::new));
+        let mut start = 0;
+        let mut len = leaves;
+        while len > 1 {
+            for idx in 0..len / 2 {
+                let even = start + 2 * idx;
+                let parent = pair(&nodes[even + 1].value, &nodes[even].value);
+                nodes.push(Node::new(parent));
+            }
+            if len & 1 == 1 {
+                let lone = nodes[start + len - 1].value;
+                let parent = match padding.sibling(&lone) {
+                    Some(sibling) => pair(&sibling, &lone),
+                    None => lone,
+                };
+                nodes.push(Node::new(parent));
+            }
+            start += len;
+            len = len.div_ceil(2);
         }
+        MerkleTree::from_nodes_padded(nodes, leaves, padding)
+    }
+
+    fn from_nodes_padded(nodes: Vec<Node>, leaves: usize, padding: Padding) -> Self {
+        MerkleTree::from_store(nodes, leaves, padding)
+    }
+}
+
+impl<H: MerkleHasher, S: NodeStore> MerkleTree<H, S> {
+    /// A tree over the nodes in `nodes`, each leaf's id its position.
+    fn from_store(nodes: S, leaves: usize, padding: Padding) -> Self {
         MerkleTree {
-            nodes: nodes,
-            leaves: leaves,
+            nodes,
+            leaves,
+            layout: Layout::new(leaves).expect("trees have at least one leaf"),
+            padding,
+            labels: Map::new(),
+            leaf_labels: Map::new(),
+            ids: Vec::new(),
+            id_positions: Vec::new(),
+            positions: None,
+            hasher: PhantomData,
         }
     }
 
@@ -61,58 +477,241 @@ impl MerkleTree {
     }
 
     pub fn root(&self) -> [u8; 32] {
-        self.nodes[self.size() - 1].value
+        self.nodes.get(self.size() - 1)
+    }
+
+    pub fn padding(&self) -> Padding {
+        self.padding
+    }
+
+    pub fn node(&self, idx: usize) -> Result<[u8; 32], Error> {
+        self.check(idx)?;
+        Ok(self.nodes.get(idx))
     }
 
-    pub fn path(&self, idx: usize) -> Vec<([u8; 32], bool)> {
-        let mut hashes = Vec::new();
+    /// Sibling hashes from leaf `idx` up to the root. Levels where the path
+    /// node is promoted contribute no entry.
+    pub fn path(&self, idx: usize) -> Result<Path, Error> {
+        self.check(idx)?;
+        if idx >= self.leaves() {
+            return Err(Error::NotALeaf(idx));
+        }
+        Ok(self
+            .steps(idx)
+            .into_iter()
+            .map(|(_, entry)| entry)
+            .collect())
+    }
+
+    /// Path entries from `idx` upwards, each with the node it is applied to.
+    pub(crate) fn steps(&self, idx: usize) -> Vec<(usize, ([u8; 32], bool))> {
+        let mut steps = Vec::with_capacity(self.layout.levels() - 1);
         let mut cidx = idx;
-        while cidx != self.size() - 1 {
-            hashes.push((self.nodes[self.sibling_idx(cidx)].value, (cidx % 2) != 0));
-            cidx = self.parent_idx(cidx);
+        while cidx != self.layout.root() {
+            let (_, offset) = self.layout.level_offset(cidx).expect("node is in range");
+            let sibling = match self.layout.sibling(cidx) {
+                Some(sibling) => Some(self.nodes.get(sibling)),
+                None => self.padding.sibling(&self.nodes.get(cidx)),
+            };
+            if let Some(sibling) = sibling {
+                steps.push((cidx, (sibling, offset & 1 == 1)));
+            }
+            cidx = self
+                .layout
+                .parent(cidx)
+                .expect("only the root has no parent");
         }
-        hashes
+        steps
     }
 
-    pub fn proof(&self, item: &[u8], idx: usize) -> Option<Vec<([u8; 32], bool)>> {
-        if idx >= self.size() || (hash_data(item) != self.nodes[idx].value) {
+    pub fn proof(&self, item: &[u8], idx: usize) -> Option<Path> {
+        if idx >= self.leaves() || (H::hash_data(item) != self.nodes.get(idx)) {
             None
         } else {
-            Some(self.path(idx))
+            self.path(idx).ok()
+        }
+    }
+
+    /// Attaches an application label to a leaf. Labels are not committed to
+    /// by the root; each label may name at most one leaf.
+    pub fn set_label<L: AsRef<[u8]>>(&mut self, idx: usize, label: L) -> bool {
+        let label = label.as_ref();
+        if idx >= self.leaves() || self.labels.get(label).is_some_and(|&i| i != idx) {
+            return false;
+        }
+        if let Some(old) = self.leaf_labels.insert(idx, label.to_vec()) {
+            self.labels.remove(&old);
         }
+        self.labels.insert(label.to_vec(), idx);
+        true
     }
 
-    pub fn parent_idx(&self, idx: usize) -> usize {
-        idx / 2 + self.leaves()
+    pub fn label(&self, idx: usize) -> Option<&[u8]> {
+        self.leaf_labels.get(&idx).map(Vec::as_slice)
     }
 
-    pub fn sibling_idx(&self, idx: usize) -> usize {
-        if idx % 2 == 0 {
-            idx + 1
+    pub fn leaf_by_label<L: AsRef<[u8]>>(&self, label: L) -> Option<usize> {
+        self.labels.get(label.as_ref()).copied()
+    }
+
+    pub fn proof_by_label<L: AsRef<[u8]>>(&self, label: L) -> Option<Path> {
+        self.leaf_by_label(label)
+            .and_then(|idx| self.path(idx).ok())
+    }
+
+    /// Stable identifier of the leaf at `idx`. Identifiers are handed out
+    /// in the order leaves are given, by construction and then by `append`,
+    /// and keep naming the same leaf when a sorted constructor moves it.
+    pub fn leaf_id(&self, idx: usize) -> Option<u64> {
+        if self.ids.is_empty() {
+            return (idx < self.leaves).then_some(idx as u64);
+        }
+        self.ids.get(idx).copied()
+    }
+
+    pub fn leaf_by_id(&self, id: u64) -> Option<usize> {
+        if self.id_positions.is_empty() {
+            return (id < self.leaves as u64).then_some(id as usize);
+        }
+        let id = usize::try_from(id).ok()?;
+        self.id_positions.get(id).copied()
+    }
+
+    /// Id the next appended leaf will get.
+    pub fn next_id(&self) -> u64 {
+        self.leaves as u64
+    }
+
+    /// Gives leaf `idx` the id `ids[idx]`. Returns `None` unless `ids` is
+    /// a permutation of `0..leaves`.
+    #[cfg(feature = "std")]
+    pub(crate) fn with_ids(mut self, ids: Vec<u64>) -> Option<Self> {
+        if ids.len() != self.leaves {
+            return None;
+        }
+        let mut id_positions = alloc::vec![usize::MAX; self.leaves];
+        for (idx, &id) in ids.iter().enumerate() {
+            let slot = id_positions.get_mut(usize::try_from(id).ok()?)?;
+            if *slot != usize::MAX {
+                return None;
+            }
+            *slot = idx;
+        }
+        if id_positions.iter().enumerate().any(|(id, &idx)| id != idx) {
+            self.ids = ids;
+            self.id_positions = id_positions;
         } else {
-            idx - 1
+            self.ids.clear();
+            self.id_positions.clear();
         }
+        Some(self)
     }
 
-    pub fn left_idx(&self, idx: usize) -> usize {
-        2 * (idx - self.leaves()) + 1
+    pub fn proof_by_id(&self, id: u64) -> Option<Path> {
+        self.leaf_by_id(id).and_then(|idx| self.path(idx).ok())
     }
 
-    pub fn right_idx(&self, idx: usize) -> usize {
-        2 * (idx - self.leaves())
+    fn check(&self, idx: usize) -> Result<(), Error> {
+        if idx < self.size() {
+            Ok(())
+        } else {
+            Err(Error::IndexOutOfRange {
+                index: idx,
+                size: self.size(),
+            })
+        }
+    }
+
+    pub fn parent_idx(&self, idx: usize) -> Result<usize, Error> {
+        self.check(idx)?;
+        self.layout.parent(idx).ok_or(Error::Root(idx))
+    }
+
+    pub fn sibling_idx(&self, idx: usize) -> Result<usize, Error> {
+        self.check(idx)?;
+        if idx == self.layout.root() {
+            return Err(Error::Root(idx));
+        }
+        self.layout.sibling(idx).ok_or(Error::NoSibling(idx))
+    }
+
+    /// Child hashed first: the one at the odd offset. Fails with
+    /// `NoSibling` naming the other child if that child is alone.
+    pub fn left_idx(&self, idx: usize) -> Result<usize, Error> {
+        self.check(idx)?;
+        let (even, odd) = self
+            .layout
+            .children(idx)
+            .ok_or(Error::NotAnInternalNode(idx))?;
+        odd.ok_or(Error::NoSibling(even))
+    }
+
+    pub fn right_idx(&self, idx: usize) -> Result<usize, Error> {
+        self.check(idx)?;
+        let (even, _) = self
+            .layout
+            .children(idx)
+            .ok_or(Error::NotAnInternalNode(idx))?;
+        Ok(even)
     }
 }
 
-pub fn verify_proof(item: &[u8], root: [u8; 32], proof: &Vec<([u8; 32], bool)>) -> bool {
-    let mut candidate = hash_data(item);
-    for (hash, parity) in proof.iter() {
-        if *parity {
-            candidate = hash_pair(&candidate, &hash);
-        } else {
-            candidate = hash_pair(&hash, &candidate);
+/// Checks `proof` for `item` against `root`, reporting why it failed. With
+/// only the root to compare against, a wrong proof shows up at the root
+/// level; `MerkleTree::diagnose` can locate it. Proofs deeper than
+/// `bounded::MAX_DEPTH` fail as `BadLength` without being hashed, leaving
+/// the leaf hash as `computed`.
+pub fn verify_proof(
+    item: &[u8],
+    root: [u8; 32],
+    proof: &[([u8; 32], bool)],
+) -> VerificationOutcome {
+    verify_proof_with::<Sha256>(item, root, proof)
+}
+
+/// Like `verify_proof`, for a tree hashed with `H`.
+pub fn verify_proof_with<H: MerkleHasher>(
+    item: &[u8],
+    root: [u8; 32],
+    proof: &[([u8; 32], bool)],
+) -> VerificationOutcome {
+    verify_proof_in::<H>(&mut H::Context::default(), item, root, proof)
+}
+
+/// Like `verify_proof_with`, hashing through `ctx`, so that a caller
+/// checking many proofs sets up its hasher once.
+pub fn verify_proof_in<H: MerkleHasher>(
+    ctx: &mut H::Context,
+    item: &[u8],
+    root: [u8; 32],
+    proof: &[([u8; 32], bool)],
+) -> VerificationOutcome {
+    let mut candidate = H::hash_data_with(ctx, item);
+    let kind = if proof.len() > bounded::MAX_DEPTH {
+        FailureKind::BadLength
+    } else if proof.is_empty() {
+        FailureKind::BadLeaf
+    } else {
+        FailureKind::BadPath
+    };
+    if kind != FailureKind::BadLength {
+        for (hash, parity) in proof.iter() {
+            if *parity {
+                candidate = H::hash_pair_with(ctx, &candidate, hash);
+            } else {
+                candidate = H::hash_pair_with(ctx, hash, &candidate);
+            }
+        }
+        if candidate == root {
+            return VerificationOutcome::Valid;
         }
     }
-    candidate == root
+    VerificationOutcome::Invalid(Failure {
+        kind,
+        level: proof.len(),
+        expected: root,
+        computed: candidate,
+    })
 }
 
 #[cfg(test)]
@@ -139,8 +738,8 @@ mod tests {
         for idx in tree.leaves()..tree.size() {
             assert_eq!(
                 hash_pair(
-                    &tree.nodes[tree.left_idx(idx)].value,
-                    &tree.nodes[tree.right_idx(idx)].value
+                    &tree.nodes[tree.left_idx(idx).unwrap()].value,
+                    &tree.nodes[tree.right_idx(idx).unwrap()].value
                 ),
                 tree.nodes[idx].value
             );
@@ -165,7 +764,7 @@ mod tests {
 
         let item = &data.chunks(data.len() / 4).nth(2).unwrap();
         let proof = tree.proof(item, 2).unwrap();
-        assert!(verify_proof(item, tree.root(), &proof));
+        assert!(verify_proof(item, tree.root(), &proof).is_valid());
     }
 
     #[test]
@@ -176,6 +775,183 @@ mod tests {
         let item = &data.chunks(data.len() / 4).nth(2).unwrap();
         let mut proof = tree.proof(item, 2).unwrap();
         proof[0].1 = !proof[0].1;
-        assert_eq!(verify_proof(item, tree.root(), &proof), false);
+        assert!(!verify_proof(item, tree.root(), &proof).is_valid());
+    }
+
+    #[test]
+    fn labelled_leaves() {
+        let data = b"asdfjkln12345678";
+        let mut tree = MerkleTree::with_labels(data, 4, ["a", "b", "invoice-2024-001"]).unwrap();
+
+        assert_eq!(tree.leaf_by_label("invoice-2024-001"), Some(2));
+        assert_eq!(tree.label(1), Some(&b"b"[..]));
+        assert!(tree.label(3).is_none());
+
+        let item = &data.chunks(data.len() / 4).nth(2).unwrap();
+        let proof = tree.proof_by_label("invoice-2024-001").unwrap();
+        assert!(verify_proof(item, tree.root(), &proof).is_valid());
+
+        assert!(!tree.set_label(3, "a"));
+        assert!(!tree.set_label(4, "d"));
+        assert!(tree.set_label(2, b"relabelled"));
+        assert!(tree.proof_by_label("invoice-2024-001").is_none());
+        assert_eq!(tree.leaf_by_label(b"relabelled"), Some(2));
+
+        assert!(MerkleTree::with_labels(data, 4, ["a", "b", "a"]).is_none());
+        assert!(MerkleTree::with_labels(data, 4, ["a", "b", "c", "d", "e"]).is_none());
+        assert!(MerkleTree::with_labels(data, 4, Vec::<&str>::new()).is_some());
+    }
+
+    #[test]
+    fn stable_ids() {
+        let data = b"asdfjkln12345678";
+        let tree = MerkleTree::new(data, 4);
+
+        assert_eq!(tree.leaf_id(3), Some(3));
+        assert!(tree.leaf_id(4).is_none());
+        assert_eq!(tree.next_id(), 4);
+
+        let item = &data.chunks(data.len() / 4).nth(1).unwrap();
+        let proof = tree.proof_by_id(tree.leaf_id(1).unwrap()).unwrap();
+        assert!(verify_proof(item, tree.root(), &proof).is_valid());
+        assert!(tree.proof_by_id(4).is_none());
+    }
+
+    #[test]
+    fn navigation_errors() {
+        let tree = MerkleTree::new(b"asdfjkln12345678", 4);
+        assert_eq!(tree.path(5), Err(Error::NotALeaf(5)));
+        assert_eq!(
+            tree.path(7),
+            Err(Error::IndexOutOfRange { index: 7, size: 7 })
+        );
+        assert_eq!(tree.parent_idx(6), Err(Error::Root(6)));
+        assert_eq!(tree.sibling_idx(6), Err(Error::Root(6)));
+        assert_eq!(tree.left_idx(3), Err(Error::NotAnInternalNode(3)));
+        assert_eq!(
+            tree.right_idx(9),
+            Err(Error::IndexOutOfRange { index: 9, size: 7 })
+        );
+        assert_eq!(tree.node(6), Ok(tree.root()));
+        assert!(tree.node(7).is_err());
+    }
+
+    #[test]
+    fn invariants() {
+        for &leaves in [1, 2, 4, 8, 32].iter() {
+            let data: Vec<u8> = (0..leaves * 3).map(|i| i as u8).collect();
+            let tree = MerkleTree::new(&data, leaves);
+            let root = tree.size() - 1;
+            assert_eq!(tree.size(), 2 * leaves - 1);
+
+            for idx in 0..root {
+                let parent = tree.parent_idx(idx).unwrap();
+                let sibling = tree.sibling_idx(idx).unwrap();
+                assert!(parent > idx);
+                assert_ne!(sibling, idx);
+                assert_eq!(tree.sibling_idx(sibling), Ok(idx));
+                assert_eq!(tree.parent_idx(sibling), Ok(parent));
+                let children = [
+                    tree.left_idx(parent).unwrap(),
+                    tree.right_idx(parent).unwrap(),
+                ];
+                assert!(children.contains(&idx) && children.contains(&sibling));
+            }
+            for idx in leaves..tree.size() {
+                let (left, right) = (tree.left_idx(idx).unwrap(), tree.right_idx(idx).unwrap());
+                assert_eq!(left & 1, 1);
+                assert_eq!(
+                    tree.node(idx),
+                    Ok(hash_pair(
+                        &tree.node(left).unwrap(),
+                        &tree.node(right).unwrap()
+                    ))
+                );
+                assert_eq!(tree.path(idx), Err(Error::NotALeaf(idx)));
+            }
+            for (idx, item) in data.chunks(3).enumerate() {
+                let path = tree.path(idx).unwrap();
+                assert_eq!(path.len(), tree.depth());
+                assert!(verify_proof(item, tree.root(), &path).is_valid());
+            }
+        }
+    }
+
+    #[test]
+    fn pluggable_hashers() {
+        use sha2::{Sha512, Sha512Trunc256};
+
+        let data = b"asdfjkln12345678";
+        let sha256 = MerkleTree::new(data, 4);
+        let trunc = MerkleTree::<Sha512Trunc256>::build(data, 4);
+        let wide = MerkleTree::<Truncated<Sha512>>::build(data, 4);
+        assert_ne!(sha256.root(), trunc.root());
+        assert_ne!(trunc.root(), wide.root());
+
+        let path = trunc.proof(b"jkln", 1).unwrap();
+        assert!(verify_proof_with::<Sha512Trunc256>(b"jkln", trunc.root(), &path).is_valid());
+        assert!(!verify_proof(b"jkln", trunc.root(), &path).is_valid());
+        assert!(wide.proof(b"jkln", 1).is_some());
+    }
+
+    #[test]
+    fn odd_leaf_counts() {
+        let items: Vec<[u8; 1]> = (0..7u8).map(|i| [i]).collect();
+        for &padding in [Padding::Promote, Padding::DuplicateLast, Padding::Zero].iter() {
+            for leaves in 1..=items.len() {
+                let tree = MerkleTree::from_leaves_padded(&items[..leaves], padding).unwrap();
+                assert_eq!(tree.size(), tree.layout().nodes());
+                for (idx, item) in items[..leaves].iter().enumerate() {
+                    let path = tree.proof(item, idx).unwrap();
+                    assert!(verify_proof(item, tree.root(), &path).is_valid());
+                    assert_eq!(
+                        tree.diagnose(item, idx, &path),
+                        Ok(VerificationOutcome::Valid)
+                    );
+                }
+            }
+        }
+
+        let [a, b, c] = [hash_data(&[0]), hash_data(&[1]), hash_data(&[2])];
+        let ab = hash_pair(&b, &a);
+        let root = |padding| {
+            MerkleTree::from_leaves_padded(&items[..3], padding)
+                .unwrap()
+                .root()
+        };
+        assert_eq!(root(Padding::Promote), hash_pair(&c, &ab));
+        assert_eq!(
+            root(Padding::DuplicateLast),
+            hash_pair(&hash_pair(&c, &c), &ab)
+        );
+        assert_eq!(
+            root(Padding::Zero),
+            hash_pair(&hash_pair(&[0; 32], &c), &ab)
+        );
+
+        let tree = MerkleTree::from_leaves(&items[..5]).unwrap();
+        assert_eq!(tree.path(4).unwrap(), vec![(tree.node(8).unwrap(), true)]);
+        assert_eq!(tree.sibling_idx(4), Err(Error::NoSibling(4)));
+        assert_eq!(tree.left_idx(7), Err(Error::NoSibling(4)));
+        assert_eq!(
+            MerkleTree::new(b"abcdef", 6).root(),
+            MerkleTree::from_leaves(b"abcdef".chunks(1)).unwrap().root()
+        );
+    }
+
+    #[test]
+    fn variable_length_leaves() {
+        let tree = MerkleTree::from_leaves(vec!["a", "longer item", "", "xyz"]).unwrap();
+        assert_eq!(tree.leaves(), 4);
+        let path = tree.proof(b"longer item", 1).unwrap();
+        assert!(verify_proof(b"longer item", tree.root(), &path).is_valid());
+        assert!(tree.proof(b"", 2).is_some());
+
+        let packed = MerkleTree::from_leaves(b"asdfjkln12345678".chunks(4)).unwrap();
+        assert_eq!(
+            packed.root(),
+            MerkleTree::new(b"asdfjkln12345678", 4).root()
+        );
+        assert!(MerkleTree::from_leaves(Vec::<Vec<u8>>::new()).is_none());
     }
 }
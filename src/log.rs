@@ -0,0 +1,610 @@
+//! An append-only transparency log following RFC 6962 (Certificate
+//! Transparency). The log uses CT's hashing, not this crate's tree layout:
+//! leaves are `H(0x00 || data)`, nodes are `H(0x01 || left || right)` in
+//! position order, and a log of `n > 1` leaves splits into a perfect left
+//! subtree of the largest power of two below `n` and the rest.
+//!
+//! Appends only hash the perfect subtrees they complete, so the log never
+//! rebuilds. Inclusion and consistency proofs are generated and verified as
+//! in RFC 6962 section 2.1 (verification as in RFC 9162 section 2.1).
+//!
+//! Proofs only read the roots of perfect subtrees, so a [`StoredLog`] keeps
+//! those in a [`NodeStore`] such as a [`FileStore`] and serves proofs by
+//! reading the few it needs, without the log in memory. Each append writes
+//! one record: the leaf hash, then the roots it completes from the bottom
+//! up. Records of the first `n` leaves hold `2n - popcount(n)` hashes, so
+//! any root is found from its height and offset alone.
+
+use crate::estimate::{consistency_proof_hashes, split_point};
+use crate::memory::{vec_bytes, HeapSize};
+use crate::{FileStore, NodeStore};
+use sha2::{Digest, Sha256};
+use std::convert::{Infallible, TryFrom};
+
+pub fn leaf_hash(data: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update([0]);
+    hasher.update(data);
+    hasher.finalize().into()
+}
+
+pub fn node_hash(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update([1]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+/// Root of the empty log, `H("")`.
+pub fn empty_root() -> [u8; 32] {
+    crate::EMPTY_ROOT
+}
+
+/// The recursions of RFC 6962 section 2.1 over a source of perfect subtree
+/// roots.
+trait Subtrees {
+    type Error;
+
+    /// Root of leaves `index * 2^height .. (index + 1) * 2^height`.
+    fn perfect(&self, height: u32, index: usize) -> Result<[u8; 32], Self::Error>;
+
+    /// Root of leaves `start..end`, which the RFC's recursion only asks for
+    /// with `start` a multiple of the largest power of two below `end -
+    /// start` (or of `end - start` itself when that is a power of two).
+    fn subtree(&self, start: usize, end: usize) -> Result<[u8; 32], Self::Error> {
+        let len = end - start;
+        if len.is_power_of_two() && start & (len - 1) == 0 {
+            return self.perfect(len.trailing_zeros(), start / len);
+        }
+        let k = split_point(len);
+        Ok(node_hash(
+            &self.subtree(start, start + k)?,
+            &self.subtree(start + k, end)?,
+        ))
+    }
+
+    fn path(
+        &self,
+        index: usize,
+        start: usize,
+        end: usize,
+        proof: &mut Vec<[u8; 32]>,
+    ) -> Result<(), Self::Error> {
+        if end - start == 1 {
+            return Ok(());
+        }
+        let k = split_point(end - start);
+        if index < start + k {
+            self.path(index, start, start + k, proof)?;
+            proof.push(self.subtree(start + k, end)?);
+        } else {
+            self.path(index, start + k, end, proof)?;
+            proof.push(self.subtree(start, start + k)?);
+        }
+        Ok(())
+    }
+
+    /// SUBPROOF(m, D[start:end], complete) from RFC 6962 section 2.1.2.
+    fn subproof(
+        &self,
+        m: usize,
+        start: usize,
+        end: usize,
+        complete: bool,
+        proof: &mut Vec<[u8; 32]>,
+    ) -> Result<(), Self::Error> {
+        let n = end - start;
+        if m == n {
+            if !complete {
+                proof.push(self.subtree(start, end)?);
+            }
+            return Ok(());
+        }
+        let k = split_point(n);
+        if m <= k {
+            self.subproof(m, start, start + k, complete, proof)?;
+            proof.push(self.subtree(start + k, end)?);
+        } else {
+            self.subproof(m - k, start + k, end, false, proof)?;
+            proof.push(self.subtree(start, start + k)?);
+        }
+        Ok(())
+    }
+
+    fn root_of(&self, size: usize) -> Result<[u8; 32], Self::Error> {
+        match size {
+            0 => Ok(empty_root()),
+            _ => self.subtree(0, size),
+        }
+    }
+
+    fn inclusion_of(&self, index: usize, size: usize) -> Result<Vec<[u8; 32]>, Self::Error> {
+        let mut proof = Vec::new();
+        self.path(index, 0, size, &mut proof)?;
+        Ok(proof)
+    }
+
+    fn consistency_of(&self, old: usize, new: usize) -> Result<Vec<[u8; 32]>, Self::Error> {
+        let mut proof = Vec::new();
+        if old > 0 && old < new {
+            self.subproof(old, 0, new, true, &mut proof)?;
+        }
+        Ok(proof)
+    }
+}
+
+fn infallible<T>(result: Result<T, Infallible>) -> T {
+    match result {
+        Ok(value) => value,
+        Err(never) => match never {},
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MerkleLog {
+    /// `levels[h][i]` is the root of leaves `i * 2^h .. (i + 1) * 2^h`.
+    levels: Vec<Vec<[u8; 32]>>,
+}
+
+impl MerkleLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> u64 {
+        self.levels.first().map_or(0, |leaves| leaves.len() as u64)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Appends a leaf and returns its index.
+    pub fn append(&mut self, data: &[u8]) -> u64 {
+        let index = self.len();
+        let mut node = leaf_hash(data);
+        let mut height = 0;
+        loop {
+            if self.levels.len() == height {
+                self.levels.push(Vec::new());
+            }
+            let level = &mut self.levels[height];
+            level.push(node);
+            if level.len() & 1 == 1 {
+                break;
+            }
+            node = node_hash(&level[level.len() - 2], &level[level.len() - 1]);
+            height += 1;
+        }
+        index
+    }
+
+    pub fn leaf(&self, index: u64) -> Option<[u8; 32]> {
+        self.levels
+            .first()?
+            .get(usize::try_from(index).ok()?)
+            .copied()
+    }
+
+    pub fn root(&self) -> [u8; 32] {
+        self.root_at(self.len())
+            .expect("the current size is in range")
+    }
+
+    /// Root of the log when it held its first `size` leaves.
+    pub fn root_at(&self, size: u64) -> Option<[u8; 32]> {
+        if size > self.len() {
+            return None;
+        }
+        Some(infallible(self.root_of(size as usize)))
+    }
+
+    /// Audit path for leaf `index` in the log at `size`, from the leaf up.
+    pub fn inclusion_proof(&self, index: u64, size: u64) -> Option<Vec<[u8; 32]>> {
+        if index >= size || size > self.len() {
+            return None;
+        }
+        Some(infallible(self.inclusion_of(index as usize, size as usize)))
+    }
+
+    /// Proof that the log at `old` is a prefix of the log at `new`. Empty
+    /// when `old` is 0 or equal to `new`.
+    pub fn consistency_proof(&self, old: u64, new: u64) -> Option<Vec<[u8; 32]>> {
+        if old > new || new > self.len() {
+            return None;
+        }
+        Some(infallible(self.consistency_of(old as usize, new as usize)))
+    }
+
+    /// Hashes in `consistency_proof(old, new)`, known from the sizes
+    /// alone. Returns `None` if `old > new`.
+    pub fn consistency_proof_len(old: u64, new: u64) -> Option<usize> {
+        let old = usize::try_from(old).ok()?;
+        consistency_proof_hashes(old, usize::try_from(new).ok()?)
+    }
+}
+
+impl Subtrees for MerkleLog {
+    type Error = Infallible;
+
+    fn perfect(&self, height: u32, index: usize) -> Result<[u8; 32], Infallible> {
+        Ok(self.levels[height as usize][index])
+    }
+}
+
+/// Hashes in the records of the first `leaves` appends.
+fn records(leaves: usize) -> usize {
+    2 * leaves - leaves.count_ones() as usize
+}
+
+/// A log kept in a node store, one record per append (see the module
+/// docs). Proofs read `O(log^2 n)` hashes from the store, and appends read
+/// and write `O(log n)`.
+#[derive(Debug)]
+pub struct StoredLog<S: NodeStore = FileStore> {
+    store: S,
+    leaves: usize,
+}
+
+impl<S: NodeStore> StoredLog<S> {
+    /// Opens the log in `store`, which may be empty. Returns `None` if the
+    /// store ends part way through a record, as after a torn append.
+    pub fn open(store: S) -> Option<Self> {
+        // `records` is increasing, and `records(n) >= n`.
+        let (mut low, mut high) = (0, store.len());
+        while low < high {
+            let mid = low + (high - low) / 2;
+            if records(mid) < store.len() {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        if records(low) != store.len() {
+            return None;
+        }
+        Some(StoredLog { store, leaves: low })
+    }
+
+    pub fn len(&self) -> u64 {
+        self.leaves as u64
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.leaves == 0
+    }
+
+    /// Appends a leaf and returns its index. If the store fails part way,
+    /// the record is torn and `open` refuses the store until it is cut
+    /// back to the last whole record.
+    pub fn append(&mut self, data: &[u8]) -> Result<u64, S::Error> {
+        let index = self.leaves;
+        let mut node = leaf_hash(data);
+        let mut at = self.store.len();
+        self.store.try_put(at, node)?;
+        let mut height = 0;
+        while (index >> height) & 1 == 1 {
+            let left = self.perfect(height, (index >> height) - 1)?;
+            node = node_hash(&left, &node);
+            at += 1;
+            self.store.try_put(at, node)?;
+            height += 1;
+        }
+        self.leaves += 1;
+        Ok(index as u64)
+    }
+
+    /// Like `MerkleLog::root_at`.
+    pub fn root_at(&self, size: u64) -> Result<Option<[u8; 32]>, S::Error> {
+        if size > self.len() {
+            return Ok(None);
+        }
+        self.root_of(size as usize).map(Some)
+    }
+
+    /// Like `MerkleLog::inclusion_proof`.
+    pub fn inclusion_proof(
+        &self,
+        index: u64,
+        size: u64,
+    ) -> Result<Option<Vec<[u8; 32]>>, S::Error> {
+        if index >= size || size > self.len() {
+            return Ok(None);
+        }
+        self.inclusion_of(index as usize, size as usize).map(Some)
+    }
+
+    /// Like `MerkleLog::consistency_proof`.
+    pub fn consistency_proof(&self, old: u64, new: u64) -> Result<Option<Vec<[u8; 32]>>, S::Error> {
+        if old > new || new > self.len() {
+            return Ok(None);
+        }
+        self.consistency_of(old as usize, new as usize).map(Some)
+    }
+
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    pub fn into_store(self) -> S {
+        self.store
+    }
+}
+
+impl<S: NodeStore> Subtrees for StoredLog<S> {
+    type Error = S::Error;
+
+    /// The root is the last hash of the record of the subtree's last leaf.
+    fn perfect(&self, height: u32, index: usize) -> Result<[u8; 32], S::Error> {
+        let last = ((index + 1) << height) - 1;
+        self.store.try_get(records(last) + height as usize)
+    }
+}
+
+/// Checks that `data` is leaf `index` of the log of `size` leaves with
+/// `root`.
+pub fn verify_inclusion(
+    data: &[u8],
+    index: u64,
+    size: u64,
+    root: [u8; 32],
+    proof: &[[u8; 32]],
+) -> bool {
+    if index >= size {
+        return false;
+    }
+    let (mut fnode, mut snode) = (index, size - 1);
+    let mut candidate = leaf_hash(data);
+    for sibling in proof {
+        if snode == 0 {
+            return false;
+        }
+        if fnode & 1 == 1 || fnode == snode {
+            candidate = node_hash(sibling, &candidate);
+            if fnode & 1 == 0 {
+                while fnode & 1 == 0 && fnode != 0 {
+                    fnode >>= 1;
+                    snode >>= 1;
+                }
+            }
+        } else {
+            candidate = node_hash(&candidate, sibling);
+        }
+        fnode >>= 1;
+        snode >>= 1;
+    }
+    snode == 0 && candidate == root
+}
+
+/// Checks that the log of `old` leaves with `old_root` is a prefix of the
+/// log of `new` leaves with `new_root`.
+pub fn verify_consistency(
+    old: u64,
+    new: u64,
+    old_root: [u8; 32],
+    new_root: [u8; 32],
+    proof: &[[u8; 32]],
+) -> bool {
+    if old > new {
+        return false;
+    }
+    if old == new {
+        return proof.is_empty() && old_root == new_root;
+    }
+    if old == 0 {
+        return proof.is_empty();
+    }
+    // A perfect old tree is its own first node and is left out of the proof.
+    let mut nodes: Vec<[u8; 32]> = Vec::with_capacity(proof.len() + 1);
+    if old.is_power_of_two() {
+        nodes.push(old_root);
+    }
+    nodes.extend_from_slice(proof);
+    let (first, rest) = match nodes.split_first() {
+        Some(split) => split,
+        None => return false,
+    };
+
+    let (mut fnode, mut snode) = (old - 1, new - 1);
+    while fnode & 1 == 1 {
+        fnode >>= 1;
+        snode >>= 1;
+    }
+    let (mut fr, mut sr) = (*first, *first);
+    for sibling in rest {
+        if snode == 0 {
+            return false;
+        }
+        if fnode & 1 == 1 || fnode == snode {
+            fr = node_hash(sibling, &fr);
+            sr = node_hash(sibling, &sr);
+            if fnode & 1 == 0 {
+                while fnode & 1 == 0 && fnode != 0 {
+                    fnode >>= 1;
+                    snode >>= 1;
+                }
+            }
+        } else {
+            sr = node_hash(&sr, sibling);
+        }
+        fnode >>= 1;
+        snode >>= 1;
+    }
+    snode == 0 && fr == old_root && sr == new_root
+}
+
+impl HeapSize for MerkleLog {
+    fn heap_size_bytes(&self) -> usize {
+        vec_bytes(&self.levels) + self.levels.iter().map(vec_bytes).sum::<usize>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn log(size: u64) -> MerkleLog {
+        let mut log = MerkleLog::new();
+        for i in 0..size {
+            assert_eq!(log.append(&i.to_le_bytes()), i);
+        }
+        log
+    }
+
+    /// MTH from RFC 6962 section 2.1, computed directly.
+    fn reference_root(leaves: &[[u8; 32]]) -> [u8; 32] {
+        match leaves.len() {
+            0 => empty_root(),
+            1 => leaves[0],
+            n => {
+                let k = split_point(n);
+                node_hash(&reference_root(&leaves[..k]), &reference_root(&leaves[k..]))
+            }
+        }
+    }
+
+    #[test]
+    fn roots_match_rfc() {
+        let log = log(13);
+        let leaves: Vec<[u8; 32]> = (0..13u64).map(|i| leaf_hash(&i.to_le_bytes())).collect();
+        for size in 0..=13 {
+            assert_eq!(
+                log.root_at(size),
+                Some(reference_root(&leaves[..size as usize]))
+            );
+        }
+        assert_eq!(log.root_at(14), None);
+        assert_eq!(MerkleLog::new().root(), empty_root());
+
+        // From the Certificate Transparency test vectors.
+        let mut single = MerkleLog::new();
+        single.append(b"");
+        assert_eq!(single.root()[..4], [0x6e, 0x34, 0x0b, 0x9c][..]);
+    }
+
+    #[test]
+    fn inclusion_proofs() {
+        let log = log(13);
+        for size in 1..=13 {
+            let root = log.root_at(size).unwrap();
+            for index in 0..size {
+                let proof = log.inclusion_proof(index, size).unwrap();
+                assert!(verify_inclusion(
+                    &index.to_le_bytes(),
+                    index,
+                    size,
+                    root,
+                    &proof
+                ));
+                assert!(!verify_inclusion(&[0xff], index, size, root, &proof));
+                if size > 1 {
+                    assert!(!verify_inclusion(
+                        &index.to_le_bytes(),
+                        index,
+                        size,
+                        root,
+                        &proof[1..]
+                    ));
+                }
+            }
+        }
+        assert_eq!(log.inclusion_proof(3, 3), None);
+        assert_eq!(log.inclusion_proof(0, 14), None);
+    }
+
+    #[test]
+    fn stored_logs_match() {
+        use crate::Node;
+
+        let memory = log(37);
+        let mut stored = StoredLog::open(Vec::<Node>::new()).unwrap();
+        for i in 0..37u64 {
+            assert_eq!(stored.append(&i.to_le_bytes()), Ok(i));
+        }
+        assert_eq!(stored.store().len(), records(37));
+        for new in 0..=37 {
+            assert_eq!(stored.root_at(new), Ok(memory.root_at(new)));
+            for old in 0..=new {
+                assert_eq!(
+                    stored.consistency_proof(old, new),
+                    Ok(memory.consistency_proof(old, new))
+                );
+            }
+            for index in 0..new {
+                assert_eq!(
+                    stored.inclusion_proof(index, new),
+                    Ok(memory.inclusion_proof(index, new))
+                );
+            }
+        }
+        assert_eq!(stored.consistency_proof(3, 38), Ok(None));
+
+        // Record lengths tell a torn append from a whole one: the record of
+        // leaf 35 holds 3 hashes.
+        let mut nodes = stored.into_store();
+        nodes.truncate(records(35) + 1);
+        assert!(StoredLog::open(nodes).is_none());
+        let whole: Vec<Node> = (0..records(36)).map(|_| Node::new([0; 32])).collect();
+        assert!(StoredLog::open(whole).is_some_and(|log| log.len() == 36));
+    }
+
+    #[test]
+    fn file_backed_logs() {
+        let path = std::env::temp_dir().join(format!("merkle-log-{}", std::process::id()));
+        let memory = log(100);
+        let mut stored: StoredLog = StoredLog::open(FileStore::create(&path).unwrap()).unwrap();
+        for i in 0..60u64 {
+            stored.append(&i.to_le_bytes()).unwrap();
+        }
+        stored.store.flush().unwrap();
+        drop(stored);
+
+        // Reopened, the log carries on where it stopped.
+        let mut stored: StoredLog = StoredLog::open(FileStore::open(&path).unwrap()).unwrap();
+        assert_eq!(stored.len(), 60);
+        for i in 60..100u64 {
+            stored.append(&i.to_le_bytes()).unwrap();
+        }
+        let proof = stored.consistency_proof(60, 100).unwrap().unwrap();
+        assert_eq!(Some(proof.clone()), memory.consistency_proof(60, 100));
+        assert!(verify_consistency(
+            60,
+            100,
+            memory.root_at(60).unwrap(),
+            stored.root_at(100).unwrap().unwrap(),
+            &proof
+        ));
+        drop(stored);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn consistency_proofs() {
+        let log = log(13);
+        for new in 0..=13 {
+            let new_root = log.root_at(new).unwrap();
+            for old in 0..=new {
+                let old_root = log.root_at(old).unwrap();
+                let proof = log.consistency_proof(old, new).unwrap();
+                assert_eq!(
+                    Some(proof.len()),
+                    MerkleLog::consistency_proof_len(old, new)
+                );
+                assert!(verify_consistency(old, new, old_root, new_root, &proof));
+                if 0 < old && old < new {
+                    assert!(!verify_consistency(old, new, new_root, new_root, &proof));
+                    assert!(!verify_consistency(
+                        old,
+                        new,
+                        old_root,
+                        new_root,
+                        &proof[1..]
+                    ));
+                }
+            }
+        }
+        assert_eq!(log.consistency_proof(4, 3), None);
+        assert_eq!(log.consistency_proof(3, 14), None);
+    }
+}
@@ -0,0 +1,121 @@
+//! Finding leaves by content. `leaf_indices` scans the leaves, which is
+//! fine for the occasional lookup; `index_leaves` builds a map from leaf
+//! hash to positions so that lookups after it cost one map access. The map
+//! is kept current by `update` and `update_many`, and adds a hash and an
+//! index per leaf to the tree's memory.
+//!
+//! Items that occur more than once have a leaf for each occurrence, so
+//! lookups return every position, in leaf order.
+
+use crate::{MerkleHasher, MerkleTree, Proof};
+use alloc::vec::Vec;
+
+impl<H: MerkleHasher> MerkleTree<H> {
+    /// Builds the hash to position map, if it is not built already.
+    pub fn index_leaves(&mut self) {
+        if self.positions.is_some() {
+            return;
+        }
+        let mut positions = crate::Map::new();
+        for (idx, node) in self.nodes[..self.leaves].iter().enumerate() {
+            positions
+                .entry(node.value)
+                .or_insert_with(Vec::new)
+                .push(idx);
+        }
+        self.positions = Some(positions);
+    }
+
+    pub fn is_indexed(&self) -> bool {
+        self.positions.is_some()
+    }
+
+    /// Every leaf holding `item`, in leaf order.
+    pub fn leaf_indices(&self, item: &[u8]) -> Vec<usize> {
+        let hash = H::hash_data(item);
+        match &self.positions {
+            Some(positions) => positions.get(&hash).cloned().unwrap_or_default(),
+            None => (0..self.leaves)
+                .filter(|&idx| self.nodes[idx].value == hash)
+                .collect(),
+        }
+    }
+
+    /// The first leaf holding `item`.
+    pub fn leaf_index(&self, item: &[u8]) -> Option<usize> {
+        match &self.positions {
+            Some(positions) => positions
+                .get(&H::hash_data(item))
+                .and_then(|found| found.first().copied()),
+            None => self.leaf_indices(item).first().copied(),
+        }
+    }
+
+    /// A proof for the first leaf holding `item`.
+    pub fn proof_for(&self, item: &[u8]) -> Option<Proof> {
+        self.leaf_index(item)
+            .and_then(|idx| self.leaf_proof(idx).ok())
+    }
+
+    /// A proof for every leaf holding `item`, in leaf order.
+    pub fn proofs_for(&self, item: &[u8]) -> Vec<Proof> {
+        self.leaf_indices(item)
+            .into_iter()
+            .filter_map(|idx| self.leaf_proof(idx).ok())
+            .collect()
+    }
+
+    /// Records that leaf `idx` changed from `old` to `new`.
+    pub(crate) fn reposition(&mut self, idx: usize, old: [u8; 32], new: [u8; 32]) {
+        let positions = match &mut self.positions {
+            Some(positions) => positions,
+            None => return,
+        };
+        if let Some(found) = positions.get_mut(&old) {
+            found.retain(|&i| i != idx);
+            if found.is_empty() {
+                positions.remove(&old);
+            }
+        }
+        let found = positions.entry(new).or_insert_with(Vec::new);
+        if let Err(at) = found.binary_search(&idx) {
+            found.insert(at, idx);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{verify_proof, MerkleTree};
+
+    #[test]
+    fn finds_leaves_by_content() {
+        let items = ["a", "b", "a", "c", "a"];
+        let mut tree = MerkleTree::from_leaves(items).unwrap();
+        for _ in 0..2 {
+            assert_eq!(tree.leaf_indices(b"a"), vec![0, 2, 4]);
+            assert_eq!(tree.leaf_index(b"c"), Some(3));
+            assert_eq!(tree.leaf_index(b"d"), None);
+            let proof = tree.proof_for(b"b").unwrap();
+            assert_eq!(proof.index(), 1);
+            assert!(verify_proof(b"b", tree.root(), proof.path()).is_valid());
+            let proofs = tree.proofs_for(b"a");
+            assert_eq!(proofs.len(), 3);
+            assert!(proofs
+                .iter()
+                .all(|proof| verify_proof(b"a", tree.root(), proof.path()).is_valid()));
+            assert!(tree.proof_for(b"d").is_none());
+            tree.index_leaves();
+        }
+        assert!(tree.is_indexed());
+
+        tree.update(2, b"c").unwrap();
+        tree.update(0, b"b").unwrap();
+        assert_eq!(tree.leaf_indices(b"a"), vec![4]);
+        assert_eq!(tree.leaf_indices(b"b"), vec![0, 1]);
+        assert_eq!(tree.leaf_indices(b"c"), vec![2, 3]);
+        tree.update_many([(4, "d")]).unwrap();
+        assert_eq!(tree.leaf_index(b"a"), None);
+        assert_eq!(tree.leaf_index(b"d"), Some(4));
+    }
+}
@@ -0,0 +1,118 @@
+//! Heap accounting. Sizes count every allocation a value owns, using
+//! capacities rather than lengths, but not the value itself; add
+//! `size_of_val` for the inline part.
+
+use crate::MerkleTree;
+use std::collections::HashMap;
+use std::mem::size_of;
+
+pub trait HeapSize {
+    fn heap_size_bytes(&self) -> usize;
+}
+
+pub(crate) fn vec_bytes<T>(vec: &Vec<T>) -> usize {
+    vec.capacity() * size_of::<T>()
+}
+
+/// Bytes allocated by a std `HashMap`: the SwissTable keeps one slot and one
+/// control byte per bucket, plus a trailing group of control bytes, and
+/// keeps at most 7/8 of its buckets full.
+pub(crate) fn table_bytes<K, V>(map: &HashMap<K, V>) -> usize {
+    let capacity = map.capacity();
+    if capacity == 0 {
+        return 0;
+    }
+    let buckets = if capacity < 4 {
+        4
+    } else if capacity < 8 {
+        8
+    } else {
+        (capacity * 8 / 7).next_power_of_two()
+    };
+    buckets * size_of::<(K, V)>() + buckets + 16
+}
+
+/// Bytes allocated by a `BTreeMap` or `BTreeSet` of `len` entries, counting
+/// only its leaf nodes and as if each were full. Nodes hold 11 entries and
+/// are at least half full, so this is a lower bound within a factor of two.
+pub(crate) fn btree_bytes<K, V>(len: usize) -> usize {
+    const CAPACITY: usize = 11;
+    // Parent pointer, index in the parent and length.
+    const HEADER: usize = size_of::<usize>() + 4;
+    len.div_ceil(CAPACITY) * (CAPACITY * (size_of::<K>() + size_of::<V>()) + HEADER)
+}
+
+impl<H> HeapSize for MerkleTree<H> {
+    fn heap_size_bytes(&self) -> usize {
+        let labels: usize = self.labels.keys().map(Vec::capacity).sum();
+        let leaf_labels: usize = self.leaf_labels.values().map(Vec::capacity).sum();
+        let positions = self.positions.as_ref().map_or(0, |positions| {
+            table_bytes(positions) + positions.values().map(vec_bytes).sum::<usize>()
+        });
+        vec_bytes(&self.nodes)
+            + self.layout.heap_size_bytes()
+            + table_bytes(&self.labels)
+            + labels
+            + table_bytes(&self.leaf_labels)
+            + leaf_labels
+            + vec_bytes(&self.ids)
+            + vec_bytes(&self.id_positions)
+            + positions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_nodes_and_maps() {
+        let tree = MerkleTree::new(b"asdfasdfasdfasdfasdfasdfasdfasdf", 8);
+        let base = tree.heap_size_bytes();
+        assert!(base >= 15 * 32 + 8 * 8);
+        assert_eq!(table_bytes(&HashMap::<u64, usize>::new()), 0);
+
+        let mut labelled = MerkleTree::new(b"asdfasdfasdfasdfasdfasdfasdfasdf", 8);
+        labelled.set_label(0, vec![0u8; 1000]);
+        assert!(labelled.heap_size_bytes() >= base + 2000);
+    }
+
+    #[test]
+    fn table_estimate_covers_capacity() {
+        let mut map = HashMap::new();
+        for i in 0..100u64 {
+            map.insert(i, 0usize);
+            assert!(table_bytes(&map) >= map.capacity() * (16 + 1));
+        }
+        assert!(btree_bytes::<u64, u64>(100) >= 100 * 16);
+        assert_eq!(btree_bytes::<u64, u64>(0), 0);
+    }
+
+    #[test]
+    fn counts_other_trees() {
+        use crate::sparse::{self, SparseMerkleTree};
+        use crate::{CountedTree, DualTree, OzTree, PerfectMerkleTree};
+        use sha2::Sha256;
+
+        let items: Vec<[u8; 4]> = (0..16u32).map(u32::to_le_bytes).collect();
+        let tree = MerkleTree::from_leaves(&items).unwrap();
+        assert!(
+            PerfectMerkleTree::from_leaves(&items)
+                .unwrap()
+                .heap_size_bytes()
+                >= 31 * 32
+        );
+        assert!(CountedTree::from_leaves(&items).unwrap().heap_size_bytes() >= 31 * 40);
+        let dual: DualTree<Sha256, Sha256> = DualTree::from_leaves(&items).unwrap();
+        assert!(dual.heap_size_bytes() >= 2 * tree.heap_size_bytes());
+        let leaves = items.iter().map(|item| crate::hash_data(item)).collect();
+        assert!(OzTree::new(leaves).unwrap().heap_size_bytes() >= 31 * 32);
+
+        let mut sparse = SparseMerkleTree::new();
+        let empty = sparse.heap_size_bytes();
+        sparse.insert([1; 32], vec![0; 1000]);
+        // Each key adds a node per level of the keyspace.
+        assert!(sparse.heap_size_bytes() >= empty + 1000 + 256 * 66);
+        assert!(sparse::defaults_heap_size_bytes() >= 257 * 32);
+    }
+}
@@ -0,0 +1,397 @@
+//! Adapters for proofs made by other Merkle tree crates, so historical
+//! proofs keep verifying after a switch without being regenerated. Each
+//! adapter rewrites the foreign proof as a [`Path`] against the foreign
+//! root, checks it, and returns it only if it verifies.
+//!
+//! The foreign trees hash pairs in position order, where this crate hashes
+//! the odd offset first, so their roots differ from those of trees built
+//! here over the same leaves. A migrated path verifies against the old root;
+//! [`rebuild`] gives the tree to serve new proofs from.
+//!
+//! [`migrate_tree`] moves a tree of this crate to another hasher or
+//! padding. Alongside the new tree it gives a [`CrossCommitment`] to both
+//! roots and to the leaf mapping, the tree whose leaf `i` is leaf `i`'s old
+//! hash followed by its new one. With the exported mapping an auditor
+//! rebuilds all three roots and so sees that the two trees hold the same
+//! items in the same order; with a mapping proof, anyone holding an item
+//! checks that it hashes to its leaf in both.
+
+use crate::format::{Version, CURRENT};
+use crate::{Error, MerkleHasher, MerkleTree, Padding, Path, Proof, Rfc6962, TreeHead};
+use sha2::Sha256;
+use std::convert::TryInto;
+
+/// Folds `path` over a leaf hash.
+fn fold<H: MerkleHasher>(leaf: [u8; 32], path: &[([u8; 32], bool)]) -> [u8; 32] {
+    let mut ctx = H::Context::default();
+    path.iter().fold(leaf, |candidate, (sibling, first)| {
+        if *first {
+            H::hash_pair_with(&mut ctx, &candidate, sibling)
+        } else {
+            H::hash_pair_with(&mut ctx, sibling, &candidate)
+        }
+    })
+}
+
+/// Splits proof bytes as written by `rs_merkle`'s `MerkleProof::to_bytes`
+/// into hashes. Returns `None` unless the length is a multiple of 32.
+pub fn rs_merkle_hashes(bytes: &[u8]) -> Option<Vec<[u8; 32]>> {
+    if bytes.len() & 31 != 0 {
+        return None;
+    }
+    Some(
+        bytes
+            .chunks_exact(32)
+            .map(|hash| hash.try_into().unwrap())
+            .collect(),
+    )
+}
+
+/// Migrates an `rs_merkle` single-leaf proof over `Sha256`: `hashes` (from
+/// the leaf up) for leaf `index` of `leaves`. `rs_merkle` carries the last
+/// node of an odd level up unchanged, so such levels have no hash. The
+/// result verifies with `verify_proof` when `leaf` is `hash_data` of the
+/// item.
+pub fn from_rs_merkle(
+    leaf: [u8; 32],
+    index: usize,
+    leaves: usize,
+    hashes: &[[u8; 32]],
+    root: [u8; 32],
+) -> Option<Proof> {
+    if index >= leaves {
+        return None;
+    }
+    let mut hashes = hashes.iter();
+    let mut path = Vec::new();
+    let (mut offset, mut len) = (index, leaves);
+    while len > 1 {
+        if offset & 1 == 1 || offset + 1 < len {
+            path.push((*hashes.next()?, offset & 1 == 0));
+        }
+        offset /= 2;
+        len = len.div_ceil(2);
+    }
+    if hashes.next().is_some() || fold::<Sha256>(leaf, &path) != root {
+        return None;
+    }
+    Some(Proof::new(index as u64, path))
+}
+
+/// Migrates a `merkletree` (merkle_light) proof: `lemma` is the leaf hash,
+/// the siblings from the leaf up and the root; `path[i]` is set where the
+/// node at level `i` is the left child. Those crates hash as [`Rfc6962`],
+/// so the result verifies with `verify_proof_with::<Rfc6962>`. Returns the
+/// root with the path.
+pub fn from_merkletree(lemma: &[[u8; 32]], path: &[bool]) -> Option<([u8; 32], Path)> {
+    let (&leaf, rest) = lemma.split_first()?;
+    let (&root, siblings) = rest.split_last()?;
+    if siblings.len() != path.len() {
+        return None;
+    }
+    let path: Path = siblings.iter().copied().zip(path.iter().copied()).collect();
+    if fold::<Rfc6962>(leaf, &path) != root {
+        return None;
+    }
+    Some((root, path))
+}
+
+/// A tree here over the leaf hashes of a foreign tree. `Padding::Promote`
+/// matches the level shapes of `rs_merkle`.
+pub fn rebuild(leaf_hashes: Vec<[u8; 32]>, padding: Padding) -> Option<MerkleTree> {
+    if leaf_hashes.is_empty() {
+        return None;
+    }
+    Some(MerkleTree::from_leaf_hashes(leaf_hashes, padding))
+}
+
+/// A leaf's hash in the old tree and in the new one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LeafMapping {
+    pub old: [u8; 32],
+    pub new: [u8; 32],
+}
+
+impl LeafMapping {
+    /// `old || new`, the item of the pair's leaf in the mapping tree.
+    pub fn to_bytes(&self) -> [u8; 64] {
+        let mut bytes = [0u8; 64];
+        bytes[..32].copy_from_slice(&self.old);
+        bytes[32..].copy_from_slice(&self.new);
+        bytes
+    }
+}
+
+/// Both roots of a migration and the root of its leaf mapping, hashed with
+/// the new hasher and padding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CrossCommitment {
+    pub leaves: u64,
+    pub old_padding: Padding,
+    pub old_root: [u8; 32],
+    pub new_padding: Padding,
+    pub new_root: [u8; 32],
+    pub mapping_root: [u8; 32],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MigrationError {
+    /// The items are not as many as the old tree's leaves.
+    LeafCount { leaves: usize, items: usize },
+    /// The item does not hash to the old tree's leaf at this index.
+    LeafMismatch(usize),
+}
+
+pub struct Migration<N: MerkleHasher> {
+    pub tree: MerkleTree<N>,
+    pub commitment: CrossCommitment,
+    mapping: Vec<LeafMapping>,
+    mapping_tree: MerkleTree<N>,
+}
+
+/// Rebuilds `old` over `items`, its leaves in order, with hasher `N` and
+/// `padding`. Each item is checked against its old leaf first, so the
+/// commitment is only made for the items the old tree holds.
+pub fn migrate_tree<O, N, T>(
+    old: &MerkleTree<O>,
+    items: &[T],
+    padding: Padding,
+) -> Result<Migration<N>, MigrationError>
+where
+    O: MerkleHasher,
+    N: MerkleHasher,
+    T: AsRef<[u8]>,
+{
+    if items.len() != old.leaves() {
+        return Err(MigrationError::LeafCount {
+            leaves: old.leaves(),
+            items: items.len(),
+        });
+    }
+    let (mut old_ctx, mut new_ctx) = (O::Context::default(), N::Context::default());
+    let mut mapping = Vec::with_capacity(items.len());
+    for (idx, item) in items.iter().enumerate() {
+        let leaf = O::hash_data_with(&mut old_ctx, item.as_ref());
+        if old.node(idx) != Ok(leaf) {
+            return Err(MigrationError::LeafMismatch(idx));
+        }
+        mapping.push(LeafMapping {
+            old: leaf,
+            new: N::hash_data_with(&mut new_ctx, item.as_ref()),
+        });
+    }
+    let tree = MerkleTree::from_leaf_hashes(mapping.iter().map(|pair| pair.new).collect(), padding);
+    let mapping_tree: MerkleTree<N> =
+        MerkleTree::build_from_leaves_padded(mapping.iter().map(LeafMapping::to_bytes), padding)
+            .expect("trees have at least one leaf");
+    let commitment = CrossCommitment {
+        leaves: items.len() as u64,
+        old_padding: old.padding(),
+        old_root: old.root(),
+        new_padding: padding,
+        new_root: tree.root(),
+        mapping_root: mapping_tree.root(),
+    };
+    Ok(Migration {
+        tree,
+        commitment,
+        mapping,
+        mapping_tree,
+    })
+}
+
+impl<N: MerkleHasher> Migration<N> {
+    /// The leaf mapping to export to auditors, in leaf order.
+    pub fn mapping(&self) -> &[LeafMapping] {
+        &self.mapping
+    }
+
+    /// Proof of leaf `idx`'s pair under the commitment's mapping root.
+    pub fn mapping_proof(&self, idx: usize) -> Result<Proof, Error> {
+        self.mapping_tree.leaf_proof(idx)
+    }
+}
+
+impl CrossCommitment {
+    /// Checks an exported mapping: that its old hashes give the old root,
+    /// its new hashes the new root, and its pairs the mapping root.
+    pub fn verify_mapping<O: MerkleHasher, N: MerkleHasher>(
+        &self,
+        mapping: &[LeafMapping],
+    ) -> bool {
+        if mapping.is_empty() || mapping.len() as u64 != self.leaves {
+            return false;
+        }
+        let old: MerkleTree<O> = MerkleTree::from_leaf_hashes(
+            mapping.iter().map(|pair| pair.old).collect(),
+            self.old_padding,
+        );
+        let new: MerkleTree<N> = MerkleTree::from_leaf_hashes(
+            mapping.iter().map(|pair| pair.new).collect(),
+            self.new_padding,
+        );
+        let pairs: Option<MerkleTree<N>> = MerkleTree::build_from_leaves_padded(
+            mapping.iter().map(LeafMapping::to_bytes),
+            self.new_padding,
+        );
+        old.root() == self.old_root
+            && new.root() == self.new_root
+            && pairs.is_some_and(|pairs| pairs.root() == self.mapping_root)
+    }
+
+    /// Checks that `item` hashes to the pair `proof` shows under the
+    /// mapping root, so it is the same leaf of both trees.
+    pub fn verify_item<O: MerkleHasher, N: MerkleHasher>(
+        &self,
+        item: &[u8],
+        proof: &Proof,
+    ) -> bool {
+        let pair = LeafMapping {
+            old: O::hash_data(item),
+            new: N::hash_data(item),
+        };
+        let head = TreeHead {
+            size: self.leaves,
+            root: self.mapping_root,
+        };
+        proof
+            .verify_with::<N>(&pair.to_bytes(), &head, self.new_padding)
+            .is_valid()
+    }
+
+    /// Format version (1 byte), leaves (u64 LE), then the old padding and
+    /// root, the new padding and root, and the mapping root.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(107);
+        out.push(CURRENT.byte());
+        out.extend_from_slice(&self.leaves.to_le_bytes());
+        out.push(self.old_padding.byte());
+        out.extend_from_slice(&self.old_root);
+        out.push(self.new_padding.byte());
+        out.extend_from_slice(&self.new_root);
+        out.extend_from_slice(&self.mapping_root);
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != 107 || Version::from_byte(bytes[0])? != CURRENT {
+            return None;
+        }
+        Some(CrossCommitment {
+            leaves: u64::from_le_bytes(bytes[1..9].try_into().unwrap()),
+            old_padding: Padding::from_byte(bytes[9])?,
+            old_root: bytes[10..42].try_into().unwrap(),
+            new_padding: Padding::from_byte(bytes[42])?,
+            new_root: bytes[43..75].try_into().unwrap(),
+            mapping_root: bytes[75..107].try_into().unwrap(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{hash_data, hash_pair, verify_proof, verify_proof_with};
+
+    /// Root of an `rs_merkle` tree: pairs hashed left to right, lone nodes
+    /// carried up.
+    fn rs_merkle_root(mut level: Vec<[u8; 32]>) -> [u8; 32] {
+        while level.len() > 1 {
+            level = level
+                .chunks(2)
+                .map(|pair| match pair {
+                    [left, right] => hash_pair(left, right),
+                    [lone] => *lone,
+                    _ => unreachable!(),
+                })
+                .collect();
+        }
+        level[0]
+    }
+
+    #[test]
+    fn rs_merkle_proofs() {
+        let items = [b"a", b"b", b"c", b"d", b"e"];
+        let leaves: Vec<[u8; 32]> = items.iter().map(|item| hash_data(*item)).collect();
+        let root = rs_merkle_root(leaves.clone());
+        // Leaf 2: sibling d, then the (a, b) node, then e carried to the top.
+        let ab = hash_pair(&leaves[0], &leaves[1]);
+        let bytes: Vec<u8> = [leaves[3], ab, leaves[4]].concat();
+        let hashes = rs_merkle_hashes(&bytes).unwrap();
+
+        let proof = from_rs_merkle(leaves[2], 2, 5, &hashes, root).unwrap();
+        assert!(verify_proof(b"c", root, proof.path()).is_valid());
+        assert!(from_rs_merkle(leaves[2], 2, 5, &hashes[..2], root).is_none());
+        assert!(from_rs_merkle(leaves[1], 2, 5, &hashes, root).is_none());
+        assert!(rs_merkle_hashes(&bytes[1..]).is_none());
+
+        // Leaf 4 is carried up past two levels.
+        let abcd = hash_pair(&ab, &hash_pair(&leaves[2], &leaves[3]));
+        let proof = from_rs_merkle(leaves[4], 4, 5, &[abcd], root).unwrap();
+        assert!(verify_proof(b"e", root, proof.path()).is_valid());
+
+        let tree = rebuild(leaves, Padding::Promote).unwrap();
+        assert_eq!(tree.leaves(), 5);
+        assert!(verify_proof(b"c", tree.root(), &tree.path(2).unwrap()).is_valid());
+    }
+
+    #[test]
+    fn merkletree_proofs() {
+        assert_eq!(Rfc6962::hash_data(b"x"), crate::log::leaf_hash(b"x"));
+        let leaf = Rfc6962::hash_data(b"c");
+        let (left, right) = (Rfc6962::hash_data(b"a"), Rfc6962::hash_data(b"d"));
+        let inner = Rfc6962::hash_pair(&leaf, &right);
+        let root = Rfc6962::hash_pair(&left, &inner);
+
+        let (migrated_root, path) =
+            from_merkletree(&[leaf, right, left, root], &[true, false]).unwrap();
+        assert_eq!(migrated_root, root);
+        assert!(verify_proof_with::<Rfc6962>(b"c", root, &path).is_valid());
+        assert!(from_merkletree(&[leaf, right, left, root], &[false, false]).is_none());
+        assert!(from_merkletree(&[leaf, right, left, root], &[true]).is_none());
+        assert!(from_merkletree(&[leaf], &[]).is_none());
+    }
+
+    #[test]
+    fn migrates_with_cross_commitment() {
+        use sha2::{Sha256, Sha512Trunc256};
+        let items: Vec<Vec<u8>> = (0..7u8).map(|i| vec![i; 3]).collect();
+        let old: MerkleTree = MerkleTree::from_leaves(&items).unwrap();
+        let migration: Migration<Sha512Trunc256> =
+            migrate_tree(&old, &items, Padding::Zero).unwrap();
+        let expected =
+            MerkleTree::<Sha512Trunc256>::build_from_leaves_padded(&items, Padding::Zero).unwrap();
+        assert_eq!(migration.tree.root(), expected.root());
+
+        let commitment = migration.commitment;
+        assert_eq!(commitment.old_root, old.root());
+        assert!(commitment.verify_mapping::<Sha256, Sha512Trunc256>(migration.mapping()));
+        let mut swapped = migration.mapping().to_vec();
+        swapped.swap(1, 2);
+        assert!(!commitment.verify_mapping::<Sha256, Sha512Trunc256>(&swapped));
+        assert!(!commitment.verify_mapping::<Sha256, Sha512Trunc256>(&swapped[..6]));
+
+        let proof = migration.mapping_proof(4).unwrap();
+        assert!(commitment.verify_item::<Sha256, Sha512Trunc256>(&items[4], &proof));
+        assert!(!commitment.verify_item::<Sha256, Sha512Trunc256>(&items[3], &proof));
+        assert!(!commitment.verify_item::<Sha256, Sha256>(&items[4], &proof));
+        assert_eq!(
+            CrossCommitment::from_bytes(&commitment.to_bytes()),
+            Some(commitment)
+        );
+
+        let mut tampered = items.clone();
+        tampered[5] = b"x".to_vec();
+        assert_eq!(
+            migrate_tree::<_, Sha256, _>(&old, &tampered, Padding::Zero).err(),
+            Some(MigrationError::LeafMismatch(5))
+        );
+        assert_eq!(
+            migrate_tree::<_, Sha256, _>(&old, &items[..6], Padding::Zero).err(),
+            Some(MigrationError::LeafCount {
+                leaves: 7,
+                items: 6
+            })
+        );
+    }
+}
@@ -0,0 +1,140 @@
+//! Proof-of-work over tree heads. A nonce leaf is paired with the tree root
+//! the same way `FilteredTree` pairs its filter:
+//! `work_root = hash_pair(root, hash_data(nonce_le))`.
+
+use crate::hash_data;
+use crate::progress::CancelToken;
+use crate::MerkleHasher;
+use sha2::Sha256;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Attempts per thread between progress reports and cancellation checks.
+pub const REPORT_INTERVAL: u64 = 4096;
+
+pub fn work_root(root: &[u8; 32], nonce: u64) -> [u8; 32] {
+    work_root_in(&mut Sha256::default(), root, nonce)
+}
+
+fn work_root_in(ctx: &mut Sha256, root: &[u8; 32], nonce: u64) -> [u8; 32] {
+    let nonce_hash = Sha256::hash_data_with(ctx, &nonce.to_le_bytes());
+    Sha256::hash_pair_with(ctx, root, &nonce_hash)
+}
+
+pub fn leading_zero_bits(hash: &[u8; 32]) -> u32 {
+    let mut bits = 0;
+    for byte in hash {
+        bits += byte.leading_zeros();
+        if *byte != 0 {
+            break;
+        }
+    }
+    bits
+}
+
+pub fn verify_work(root: &[u8; 32], nonce: u64, difficulty: u32) -> bool {
+    leading_zero_bits(&work_root(root, nonce)) >= difficulty
+}
+
+/// Path entry extending a proof against `root` to one against the work root.
+pub fn nonce_entry(nonce: u64) -> ([u8; 32], bool) {
+    (hash_data(&nonce.to_le_bytes()), true)
+}
+
+/// Searches for the smallest nonce whose work root has at least `difficulty`
+/// leading zero bits, on `threads` threads. The answer does not depend on
+/// the thread count. `progress` receives the attempts made by one thread
+/// since its last report. Returns `None` if cancelled.
+pub fn mine<F>(
+    root: &[u8; 32],
+    difficulty: u32,
+    threads: usize,
+    progress: F,
+    cancel: &CancelToken,
+) -> Option<u64>
+where
+    F: Fn(u64) + Sync,
+{
+    let threads = threads.max(1) as u64;
+    let best = AtomicU64::new(u64::MAX);
+    std::thread::scope(|scope| {
+        for start in 0..threads {
+            let (best, progress) = (&best, &progress);
+            scope.spawn(move || {
+                let mut ctx = Sha256::default();
+                let mut nonce = start;
+                let mut attempts = 0;
+                while nonce < best.load(Ordering::Relaxed) {
+                    if leading_zero_bits(&work_root_in(&mut ctx, root, nonce)) >= difficulty {
+                        best.fetch_min(nonce, Ordering::Relaxed);
+                        break;
+                    }
+                    attempts += 1;
+                    if attempts == REPORT_INTERVAL {
+                        progress(attempts);
+                        attempts = 0;
+                        if cancel.is_cancelled() {
+                            break;
+                        }
+                    }
+                    nonce = match nonce.checked_add(threads) {
+                        Some(next) => next,
+                        None => break,
+                    };
+                }
+            });
+        }
+    });
+    match best.into_inner() {
+        _ if cancel.is_cancelled() => None,
+        u64::MAX => None,
+        nonce => Some(nonce),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{verify_proof, MerkleTree};
+    use std::sync::atomic::AtomicU64;
+
+    #[test]
+    fn finds_same_nonce_on_any_thread_count() {
+        let tree = MerkleTree::new(b"asdfjkln12345678", 4);
+        let root = tree.root();
+        let attempts = AtomicU64::new(0);
+        let count = |n| {
+            attempts.fetch_add(n, Ordering::Relaxed);
+        };
+        let nonce = mine(&root, 14, 1, count, &CancelToken::new()).unwrap();
+        assert!(verify_work(&root, nonce, 14));
+        assert!((0..nonce).all(|n| !verify_work(&root, n, 14)));
+        assert!(attempts.load(Ordering::Relaxed) > 0);
+        for threads in [2, 3, 8] {
+            assert_eq!(
+                mine(&root, 14, threads, |_| {}, &CancelToken::new()),
+                Some(nonce)
+            );
+        }
+
+        let mut proof = tree.path(1).unwrap();
+        proof.push(nonce_entry(nonce));
+        assert!(verify_proof(b"jkln", work_root(&root, nonce), &proof).is_valid());
+    }
+
+    #[test]
+    fn cancellation() {
+        let cancel = CancelToken::new();
+        cancel.cancel();
+        assert_eq!(mine(&[0; 32], 200, 2, |_| {}, &cancel), None);
+    }
+
+    #[test]
+    fn counts_zero_bits() {
+        let mut hash = [0xff; 32];
+        assert_eq!(leading_zero_bits(&hash), 0);
+        hash[0] = 0;
+        hash[1] = 0x1f;
+        assert_eq!(leading_zero_bits(&hash), 11);
+        assert_eq!(leading_zero_bits(&[0; 32]), 256);
+    }
+}
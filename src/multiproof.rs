@@ -0,0 +1,500 @@
+//! Proofs for several leaves at once. Paths of nearby leaves share most of
+//! their nodes; a multiproof carries each needed sibling once and leaves out
+//! every node the verifier can compute from the proven leaves themselves.
+//!
+//! Hashes are ordered level by level from the leaves up, and by offset
+//! within a level: for each node the verifier knows, in offset order, the
+//! sibling is the next hash unless it is known too or the node is alone on
+//! its level.
+//!
+//! A [`RangeProof`] is the multiproof of a run of consecutive leaves, whose
+//! hashes are just the siblings along the run's two edges.
+//!
+//! Both proofs name the leaf count and padding of their tree, which decide
+//! where every hash goes. Verifiers take the trusted [`TreeHead`] and
+//! padding and refuse proofs that name another shape, since indices
+//! re-presented under a different shape can still reach the same root.
+
+#[cfg(feature = "std")]
+use crate::format::{Version, CURRENT};
+use crate::{estimate, Error, MerkleHasher, MerkleTree, Padding, TreeHead};
+use alloc::vec::Vec;
+use sha2::Sha256;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "borsh",
+    derive(borsh::BorshSerialize, borsh::BorshDeserialize)
+)]
+#[cfg_attr(
+    feature = "scale",
+    derive(parity_scale_codec::Encode, parity_scale_codec::Decode)
+)]
+pub struct MultiProof {
+    leaves: u64,
+    padding: Padding,
+    indices: Vec<u64>,
+    hashes: Vec<[u8; 32]>,
+}
+
+impl MultiProof {
+    /// Leaf count of the tree the proof was taken from.
+    pub fn leaves(&self) -> u64 {
+        self.leaves
+    }
+
+    pub fn padding(&self) -> Padding {
+        self.padding
+    }
+
+    /// Proven leaf indices, ascending and without repeats.
+    pub fn indices(&self) -> &[u64] {
+        &self.indices
+    }
+
+    pub fn hashes(&self) -> &[[u8; 32]] {
+        &self.hashes
+    }
+
+    /// Hashes the multiproof of `indices` in a tree of `leaves` leaves will
+    /// carry, without building it. Returns `None` if an index is out of
+    /// range.
+    pub fn estimate(indices: &[usize], leaves: usize) -> Option<usize> {
+        estimate::multiproof_hashes(indices, leaves)
+    }
+}
+
+/// Most entries set aside before reading, whatever count a stream claims.
+#[cfg(feature = "std")]
+const PREALLOCATE: u64 = 4096;
+
+#[cfg(feature = "std")]
+impl MultiProof {
+    /// Writes the proof as format version, padding byte, leaf count, index
+    /// count, the indices, hash count, then the hashes, integers as u64 LE.
+    /// Proofs are written and read a field at a time, so a large one can
+    /// go through a compressed stream (see `compress`) without a second
+    /// copy.
+    pub fn write_to<W: std::io::Write>(&self, mut writer: W) -> std::io::Result<()> {
+        writer.write_all(&[CURRENT.byte(), self.padding.byte()])?;
+        writer.write_all(&self.leaves.to_le_bytes())?;
+        writer.write_all(&(self.indices.len() as u64).to_le_bytes())?;
+        for idx in &self.indices {
+            writer.write_all(&idx.to_le_bytes())?;
+        }
+        writer.write_all(&(self.hashes.len() as u64).to_le_bytes())?;
+        for hash in &self.hashes {
+            writer.write_all(hash)?;
+        }
+        Ok(())
+    }
+
+    /// Reads a proof written by `write_to`, leaving the reader just past
+    /// it.
+    pub fn read_from<R: std::io::Read>(mut reader: R) -> std::io::Result<Self> {
+        use std::io::{Error, ErrorKind};
+
+        let invalid = || Error::new(ErrorKind::InvalidData, "not a multiproof");
+        let u64_le = |reader: &mut R| -> std::io::Result<u64> {
+            let mut bytes = [0; 8];
+            reader.read_exact(&mut bytes)?;
+            Ok(u64::from_le_bytes(bytes))
+        };
+        let mut header = [0; 2];
+        reader.read_exact(&mut header)?;
+        if Version::from_byte(header[0]) != Some(Version::V1) {
+            return Err(invalid());
+        }
+        let padding = Padding::from_byte(header[1]).ok_or_else(invalid)?;
+        let leaves = u64_le(&mut reader)?;
+        let count = u64_le(&mut reader)?;
+        let mut indices = Vec::with_capacity(count.min(PREALLOCATE) as usize);
+        for _ in 0..count {
+            indices.push(u64_le(&mut reader)?);
+        }
+        let count = u64_le(&mut reader)?;
+        let mut hashes = Vec::with_capacity(count.min(PREALLOCATE) as usize);
+        for _ in 0..count {
+            let mut hash = [0; 32];
+            reader.read_exact(&mut hash)?;
+            hashes.push(hash);
+        }
+        Ok(MultiProof {
+            leaves,
+            padding,
+            indices,
+            hashes,
+        })
+    }
+}
+
+impl<H: MerkleHasher> MerkleTree<H> {
+    /// One proof for every leaf in `indices`, in any order and possibly
+    /// repeated.
+    pub fn multiproof(&self, indices: &[usize]) -> Result<MultiProof, Error> {
+        for &idx in indices {
+            self.path(idx)?;
+        }
+        let mut known: Vec<usize> = indices.to_vec();
+        known.sort_unstable();
+        known.dedup();
+        let proven = known.iter().map(|&idx| idx as u64).collect();
+
+        let mut hashes = Vec::new();
+        let (mut start, mut len) = (0, self.leaves());
+        while len > 1 {
+            let mut i = 0;
+            while i < known.len() {
+                let offset = known[i];
+                let sibling = offset ^ 1;
+                if known.get(i + 1) == Some(&sibling) {
+                    i += 1;
+                } else if sibling < len {
+                    hashes.push(self.nodes[start + sibling].value);
+                }
+                i += 1;
+            }
+            known = known.iter().map(|offset| offset / 2).collect();
+            known.dedup();
+            start += len;
+            len = len.div_ceil(2);
+        }
+        Ok(MultiProof {
+            leaves: self.leaves() as u64,
+            padding: self.padding(),
+            indices: proven,
+            hashes,
+        })
+    }
+}
+
+/// Proof that consecutive leaves, from a start index given to the verifier,
+/// are in a tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "borsh",
+    derive(borsh::BorshSerialize, borsh::BorshDeserialize)
+)]
+pub struct RangeProof {
+    leaves: u64,
+    padding: Padding,
+    hashes: Vec<[u8; 32]>,
+}
+
+impl RangeProof {
+    /// Leaf count of the tree the proof was taken from.
+    pub fn leaves(&self) -> u64 {
+        self.leaves
+    }
+
+    pub fn padding(&self) -> Padding {
+        self.padding
+    }
+
+    pub fn hashes(&self) -> &[[u8; 32]] {
+        &self.hashes
+    }
+}
+
+impl<H: MerkleHasher> MerkleTree<H> {
+    /// One proof for leaves `start..end`.
+    pub fn range_proof(&self, start: usize, end: usize) -> Result<RangeProof, Error> {
+        if start >= end {
+            return Err(Error::EmptyRange { start, end });
+        }
+        let indices: Vec<usize> = (start..end).collect();
+        let proof = self.multiproof(&indices)?;
+        Ok(RangeProof {
+            leaves: proof.leaves,
+            padding: proof.padding,
+            hashes: proof.hashes,
+        })
+    }
+}
+
+/// Checks that `chunks` are the leaves from `start` on of the tree `head`
+/// commits to.
+pub fn verify_range_proof<T: AsRef<[u8]>>(
+    chunks: &[T],
+    start: u64,
+    head: &TreeHead,
+    proof: &RangeProof,
+) -> bool {
+    verify_range_proof_with::<Sha256, T>(chunks, start, head, Padding::default(), proof)
+}
+
+/// Like `verify_range_proof`, for a tree hashed with `H` and closed with
+/// `padding`.
+pub fn verify_range_proof_with<H: MerkleHasher, T: AsRef<[u8]>>(
+    chunks: &[T],
+    start: u64,
+    head: &TreeHead,
+    padding: Padding,
+    proof: &RangeProof,
+) -> bool {
+    let end = match start.checked_add(chunks.len() as u64) {
+        Some(end) if end <= proof.leaves => end,
+        _ => return false,
+    };
+    let multiproof = MultiProof {
+        leaves: proof.leaves,
+        padding: proof.padding,
+        indices: (start..end).collect(),
+        hashes: proof.hashes.clone(),
+    };
+    verify_multiproof_with::<H, T>(chunks, head, padding, &multiproof)
+}
+
+/// Checks that `items`, given in the order of `proof.indices()`, are the
+/// leaves at those indices of the tree `head` commits to.
+pub fn verify_multiproof<T: AsRef<[u8]>>(items: &[T], head: &TreeHead, proof: &MultiProof) -> bool {
+    verify_multiproof_with::<Sha256, T>(items, head, Padding::default(), proof)
+}
+
+/// Like `verify_multiproof`, for a tree hashed with `H` and closed with
+/// `padding`. A proof naming another leaf count or padding is refused.
+pub fn verify_multiproof_with<H: MerkleHasher, T: AsRef<[u8]>>(
+    items: &[T],
+    head: &TreeHead,
+    padding: Padding,
+    proof: &MultiProof,
+) -> bool {
+    if !well_formed(items.len(), head, padding, proof) {
+        return false;
+    }
+    let root = head.root;
+    let mut ctx = H::Context::default();
+    let mut known: Vec<(u64, [u8; 32])> = proof
+        .indices
+        .iter()
+        .zip(items)
+        .map(|(&idx, item)| (idx, H::hash_data_with(&mut ctx, item.as_ref())))
+        .collect();
+    let mut hashes = proof.hashes.iter();
+    let mut len = proof.leaves;
+    while len > 1 {
+        let mut parents = Vec::with_capacity(known.len());
+        let mut i = 0;
+        while i < known.len() {
+            let (offset, hash) = known[i];
+            let parent = match known.get(i + 1) {
+                Some(&(next, sibling)) if next == offset ^ 1 => {
+                    i += 1;
+                    H::hash_pair_with(&mut ctx, &sibling, &hash)
+                }
+                _ if offset ^ 1 >= len => proof.padding.parent::<H>(&mut ctx, &hash),
+                _ => {
+                    let sibling = match hashes.next() {
+                        Some(sibling) => sibling,
+                        None => return false,
+                    };
+                    if offset & 1 == 1 {
+                        H::hash_pair_with(&mut ctx, &hash, sibling)
+                    } else {
+                        H::hash_pair_with(&mut ctx, sibling, &hash)
+                    }
+                }
+            };
+            parents.push((offset / 2, parent));
+            i += 1;
+        }
+        known = parents;
+        len = len.div_ceil(2);
+    }
+    hashes.next().is_none() && known == [(0, root)]
+}
+
+/// Whether `proof` is for `items` leaves of the tree shape `head` and
+/// `padding` describe, with its indices in range, ascending and distinct.
+pub(crate) fn well_formed(
+    items: usize,
+    head: &TreeHead,
+    padding: Padding,
+    proof: &MultiProof,
+) -> bool {
+    proof.leaves == head.size
+        && proof.padding == padding
+        && items != 0
+        && items == proof.indices.len()
+        && proof.indices.windows(2).all(|pair| pair[0] < pair[1])
+        && proof
+            .indices
+            .last()
+            .is_some_and(|&last| last < proof.leaves)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shares_nodes() {
+        let data = b"asdfjkln12345678zxcvqwer09876543";
+        let tree = MerkleTree::new(data, 8);
+        let item = |idx: usize| &data[4 * idx..4 * idx + 4];
+        for indices in [
+            vec![2],
+            vec![3, 2],
+            vec![0, 7],
+            vec![1, 4, 5, 6],
+            (0..8).collect(),
+        ] {
+            let proof = tree.multiproof(&indices).unwrap();
+            assert_eq!(
+                Some(proof.hashes().len()),
+                MultiProof::estimate(&indices, 8)
+            );
+            let items: Vec<&[u8]> = proof
+                .indices()
+                .iter()
+                .map(|&idx| item(idx as usize))
+                .collect();
+            assert!(verify_multiproof(&items, &tree.head(), &proof));
+        }
+
+        let proof = tree.multiproof(&[5, 1, 5]).unwrap();
+        assert_eq!(proof.indices(), &[1, 5]);
+        assert!(verify_multiproof(&[item(1), item(5)], &tree.head(), &proof));
+        assert!(!verify_multiproof(
+            &[item(5), item(1)],
+            &tree.head(),
+            &proof
+        ));
+        assert!(!verify_multiproof(&[item(1)], &tree.head(), &proof));
+
+        let mut short = proof.clone();
+        short.hashes.pop();
+        assert!(!verify_multiproof(
+            &[item(1), item(5)],
+            &tree.head(),
+            &short
+        ));
+        let mut long = proof;
+        long.hashes.push([0; 32]);
+        assert!(!verify_multiproof(&[item(1), item(5)], &tree.head(), &long));
+
+        // The shape comes from the trusted head, not the proof.
+        let mut resized = tree.multiproof(&[1, 5]).unwrap();
+        resized.leaves = 6;
+        assert!(!verify_multiproof(
+            &[item(1), item(5)],
+            &tree.head(),
+            &resized
+        ));
+
+        assert_eq!(tree.multiproof(&[8]), Err(Error::NotALeaf(8)));
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn stream_round_trip() {
+        let tree =
+            MerkleTree::from_leaves_padded(["a", "b", "c", "d", "e"], Padding::Zero).unwrap();
+        let proof = tree.multiproof(&[1, 4]).unwrap();
+        let mut bytes = Vec::new();
+        proof.write_to(&mut bytes).unwrap();
+        assert_eq!(
+            bytes.len(),
+            2 + 8 + 8 + 2 * 8 + 8 + 32 * proof.hashes().len()
+        );
+        assert_eq!(MultiProof::read_from(&bytes[..]).unwrap(), proof);
+        assert!(MultiProof::read_from(&bytes[..bytes.len() - 1]).is_err());
+        let mut unknown = bytes;
+        unknown[1] = 3;
+        assert!(MultiProof::read_from(&unknown[..]).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "scale")]
+    fn scale_round_trip() {
+        use parity_scale_codec::{Decode, DecodeAll, Encode};
+
+        let tree =
+            MerkleTree::from_leaves_padded(["a", "b", "c", "d", "e"], Padding::Zero).unwrap();
+        let proof = tree.multiproof(&[1, 4]).unwrap();
+        let bytes = proof.encode();
+        assert_eq!(MultiProof::decode_all(&mut &bytes[..]), Ok(proof.clone()));
+        let decoded = MultiProof::decode(&mut &bytes[..]).unwrap();
+        assert!(verify_multiproof_with::<Sha256, _>(
+            &["b", "e"],
+            &tree.head(),
+            Padding::Zero,
+            &decoded
+        ));
+        assert!(!verify_multiproof(&["b", "e"], &tree.head(), &decoded));
+
+        // Leaf count, then the padding byte.
+        let mut unknown = bytes;
+        unknown[8] = 3;
+        assert!(MultiProof::decode(&mut &unknown[..]).is_err());
+    }
+
+    #[test]
+    fn odd_trees() {
+        let items = ["a", "b", "c", "d", "e", "f", "g"];
+        for &padding in [Padding::Promote, Padding::DuplicateLast, Padding::Zero].iter() {
+            let tree = MerkleTree::from_leaves_padded(items, padding).unwrap();
+            for indices in [vec![6], vec![4, 6], vec![0, 5, 6], vec![1, 2, 3]] {
+                let proof = tree.multiproof(&indices).unwrap();
+                assert_eq!(
+                    Some(proof.hashes().len()),
+                    MultiProof::estimate(&indices, items.len())
+                );
+                let proven: Vec<&str> = proof
+                    .indices()
+                    .iter()
+                    .map(|&idx| items[idx as usize])
+                    .collect();
+                let head = tree.head();
+                assert!(verify_multiproof_with::<Sha256, _>(
+                    &proven, &head, padding, &proof
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn proves_ranges() {
+        let chunks: Vec<[u8; 3]> = (0..11u8).map(|i| [i; 3]).collect();
+        for &padding in [Padding::Promote, Padding::DuplicateLast, Padding::Zero].iter() {
+            let tree = MerkleTree::from_leaves_padded(&chunks, padding).unwrap();
+            for (start, end) in [(0, 11), (0, 1), (3, 7), (4, 8), (10, 11), (5, 11)] {
+                let proof = tree.range_proof(start, end).unwrap();
+                let verify = |chunks: &[[u8; 3]], start: u64| {
+                    verify_range_proof_with::<Sha256, _>(
+                        chunks,
+                        start,
+                        &tree.head(),
+                        padding,
+                        &proof,
+                    )
+                };
+                assert!(verify(&chunks[start..end], start as u64));
+                assert!(!verify(&chunks[start..end], start as u64 + 1));
+                if end - start > 1 {
+                    assert!(!verify(&chunks[start..end - 1], start as u64));
+                }
+            }
+            // The whole tree needs no hashes; a run only its edges.
+            assert!(tree.range_proof(0, 11).unwrap().hashes().is_empty());
+            assert!(tree.range_proof(4, 8).unwrap().hashes().len() <= 2);
+        }
+
+        let tree = MerkleTree::from_leaves(&chunks).unwrap();
+        assert_eq!(
+            tree.range_proof(3, 3),
+            Err(Error::EmptyRange { start: 3, end: 3 })
+        );
+        assert_eq!(tree.range_proof(9, 12), Err(Error::NotALeaf(11)));
+        let proof = tree.range_proof(9, 11).unwrap();
+        assert!(verify_range_proof(&chunks[9..], 9, &tree.head(), &proof));
+        assert!(!verify_range_proof(
+            &chunks[9..],
+            u64::MAX,
+            &tree.head(),
+            &proof
+        ));
+    }
+}
@@ -0,0 +1,358 @@
+//! Where a tree keeps its node hashes. By default they sit in a
+//! `Vec<Node>`; a [`NodeStore`] can hold them elsewhere, so that trees too
+//! large for memory are built into and served from, say, a [`FileStore`].
+//! `root`, `path`, `proof` and the navigation methods work on any store;
+//! the other tree operations are for in-memory trees.
+//!
+//! Nodes are stored in node index order (leaves, then each level up), so
+//! `Layout::node_index` maps a level and offset to a store index.
+//!
+//! Stores implement the fallible `try_get` and `try_put`. The tree methods
+//! above read through `get`, which panics if the store fails; a store
+//! that can fail is built with `try_build_in` and served with `try_path`.
+
+use crate::{Layout, MerkleHasher, MerkleTree, Node, Padding, Path};
+use alloc::vec::Vec;
+use core::convert::Infallible;
+#[cfg(feature = "std")]
+use core::convert::TryInto;
+use core::fmt::Debug;
+#[cfg(feature = "std")]
+use std::fs::{File, OpenOptions};
+#[cfg(feature = "std")]
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+pub trait NodeStore {
+    type Error: Debug;
+
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Hash of node `idx`, which is below `len`.
+    fn try_get(&self, idx: usize) -> Result<[u8; 32], Self::Error>;
+
+    /// Overwrites node `idx`, or appends a node when `idx` is `len`.
+    fn try_put(&mut self, idx: usize, hash: [u8; 32]) -> Result<(), Self::Error>;
+
+    /// Like `try_get`, panicking if the store fails.
+    fn get(&self, idx: usize) -> [u8; 32] {
+        self.try_get(idx).expect("node store read failed")
+    }
+
+    /// Like `try_put`, panicking if the store fails.
+    fn put(&mut self, idx: usize, hash: [u8; 32]) {
+        self.try_put(idx, hash).expect("node store write failed")
+    }
+}
+
+impl NodeStore for Vec<Node> {
+    type Error = Infallible;
+
+    fn len(&self) -> usize {
+        Vec::len(self)
+    }
+
+    fn try_get(&self, idx: usize) -> Result<[u8; 32], Infallible> {
+        Ok(self[idx].value)
+    }
+
+    fn try_put(&mut self, idx: usize, hash: [u8; 32]) -> Result<(), Infallible> {
+        if idx == Vec::len(self) {
+            self.push(Node::new(hash));
+        } else {
+            self[idx].value = hash;
+        }
+        Ok(())
+    }
+}
+
+impl<H: MerkleHasher, S: NodeStore> MerkleTree<H, S> {
+    /// Like `build_from_leaves_padded`, writing the nodes to `store`, which
+    /// must be empty. Levels are read back from the store as they are
+    /// hashed, so only the store grows with the tree. Panics if the store
+    /// fails; see `try_build_in`.
+    pub fn build_in<I>(store: S, items: I, padding: Padding) -> Option<Self>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        MerkleTree::try_build_in(store, items, padding).expect("node store failed")
+    }
+
+    /// Like `build_in`, returning the store's first error.
+    pub fn try_build_in<I>(
+        mut store: S,
+        items: I,
+        padding: Padding,
+    ) -> Result<Option<Self>, S::Error>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        assert!(store.is_empty(), "store must be empty");
+        let mut ctx = H::Context::default();
+        for item in items {
+            let leaf = H::hash_data_with(&mut ctx, item.as_ref());
+            store.try_put(store.len(), leaf)?;
+        }
+        let leaves = store.len();
+        if leaves == 0 {
+            return Ok(None);
+        }
+        let mut start = 0;
+        let mut len = leaves;
+        while len > 1 {
+            for idx in 0..len / 2 {
+                let even = start + 2 * idx;
+                let (odd, even) = (store.try_get(even + 1)?, store.try_get(even)?);
+                let parent = H::hash_pair_with(&mut ctx, &odd, &even);
+                store.try_put(store.len(), parent)?;
+            }
+            if len & 1 == 1 {
+                let lone = store.try_get(start + len - 1)?;
+                let parent = padding.parent::<H>(&mut ctx, &lone);
+                store.try_put(store.len(), parent)?;
+            }
+            start += len;
+            len = len.div_ceil(2);
+        }
+        Ok(Some(MerkleTree::from_store(store, leaves, padding)))
+    }
+
+    /// Like `path`, returning the store's error instead of panicking.
+    /// `Ok(None)` if `idx` is not a leaf.
+    pub fn try_path(&self, idx: usize) -> Result<Option<Path>, S::Error> {
+        if idx >= self.leaves() {
+            return Ok(None);
+        }
+        let mut path = Vec::with_capacity(self.layout.levels() - 1);
+        let mut cidx = idx;
+        while cidx != self.layout.root() {
+            let (_, offset) = self.layout.level_offset(cidx).expect("node is in range");
+            let sibling = match self.layout.sibling(cidx) {
+                Some(sibling) => Some(self.nodes.try_get(sibling)?),
+                None => self.padding.sibling(&self.nodes.try_get(cidx)?),
+            };
+            if let Some(sibling) = sibling {
+                path.push((sibling, offset & 1 == 1));
+            }
+            cidx = self
+                .layout
+                .parent(cidx)
+                .expect("only the root has no parent");
+        }
+        Ok(Some(path))
+    }
+
+    /// A tree over a store filled by `build_in`. Only the node count is
+    /// checked against `leaves`; the hashes are trusted as stored.
+    pub fn open(store: S, leaves: usize, padding: Padding) -> Option<Self> {
+        if Layout::new(leaves)?.nodes() != store.len() {
+            return None;
+        }
+        Some(MerkleTree::from_store(store, leaves, padding))
+    }
+
+    pub fn store(&self) -> &S {
+        &self.nodes
+    }
+
+    pub fn into_store(self) -> S {
+        self.nodes
+    }
+}
+
+/// Nodes appended to `pending` before they are written out together.
+#[cfg(feature = "std")]
+const BATCH: usize = 1 << 16;
+
+/// Node hashes in a file, 32 bytes each in node index order. Appends are
+/// batched in memory; call `flush` to write them out and see any error,
+/// as dropping the store flushes but discards errors. A failed read or
+/// write is returned by `try_get` and `try_put` and panics in `get` and
+/// `put`.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct FileStore {
+    file: File,
+    /// Nodes in the file; the rest are in `pending`.
+    written: usize,
+    pending: Vec<u8>,
+}
+
+#[cfg(feature = "std")]
+impl FileStore {
+    /// Creates an empty store at `path`, truncating any file there.
+    pub fn create<P: AsRef<std::path::Path>>(path: P) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        Ok(FileStore {
+            file,
+            written: 0,
+            pending: Vec::new(),
+        })
+    }
+
+    /// Opens a store written earlier, for `MerkleTree::open`.
+    pub fn open<P: AsRef<std::path::Path>>(path: P) -> io::Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        let bytes = file.metadata()?.len();
+        if bytes % 32 != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "node store is not a whole number of hashes",
+            ));
+        }
+        let written = (bytes / 32)
+            .try_into()
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "node store too large"))?;
+        Ok(FileStore {
+            file,
+            written,
+            pending: Vec::new(),
+        })
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        if !self.pending.is_empty() {
+            self.file.seek(SeekFrom::Start(32 * self.written as u64))?;
+            self.file.write_all(&self.pending)?;
+            self.written += self.pending.len() / 32;
+            self.pending.clear();
+        }
+        self.file.flush()
+    }
+}
+
+#[cfg(feature = "std")]
+impl NodeStore for FileStore {
+    type Error = io::Error;
+
+    fn len(&self) -> usize {
+        self.written + self.pending.len() / 32
+    }
+
+    fn try_get(&self, idx: usize) -> io::Result<[u8; 32]> {
+        let mut hash = [0; 32];
+        if idx >= self.written {
+            let at = 32 * (idx - self.written);
+            hash.copy_from_slice(&self.pending[at..at + 32]);
+        } else {
+            // `&File` reads and seeks without needing `&mut self`.
+            let mut file = &self.file;
+            file.seek(SeekFrom::Start(32 * idx as u64))?;
+            file.read_exact(&mut hash)?;
+        }
+        Ok(hash)
+    }
+
+    fn try_put(&mut self, idx: usize, hash: [u8; 32]) -> io::Result<()> {
+        if idx >= self.written {
+            let at = 32 * (idx - self.written);
+            if at == self.pending.len() {
+                self.pending.extend_from_slice(&hash);
+                if self.pending.len() >= 32 * BATCH {
+                    self.flush()?;
+                }
+            } else {
+                self.pending[at..at + 32].copy_from_slice(&hash);
+            }
+            Ok(())
+        } else {
+            self.file.seek(SeekFrom::Start(32 * idx as u64))?;
+            self.file.write_all(&hash)
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Drop for FileStore {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::verify_proof;
+
+    #[test]
+    fn file_backed_trees_match() {
+        let path = std::env::temp_dir().join(format!("merkle-nodes-{}", std::process::id()));
+        // Enough nodes to be flushed part way through the build.
+        let items: Vec<Vec<u8>> = (0..BATCH as u32 / 2 + 5)
+            .map(|i| i.to_le_bytes().to_vec())
+            .collect();
+        for &padding in [Padding::Promote, Padding::DuplicateLast, Padding::Zero].iter() {
+            for &len in [1, 2, 7, items.len()].iter() {
+                let expected = MerkleTree::from_leaves_padded(&items[..len], padding).unwrap();
+                let store = FileStore::create(&path).unwrap();
+                let tree: MerkleTree<sha2::Sha256, FileStore> =
+                    MerkleTree::build_in(store, &items[..len], padding).unwrap();
+                assert_eq!(tree.root(), expected.root());
+                assert_eq!(tree.size(), expected.size());
+                for idx in [0, len / 2, len - 1] {
+                    assert_eq!(tree.path(idx), expected.path(idx));
+                    assert_eq!(
+                        tree.proof(&items[idx], idx),
+                        expected.proof(&items[idx], idx)
+                    );
+                }
+                assert_eq!(tree.leaf_id(len - 1), Some(len as u64 - 1));
+                assert_eq!(tree.leaf_by_id(len as u64), None);
+                tree.into_store().flush().unwrap();
+            }
+        }
+
+        let store = FileStore::open(&path).unwrap();
+        let len = items.len();
+        assert!(MerkleTree::<sha2::Sha256, _>::open(
+            FileStore::open(&path).unwrap(),
+            len - 1,
+            Padding::Zero
+        )
+        .is_none());
+        let tree: MerkleTree<sha2::Sha256, FileStore> =
+            MerkleTree::open(store, len, Padding::Zero).unwrap();
+        let path_3 = tree.proof(&items[3], 3).unwrap();
+        assert!(verify_proof(&items[3], tree.root(), &path_3).is_valid());
+        assert_eq!(tree.try_path(3).unwrap(), Some(path_3));
+        assert_eq!(tree.try_path(len).unwrap(), None);
+
+        // Cut short under an open tree, reads fail instead of panicking.
+        std::fs::OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .and_then(|file| file.set_len(64))
+            .unwrap();
+        assert!(tree.try_path(3).is_err());
+        assert_eq!(
+            tree.store().try_get(5).unwrap_err().kind(),
+            io::ErrorKind::UnexpectedEof
+        );
+        drop(tree);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn vec_store() {
+        let items = ["a", "b", "c", "d", "e"];
+        let tree: MerkleTree = MerkleTree::build_in(Vec::new(), items, Padding::Zero).unwrap();
+        let expected = MerkleTree::from_leaves_padded(items, Padding::Zero).unwrap();
+        assert_eq!(tree.root(), expected.root());
+        assert!(MerkleTree::<sha2::Sha256>::build_in(
+            Vec::new(),
+            Vec::<&str>::new(),
+            Padding::Zero
+        )
+        .is_none());
+    }
+}
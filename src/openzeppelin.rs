@@ -0,0 +1,283 @@
+//! Trees in OpenZeppelin's convention, for proofs checked on chain with
+//! `MerkleProof.verify`. Leaves are keccak256 hashes, pairs are hashed in
+//! sorted order so proofs carry no direction bits, and nodes sit in the
+//! array layout of `@openzeppelin/merkle-tree`: the root at 0, the children
+//! of `i` at `2i + 1` and `2i + 2`, and leaf `i` at `len - 1 - i`. Roots and
+//! proofs are the ones that library computes for the same leaf hashes.
+//!
+//! `from_leaves` hashes each ABI-encoded leaf once, the usual pairing with
+//! `keccak256(abi.encode(...))` in a contract. `StandardMerkleTree` hashes
+//! its leaves twice; build those trees with [`standard_leaf`] and
+//! [`OzTree::sorted`], its default leaf order.
+
+use crate::memory::{vec_bytes, HeapSize};
+
+pub struct OzTree {
+    nodes: Vec<[u8; 32]>,
+    leaves: usize,
+}
+
+impl OzTree {
+    /// Leaves in the order given. Returns `None` if there are none.
+    pub fn new(leaf_hashes: Vec<[u8; 32]>) -> Option<Self> {
+        if leaf_hashes.is_empty() {
+            return None;
+        }
+        let leaves = leaf_hashes.len();
+        let len = 2 * leaves - 1;
+        let mut nodes = vec![[0; 32]; len];
+        for (i, leaf) in leaf_hashes.into_iter().enumerate() {
+            nodes[len - 1 - i] = leaf;
+        }
+        for i in (0..len - leaves).rev() {
+            nodes[i] = hash_sorted_pair(&nodes[2 * i + 1], &nodes[2 * i + 2]);
+        }
+        Some(OzTree { nodes, leaves })
+    }
+
+    /// Leaves sorted by hash, as `@openzeppelin/merkle-tree` does unless
+    /// told not to. Leaf indices then follow the sorted order.
+    pub fn sorted(mut leaf_hashes: Vec<[u8; 32]>) -> Option<Self> {
+        leaf_hashes.sort_unstable();
+        OzTree::new(leaf_hashes)
+    }
+
+    /// One leaf per ABI-encoded item, hashed with keccak256, in the order
+    /// given.
+    pub fn from_leaves<I>(items: I) -> Option<Self>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        OzTree::new(
+            items
+                .into_iter()
+                .map(|item| keccak256(item.as_ref()))
+                .collect(),
+        )
+    }
+
+    pub fn leaves(&self) -> usize {
+        self.leaves
+    }
+
+    pub fn root(&self) -> [u8; 32] {
+        self.nodes[0]
+    }
+
+    pub fn leaf(&self, idx: usize) -> Option<[u8; 32]> {
+        if idx < self.leaves {
+            Some(self.nodes[self.nodes.len() - 1 - idx])
+        } else {
+            None
+        }
+    }
+
+    /// Siblings from leaf `idx` up, the `proof` argument of
+    /// `MerkleProof.verify`.
+    pub fn proof(&self, idx: usize) -> Option<Vec<[u8; 32]>> {
+        if idx >= self.leaves {
+            return None;
+        }
+        let mut proof = Vec::new();
+        let mut node = self.nodes.len() - 1 - idx;
+        while node > 0 {
+            let sibling = if node & 1 == 1 { node + 1 } else { node - 1 };
+            proof.push(self.nodes[sibling]);
+            node = (node - 1) / 2;
+        }
+        Some(proof)
+    }
+}
+
+/// `MerkleProof.verify`: folds `proof` over `leaf` with sorted pairs.
+pub fn verify_sorted(leaf: [u8; 32], root: [u8; 32], proof: &[[u8; 32]]) -> bool {
+    proof.iter().fold(leaf, |candidate, sibling| {
+        hash_sorted_pair(&candidate, sibling)
+    }) == root
+}
+
+/// `keccak256` of the smaller hash followed by the larger.
+pub fn hash_sorted_pair(a: &[u8; 32], b: &[u8; 32]) -> [u8; 32] {
+    let (first, second) = if a <= b { (a, b) } else { (b, a) };
+    let mut bytes = [0u8; 64];
+    bytes[..32].copy_from_slice(first);
+    bytes[32..].copy_from_slice(second);
+    keccak256(&bytes)
+}
+
+/// `StandardMerkleTree` leaf hash: `keccak256(keccak256(encoded))`, where
+/// `encoded` is the leaf's `abi.encode`.
+pub fn standard_leaf(encoded: &[u8]) -> [u8; 32] {
+    keccak256(&keccak256(encoded))
+}
+
+const RATE: usize = 136;
+
+const ROUND_CONSTANTS: [u64; 24] = [
+    0x0000000000000001,
+    0x0000000000008082,
+    0x800000000000808a,
+    0x8000000080008000,
+    0x000000000000808b,
+    0x0000000080000001,
+    0x8000000080008081,
+    0x8000000000008009,
+    0x000000000000008a,
+    0x0000000000000088,
+    0x0000000080008009,
+    0x000000008000000a,
+    0x000000008000808b,
+    0x800000000000008b,
+    0x8000000000008089,
+    0x8000000000008003,
+    0x8000000000008002,
+    0x8000000000000080,
+    0x000000000000800a,
+    0x800000008000000a,
+    0x8000000080008081,
+    0x8000000000008080,
+    0x0000000080000001,
+    0x8000000080008008,
+];
+
+/// Rotation of each lane visited by the combined rho and pi steps.
+const ROTATIONS: [u32; 24] = [
+    1, 3, 6, 10, 15, 21, 28, 36, 45, 55, 2, 14, 27, 41, 56, 8, 25, 43, 62, 18, 39, 61, 20, 44,
+];
+
+/// Lane order of the combined rho and pi steps.
+const LANES: [usize; 24] = [
+    10, 7, 11, 17, 18, 3, 5, 16, 8, 21, 24, 4, 15, 23, 19, 13, 12, 2, 20, 14, 22, 9, 6, 1,
+];
+
+fn keccak_f(state: &mut [u64; 25]) {
+    for &constant in ROUND_CONSTANTS.iter() {
+        let mut parity = [0u64; 5];
+        for (x, column) in parity.iter_mut().enumerate() {
+            *column = (0..25).step_by(5).fold(0, |acc, y| acc ^ state[x + y]);
+        }
+        for x in 0..5 {
+            let theta = parity[(x + 4) % 5] ^ parity[(x + 1) % 5].rotate_left(1);
+            for y in (0..25).step_by(5) {
+                state[x + y] ^= theta;
+            }
+        }
+
+        let mut carried = state[1];
+        for (&lane, &rotation) in LANES.iter().zip(ROTATIONS.iter()) {
+            let next = state[lane];
+            state[lane] = carried.rotate_left(rotation);
+            carried = next;
+        }
+
+        for y in (0..25).step_by(5) {
+            let mut row = [0u64; 5];
+            row.copy_from_slice(&state[y..y + 5]);
+            for x in 0..5 {
+                state[y + x] = row[x] ^ (!row[(x + 1) % 5] & row[(x + 2) % 5]);
+            }
+        }
+
+        state[0] ^= constant;
+    }
+}
+
+/// Ethereum's keccak256: Keccak-f[1600] with the original `0x01` padding,
+/// not SHA3-256.
+pub fn keccak256(data: &[u8]) -> [u8; 32] {
+    let mut state = [0u64; 25];
+    let absorb = |state: &mut [u64; 25], block: &[u8]| {
+        for (lane, bytes) in state.iter_mut().zip(block.chunks(8)) {
+            let mut word = [0u8; 8];
+            word.copy_from_slice(bytes);
+            *lane ^= u64::from_le_bytes(word);
+        }
+        keccak_f(state);
+    };
+    let mut blocks = data.chunks_exact(RATE);
+    for block in &mut blocks {
+        absorb(&mut state, block);
+    }
+    let rest = blocks.remainder();
+    let mut last = [0u8; RATE];
+    last[..rest.len()].copy_from_slice(rest);
+    last[rest.len()] ^= 0x01;
+    last[RATE - 1] ^= 0x80;
+    absorb(&mut state, &last);
+
+    let mut out = [0u8; 32];
+    for (bytes, lane) in out.chunks_mut(8).zip(state.iter()) {
+        bytes.copy_from_slice(&lane.to_le_bytes());
+    }
+    out
+}
+
+impl HeapSize for OzTree {
+    fn heap_size_bytes(&self) -> usize {
+        vec_bytes(&self.nodes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    #[test]
+    fn keccak_vectors() {
+        assert_eq!(
+            hex(&keccak256(b"")),
+            "c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470"
+        );
+        assert_eq!(
+            hex(&keccak256(b"abc")),
+            "4e03657aea45a94fc7d47ba826c8d667c0d1e6e33a64a036ec44f58fa12d6c45"
+        );
+        assert_eq!(
+            hex(&keccak256(b"The quick brown fox jumps over the lazy dog")),
+            "4d741b6f1eb29cb2a9b9911c82f56fa8d73b04959d3d9d222895df6c0b28aa15"
+        );
+        // Padding takes a block of its own after a full one.
+        assert_ne!(keccak256(&[0x61; RATE]), keccak256(&[0x61; RATE - 1]));
+    }
+
+    #[test]
+    fn array_layout() {
+        let leaves: Vec<[u8; 32]> = (0..3u8).map(|i| keccak256(&[i])).collect();
+        let tree = OzTree::new(leaves.clone()).unwrap();
+        // [root, n1, leaf 2, leaf 1, leaf 0] with n1 over leaves 1 and 0.
+        let n1 = hash_sorted_pair(&leaves[1], &leaves[0]);
+        assert_eq!(tree.root(), hash_sorted_pair(&n1, &leaves[2]));
+        assert_eq!(tree.proof(0), Some(vec![leaves[1], leaves[2]]));
+        assert_eq!(tree.proof(2), Some(vec![n1]));
+        assert_eq!(tree.leaf(1), Some(leaves[1]));
+        assert!(tree.proof(3).is_none());
+
+        let single = OzTree::new(vec![leaves[0]]).unwrap();
+        assert_eq!((single.root(), single.proof(0)), (leaves[0], Some(vec![])));
+        assert!(OzTree::new(Vec::new()).is_none());
+    }
+
+    #[test]
+    fn proofs_verify_without_directions() {
+        let items: Vec<Vec<u8>> = (0..13u8).map(|i| vec![i; 64]).collect();
+        let tree = OzTree::from_leaves(&items).unwrap();
+        for (idx, item) in items.iter().enumerate() {
+            let proof = tree.proof(idx).unwrap();
+            assert!(verify_sorted(keccak256(item), tree.root(), &proof));
+            assert!(!verify_sorted(standard_leaf(item), tree.root(), &proof));
+        }
+
+        let hashes: Vec<[u8; 32]> = items.iter().map(|item| standard_leaf(item)).collect();
+        let mut reversed = hashes.clone();
+        reversed.reverse();
+        let sorted = OzTree::sorted(hashes).unwrap();
+        assert_eq!(sorted.root(), OzTree::sorted(reversed).unwrap().root());
+        let first = sorted.leaf(0).unwrap();
+        assert!((1..13).all(|idx| sorted.leaf(idx).unwrap() > first));
+    }
+}
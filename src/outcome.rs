@@ -0,0 +1,182 @@
+//! Detailed verification results. `verify_proof` can only compare the final
+//! hash with the root; [`MerkleTree::diagnose`] has the tree and reports
+//! the first level at which a proof departs from it.
+
+use crate::{Error, MerkleHasher, MerkleTree};
+use alloc::format;
+use alloc::string::String;
+use core::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureKind {
+    /// The item does not hash to the leaf.
+    BadLeaf,
+    /// A sibling hash or direction is wrong.
+    BadPath,
+    /// The proof has the wrong number of entries.
+    BadLength,
+    /// The path's directions are not those of the leaf the proof claims.
+    BadIndex,
+}
+
+/// Where verification failed: at `level` (0 is the leaf, the proof length is
+/// the root) the proof produced `computed` where `expected` was required.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Failure {
+    pub kind: FailureKind,
+    pub level: usize,
+    pub expected: [u8; 32],
+    pub computed: [u8; 32],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerificationOutcome {
+    Valid,
+    Invalid(Failure),
+}
+
+impl VerificationOutcome {
+    pub fn is_valid(&self) -> bool {
+        *self == VerificationOutcome::Valid
+    }
+
+    pub fn failure(&self) -> Option<&Failure> {
+        match self {
+            VerificationOutcome::Valid => None,
+            VerificationOutcome::Invalid(failure) => Some(failure),
+        }
+    }
+}
+
+fn hex(hash: &[u8; 32]) -> String {
+    hash.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            FailureKind::BadLeaf => "bad leaf",
+            FailureKind::BadPath => "bad path",
+            FailureKind::BadLength => "bad length",
+            FailureKind::BadIndex => "bad index",
+        };
+        write!(
+            f,
+            "{} at level {}: expected {}, computed {}",
+            kind,
+            self.level,
+            hex(&self.expected),
+            hex(&self.computed)
+        )
+    }
+}
+
+impl<H: MerkleHasher> MerkleTree<H> {
+    /// Checks `proof` for `item` at leaf `idx` against this tree, level by
+    /// level, so a failure names the first wrong hash rather than only the
+    /// root.
+    pub fn diagnose(
+        &self,
+        item: &[u8],
+        idx: usize,
+        proof: &[([u8; 32], bool)],
+    ) -> Result<VerificationOutcome, Error> {
+        self.path(idx)?;
+        let steps = self.steps(idx);
+        let invalid = |kind, level, expected, computed| {
+            Ok(VerificationOutcome::Invalid(Failure {
+                kind,
+                level,
+                expected,
+                computed,
+            }))
+        };
+
+        let mut ctx = H::Context::default();
+        let mut candidate = H::hash_data_with(&mut ctx, item);
+        let mut expected = self.nodes[idx].value;
+        if candidate != expected {
+            return invalid(FailureKind::BadLeaf, 0, expected, candidate);
+        }
+        for (level, (entry, &(node, actual))) in proof.iter().zip(steps.iter()).enumerate() {
+            candidate = if entry.1 {
+                H::hash_pair_with(&mut ctx, &candidate, &entry.0)
+            } else {
+                H::hash_pair_with(&mut ctx, &entry.0, &candidate)
+            };
+            expected = self.nodes[self.parent_idx(node)?].value;
+            if *entry != actual {
+                return invalid(FailureKind::BadPath, level + 1, expected, candidate);
+            }
+        }
+        if proof.len() != steps.len() {
+            let level = proof.len().min(steps.len());
+            return invalid(FailureKind::BadLength, level, self.root(), candidate);
+        }
+        Ok(VerificationOutcome::Valid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::verify_proof;
+
+    #[test]
+    fn reports_root_mismatch() {
+        let tree = MerkleTree::new(b"asdfjkln12345678", 4);
+        let path = tree.path(1).unwrap();
+        assert!(verify_proof(b"jkln", tree.root(), &path).is_valid());
+
+        let outcome = verify_proof(b"jkl?", tree.root(), &path);
+        let failure = outcome.failure().unwrap();
+        assert_eq!((failure.kind, failure.level), (FailureKind::BadPath, 2));
+        assert_eq!(failure.expected, tree.root());
+        assert!(failure
+            .to_string()
+            .starts_with("bad path at level 2: expected "));
+
+        let long = vec![([0; 32], true); 65];
+        assert_eq!(
+            verify_proof(b"jkln", tree.root(), &long)
+                .failure()
+                .unwrap()
+                .kind,
+            FailureKind::BadLength
+        );
+    }
+
+    #[test]
+    fn diagnoses_against_the_tree() {
+        let tree = MerkleTree::new(b"asdfjkln12345678", 4);
+        let path = tree.path(1).unwrap();
+        assert_eq!(
+            tree.diagnose(b"jkln", 1, &path),
+            Ok(VerificationOutcome::Valid)
+        );
+
+        let failure = *tree.diagnose(b"nope", 1, &path).unwrap().failure().unwrap();
+        assert_eq!((failure.kind, failure.level), (FailureKind::BadLeaf, 0));
+        assert_eq!(failure.expected, tree.node(1).unwrap());
+
+        let mut wrong = path.clone();
+        wrong[1].0[0] ^= 1;
+        let failure = *tree
+            .diagnose(b"jkln", 1, &wrong)
+            .unwrap()
+            .failure()
+            .unwrap();
+        assert_eq!((failure.kind, failure.level), (FailureKind::BadPath, 2));
+        assert_eq!(failure.expected, tree.root());
+
+        let failure = *tree
+            .diagnose(b"jkln", 1, &path[..1])
+            .unwrap()
+            .failure()
+            .unwrap();
+        assert_eq!((failure.kind, failure.level), (FailureKind::BadLength, 1));
+        assert_eq!(failure.computed, tree.node(4).unwrap());
+
+        assert_eq!(tree.diagnose(b"jkln", 5, &path), Err(Error::NotALeaf(5)));
+    }
+}
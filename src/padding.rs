@@ -0,0 +1,80 @@
+//! How a level with an odd number of nodes is closed off. Pairs are hashed
+//! odd offset first, so the last node of an odd level sits at an even offset
+//! and its missing sibling would be hashed before it. Trees over a power of
+//! two leaves never have a lone node, so every rule gives the same tree.
+
+use crate::MerkleHasher;
+use alloc::vec::Vec;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "borsh",
+    derive(borsh::BorshSerialize, borsh::BorshDeserialize)
+)]
+#[cfg_attr(feature = "borsh", borsh(use_discriminant = true))]
+#[cfg_attr(
+    feature = "scale",
+    derive(parity_scale_codec::Encode, parity_scale_codec::Decode)
+)]
+pub enum Padding {
+    /// The lone node moves up a level unchanged and contributes no path
+    /// entry there.
+    #[default]
+    Promote = 0,
+    /// The lone node is paired with itself, as in Bitcoin. Like Bitcoin's
+    /// trees, `[a, b, c]` and `[a, b, c, c]` then share a root.
+    DuplicateLast = 1,
+    /// The lone node is paired with 32 zero bytes.
+    Zero = 2,
+}
+
+impl Padding {
+    pub fn from_byte(byte: u8) -> Option<Padding> {
+        match byte {
+            0 => Some(Padding::Promote),
+            1 => Some(Padding::DuplicateLast),
+            2 => Some(Padding::Zero),
+            _ => None,
+        }
+    }
+
+    pub fn byte(self) -> u8 {
+        self as u8
+    }
+
+    /// The hash standing in for a lone node's sibling, if it gets one.
+    pub fn sibling(self, lone: &[u8; 32]) -> Option<[u8; 32]> {
+        match self {
+            Padding::Promote => None,
+            Padding::DuplicateLast => Some(*lone),
+            Padding::Zero => Some([0; 32]),
+        }
+    }
+
+    /// The parent of a lone node.
+    pub fn parent<H: MerkleHasher>(self, ctx: &mut H::Context, lone: &[u8; 32]) -> [u8; 32] {
+        match self.sibling(lone) {
+            Some(sibling) => H::hash_pair_with(ctx, &sibling, lone),
+            None => *lone,
+        }
+    }
+
+    /// The directions of leaf `index`'s path in a tree of `leaves` leaves,
+    /// from the leaf up, or `None` if there is no such leaf.
+    pub fn directions(self, leaves: u64, index: u64) -> Option<Vec<bool>> {
+        if index >= leaves {
+            return None;
+        }
+        let mut directions = Vec::new();
+        let (mut len, mut offset) = (leaves, index);
+        while len > 1 {
+            if offset ^ 1 < len || self != Padding::Promote {
+                directions.push(offset & 1 == 1);
+            }
+            len = len.div_ceil(2);
+            offset /= 2;
+        }
+        Some(directions)
+    }
+}
@@ -0,0 +1,70 @@
+use crate::{MerkleHasher, MerkleTree, Path};
+
+/// One page of a listing. `next` is the offset to pass to get the following
+/// page, or `None` once the listing is exhausted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub next: Option<usize>,
+}
+
+impl<H: MerkleHasher> MerkleTree<H> {
+    pub fn leaves_page(&self, offset: usize, limit: usize) -> Page<[u8; 32]> {
+        self.page(offset, limit, |idx| self.nodes[idx].value)
+    }
+
+    pub fn proof_page(&self, offset: usize, limit: usize) -> Page<(usize, [u8; 32], Path)> {
+        self.page(offset, limit, |idx| {
+            (
+                idx,
+                self.nodes[idx].value,
+                self.path(idx).unwrap_or_default(),
+            )
+        })
+    }
+
+    fn page<T, F: Fn(usize) -> T>(&self, offset: usize, limit: usize, item: F) -> Page<T> {
+        let start = offset.min(self.leaves());
+        let end = start.saturating_add(limit).min(self.leaves());
+        Page {
+            items: (start..end).map(item).collect(),
+            next: if end < self.leaves() { Some(end) } else { None },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::verify_proof;
+
+    #[test]
+    fn pages_cover_leaves() {
+        let data = b"asdfasdfasdfasdfasdfasdfasdfasdf";
+        let tree = MerkleTree::new(data, 8);
+
+        let mut offset = Some(0);
+        let mut seen = Vec::new();
+        while let Some(start) = offset {
+            let page = tree.leaves_page(start, 3);
+            seen.extend(page.items);
+            offset = page.next;
+        }
+        assert_eq!(seen, tree.leaves_page(0, 8).items);
+        assert_eq!(seen.len(), 8);
+        assert!(tree.leaves_page(20, 3).items.is_empty());
+    }
+
+    #[test]
+    fn proof_pages_verify() {
+        let data = b"asdfjkln12345678";
+        let tree = MerkleTree::new(data, 4);
+
+        let page = tree.proof_page(1, 2);
+        assert_eq!(page.next, Some(3));
+        for (idx, _, proof) in page.items {
+            let item = data.chunks(4).nth(idx).unwrap();
+            assert!(verify_proof(item, tree.root(), &proof).is_valid());
+        }
+    }
+}
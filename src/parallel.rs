@@ -0,0 +1,248 @@
+//! Tree construction on several threads. Leaves hash independently, and so
+//! do the pairs of each level, so every level is cut into one run per
+//! thread; the levels themselves still go one after another. The result is
+//! the same tree as the sequential constructors build.
+//!
+//! Multiproofs are verified the same way: the proven leaves, then each
+//! level of the reconstruction frontier, are split across threads. Which
+//! proof hash every parent takes depends only on the offsets, so each level
+//! is planned on the calling thread before its hashing is handed out.
+//!
+//! With the `rayon` feature the runs go to rayon's global pool, so they
+//! share its threads with the rest of the program; without it each run gets
+//! a scoped thread of its own.
+
+use crate::multiproof::well_formed;
+use crate::{Layout, MerkleHasher, MerkleTree, MultiProof, Node, Padding, TreeHead};
+use sha2::Sha256;
+
+/// Fewest hashes worth handing to a thread of their own.
+const MIN_RUN: usize = 1024;
+
+/// Sets `out[i] = hash(ctx, i)` on up to `threads` threads, each with its
+/// own context.
+fn fill<H, F>(out: &mut [[u8; 32]], threads: usize, hash: F)
+where
+    H: MerkleHasher,
+    F: Fn(&mut H::Context, usize) -> [u8; 32] + Sync,
+{
+    let threads = threads.min(out.len().div_ceil(MIN_RUN)).max(1);
+    let run = out.len().div_ceil(threads).max(1);
+    let hash = &hash;
+    let fill_run = move |start: usize, run: &mut [[u8; 32]]| {
+        let mut ctx = H::Context::default();
+        for (i, slot) in run.iter_mut().enumerate() {
+            *slot = hash(&mut ctx, start + i);
+        }
+    };
+    if threads == 1 {
+        fill_run(0, out);
+        return;
+    }
+    #[cfg(feature = "rayon")]
+    {
+        use rayon::prelude::*;
+        out.par_chunks_mut(run)
+            .enumerate()
+            .for_each(|(n, chunk)| fill_run(n * run, chunk));
+    }
+    #[cfg(not(feature = "rayon"))]
+    std::thread::scope(|scope| {
+        for (n, chunk) in out.chunks_mut(run).enumerate() {
+            scope.spawn(move || fill_run(n * run, chunk));
+        }
+    });
+}
+
+impl MerkleTree {
+    /// Same tree as `from_leaves`, built on up to `threads` threads.
+    pub fn par_from_leaves<T: AsRef<[u8]> + Sync>(items: &[T], threads: usize) -> Option<Self> {
+        MerkleTree::par_build_from_leaves_padded(items, Padding::default(), threads)
+    }
+}
+
+impl<H: MerkleHasher> MerkleTree<H> {
+    /// Same tree as `build_from_leaves_padded`, built on up to `threads`
+    /// threads. Small levels stay on the calling thread.
+    pub fn par_build_from_leaves_padded<T: AsRef<[u8]> + Sync>(
+        items: &[T],
+        padding: Padding,
+        threads: usize,
+    ) -> Option<Self> {
+        let layout = Layout::new(items.len())?;
+        let mut hashes = vec![[0; 32]; layout.nodes()];
+        fill::<H, _>(&mut hashes[..items.len()], threads, |ctx, i| {
+            H::hash_data_with(ctx, items[i].as_ref())
+        });
+
+        let (mut start, mut len) = (0, items.len());
+        while len > 1 {
+            let (below, above) = hashes.split_at_mut(start + len);
+            let level = &below[start..];
+            fill::<H, _>(&mut above[..len / 2], threads, |ctx, i| {
+                H::hash_pair_with(ctx, &level[2 * i + 1], &level[2 * i])
+            });
+            if len & 1 == 1 {
+                above[len / 2] = padding.parent::<H>(&mut H::Context::default(), &level[len - 1]);
+            }
+            start += len;
+            len = len.div_ceil(2);
+        }
+        let nodes = hashes.into_iter().map(Node::new).collect();
+        Some(MerkleTree::from_nodes_padded(nodes, items.len(), padding))
+    }
+}
+
+/// Where a parent on the frontier gets its children, by position in the
+/// level below.
+#[derive(Clone, Copy)]
+enum Parent {
+    /// Both children are known, the even one at this position.
+    Pair(usize),
+    /// The child is alone on its level.
+    Lone(usize),
+    /// The child's sibling is the proof hash at the second position.
+    Proof(usize, usize),
+}
+
+/// Same answer as `verify_multiproof`, hashing on up to `threads` threads.
+pub fn par_verify_multiproof<T: AsRef<[u8]> + Sync>(
+    items: &[T],
+    head: &TreeHead,
+    proof: &MultiProof,
+    threads: usize,
+) -> bool {
+    par_verify_multiproof_with::<Sha256, T>(items, head, Padding::default(), proof, threads)
+}
+
+/// Same answer as `verify_multiproof_with`, hashing on up to `threads`
+/// threads.
+pub fn par_verify_multiproof_with<H: MerkleHasher, T: AsRef<[u8]> + Sync>(
+    items: &[T],
+    head: &TreeHead,
+    padding: Padding,
+    proof: &MultiProof,
+    threads: usize,
+) -> bool {
+    if !well_formed(items.len(), head, padding, proof) {
+        return false;
+    }
+    let mut known = vec![[0; 32]; items.len()];
+    fill::<H, _>(&mut known, threads, |ctx, i| {
+        H::hash_data_with(ctx, items[i].as_ref())
+    });
+    let mut offsets = proof.indices().to_vec();
+    let mut hashes = proof.hashes();
+    let mut len = proof.leaves();
+    while len > 1 {
+        let mut plan = Vec::with_capacity(offsets.len());
+        let mut above = Vec::with_capacity(offsets.len());
+        let (mut i, mut used) = (0, 0);
+        while i < offsets.len() {
+            let offset = offsets[i];
+            if offsets.get(i + 1) == Some(&(offset ^ 1)) {
+                plan.push(Parent::Pair(i));
+                i += 1;
+            } else if offset ^ 1 >= len {
+                plan.push(Parent::Lone(i));
+            } else {
+                plan.push(Parent::Proof(i, used));
+                used += 1;
+            }
+            above.push(offset / 2);
+            i += 1;
+        }
+        if used > hashes.len() {
+            return false;
+        }
+        let (level, rest) = hashes.split_at(used);
+        let mut parents = vec![[0; 32]; plan.len()];
+        fill::<H, _>(&mut parents, threads, |ctx, p| match plan[p] {
+            Parent::Pair(even) => H::hash_pair_with(ctx, &known[even + 1], &known[even]),
+            Parent::Lone(lone) => padding.parent::<H>(ctx, &known[lone]),
+            Parent::Proof(child, sibling) if offsets[child] & 1 == 1 => {
+                H::hash_pair_with(ctx, &known[child], &level[sibling])
+            }
+            Parent::Proof(child, sibling) => H::hash_pair_with(ctx, &level[sibling], &known[child]),
+        });
+        known = parents;
+        offsets = above;
+        hashes = rest;
+        len = len.div_ceil(2);
+    }
+    hashes.is_empty() && known == [head.root]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_sequential_build() {
+        let items: Vec<[u8; 4]> = (0..5000u32).map(u32::to_le_bytes).collect();
+        for &padding in [Padding::Promote, Padding::DuplicateLast, Padding::Zero].iter() {
+            for &leaves in [1, 2, 7, 1024, 4097, 5000].iter() {
+                let sequential = MerkleTree::from_leaves_padded(&items[..leaves], padding).unwrap();
+                for &threads in [0, 1, 4].iter() {
+                    let parallel: MerkleTree = MerkleTree::par_build_from_leaves_padded(
+                        &items[..leaves],
+                        padding,
+                        threads,
+                    )
+                    .unwrap();
+                    assert_eq!(parallel.root(), sequential.root());
+                    assert_eq!(parallel.path(leaves - 1), sequential.path(leaves - 1));
+                }
+            }
+        }
+        assert!(MerkleTree::par_from_leaves::<[u8; 4]>(&[], 4).is_none());
+    }
+
+    #[test]
+    fn verifies_multiproofs() {
+        let items: Vec<[u8; 4]> = (0..5000u32).map(u32::to_le_bytes).collect();
+        for &padding in [Padding::Promote, Padding::DuplicateLast, Padding::Zero].iter() {
+            let tree = MerkleTree::from_leaves_padded(&items, padding).unwrap();
+            let head = TreeHead {
+                size: 5000,
+                root: tree.root(),
+            };
+            let every: Vec<usize> = (0..5000).collect();
+            let sparse: Vec<usize> = (0..5000).step_by(7).chain([4999]).collect();
+            for indices in [every, sparse, vec![0], vec![2048, 4999]] {
+                let proof = tree.multiproof(&indices).unwrap();
+                let proven: Vec<[u8; 4]> = proof
+                    .indices()
+                    .iter()
+                    .map(|&idx| items[idx as usize])
+                    .collect();
+                for &threads in [0, 1, 3, 8].iter() {
+                    assert!(par_verify_multiproof_with::<Sha256, _>(
+                        &proven, &head, padding, &proof, threads
+                    ));
+                }
+                let mut wrong = proven.clone();
+                wrong[proven.len() / 2][0] ^= 1;
+                assert!(!par_verify_multiproof_with::<Sha256, _>(
+                    &wrong, &head, padding, &proof, 4
+                ));
+            }
+        }
+
+        let tree = MerkleTree::from_leaves(&items[..8]).unwrap();
+        let proof = tree.multiproof(&[1, 5]).unwrap();
+        let proven = [items[1], items[5]];
+        assert!(par_verify_multiproof(&proven, &tree.head(), &proof, 2));
+        assert!(!par_verify_multiproof(
+            &proven[..1],
+            &tree.head(),
+            &proof,
+            2
+        ));
+        let shorter = TreeHead {
+            size: 7,
+            ..tree.head()
+        };
+        assert!(!par_verify_multiproof(&proven, &shorter, &proof, 2));
+    }
+}
@@ -0,0 +1,260 @@
+//! Trees whose leaf count is a power of two, for fixed-shape workloads such
+//! as per-block transaction trees. With no odd levels there is no padding
+//! to consult, so nodes sit in heap order (the root at 1, the children of
+//! `i` at `2i` and `2i + 1`, leaf `j` at `n + j`) and walking up is a
+//! shift, the sibling an xor. Roots match [`MerkleTree`](crate::MerkleTree)
+//! over the same leaves under any padding.
+//!
+//! A [`PerfectProof`] carries no direction bits: they are the bits of the
+//! leaf index. Binary layout: format version (1 byte), leaf index (u64 LE),
+//! depth (1 byte), then the sibling hashes from the leaf up.
+
+use crate::format::{Version, CURRENT};
+#[cfg(feature = "std")]
+use crate::memory::{vec_bytes, HeapSize};
+use crate::{Error, MerkleHasher, Proof};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::convert::TryInto;
+use core::marker::PhantomData;
+use sha2::Sha256;
+
+pub struct PerfectMerkleTree<H = Sha256> {
+    /// Heap order; `nodes[0]` is unused.
+    nodes: Vec<[u8; 32]>,
+    hasher: PhantomData<H>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PerfectProof {
+    index: u64,
+    siblings: Vec<[u8; 32]>,
+}
+
+impl PerfectMerkleTree {
+    /// Returns `None` unless the number of items is a power of two.
+    pub fn from_leaves<I>(items: I) -> Option<Self>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        PerfectMerkleTree::build(items)
+    }
+}
+
+impl<H: MerkleHasher> PerfectMerkleTree<H> {
+    /// Like `from_leaves`, hashing with `H`.
+    pub fn build<I>(items: I) -> Option<Self>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        let mut ctx = H::Context::default();
+        let hashes = items
+            .into_iter()
+            .map(|item| H::hash_data_with(&mut ctx, item.as_ref()))
+            .collect();
+        PerfectMerkleTree::from_leaf_hashes(hashes)
+    }
+
+    /// Over already-hashed leaves, of which there must be a power of two.
+    pub fn from_leaf_hashes(hashes: Vec<[u8; 32]>) -> Option<Self> {
+        let leaves = hashes.len();
+        if !leaves.is_power_of_two() {
+            return None;
+        }
+        let mut nodes = vec![[0; 32]; leaves];
+        nodes.extend(hashes);
+        let mut ctx = H::Context::default();
+        for i in (1..leaves).rev() {
+            nodes[i] = H::hash_pair_with(&mut ctx, &nodes[2 * i + 1], &nodes[2 * i]);
+        }
+        Some(PerfectMerkleTree {
+            nodes,
+            hasher: PhantomData,
+        })
+    }
+
+    pub fn leaves(&self) -> usize {
+        self.nodes.len() / 2
+    }
+
+    /// Levels above the leaves, and so the length of every proof.
+    pub fn depth(&self) -> usize {
+        self.leaves().trailing_zeros() as usize
+    }
+
+    pub fn root(&self) -> [u8; 32] {
+        self.nodes[1]
+    }
+
+    pub fn leaf(&self, idx: usize) -> Option<[u8; 32]> {
+        (idx < self.leaves()).then(|| self.nodes[self.leaves() + idx])
+    }
+
+    pub fn proof(&self, idx: usize) -> Result<PerfectProof, Error> {
+        if idx >= self.leaves() {
+            return Err(Error::NotALeaf(idx));
+        }
+        let mut node = self.leaves() + idx;
+        let mut siblings = Vec::with_capacity(self.depth());
+        while node > 1 {
+            siblings.push(self.nodes[node ^ 1]);
+            node >>= 1;
+        }
+        Ok(PerfectProof {
+            index: idx as u64,
+            siblings,
+        })
+    }
+
+    /// Replaces leaf `idx` with `data` and returns the new root.
+    pub fn update(&mut self, idx: usize, data: &[u8]) -> Result<[u8; 32], Error> {
+        if idx >= self.leaves() {
+            return Err(Error::NotALeaf(idx));
+        }
+        let mut ctx = H::Context::default();
+        let mut node = self.leaves() + idx;
+        self.nodes[node] = H::hash_data_with(&mut ctx, data);
+        while node > 1 {
+            node >>= 1;
+            self.nodes[node] =
+                H::hash_pair_with(&mut ctx, &self.nodes[2 * node + 1], &self.nodes[2 * node]);
+        }
+        Ok(self.root())
+    }
+}
+
+impl PerfectProof {
+    pub fn index(&self) -> u64 {
+        self.index
+    }
+
+    pub fn siblings(&self) -> &[[u8; 32]] {
+        &self.siblings
+    }
+
+    pub fn verify(&self, item: &[u8], root: [u8; 32]) -> bool {
+        self.verify_with::<Sha256>(item, root)
+    }
+
+    /// Checks `item` against `root`, also requiring the index to fit the
+    /// tree the proof's length implies.
+    pub fn verify_with<H: MerkleHasher>(&self, item: &[u8], root: [u8; 32]) -> bool {
+        if self.siblings.len() > 63 || self.index >> self.siblings.len() != 0 {
+            return false;
+        }
+        let mut ctx = H::Context::default();
+        let mut candidate = H::hash_data_with(&mut ctx, item);
+        for (level, sibling) in self.siblings.iter().enumerate() {
+            candidate = if self.index >> level & 1 == 1 {
+                H::hash_pair_with(&mut ctx, &candidate, sibling)
+            } else {
+                H::hash_pair_with(&mut ctx, sibling, &candidate)
+            };
+        }
+        candidate == root
+    }
+
+    /// The same proof with its directions spelled out, for the general
+    /// verifiers.
+    pub fn into_proof(self) -> Proof {
+        let index = self.index;
+        let path = self
+            .siblings
+            .into_iter()
+            .enumerate()
+            .map(|(level, sibling)| (sibling, index >> level & 1 == 1))
+            .collect();
+        Proof::new(index, path)
+    }
+
+    /// Returns `None` for proofs deeper than 63.
+    pub fn to_bytes(&self) -> Option<Vec<u8>> {
+        if self.siblings.len() > 63 {
+            return None;
+        }
+        let mut out = Vec::with_capacity(10 + 32 * self.siblings.len());
+        out.push(CURRENT.byte());
+        out.extend_from_slice(&self.index.to_le_bytes());
+        out.push(self.siblings.len() as u8);
+        for sibling in &self.siblings {
+            out.extend_from_slice(sibling);
+        }
+        Some(out)
+    }
+
+    /// Rejects trailing bytes and indices past the depth.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 10 || Version::from_byte(bytes[0]) != Some(Version::V1) {
+            return None;
+        }
+        let index = u64::from_le_bytes(bytes[1..9].try_into().unwrap());
+        let depth = bytes[9] as usize;
+        if depth > 63 || index >> depth != 0 || bytes.len() != 10 + 32 * depth {
+            return None;
+        }
+        let siblings = bytes[10..]
+            .chunks_exact(32)
+            .map(|sibling| sibling.try_into().unwrap())
+            .collect();
+        Some(PerfectProof { index, siblings })
+    }
+}
+
+#[cfg(feature = "std")]
+impl<H> HeapSize for PerfectMerkleTree<H> {
+    fn heap_size_bytes(&self) -> usize {
+        vec_bytes(&self.nodes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MerkleTree, Padding};
+
+    #[test]
+    fn matches_general_tree() {
+        let items: Vec<[u8; 2]> = (0..16u8).map(|i| [i, 0]).collect();
+        let mut tree = PerfectMerkleTree::from_leaves(&items).unwrap();
+        let general = MerkleTree::from_leaves_padded(&items, Padding::Zero).unwrap();
+        assert_eq!((tree.root(), tree.depth()), (general.root(), 4));
+        for (idx, item) in items.iter().enumerate() {
+            let proof = tree.proof(idx).unwrap();
+            assert!(proof.verify(item, tree.root()));
+            assert!(!proof.verify(b"other", tree.root()));
+            assert_eq!(proof.clone().into_proof(), general.leaf_proof(idx).unwrap());
+            let bytes = proof.to_bytes().unwrap();
+            assert_eq!(bytes.len(), 10 + 32 * 4);
+            assert_eq!(PerfectProof::from_bytes(&bytes), Some(proof));
+        }
+
+        let mut updated = MerkleTree::from_leaves(&items).unwrap();
+        let root = tree.update(5, b"five").unwrap();
+        assert_eq!(root, updated.update(5, b"five").unwrap());
+        assert!(tree.proof(5).unwrap().verify(b"five", root));
+        assert_eq!(tree.update(16, b"x"), Err(Error::NotALeaf(16)));
+
+        let single = PerfectMerkleTree::from_leaves(["only"]).unwrap();
+        assert!(single.proof(0).unwrap().verify(b"only", single.root()));
+    }
+
+    #[test]
+    fn rejects_other_shapes() {
+        assert!(PerfectMerkleTree::from_leaves(["a", "b", "c"]).is_none());
+        assert!(PerfectMerkleTree::from_leaves(Vec::<&str>::new()).is_none());
+
+        let tree = PerfectMerkleTree::from_leaves(["a", "b", "c", "d"]).unwrap();
+        let mut bytes = tree.proof(3).unwrap().to_bytes().unwrap();
+        // Index 4 does not fit a proof of depth 2.
+        bytes[1] = 4;
+        assert!(PerfectProof::from_bytes(&bytes).is_none());
+        let forged = PerfectProof {
+            index: 4,
+            siblings: tree.proof(0).unwrap().siblings().to_vec(),
+        };
+        assert!(!forged.verify(b"a", tree.root()));
+        assert!(PerfectProof::from_bytes(&bytes[..bytes.len() - 1]).is_none());
+    }
+}
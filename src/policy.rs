@@ -0,0 +1,214 @@
+//! Rules on where a proven leaf may sit. An [`IndexPolicy`] checks a proof
+//! as `verify_proof` does and then holds its index to each of its rules in
+//! turn, reporting the first one broken as a [`PolicyFailure`].
+//!
+//! The index checked is the one the path's directions spell out for a tree
+//! of the policy's shape, not only the one the proof claims: a proof whose
+//! directions lead to another leaf is `Misplaced`, so a valid proof cannot
+//! be relabelled past a cutoff.
+
+use crate::{verify_proof_with, Failure, MerkleHasher, Padding, Proof, VerificationOutcome};
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::fmt;
+use core::ops::Range;
+use sha2::Sha256;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PolicyRule {
+    /// The index is below the cutoff.
+    Below(u64),
+    /// The index is at or past the start.
+    AtLeast(u64),
+    Within(Range<u64>),
+    /// A predicate added with `IndexPolicy::require`, by its name.
+    Custom(&'static str),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PolicyFailure {
+    /// The proof does not reach the root.
+    Invalid(Failure),
+    /// The proof's directions are not those of the leaf it claims, or it
+    /// claims a leaf past the end of the tree.
+    Misplaced { index: u64 },
+    /// The leaf is proven but breaks `rule`.
+    Violated { index: u64, rule: PolicyRule },
+}
+
+type Predicate<'a> = Box<dyn Fn(u64) -> bool + 'a>;
+
+pub struct IndexPolicy<'a> {
+    leaves: u64,
+    padding: Padding,
+    rules: Vec<(PolicyRule, Option<Predicate<'a>>)>,
+}
+
+impl fmt::Debug for IndexPolicy<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let rules: Vec<&PolicyRule> = self.rules.iter().map(|(rule, _)| rule).collect();
+        f.debug_struct("IndexPolicy")
+            .field("leaves", &self.leaves)
+            .field("padding", &self.padding)
+            .field("rules", &rules)
+            .finish()
+    }
+}
+
+impl<'a> IndexPolicy<'a> {
+    /// A policy with no rules for proofs in a tree of `leaves` leaves.
+    pub fn new(leaves: u64, padding: Padding) -> Self {
+        IndexPolicy {
+            leaves,
+            padding,
+            rules: Vec::new(),
+        }
+    }
+
+    pub fn below(mut self, cutoff: u64) -> Self {
+        self.rules.push((PolicyRule::Below(cutoff), None));
+        self
+    }
+
+    pub fn at_least(mut self, start: u64) -> Self {
+        self.rules.push((PolicyRule::AtLeast(start), None));
+        self
+    }
+
+    pub fn within(mut self, range: Range<u64>) -> Self {
+        self.rules.push((PolicyRule::Within(range), None));
+        self
+    }
+
+    /// Adds `check`, reported as `PolicyRule::Custom(name)` when it fails.
+    pub fn require<F: Fn(u64) -> bool + 'a>(mut self, name: &'static str, check: F) -> Self {
+        self.rules
+            .push((PolicyRule::Custom(name), Some(Box::new(check))));
+        self
+    }
+
+    /// Holds `index` to the rules, in the order they were added.
+    pub fn check_index(&self, index: u64) -> Result<(), PolicyFailure> {
+        for (rule, predicate) in &self.rules {
+            let allowed = match (rule, predicate) {
+                (_, Some(predicate)) => predicate(index),
+                (PolicyRule::Below(cutoff), None) => index < *cutoff,
+                (PolicyRule::AtLeast(start), None) => index >= *start,
+                (PolicyRule::Within(range), None) => range.contains(&index),
+                (PolicyRule::Custom(_), None) => unreachable!("custom rules have a predicate"),
+            };
+            if !allowed {
+                return Err(PolicyFailure::Violated {
+                    index,
+                    rule: rule.clone(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Verifies `proof` for `item` against `root` and returns the leaf's
+    /// index if it passes every rule.
+    pub fn verify(&self, item: &[u8], root: [u8; 32], proof: &Proof) -> Result<u64, PolicyFailure> {
+        self.verify_with::<Sha256>(item, root, proof)
+    }
+
+    pub fn verify_with<H: MerkleHasher>(
+        &self,
+        item: &[u8],
+        root: [u8; 32],
+        proof: &Proof,
+    ) -> Result<u64, PolicyFailure> {
+        let index = proof.index();
+        let placed = self
+            .padding
+            .directions(self.leaves, index)
+            .is_some_and(|expected| proof.path().iter().map(|&(_, odd)| odd).eq(expected));
+        if !placed {
+            return Err(PolicyFailure::Misplaced { index });
+        }
+        if let VerificationOutcome::Invalid(failure) =
+            verify_proof_with::<H>(item, root, proof.path())
+        {
+            return Err(PolicyFailure::Invalid(failure));
+        }
+        self.check_index(index)?;
+        Ok(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MerkleTree;
+
+    #[test]
+    fn enforces_index_rules() {
+        let items: Vec<[u8; 1]> = (0..10u8).map(|i| [i]).collect();
+        let tree = MerkleTree::from_leaves(&items).unwrap();
+        let proof = |idx: usize| tree.leaf_proof(idx).unwrap();
+        let policy = IndexPolicy::new(10, Padding::Promote).below(8);
+        assert_eq!(policy.verify(&items[3], tree.root(), &proof(3)), Ok(3));
+        assert_eq!(
+            policy.verify(&items[8], tree.root(), &proof(8)),
+            Err(PolicyFailure::Violated {
+                index: 8,
+                rule: PolicyRule::Below(8)
+            })
+        );
+
+        // Rules are checked in order; the first broken one is reported.
+        let epoch = 4..8;
+        let policy = IndexPolicy::new(10, Padding::Promote)
+            .within(epoch.clone())
+            .require("even", |idx| idx & 1 == 0);
+        assert_eq!(policy.verify(&items[6], tree.root(), &proof(6)), Ok(6));
+        assert_eq!(
+            policy.verify(&items[5], tree.root(), &proof(5)),
+            Err(PolicyFailure::Violated {
+                index: 5,
+                rule: PolicyRule::Custom("even")
+            })
+        );
+        assert_eq!(
+            policy.check_index(9),
+            Err(PolicyFailure::Violated {
+                index: 9,
+                rule: PolicyRule::Within(epoch)
+            })
+        );
+        assert_eq!(
+            IndexPolicy::new(10, Padding::Promote)
+                .at_least(2)
+                .check_index(1),
+            Err(PolicyFailure::Violated {
+                index: 1,
+                rule: PolicyRule::AtLeast(2)
+            })
+        );
+    }
+
+    #[test]
+    fn refuses_relabelled_proofs() {
+        let items: Vec<[u8; 1]> = (0..10u8).map(|i| [i]).collect();
+        let tree = MerkleTree::from_leaves_padded(&items, Padding::Zero).unwrap();
+        let policy = IndexPolicy::new(10, Padding::Zero).below(5);
+        let late = tree.leaf_proof(9).unwrap();
+        let relabelled = Proof::new(1, late.path().clone());
+        assert_eq!(
+            policy.verify(&items[9], tree.root(), &relabelled),
+            Err(PolicyFailure::Misplaced { index: 1 })
+        );
+        let past = Proof::new(10, late.path().clone());
+        assert_eq!(
+            policy.verify(&items[9], tree.root(), &past),
+            Err(PolicyFailure::Misplaced { index: 10 })
+        );
+        let early = tree.leaf_proof(2).unwrap();
+        assert!(matches!(
+            policy.verify(&items[3], tree.root(), &early),
+            Err(PolicyFailure::Invalid(_))
+        ));
+        assert!(alloc::format!("{:?}", policy).contains("Below(5)"));
+    }
+}
@@ -0,0 +1,162 @@
+use crate::{Layout, MerkleTree, Node, Padding};
+use sha2::Sha256;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Leaves hashed between progress reports and cancellation checks.
+pub const REPORT_INTERVAL: usize = 1024;
+
+/// Snapshot passed to the progress callback. Byte counts map directly onto
+/// e.g. `indicatif::ProgressBar::set_position` with `total_bytes` as length.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Progress {
+    pub bytes_hashed: u64,
+    pub total_bytes: u64,
+    pub levels_completed: usize,
+    pub total_levels: usize,
+}
+
+/// Cooperative cancellation flag, cheap to clone and share with a UI thread.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProgressError {
+    /// The token was cancelled before the tree was finished.
+    Cancelled,
+    /// `leaves` is zero or more than `data` has bytes.
+    NoLeaves,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        CancelToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+impl MerkleTree {
+    /// Same tree as `MerkleTree::new`, reporting progress every
+    /// `REPORT_INTERVAL` leaves and after each level, and giving up as soon
+    /// as `cancel` is set.
+    pub fn new_with_progress<F: FnMut(&Progress)>(
+        data: &[u8],
+        leaves: usize,
+        mut report: F,
+        cancel: &CancelToken,
+    ) -> Result<Self, ProgressError> {
+        let chunk_size = match data.len().checked_div(leaves) {
+            Some(size) if size > 0 => size,
+            _ => return Err(ProgressError::NoLeaves),
+        };
+        let layout = Layout::new(leaves).ok_or(ProgressError::NoLeaves)?;
+        let padding = Padding::default();
+        let mut progress = Progress {
+            bytes_hashed: 0,
+            total_bytes: (chunk_size * leaves) as u64,
+            levels_completed: 0,
+            total_levels: layout.levels(),
+        };
+        let mut nodes = Vec::with_capacity(layout.nodes());
+        for batch in data
+            .chunks(chunk_size)
+            .take(leaves)
+            .collect::<Vec<_>>()
+            .chunks(REPORT_INTERVAL)
+        {
+            if cancel.is_cancelled() {
+                return Err(ProgressError::Cancelled);
+            }
+            nodes.extend(batch.iter().map(|chunk| Node::as_leaf(chunk)));
+            progress.bytes_hashed += (batch.len() * chunk_size) as u64;
+            report(&progress);
+        }
+
+        let mut ctx = Sha256::default();
+        for level in 0..layout.levels() - 1 {
+            let start = layout.node_index(level, 0).expect("level is in the tree");
+            let len = layout.level_size(level).expect("level is in the tree");
+            for i in 0..len / 2 {
+                if i % REPORT_INTERVAL == 0 && cancel.is_cancelled() {
+                    return Err(ProgressError::Cancelled);
+                }
+                let even = start + 2 * i;
+                let parent = Node::as_parent(&nodes[even + 1], &nodes[even]);
+                nodes.push(parent);
+            }
+            if len & 1 == 1 {
+                let lone = nodes[start + len - 1].value;
+                nodes.push(Node::new(padding.parent::<Sha256>(&mut ctx, &lone)));
+            }
+            progress.levels_completed += 1;
+            report(&progress);
+        }
+        progress.levels_completed = progress.total_levels;
+        report(&progress);
+        Ok(MerkleTree::from_nodes_padded(nodes, leaves, padding))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_progress() {
+        let data = vec![7u8; 4 * 4096];
+        let mut reports = Vec::new();
+        let tree =
+            MerkleTree::new_with_progress(&data, 4096, |p| reports.push(*p), &CancelToken::new())
+                .unwrap();
+
+        assert_eq!(tree.root(), MerkleTree::new(&data, 4096).root());
+        assert_eq!(reports[3].bytes_hashed, reports[3].total_bytes);
+        assert_eq!(reports[3].levels_completed, 0);
+        let last = reports.last().unwrap();
+        assert_eq!(last.levels_completed, 13);
+        assert_eq!(last.total_levels, 13);
+        assert!(reports
+            .windows(2)
+            .all(|w| w[0].bytes_hashed <= w[1].bytes_hashed));
+
+        // 1500 leaves make levels of 1500, 750, 375, 188, ... 2 and 1 nodes.
+        let data = vec![7u8; 3 * 1500];
+        let mut last = None;
+        let tree =
+            MerkleTree::new_with_progress(&data, 1500, |p| last = Some(*p), &CancelToken::new())
+                .unwrap();
+        assert_eq!(tree.root(), MerkleTree::new(&data, 1500).root());
+        assert_eq!(last.unwrap().total_levels, 12);
+        for leaves in [0, data.len() + 1] {
+            assert_eq!(
+                MerkleTree::new_with_progress(&data, leaves, |_| {}, &CancelToken::new()).err(),
+                Some(ProgressError::NoLeaves)
+            );
+        }
+    }
+
+    #[test]
+    fn cancels() {
+        let data = vec![7u8; 4 * 4096];
+        let cancel = CancelToken::new();
+        let handle = cancel.clone();
+        let result = MerkleTree::new_with_progress(
+            &data,
+            4096,
+            |p| {
+                if p.bytes_hashed > 0 {
+                    handle.cancel();
+                }
+            },
+            &cancel,
+        );
+        assert_eq!(result.err(), Some(ProgressError::Cancelled));
+    }
+}
@@ -0,0 +1,596 @@
+//! Prolly trees: ordered maps stored as a tree of content-defined chunks.
+//! An entry closes its chunk when its digest falls below a threshold, so
+//! chunk boundaries depend only on the entries themselves. Two maps that
+//! share most of their entries therefore share most of their chunks, which
+//! a [`ChunkStore`] keeps once, and diffs only need to open the chunks
+//! that differ.
+//!
+//! Chunk encoding: level (u8, leaves are 0), entry count (u32 LE), then per
+//! entry the key (u32 LE length, bytes) followed by either the value
+//! (u32 LE length, bytes) in a leaf or the child hash in an internal chunk.
+//! An internal entry's key is the last key of its child. Chunks are
+//! addressed by `hash_data` of their encoding.
+
+use crate::hash_data;
+use crate::memory::{table_bytes, HeapSize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::convert::TryInto;
+
+/// Expected entries per chunk.
+pub const TARGET_FANOUT: u32 = 16;
+
+#[derive(Debug, Clone, Default)]
+pub struct ChunkStore {
+    chunks: HashMap<[u8; 32], Vec<u8>>,
+}
+
+impl ChunkStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.chunks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    /// Total size of the stored chunk encodings.
+    pub fn bytes(&self) -> usize {
+        self.chunks.values().map(Vec::len).sum()
+    }
+
+    pub fn get(&self, hash: &[u8; 32]) -> Option<&[u8]> {
+        self.chunks.get(hash).map(Vec::as_slice)
+    }
+
+    fn put(&mut self, chunk: Vec<u8>) -> [u8; 32] {
+        let hash = hash_data(&chunk);
+        self.chunks.entry(hash).or_insert(chunk);
+        hash
+    }
+
+    fn chunk(&self, hash: &[u8; 32]) -> Option<Chunk> {
+        Chunk::decode(self.get(hash)?)
+    }
+
+    /// Mark and sweep: keeps the chunks reachable from `pinned` and drops
+    /// the rest. Returns the number of chunk bytes reclaimed.
+    pub fn collect_garbage(&mut self, pinned: &[[u8; 32]]) -> usize {
+        let mut live = HashSet::new();
+        let mut stack: Vec<[u8; 32]> = pinned.to_vec();
+        while let Some(hash) = stack.pop() {
+            if !live.insert(hash) {
+                continue;
+            }
+            if let Some(Entries::Internal(entries)) = self.chunk(&hash).map(|chunk| chunk.entries) {
+                stack.extend(entries.into_iter().map(|(_, child)| child));
+            }
+        }
+        let before = self.bytes();
+        self.chunks.retain(|hash, _| live.contains(hash));
+        before - self.bytes()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Entries {
+    Leaf(Vec<(Vec<u8>, Vec<u8>)>),
+    Internal(Vec<(Vec<u8>, [u8; 32])>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Chunk {
+    level: u8,
+    entries: Entries,
+}
+
+fn put_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    out.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+    out.extend_from_slice(bytes);
+}
+
+fn take<'a>(bytes: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+    if bytes.len() < len {
+        return None;
+    }
+    let (head, rest) = bytes.split_at(len);
+    *bytes = rest;
+    Some(head)
+}
+
+fn take_bytes<'a>(bytes: &mut &'a [u8]) -> Option<&'a [u8]> {
+    let len = u32::from_le_bytes(take(bytes, 4)?.try_into().unwrap());
+    take(bytes, len.try_into().ok()?)
+}
+
+impl Chunk {
+    fn len(&self) -> usize {
+        match &self.entries {
+            Entries::Leaf(entries) => entries.len(),
+            Entries::Internal(entries) => entries.len(),
+        }
+    }
+
+    fn last_key(&self) -> Option<&[u8]> {
+        match &self.entries {
+            Entries::Leaf(entries) => entries.last().map(|(key, _)| key.as_slice()),
+            Entries::Internal(entries) => entries.last().map(|(key, _)| key.as_slice()),
+        }
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut out = vec![self.level];
+        out.extend_from_slice(&(self.len() as u32).to_le_bytes());
+        match &self.entries {
+            Entries::Leaf(entries) => {
+                for (key, value) in entries {
+                    put_bytes(&mut out, key);
+                    put_bytes(&mut out, value);
+                }
+            }
+            Entries::Internal(entries) => {
+                for (key, child) in entries {
+                    put_bytes(&mut out, key);
+                    out.extend_from_slice(child);
+                }
+            }
+        }
+        out
+    }
+
+    /// Parses a chunk, requiring strictly increasing keys.
+    fn decode(mut bytes: &[u8]) -> Option<Chunk> {
+        let level = take(&mut bytes, 1)?[0];
+        let count = u32::from_le_bytes(take(&mut bytes, 4)?.try_into().unwrap());
+        let mut keys: Vec<&[u8]> = Vec::new();
+        let entries = if level == 0 {
+            let mut entries = Vec::new();
+            for _ in 0..count {
+                let key = take_bytes(&mut bytes)?;
+                keys.push(key);
+                entries.push((key.to_vec(), take_bytes(&mut bytes)?.to_vec()));
+            }
+            Entries::Leaf(entries)
+        } else {
+            let mut entries = Vec::new();
+            for _ in 0..count {
+                let key = take_bytes(&mut bytes)?;
+                keys.push(key);
+                entries.push((key.to_vec(), take(&mut bytes, 32)?.try_into().unwrap()));
+            }
+            Entries::Internal(entries)
+        };
+        if !bytes.is_empty() || keys.windows(2).any(|pair| pair[0] >= pair[1]) {
+            return None;
+        }
+        Some(Chunk { level, entries })
+    }
+}
+
+fn is_boundary(digest: &[u8; 32]) -> bool {
+    u32::from(digest[0]) < 256 / TARGET_FANOUT
+}
+
+/// Splits one level's items into chunks. A level that would not shrink is
+/// kept in a single chunk so the tree always has a root.
+fn chunk_level<T: Clone>(items: &[T], digest: impl Fn(&T) -> [u8; 32]) -> Vec<Vec<T>> {
+    let mut chunks = Vec::new();
+    let mut current = Vec::new();
+    for item in items {
+        current.push(item.clone());
+        if is_boundary(&digest(item)) {
+            chunks.push(std::mem::take(&mut current));
+        }
+    }
+    if !current.is_empty() || chunks.is_empty() {
+        chunks.push(current);
+    }
+    if chunks.len() == items.len() && items.len() > 1 {
+        return vec![items.to_vec()];
+    }
+    chunks
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    pub key: Vec<u8>,
+    pub old: Option<Vec<u8>>,
+    pub new: Option<Vec<u8>>,
+}
+
+/// One version of a map, identified by the hash of its root chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProllyTree {
+    root: [u8; 32],
+}
+
+impl ProllyTree {
+    /// Stores a map's chunks and returns its tree.
+    pub fn build<I>(store: &mut ChunkStore, entries: I) -> Self
+    where
+        I: IntoIterator<Item = (Vec<u8>, Vec<u8>)>,
+    {
+        let entries: Vec<(Vec<u8>, Vec<u8>)> = entries
+            .into_iter()
+            .collect::<BTreeMap<_, _>>()
+            .into_iter()
+            .collect();
+        let mut children: Vec<(Vec<u8>, [u8; 32])> = Vec::new();
+        let leaves = chunk_level(&entries, |(key, value)| {
+            let mut bytes = Vec::new();
+            put_bytes(&mut bytes, key);
+            put_bytes(&mut bytes, value);
+            hash_data(&bytes)
+        });
+        for leaf in leaves {
+            let chunk = Chunk {
+                level: 0,
+                entries: Entries::Leaf(leaf),
+            };
+            let key = chunk.last_key().unwrap_or_default().to_vec();
+            children.push((key, store.put(chunk.encode())));
+        }
+        let mut level = 1;
+        while children.len() > 1 {
+            let mut parents = Vec::new();
+            for group in chunk_level(&children, |(_, hash)| *hash) {
+                let chunk = Chunk {
+                    level,
+                    entries: Entries::Internal(group),
+                };
+                let key = chunk.last_key().unwrap_or_default().to_vec();
+                parents.push((key, store.put(chunk.encode())));
+            }
+            children = parents;
+            level += 1;
+        }
+        ProllyTree {
+            root: children[0].1,
+        }
+    }
+
+    /// Reopens a tree stored earlier.
+    pub fn open(store: &ChunkStore, root: [u8; 32]) -> Option<Self> {
+        store.chunk(&root).map(|_| ProllyTree { root })
+    }
+
+    pub fn root(&self) -> [u8; 32] {
+        self.root
+    }
+
+    /// Every entry in key order, or `None` if a chunk is missing.
+    pub fn entries(&self, store: &ChunkStore) -> Option<Vec<(Vec<u8>, Vec<u8>)>> {
+        let mut out = Vec::new();
+        collect(store, &self.root, &mut out)?;
+        Some(out)
+    }
+
+    /// Applies inserts (`Some`) and removals (`None`) as a new version.
+    /// Chunks away from the changed keys are reused from the store.
+    pub fn update<I>(&self, store: &mut ChunkStore, changes: I) -> Option<Self>
+    where
+        I: IntoIterator<Item = (Vec<u8>, Option<Vec<u8>>)>,
+    {
+        let mut entries: BTreeMap<Vec<u8>, Vec<u8>> = self.entries(store)?.into_iter().collect();
+        for (key, value) in changes {
+            match value {
+                Some(value) => entries.insert(key, value),
+                None => entries.remove(&key),
+            };
+        }
+        Some(ProllyTree::build(store, entries))
+    }
+
+    /// Chunks from the root to the leaf responsible for `key`.
+    fn route(&self, store: &ChunkStore, key: &[u8]) -> Option<Vec<[u8; 32]>> {
+        let mut path = vec![self.root];
+        loop {
+            let chunk = store.chunk(&path[path.len() - 1])?;
+            match chunk.entries {
+                Entries::Leaf(_) => return Some(path),
+                Entries::Internal(entries) => path.push(child_for(&entries, key)?.1),
+            }
+        }
+    }
+
+    pub fn get(&self, store: &ChunkStore, key: &[u8]) -> Option<Vec<u8>> {
+        let path = self.route(store, key)?;
+        match store.chunk(&path[path.len() - 1])?.entries {
+            Entries::Leaf(entries) => lookup(&entries, key).map(<[u8]>::to_vec),
+            Entries::Internal(_) => None,
+        }
+    }
+
+    /// Proof of the value stored under `key`, or of its absence.
+    pub fn prove(&self, store: &ChunkStore, key: &[u8]) -> Option<ProllyProof> {
+        let chunks = self
+            .route(store, key)?
+            .iter()
+            .map(|hash| store.get(hash).map(<[u8]>::to_vec))
+            .collect::<Option<Vec<_>>>()?;
+        Some(ProllyProof { chunks })
+    }
+
+    /// Changes turning `self` into `other`. Subtrees with the same hash on
+    /// both sides are skipped without being opened.
+    pub fn diff(&self, store: &ChunkStore, other: &ProllyTree) -> Option<Vec<Change>> {
+        let mut old = vec![store.chunk(&self.root)?];
+        let mut new = vec![store.chunk(&other.root)?];
+        let mut old_hashes = vec![self.root];
+        let mut new_hashes = vec![other.root];
+        loop {
+            let shared: HashSet<[u8; 32]> = old_hashes
+                .iter()
+                .filter(|hash| new_hashes.contains(hash))
+                .copied()
+                .collect();
+            let keep = |chunks: Vec<Chunk>, hashes: Vec<[u8; 32]>| {
+                chunks
+                    .into_iter()
+                    .zip(hashes)
+                    .filter(|(_, hash)| !shared.contains(hash))
+                    .unzip::<_, _, Vec<_>, Vec<_>>()
+            };
+            let (o, oh) = keep(old, old_hashes);
+            let (n, nh) = keep(new, new_hashes);
+            let (old_level, new_level) = (level(&o), level(&n));
+            if old_level == 0 && new_level == 0 {
+                return Some(merge_changes(leaf_entries(o), leaf_entries(n)));
+            }
+            if old_level >= new_level {
+                let (chunks, hashes) = expand(store, &o)?;
+                old = chunks;
+                old_hashes = hashes;
+            } else {
+                old = o;
+                old_hashes = oh;
+            }
+            if new_level >= old_level {
+                let (chunks, hashes) = expand(store, &n)?;
+                new = chunks;
+                new_hashes = hashes;
+            } else {
+                new = n;
+                new_hashes = nh;
+            }
+        }
+    }
+}
+
+fn collect(store: &ChunkStore, hash: &[u8; 32], out: &mut Vec<(Vec<u8>, Vec<u8>)>) -> Option<()> {
+    match store.chunk(hash)?.entries {
+        Entries::Leaf(entries) => out.extend(entries),
+        Entries::Internal(entries) => {
+            for (_, child) in entries {
+                collect(store, &child, out)?;
+            }
+        }
+    }
+    Some(())
+}
+
+/// The first child whose last key is at least `key`, else the last child.
+fn child_for<'a>(
+    entries: &'a [(Vec<u8>, [u8; 32])],
+    key: &[u8],
+) -> Option<&'a (Vec<u8>, [u8; 32])> {
+    entries
+        .iter()
+        .find(|(last, _)| last.as_slice() >= key)
+        .or_else(|| entries.last())
+}
+
+fn lookup<'a>(entries: &'a [(Vec<u8>, Vec<u8>)], key: &[u8]) -> Option<&'a [u8]> {
+    entries
+        .binary_search_by(|(found, _)| found.as_slice().cmp(key))
+        .ok()
+        .map(|idx| entries[idx].1.as_slice())
+}
+
+fn level(chunks: &[Chunk]) -> u8 {
+    chunks.first().map_or(0, |chunk| chunk.level)
+}
+
+fn expand(store: &ChunkStore, chunks: &[Chunk]) -> Option<(Vec<Chunk>, Vec<[u8; 32]>)> {
+    let mut children = Vec::new();
+    let mut hashes = Vec::new();
+    for chunk in chunks {
+        if let Entries::Internal(entries) = &chunk.entries {
+            for (_, hash) in entries {
+                children.push(store.chunk(hash)?);
+                hashes.push(*hash);
+            }
+        }
+    }
+    Some((children, hashes))
+}
+
+fn leaf_entries(chunks: Vec<Chunk>) -> Vec<(Vec<u8>, Vec<u8>)> {
+    chunks
+        .into_iter()
+        .flat_map(|chunk| match chunk.entries {
+            Entries::Leaf(entries) => entries,
+            Entries::Internal(_) => Vec::new(),
+        })
+        .collect()
+}
+
+fn merge_changes(old: Vec<(Vec<u8>, Vec<u8>)>, new: Vec<(Vec<u8>, Vec<u8>)>) -> Vec<Change> {
+    let mut old: BTreeMap<Vec<u8>, Vec<u8>> = old.into_iter().collect();
+    let mut changes = Vec::new();
+    for (key, value) in new {
+        match old.remove(&key) {
+            Some(previous) if previous == value => {}
+            previous => changes.push(Change {
+                key,
+                old: previous,
+                new: Some(value),
+            }),
+        }
+    }
+    changes.extend(old.into_iter().map(|(key, value)| Change {
+        key,
+        old: Some(value),
+        new: None,
+    }));
+    changes.sort_by(|a, b| a.key.cmp(&b.key));
+    changes
+}
+
+/// The encoded chunks from the root to the leaf responsible for a key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProllyProof {
+    pub chunks: Vec<Vec<u8>>,
+}
+
+impl ProllyProof {
+    /// Checks that, in the tree with this root, `key` maps to `value`, or is
+    /// absent when `value` is `None`.
+    pub fn verify(&self, root: [u8; 32], key: &[u8], value: Option<&[u8]>) -> bool {
+        let mut expected = root;
+        let mut parent: Option<(u8, Vec<u8>)> = None;
+        for (depth, bytes) in self.chunks.iter().enumerate() {
+            if hash_data(bytes) != expected {
+                return false;
+            }
+            let chunk = match Chunk::decode(bytes) {
+                Some(chunk) => chunk,
+                None => return false,
+            };
+            if let Some((level, last)) = &parent {
+                if chunk.level + 1 != *level || chunk.last_key() != Some(last.as_slice()) {
+                    return false;
+                }
+            }
+            match &chunk.entries {
+                Entries::Leaf(entries) => {
+                    return depth + 1 == self.chunks.len() && lookup(entries, key) == value;
+                }
+                Entries::Internal(entries) => match child_for(entries, key) {
+                    Some((last, child)) => {
+                        expected = *child;
+                        parent = Some((chunk.level, last.clone()));
+                    }
+                    None => return false,
+                },
+            }
+        }
+        false
+    }
+}
+
+impl HeapSize for ChunkStore {
+    fn heap_size_bytes(&self) -> usize {
+        let chunks: usize = self.chunks.values().map(Vec::capacity).sum();
+        table_bytes(&self.chunks) + chunks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(range: std::ops::Range<u32>) -> Vec<(Vec<u8>, Vec<u8>)> {
+        range
+            .map(|i| {
+                (
+                    format!("key{:05}", i).into_bytes(),
+                    i.to_le_bytes().to_vec(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reads_and_proves() {
+        let mut store = ChunkStore::new();
+        let tree = ProllyTree::build(&mut store, entries(0..1000));
+        assert!(store.len() > 1000 / TARGET_FANOUT as usize / 2);
+        assert_eq!(tree.entries(&store), Some(entries(0..1000)));
+        assert_eq!(
+            tree.get(&store, b"key00421"),
+            Some(421u32.to_le_bytes().to_vec())
+        );
+        assert_eq!(tree.get(&store, b"key00421x"), None);
+
+        let proof = tree.prove(&store, b"key00421").unwrap();
+        let value = 421u32.to_le_bytes();
+        assert!(proof.verify(tree.root(), b"key00421", Some(&value)));
+        assert!(!proof.verify(tree.root(), b"key00421", Some(b"nope")));
+        assert!(!proof.verify(tree.root(), b"key00421", None));
+
+        let absent = tree.prove(&store, b"zzz").unwrap();
+        assert!(absent.verify(tree.root(), b"zzz", None));
+        assert!(!absent.verify([0; 32], b"zzz", None));
+    }
+
+    #[test]
+    fn shares_chunks_between_versions() {
+        let mut store = ChunkStore::new();
+        let old = ProllyTree::build(&mut store, entries(0..1000));
+        let before = store.len();
+        let new = old
+            .update(
+                &mut store,
+                vec![
+                    (b"key00500".to_vec(), Some(b"changed".to_vec())),
+                    (b"key00010".to_vec(), None),
+                    (b"new".to_vec(), Some(b"entry".to_vec())),
+                ],
+            )
+            .unwrap();
+        assert!(store.len() - before < 20);
+        assert_eq!(
+            ProllyTree::build(&mut ChunkStore::new(), new.entries(&store).unwrap()),
+            new
+        );
+
+        let changes = old.diff(&store, &new).unwrap();
+        let keys: Vec<&[u8]> = changes.iter().map(|change| change.key.as_slice()).collect();
+        assert_eq!(keys, vec![&b"key00010"[..], b"key00500", b"new"]);
+        assert_eq!(changes[0].new, None);
+        assert_eq!(changes[1].new, Some(b"changed".to_vec()));
+        assert_eq!(changes[2].old, None);
+        assert!(old.diff(&store, &old).unwrap().is_empty());
+    }
+
+    #[test]
+    fn collects_unreachable_chunks() {
+        let mut store = ChunkStore::new();
+        let old = ProllyTree::build(&mut store, entries(0..1000));
+        let new = old
+            .update(&mut store, vec![(b"key00500".to_vec(), None)])
+            .unwrap();
+        let (chunks, bytes) = (store.len(), store.bytes());
+
+        assert_eq!(store.collect_garbage(&[old.root(), new.root()]), 0);
+        let reclaimed = store.collect_garbage(&[new.root()]);
+        assert!(reclaimed > 0);
+        assert_eq!(store.bytes(), bytes - reclaimed);
+        assert!(store.len() < chunks);
+        assert_eq!(new.entries(&store).unwrap().len(), 999);
+        assert!(ProllyTree::open(&store, old.root()).is_none());
+
+        assert_eq!(store.collect_garbage(&[]), bytes - reclaimed);
+        assert!(store.is_empty());
+    }
+
+    #[test]
+    fn builds_canonically() {
+        let mut store = ChunkStore::new();
+        let mut shuffled = entries(0..300);
+        shuffled.reverse();
+        assert_eq!(
+            ProllyTree::build(&mut store, shuffled),
+            ProllyTree::build(&mut store, entries(0..300))
+        );
+        let empty = ProllyTree::build(&mut store, Vec::new());
+        assert!(empty
+            .prove(&store, b"x")
+            .unwrap()
+            .verify(empty.root(), b"x", None));
+    }
+}
@@ -0,0 +1,340 @@
+//! A self-describing inclusion proof: the leaf index together with its path.
+//!
+//! Binary layout: format version (1 byte), leaf index (u64 LE), then the
+//! path as in [`crate::compact`] without its version byte: depth (1 byte),
+//! the direction bits packed least-significant bit first, and the sibling
+//! hashes.
+
+use crate::compact::{self, decode_path_v0, encode_path};
+use crate::format::{Version, CURRENT};
+use crate::{
+    bounded, verify_proof_in, verify_proof_with, Error, Failure, FailureKind, MerkleHasher,
+    MerkleTree, Padding, Path, TreeHead, VerificationOutcome,
+};
+use alloc::vec::Vec;
+use core::convert::TryInto;
+use sha2::Sha256;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "borsh",
+    derive(borsh::BorshSerialize, borsh::BorshDeserialize)
+)]
+#[cfg_attr(
+    feature = "scale",
+    derive(parity_scale_codec::Encode, parity_scale_codec::Decode)
+)]
+pub struct Proof {
+    index: u64,
+    path: Path,
+}
+
+impl Proof {
+    pub fn new(index: u64, path: Path) -> Self {
+        Proof { index, path }
+    }
+
+    pub fn index(&self) -> u64 {
+        self.index
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn into_path(self) -> Path {
+        self.path
+    }
+
+    pub fn siblings(&self) -> impl Iterator<Item = &[u8; 32]> + '_ {
+        self.path.iter().map(|(sibling, _)| sibling)
+    }
+
+    /// Direction bits, `true` where the path node is at an odd offset.
+    pub fn directions(&self) -> impl Iterator<Item = bool> + '_ {
+        self.path.iter().map(|&(_, odd)| odd)
+    }
+
+    pub fn len(&self) -> usize {
+        self.path.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.path.is_empty()
+    }
+
+    /// Checks the proof for `item` against `head`, including that its
+    /// directions are those of its index in a tree of `head.size` leaves,
+    /// so a proof for one leaf cannot be presented as another's.
+    pub fn verify(&self, item: &[u8], head: &TreeHead) -> VerificationOutcome {
+        self.verify_with::<Sha256>(item, head, Padding::default())
+    }
+
+    pub fn verify_with<H: MerkleHasher>(
+        &self,
+        item: &[u8],
+        head: &TreeHead,
+        padding: Padding,
+    ) -> VerificationOutcome {
+        if let Some(outcome) = self.misplaced::<H>(item, head, padding) {
+            return outcome;
+        }
+        verify_proof_with::<H>(item, head.root, &self.path)
+    }
+
+    /// A `BadIndex` failure at the leaf if the directions are not those of
+    /// `self.index` in a tree of `head.size` leaves.
+    fn misplaced<H: MerkleHasher>(
+        &self,
+        item: &[u8],
+        head: &TreeHead,
+        padding: Padding,
+    ) -> Option<VerificationOutcome> {
+        let placed = padding
+            .directions(head.size, self.index)
+            .is_some_and(|expected| self.directions().eq(expected));
+        if placed {
+            return None;
+        }
+        Some(VerificationOutcome::Invalid(Failure {
+            kind: FailureKind::BadIndex,
+            level: 0,
+            expected: head.root,
+            computed: H::hash_data(item),
+        }))
+    }
+
+    /// Length of `to_bytes`'s output, for paths of up to 255 entries.
+    pub fn encoded_len(&self) -> usize {
+        8 + compact::encoded_len(self.path.len())
+    }
+
+    /// Returns `None` for paths deeper than 255.
+    pub fn to_bytes(&self) -> Option<Vec<u8>> {
+        let path = encode_path(&self.path)?;
+        let mut out = Vec::with_capacity(8 + path.len());
+        out.push(CURRENT.byte());
+        out.extend_from_slice(&self.index.to_le_bytes());
+        out.extend_from_slice(&path[1..]);
+        Some(out)
+    }
+
+    /// Rejects trailing bytes and set bitmap bits past the depth.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 9 || Version::from_byte(bytes[0]) != Some(Version::V1) {
+            return None;
+        }
+        let index = u64::from_le_bytes(bytes[1..9].try_into().unwrap());
+        match decode_path_v0(&bytes[9..])? {
+            (path, []) => Some(Proof { index, path }),
+            _ => None,
+        }
+    }
+}
+
+/// Proof of an item through two trees: `inner` proves it in a shard, and
+/// `outer` proves the shard's root in a master tree built with the shard
+/// roots as its items.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "borsh",
+    derive(borsh::BorshSerialize, borsh::BorshDeserialize)
+)]
+pub struct ComposedProof {
+    inner: Proof,
+    outer: Proof,
+}
+
+impl Proof {
+    /// Chains `inner` under `outer`. The link between them, that the root
+    /// `inner` reaches is the item `outer` proves, is checked by
+    /// `ComposedProof::verify`, since neither proof carries its root.
+    pub fn compose(inner: Proof, outer: Proof) -> ComposedProof {
+        ComposedProof { inner, outer }
+    }
+}
+
+impl ComposedProof {
+    pub fn inner(&self) -> &Proof {
+        &self.inner
+    }
+
+    pub fn outer(&self) -> &Proof {
+        &self.outer
+    }
+
+    pub fn into_parts(self) -> (Proof, Proof) {
+        (self.inner, self.outer)
+    }
+
+    /// Checks `item` against the master tree's `head`, where the item's
+    /// shard has `shard_size` leaves. A wrong inner proof reaches the wrong
+    /// shard root and so fails at the outer proof's root level; either
+    /// proof's index must match its directions, as in `Proof::verify`.
+    pub fn verify(&self, item: &[u8], shard_size: u64, head: &TreeHead) -> VerificationOutcome {
+        self.verify_with::<Sha256>(item, shard_size, head, Padding::default())
+    }
+
+    /// Like `verify`, for shards and a master tree built with `padding`.
+    pub fn verify_with<H: MerkleHasher>(
+        &self,
+        item: &[u8],
+        shard_size: u64,
+        head: &TreeHead,
+        padding: Padding,
+    ) -> VerificationOutcome {
+        let root = head.root;
+        if self.inner.len() > bounded::MAX_DEPTH {
+            // Reported as `BadLength`, without hashing.
+            return verify_proof_with::<H>(item, root, &self.inner.path);
+        }
+        let shard = TreeHead {
+            size: shard_size,
+            root,
+        };
+        if let Some(outcome) = self.inner.misplaced::<H>(item, &shard, padding) {
+            return outcome;
+        }
+        if let Some(outcome) = self.outer.misplaced::<H>(item, head, padding) {
+            return outcome;
+        }
+        let mut ctx = H::Context::default();
+        let leaf = H::hash_data_with(&mut ctx, item);
+        let shard_root = self
+            .inner
+            .path
+            .iter()
+            .fold(leaf, |candidate, (sibling, odd)| {
+                if *odd {
+                    H::hash_pair_with(&mut ctx, &candidate, sibling)
+                } else {
+                    H::hash_pair_with(&mut ctx, sibling, &candidate)
+                }
+            });
+        verify_proof_in::<H>(&mut ctx, &shard_root, root, &self.outer.path)
+    }
+}
+
+impl From<Proof> for Path {
+    fn from(proof: Proof) -> Path {
+        proof.path
+    }
+}
+
+impl<H: MerkleHasher> MerkleTree<H> {
+    /// `path(idx)` wrapped with the index it was taken for.
+    pub fn leaf_proof(&self, idx: usize) -> Result<Proof, Error> {
+        Ok(Proof::new(idx as u64, self.path(idx)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let tree = MerkleTree::new(b"asdfjkln12345678", 4);
+        let proof = tree.leaf_proof(2).unwrap();
+        assert_eq!((proof.index(), proof.len()), (2, 2));
+        assert_eq!(proof.directions().collect::<Vec<_>>(), vec![false, true]);
+        assert!(proof.verify(b"1234", &tree.head()).is_valid());
+
+        let bytes = proof.to_bytes().unwrap();
+        assert_eq!(bytes.len(), 1 + 8 + 1 + 1 + 64);
+        assert_eq!(proof.encoded_len(), bytes.len());
+        let odd = MerkleTree::from_leaves(["a", "b", "c", "d", "e"]).unwrap();
+        for idx in 0..5 {
+            let proof = odd.leaf_proof(idx).unwrap();
+            assert_eq!(proof.encoded_len(), proof.to_bytes().unwrap().len());
+        }
+        assert_eq!(bytes[1], 2);
+        assert_eq!(Proof::from_bytes(&bytes), Some(proof.clone()));
+        assert_eq!(Path::from(proof), tree.path(2).unwrap());
+
+        assert!(Proof::from_bytes(&bytes[..bytes.len() - 1]).is_none());
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(Proof::from_bytes(&trailing).is_none());
+        let mut future = bytes;
+        future[0] = 2;
+        assert!(Proof::from_bytes(&future).is_none());
+        assert_eq!(tree.leaf_proof(4), Err(Error::NotALeaf(4)));
+    }
+
+    #[test]
+    fn checks_index_against_directions() {
+        let items: Vec<[u8; 1]> = (0..8u8).map(|i| [i]).collect();
+        let tree = MerkleTree::from_leaves(&items).unwrap();
+        let head = tree.head();
+        let proof = tree.leaf_proof(3).unwrap();
+        assert!(proof.verify(&items[3], &head).is_valid());
+        let relabelled = Proof::new(5, proof.path().clone());
+        let failure = *relabelled.verify(&items[3], &head).failure().unwrap();
+        assert_eq!((failure.kind, failure.level), (FailureKind::BadIndex, 0));
+        let past = Proof::new(8, proof.path().clone());
+        assert!(!past.verify(&items[3], &head).is_valid());
+
+        // Without promotion every level has an entry, so padding matters.
+        let zero = MerkleTree::from_leaves_padded(&items[..5], Padding::Zero).unwrap();
+        let proof = zero.leaf_proof(4).unwrap();
+        let head = zero.head();
+        assert!(proof
+            .verify_with::<Sha256>(&items[4], &head, Padding::Zero)
+            .is_valid());
+        assert!(!proof.verify(&items[4], &head).is_valid());
+    }
+
+    #[test]
+    fn composes_shard_proofs() {
+        let shards = [
+            MerkleTree::new(b"asdfjkln12345678", 4),
+            MerkleTree::new(b"qwertyui", 2),
+            MerkleTree::new(b"zxcvbnm,./;'[]-=", 8),
+        ];
+        let roots: Vec<[u8; 32]> = shards.iter().map(MerkleTree::root).collect();
+        let master = MerkleTree::from_leaves(&roots).unwrap();
+        let proof = Proof::compose(
+            shards[2].leaf_proof(3).unwrap(),
+            master.leaf_proof(2).unwrap(),
+        );
+        assert!(proof.verify(b"m,", 8, &master.head()).is_valid());
+        assert!(!proof.verify(b"zx", 8, &master.head()).is_valid());
+        assert!(!proof.verify(b"m,", 8, &shards[2].head()).is_valid());
+        // A 3-leaf shard has no leaf 3.
+        assert!(!proof.verify(b"m,", 3, &master.head()).is_valid());
+
+        // The inner proof must lead to the shard the outer proof names.
+        let wrong = Proof::compose(
+            shards[2].leaf_proof(3).unwrap(),
+            master.leaf_proof(1).unwrap(),
+        );
+        assert!(!wrong.verify(b"m,", 8, &master.head()).is_valid());
+        assert_eq!(proof.clone().into_parts().0, *proof.inner());
+    }
+
+    #[test]
+    #[cfg(feature = "scale")]
+    fn scale_round_trip() {
+        use parity_scale_codec::{DecodeAll, Encode};
+
+        let tree = MerkleTree::new(b"asdfjkln12345678", 4);
+        let proof = tree.leaf_proof(2).unwrap();
+        let bytes = proof.encode();
+        // Index, a compact length of 2, then 33 bytes per entry.
+        assert_eq!(bytes.len(), 8 + 1 + 2 * 33);
+        assert_eq!(Proof::decode_all(&mut &bytes[..]), Ok(proof));
+        let head = tree.head();
+        assert_eq!(TreeHead::decode_all(&mut &head.encode()[..]), Ok(head));
+        assert!(Proof::decode_all(&mut &bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn implements_serde() {
+        fn serializable<T: serde::Serialize + serde::de::DeserializeOwned>() {}
+        serializable::<Proof>();
+    }
+}
@@ -0,0 +1,111 @@
+//! A proof held inline in a fixed-size array, for verifiers that cannot
+//! allocate. Conversion from a heap [`Path`] fails if the path is deeper
+//! than the array.
+
+use crate::bounded::LimitExceeded;
+use crate::{verify_proof, Path};
+use std::convert::TryFrom;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProofArray<const MAX_DEPTH: usize> {
+    siblings: [([u8; 32], bool); MAX_DEPTH],
+    len: usize,
+}
+
+impl<const MAX_DEPTH: usize> ProofArray<MAX_DEPTH> {
+    pub fn new() -> Self {
+        ProofArray {
+            siblings: [([0u8; 32], false); MAX_DEPTH],
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Appends the next sibling towards the root.
+    pub fn push(&mut self, sibling: [u8; 32], odd: bool) -> Result<(), LimitExceeded> {
+        if self.len == MAX_DEPTH {
+            return Err(LimitExceeded {
+                limit: MAX_DEPTH,
+                depth: self.len + 1,
+            });
+        }
+        self.siblings[self.len] = (sibling, odd);
+        self.len += 1;
+        Ok(())
+    }
+
+    pub fn as_slice(&self) -> &[([u8; 32], bool)] {
+        &self.siblings[..self.len]
+    }
+
+    pub fn verify(&self, item: &[u8], root: [u8; 32]) -> bool {
+        verify_proof(item, root, self.as_slice()).is_valid()
+    }
+}
+
+impl<const MAX_DEPTH: usize> Default for ProofArray<MAX_DEPTH> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const MAX_DEPTH: usize> TryFrom<&[([u8; 32], bool)]> for ProofArray<MAX_DEPTH> {
+    type Error = LimitExceeded;
+
+    fn try_from(path: &[([u8; 32], bool)]) -> Result<Self, LimitExceeded> {
+        if path.len() > MAX_DEPTH {
+            return Err(LimitExceeded {
+                limit: MAX_DEPTH,
+                depth: path.len(),
+            });
+        }
+        let mut proof = Self::new();
+        proof.siblings[..path.len()].copy_from_slice(path);
+        proof.len = path.len();
+        Ok(proof)
+    }
+}
+
+impl<const MAX_DEPTH: usize> From<ProofArray<MAX_DEPTH>> for Path {
+    fn from(proof: ProofArray<MAX_DEPTH>) -> Path {
+        proof.as_slice().to_vec()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MerkleTree;
+
+    #[test]
+    fn round_trips_heap_paths() {
+        let tree = MerkleTree::new(b"asdfasdfasdfasdfasdfasdfasdfasdf", 8);
+        let path = tree.path(5).unwrap();
+        let proof = ProofArray::<4>::try_from(path.as_slice()).unwrap();
+        assert_eq!(proof.len(), 3);
+        assert!(proof.verify(b"asdf", tree.root()));
+        assert_eq!(Path::from(proof), path);
+    }
+
+    #[test]
+    fn rejects_deep_paths() {
+        let tree = MerkleTree::new(b"asdfasdfasdfasdfasdfasdfasdfasdf", 8);
+        let path = tree.path(5).unwrap();
+        assert_eq!(
+            ProofArray::<2>::try_from(path.as_slice()),
+            Err(LimitExceeded { limit: 2, depth: 3 })
+        );
+
+        let mut proof = ProofArray::<2>::new();
+        proof.push(path[0].0, path[0].1).unwrap();
+        proof.push(path[1].0, path[1].1).unwrap();
+        assert!(proof.push(path[2].0, path[2].1).is_err());
+    }
+}
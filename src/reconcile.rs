@@ -0,0 +1,391 @@
+//! Set reconciliation over leaf hashes. Peers bisect the hash space by
+//! prefix, and every bucket summary carries a small invertible Bloom lookup
+//! table so that a bucket differing by only a few keys is resolved in the
+//! same round trip instead of being split further.
+
+use crate::MerkleTree;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeSet;
+use std::convert::TryInto;
+use std::ops::Bound;
+
+pub type Keys = Vec<[u8; 32]>;
+
+/// Number of cells each key is added to.
+const CELL_HASHES: usize = 3;
+
+/// The keys whose first `depth` bits equal the top bits of `value`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Prefix {
+    pub depth: u8,
+    pub value: u64,
+}
+
+impl Prefix {
+    pub const ROOT: Prefix = Prefix { depth: 0, value: 0 };
+
+    /// The two halves of this prefix, or `None` at the deepest level.
+    pub fn split(&self) -> Option<(Prefix, Prefix)> {
+        if self.depth >= 64 {
+            return None;
+        }
+        let depth = self.depth + 1;
+        let bit = 1u64 << (64 - depth);
+        Some((
+            Prefix {
+                depth,
+                value: self.value,
+            },
+            Prefix {
+                depth,
+                value: self.value | bit,
+            },
+        ))
+    }
+
+    fn bounds(&self) -> (Bound<[u8; 32]>, Bound<[u8; 32]>) {
+        let key = |value: u64| {
+            let mut key = [0u8; 32];
+            key[..8].copy_from_slice(&value.to_be_bytes());
+            key
+        };
+        let end = match self.depth {
+            0 => None,
+            depth => self.value.checked_add(1 << (64 - depth as u32)),
+        };
+        (
+            Bound::Included(key(self.value)),
+            end.map_or(Bound::Unbounded, |end| Bound::Excluded(key(end))),
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Cell {
+    count: i64,
+    keys: [u8; 32],
+    check: u64,
+}
+
+impl Cell {
+    const EMPTY: Cell = Cell {
+        count: 0,
+        keys: [0; 32],
+        check: 0,
+    };
+
+    fn toggle(&mut self, key: &[u8; 32], check: u64, count: i64) {
+        self.count += count;
+        self.check ^= check;
+        for (acc, byte) in self.keys.iter_mut().zip(key.iter()) {
+            *acc ^= byte;
+        }
+    }
+
+    fn is_pure(&self) -> bool {
+        (self.count == 1 || self.count == -1) && checksum(&self.keys) == self.check
+    }
+}
+
+fn checksum(key: &[u8; 32]) -> u64 {
+    let mut hasher = Sha256::new();
+    hasher.update(b"merkle-iblt");
+    hasher.update(key);
+    u64::from_le_bytes(hasher.finalize()[..8].try_into().unwrap())
+}
+
+/// Invertible Bloom lookup table over 32-byte keys. The cells are split
+/// into one sub-table per cell hash so a key never lands twice in a cell.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Iblt {
+    cells: Vec<Cell>,
+}
+
+impl Iblt {
+    /// A table of at least `cells` cells, rounded up to a whole number of
+    /// sub-tables.
+    pub fn new(cells: usize) -> Self {
+        let per_table = cells.div_ceil(CELL_HASHES).max(1);
+        Iblt {
+            cells: vec![Cell::EMPTY; per_table * CELL_HASHES],
+        }
+    }
+
+    pub fn cells(&self) -> usize {
+        self.cells.len()
+    }
+
+    fn positions(&self, key: &[u8; 32]) -> impl Iterator<Item = usize> {
+        let per_table = self.cells.len() / CELL_HASHES;
+        let key = *key;
+        (0..CELL_HASHES).map(move |table| {
+            let word = u32::from_le_bytes(key[8 + 4 * table..12 + 4 * table].try_into().unwrap());
+            table * per_table + word as usize % per_table
+        })
+    }
+
+    fn toggle(&mut self, key: &[u8; 32], count: i64) {
+        let check = checksum(key);
+        let positions: Vec<usize> = self.positions(key).collect();
+        for position in positions {
+            self.cells[position].toggle(key, check, count);
+        }
+    }
+
+    pub fn insert(&mut self, key: &[u8; 32]) {
+        self.toggle(key, 1);
+    }
+
+    pub fn remove(&mut self, key: &[u8; 32]) {
+        self.toggle(key, -1);
+    }
+
+    /// The table of `self - other`, or `None` if the sizes differ.
+    pub fn subtract(&self, other: &Iblt) -> Option<Iblt> {
+        if self.cells.len() != other.cells.len() {
+            return None;
+        }
+        let mut diff = self.clone();
+        for (cell, other) in diff.cells.iter_mut().zip(other.cells.iter()) {
+            cell.toggle(&other.keys, other.check, -other.count);
+        }
+        Some(diff)
+    }
+
+    /// Peels the table into the keys that were inserted and the keys that
+    /// were removed, or `None` if it holds too many keys to decode.
+    pub fn decode(&self) -> Option<(Keys, Keys)> {
+        let mut table = self.clone();
+        let mut inserted = Vec::new();
+        let mut removed = Vec::new();
+        // Every honest key peels exactly once, so a table that keeps
+        // yielding pure cells past its size was crafted to loop.
+        for _ in 0..self.cells.len() {
+            let pure = match table.cells.iter().find(|cell| cell.is_pure()) {
+                Some(cell) => *cell,
+                None => break,
+            };
+            if pure.count == 1 {
+                inserted.push(pure.keys);
+            } else {
+                removed.push(pure.keys);
+            }
+            table.toggle(&pure.keys, -pure.count);
+        }
+        if table.cells.iter().all(|cell| *cell == Cell::EMPTY) {
+            Some((inserted, removed))
+        } else {
+            None
+        }
+    }
+}
+
+/// One bucket of a peer's set: how many keys it holds, a fingerprint of
+/// those keys and a sketch of them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bucket {
+    pub prefix: Prefix,
+    pub count: u64,
+    pub fingerprint: [u8; 32],
+    pub sketch: Iblt,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Request {
+    pub prefixes: Vec<Prefix>,
+    /// Sketch size the responder should use for every bucket.
+    pub cells: usize,
+}
+
+impl Request {
+    /// The opening request, covering the whole hash space.
+    pub fn start(cells: usize) -> Self {
+        Request {
+            prefixes: vec![Prefix::ROOT],
+            cells,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.prefixes.is_empty()
+    }
+}
+
+/// Keys found so far, and the request to send next.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Reconciliation {
+    /// Keys held locally but not by the peer.
+    pub local_only: Keys,
+    /// Keys held by the peer but not locally.
+    pub remote_only: Keys,
+    /// Request round trips used.
+    pub rounds: usize,
+}
+
+/// A set of 32-byte keys, such as leaf hashes, prepared for reconciliation.
+#[derive(Debug, Clone, Default)]
+pub struct ReconcileSet {
+    keys: BTreeSet<[u8; 32]>,
+}
+
+impl ReconcileSet {
+    pub fn new<I: IntoIterator<Item = [u8; 32]>>(keys: I) -> Self {
+        ReconcileSet {
+            keys: keys.into_iter().collect(),
+        }
+    }
+
+    /// The tree's leaf hashes. Duplicate leaves collapse into one key.
+    pub fn from_tree(tree: &MerkleTree) -> Self {
+        Self::new(tree.nodes[..tree.leaves()].iter().map(|node| node.value))
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    pub fn bucket(&self, prefix: Prefix, cells: usize) -> Bucket {
+        let mut hasher = Sha256::new();
+        let mut sketch = Iblt::new(cells);
+        let mut count = 0;
+        for key in self.keys.range(prefix.bounds()) {
+            hasher.update(key);
+            sketch.insert(key);
+            count += 1;
+        }
+        Bucket {
+            prefix,
+            count,
+            fingerprint: hasher.finalize().into(),
+            sketch,
+        }
+    }
+
+    /// Answers a peer's request with one bucket per prefix.
+    pub fn respond(&self, request: &Request) -> Vec<Bucket> {
+        request
+            .prefixes
+            .iter()
+            .map(|prefix| self.bucket(*prefix, request.cells))
+            .collect()
+    }
+
+    /// Compares the peer's buckets with ours, recording every difference a
+    /// sketch could decode into `found`, and returns the request for the
+    /// buckets that still need splitting.
+    pub fn compare(&self, buckets: &[Bucket], cells: usize, found: &mut Reconciliation) -> Request {
+        let mut next = Request {
+            prefixes: Vec::new(),
+            cells,
+        };
+        for remote in buckets {
+            let local = self.bucket(remote.prefix, remote.sketch.cells());
+            if local.count == remote.count && local.fingerprint == remote.fingerprint {
+                continue;
+            }
+            let decoded = local
+                .sketch
+                .subtract(&remote.sketch)
+                .and_then(|diff| diff.decode());
+            match decoded {
+                Some((local_only, remote_only)) => {
+                    found.local_only.extend(local_only);
+                    found.remote_only.extend(remote_only);
+                }
+                None => {
+                    if let Some((low, high)) = remote.prefix.split() {
+                        next.prefixes.push(low);
+                        next.prefixes.push(high);
+                    }
+                }
+            }
+        }
+        found.rounds += 1;
+        next
+    }
+}
+
+/// Runs the protocol between two in-process sets.
+pub fn reconcile(local: &ReconcileSet, remote: &ReconcileSet, cells: usize) -> Reconciliation {
+    let mut found = Reconciliation::default();
+    let mut request = Request::start(cells);
+    while !request.is_empty() {
+        let buckets = remote.respond(&request);
+        request = local.compare(&buckets, cells, &mut found);
+    }
+    found.local_only.sort_unstable();
+    found.remote_only.sort_unstable();
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash_data;
+
+    fn keys(range: std::ops::Range<u32>) -> Vec<[u8; 32]> {
+        range.map(|i| hash_data(&i.to_le_bytes())).collect()
+    }
+
+    #[test]
+    fn decodes_small_differences() {
+        let mut table = Iblt::new(12);
+        for key in keys(0..4) {
+            table.insert(&key);
+        }
+        let mut other = Iblt::new(12);
+        for key in keys(2..5) {
+            other.insert(&key);
+        }
+        let (mut inserted, removed) = table.subtract(&other).unwrap().decode().unwrap();
+        inserted.sort_unstable();
+        let mut expected = keys(0..2);
+        expected.sort_unstable();
+        assert_eq!(inserted, expected);
+        assert_eq!(removed, keys(4..5));
+    }
+
+    #[test]
+    fn small_difference_takes_one_round() {
+        let local = ReconcileSet::new(keys(0..1000));
+        let remote = ReconcileSet::new(keys(2..1001));
+        let found = reconcile(&local, &remote, 30);
+        assert_eq!(found.rounds, 1);
+        assert_eq!(found.local_only.len(), 2);
+        assert_eq!(found.remote_only, keys(1000..1001));
+    }
+
+    #[test]
+    fn large_difference_bisects() {
+        let local = ReconcileSet::new(keys(0..500));
+        let remote = ReconcileSet::new(keys(300..900));
+        let found = reconcile(&local, &remote, 12);
+        assert!(found.rounds > 1);
+
+        let mut local_only = keys(0..300);
+        local_only.sort_unstable();
+        let mut remote_only = keys(500..900);
+        remote_only.sort_unstable();
+        assert_eq!(found.local_only, local_only);
+        assert_eq!(found.remote_only, remote_only);
+    }
+
+    #[test]
+    fn identical_trees_agree_immediately() {
+        let tree = MerkleTree::new(b"asdfjkln12345678", 4);
+        let set = ReconcileSet::from_tree(&tree);
+        assert_eq!(set.len(), 4);
+        let found = reconcile(&set, &set, 6);
+        assert_eq!(
+            found,
+            Reconciliation {
+                rounds: 1,
+                ..Default::default()
+            }
+        );
+    }
+}
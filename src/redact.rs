@@ -0,0 +1,190 @@
+//! Trees that keep their leaf payloads and can redact them. Redacting a
+//! leaf drops its payload and keeps only its leaf hash, marked as
+//! redacted, so the tree, its root and every proof are unchanged: a
+//! redacted leaf can still be proven as a hash, and other leaves are
+//! proven as before. Only the payload is lost, and with it the ability to
+//! show what the leaf held.
+//!
+//! [`records`](RedactableTree::records) is the form to store; trees rebuilt
+//! from it with `from_records` have the same root whatever was redacted.
+
+use crate::memory::{vec_bytes, HeapSize};
+use crate::{Error, MerkleHasher, MerkleTree, Padding, Path, Proof};
+use sha2::Sha256;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "borsh",
+    derive(borsh::BorshSerialize, borsh::BorshDeserialize)
+)]
+pub enum LeafRecord {
+    Payload(Vec<u8>),
+    /// A payload removed by redaction, kept as its leaf hash.
+    Redacted {
+        leaf: [u8; 32],
+    },
+}
+
+impl LeafRecord {
+    pub fn leaf_hash<H: MerkleHasher>(&self) -> [u8; 32] {
+        match self {
+            LeafRecord::Payload(payload) => H::hash_data(payload),
+            LeafRecord::Redacted { leaf } => *leaf,
+        }
+    }
+
+    pub fn is_redacted(&self) -> bool {
+        matches!(self, LeafRecord::Redacted { .. })
+    }
+
+    /// Checks the record's leaf hash against `root` along `path`, as
+    /// `verify_proof` does for a payload.
+    pub fn verify(&self, root: [u8; 32], path: &[([u8; 32], bool)]) -> bool {
+        self.verify_with::<Sha256>(root, path)
+    }
+
+    pub fn verify_with<H: MerkleHasher>(&self, root: [u8; 32], path: &[([u8; 32], bool)]) -> bool {
+        let mut ctx = H::Context::default();
+        let computed = path
+            .iter()
+            .fold(self.leaf_hash::<H>(), |candidate, (sibling, odd)| {
+                if *odd {
+                    H::hash_pair_with(&mut ctx, &candidate, sibling)
+                } else {
+                    H::hash_pair_with(&mut ctx, sibling, &candidate)
+                }
+            });
+        path.len() <= crate::bounded::MAX_DEPTH && computed == root
+    }
+}
+
+pub struct RedactableTree<H: MerkleHasher = Sha256> {
+    tree: MerkleTree<H>,
+    records: Vec<LeafRecord>,
+}
+
+impl RedactableTree {
+    pub fn from_leaves<I>(items: I) -> Option<Self>
+    where
+        I: IntoIterator,
+        I::Item: Into<Vec<u8>>,
+    {
+        RedactableTree::from_records(
+            items
+                .into_iter()
+                .map(|item| LeafRecord::Payload(item.into())),
+            Padding::default(),
+        )
+    }
+}
+
+impl<H: MerkleHasher> RedactableTree<H> {
+    /// A tree over stored records, or `None` if there are none.
+    pub fn from_records<I>(records: I, padding: Padding) -> Option<Self>
+    where
+        I: IntoIterator<Item = LeafRecord>,
+    {
+        let records: Vec<LeafRecord> = records.into_iter().collect();
+        if records.is_empty() {
+            return None;
+        }
+        let hashes = records.iter().map(LeafRecord::leaf_hash::<H>).collect();
+        Some(RedactableTree {
+            tree: MerkleTree::from_leaf_hashes(hashes, padding),
+            records,
+        })
+    }
+
+    pub fn tree(&self) -> &MerkleTree<H> {
+        &self.tree
+    }
+
+    pub fn root(&self) -> [u8; 32] {
+        self.tree.root()
+    }
+
+    pub fn records(&self) -> &[LeafRecord] {
+        &self.records
+    }
+
+    /// The payload of leaf `idx`, or `None` if it is redacted or not a
+    /// leaf.
+    pub fn payload(&self, idx: usize) -> Option<&[u8]> {
+        match self.records.get(idx)? {
+            LeafRecord::Payload(payload) => Some(payload),
+            LeafRecord::Redacted { .. } => None,
+        }
+    }
+
+    pub fn is_redacted(&self, idx: usize) -> Option<bool> {
+        self.records.get(idx).map(LeafRecord::is_redacted)
+    }
+
+    /// Drops the payload of leaf `idx` and returns its leaf hash. Redacting
+    /// a redacted leaf changes nothing.
+    pub fn redact(&mut self, idx: usize) -> Result<[u8; 32], Error> {
+        let record = self.records.get_mut(idx).ok_or(Error::NotALeaf(idx))?;
+        let leaf = record.leaf_hash::<H>();
+        *record = LeafRecord::Redacted { leaf };
+        Ok(leaf)
+    }
+
+    pub fn path(&self, idx: usize) -> Result<Path, Error> {
+        self.tree.path(idx)
+    }
+
+    pub fn proof(&self, idx: usize) -> Result<Proof, Error> {
+        self.tree.leaf_proof(idx)
+    }
+}
+
+impl<H: MerkleHasher> HeapSize for RedactableTree<H> {
+    fn heap_size_bytes(&self) -> usize {
+        let payloads: usize = self
+            .records
+            .iter()
+            .map(|record| match record {
+                LeafRecord::Payload(payload) => payload.capacity(),
+                LeafRecord::Redacted { .. } => 0,
+            })
+            .sum();
+        self.tree.heap_size_bytes() + vec_bytes(&self.records) + payloads
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::verify_proof;
+
+    #[test]
+    fn redaction_keeps_proofs() {
+        let items = ["alice", "bob", "carol", "dave", "erin"];
+        let mut tree = RedactableTree::from_leaves(items).unwrap();
+        let root = tree.root();
+        let proofs: Vec<Proof> = (0..5).map(|idx| tree.proof(idx).unwrap()).collect();
+
+        let leaf = tree.redact(2).unwrap();
+        assert_eq!(tree.redact(2), Ok(leaf));
+        assert_eq!(tree.root(), root);
+        assert_eq!((tree.payload(2), tree.is_redacted(2)), (None, Some(true)));
+        assert_eq!(tree.payload(1), Some(&b"bob"[..]));
+        for (idx, item) in items.iter().enumerate() {
+            assert_eq!(tree.proof(idx).unwrap(), proofs[idx]);
+            let record = &tree.records()[idx];
+            assert!(record.verify(root, proofs[idx].path()));
+            if idx != 2 {
+                assert!(verify_proof(item.as_bytes(), root, proofs[idx].path()).is_valid());
+            }
+        }
+        assert!(!tree.records()[2].verify(root, proofs[3].path()));
+
+        let stored = tree.records().to_vec();
+        let reloaded: RedactableTree =
+            RedactableTree::from_records(stored, Padding::default()).unwrap();
+        assert_eq!(reloaded.root(), root);
+        assert_eq!(tree.redact(5), Err(Error::NotALeaf(5)));
+        assert!(RedactableTree::from_leaves(Vec::<Vec<u8>>::new()).is_none());
+    }
+}
@@ -0,0 +1,195 @@
+//! Framing for exchanging roots and proofs over a byte stream (a serial
+//! line, TCP) without an RPC stack. Each frame is a body length (u32 LE)
+//! and the body: format version (1 byte), message kind (1 byte), then the
+//! kind's payload:
+//!
+//! ```text
+//! 0 RootRequest
+//! 1 ProofRequest   index (u64 LE)
+//! 2 Root           size (u64 LE), root
+//! 3 Proof          a Proof in its binary layout
+//! 4 NotFound       index (u64 LE)
+//! ```
+//!
+//! Frames over [`MAX_FRAME`] bytes are refused before anything is
+//! allocated for them.
+
+use crate::format::{Version, CURRENT};
+use crate::{MerkleHasher, MerkleTree, Proof, TreeHead};
+use std::convert::TryInto;
+use std::io::{self, Read, Write};
+
+/// Largest body accepted; a proof of the deepest path fits many times.
+pub const MAX_FRAME: usize = 1 << 16;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    RootRequest,
+    ProofRequest {
+        index: u64,
+    },
+    Root(TreeHead),
+    Proof(Proof),
+    /// The index asked for is not a leaf.
+    NotFound {
+        index: u64,
+    },
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+impl Message {
+    /// The frame body. Returns `None` for proofs too deep to encode.
+    pub fn to_bytes(&self) -> Option<Vec<u8>> {
+        let mut out = vec![CURRENT.byte()];
+        match self {
+            Message::RootRequest => out.push(0),
+            Message::ProofRequest { index } => {
+                out.push(1);
+                out.extend_from_slice(&index.to_le_bytes());
+            }
+            Message::Root(head) => {
+                out.push(2);
+                out.extend_from_slice(&head.size.to_le_bytes());
+                out.extend_from_slice(&head.root);
+            }
+            Message::Proof(proof) => {
+                out.push(3);
+                out.extend_from_slice(&proof.to_bytes()?);
+            }
+            Message::NotFound { index } => {
+                out.push(4);
+                out.extend_from_slice(&index.to_le_bytes());
+            }
+        }
+        Some(out)
+    }
+
+    /// Parses a frame body, rejecting unknown kinds and trailing bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 2 || Version::from_byte(bytes[0]) != Some(Version::V1) {
+            return None;
+        }
+        let payload = &bytes[2..];
+        let index = || -> Option<u64> { Some(u64::from_le_bytes(payload.try_into().ok()?)) };
+        match bytes[1] {
+            0 if payload.is_empty() => Some(Message::RootRequest),
+            1 => Some(Message::ProofRequest { index: index()? }),
+            2 if payload.len() == 40 => Some(Message::Root(TreeHead {
+                size: u64::from_le_bytes(payload[..8].try_into().unwrap()),
+                root: payload[8..].try_into().unwrap(),
+            })),
+            3 => Proof::from_bytes(payload).map(Message::Proof),
+            4 => Some(Message::NotFound { index: index()? }),
+            _ => None,
+        }
+    }
+}
+
+pub fn write_message<W: Write>(writer: &mut W, message: &Message) -> io::Result<()> {
+    let body = message
+        .to_bytes()
+        .filter(|body| body.len() <= MAX_FRAME)
+        .ok_or_else(|| invalid("message too large to frame"))?;
+    writer.write_all(&(body.len() as u32).to_le_bytes())?;
+    writer.write_all(&body)?;
+    writer.flush()
+}
+
+/// Reads one frame. A stream that ends exactly between frames gives
+/// `UnexpectedEof`, like one cut short inside a frame.
+pub fn read_message<R: Read>(reader: &mut R) -> io::Result<Message> {
+    let mut len = [0; 4];
+    reader.read_exact(&mut len)?;
+    let len = u32::from_le_bytes(len) as usize;
+    if len > MAX_FRAME {
+        return Err(invalid("frame too large"));
+    }
+    let mut body = vec![0; len];
+    reader.read_exact(&mut body)?;
+    Message::from_bytes(&body).ok_or_else(|| invalid("bad message"))
+}
+
+impl<H: MerkleHasher> MerkleTree<H> {
+    /// The answer to a request, or `None` if `request` is a response.
+    pub fn respond(&self, request: &Message) -> Option<Message> {
+        match *request {
+            Message::RootRequest => Some(Message::Root(TreeHead {
+                size: self.leaves() as u64,
+                root: self.root(),
+            })),
+            Message::ProofRequest { index } => {
+                let proof = index
+                    .try_into()
+                    .ok()
+                    .and_then(|idx| self.leaf_proof(idx).ok());
+                Some(proof.map_or(Message::NotFound { index }, Message::Proof))
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn exchanges_over_a_stream() {
+        let items = ["a", "b", "c", "d", "e"];
+        let tree = MerkleTree::from_leaves(items).unwrap();
+        let mut wire = Vec::new();
+        let requests = [
+            Message::RootRequest,
+            Message::ProofRequest { index: 3 },
+            Message::ProofRequest { index: 9 },
+        ];
+        for request in &requests {
+            write_message(&mut wire, &tree.respond(request).unwrap()).unwrap();
+        }
+
+        let mut reader = Cursor::new(wire);
+        let head = match read_message(&mut reader).unwrap() {
+            Message::Root(head) => head,
+            other => panic!("expected a root, got {:?}", other),
+        };
+        assert_eq!((head.size, head.root), (5, tree.root()));
+        match read_message(&mut reader).unwrap() {
+            Message::Proof(proof) => assert!(proof.verify(b"d", &head).is_valid()),
+            other => panic!("expected a proof, got {:?}", other),
+        }
+        assert_eq!(
+            read_message(&mut reader).unwrap(),
+            Message::NotFound { index: 9 }
+        );
+        let eof = read_message(&mut reader).unwrap_err();
+        assert_eq!(eof.kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(tree.respond(&Message::NotFound { index: 0 }), None);
+    }
+
+    #[test]
+    fn rejects_bad_frames() {
+        for message in [
+            Message::RootRequest,
+            Message::ProofRequest { index: 7 },
+            Message::NotFound { index: u64::MAX },
+        ] {
+            let bytes = message.to_bytes().unwrap();
+            assert_eq!(Message::from_bytes(&bytes), Some(message));
+            assert!(Message::from_bytes(&[bytes.clone(), vec![0]].concat()).is_none());
+        }
+        assert!(Message::from_bytes(&[CURRENT.byte(), 9]).is_none());
+
+        let oversized = ((MAX_FRAME + 1) as u32).to_le_bytes();
+        let err = read_message(&mut Cursor::new(oversized)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        let mut truncated = Vec::new();
+        write_message(&mut truncated, &Message::ProofRequest { index: 1 }).unwrap();
+        truncated.pop();
+        let err = read_message(&mut Cursor::new(truncated)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
@@ -0,0 +1,165 @@
+use crate::{verify_proof_in, MerkleTree, Padding, Path, TreeHead};
+use sha2::{Digest, Sha256};
+use std::convert::{TryFrom, TryInto};
+
+/// Derives `k` distinct leaf indices out of `leaves` from `seed`. Each draw
+/// is `H("merkle-select" || seed || counter)`, reduced by rejection sampling
+/// so every index is equally likely.
+pub fn select_indices(seed: &[u8], leaves: usize, k: usize) -> Vec<usize> {
+    let k = k.min(leaves);
+    let n = leaves as u64;
+    let zone = u64::MAX - u64::MAX % n.max(1);
+    let mut indices = Vec::with_capacity(k);
+    let mut counter = 0u64;
+    while indices.len() < k {
+        let mut hasher = Sha256::new();
+        hasher.update(b"merkle-select");
+        hasher.update(seed);
+        hasher.update(counter.to_le_bytes());
+        let digest: [u8; 32] = hasher.finalize().into();
+        counter += 1;
+
+        let draw = u64::from_le_bytes(digest[0..8].try_into().unwrap());
+        if draw >= zone {
+            continue;
+        }
+        let idx = (draw % n) as usize;
+        if !indices.contains(&idx) {
+            indices.push(idx);
+        }
+    }
+    indices
+}
+
+/// Leaf index a path was generated for, read back from its direction bits.
+/// Only paths with an entry for every level spell out their index: those
+/// of trees over a power of two leaves or closed without promotion.
+/// Returns `None` if the index does not fit in a `usize`.
+pub fn path_index(path: &[([u8; 32], bool)]) -> Option<usize> {
+    path.iter()
+        .enumerate()
+        .try_fold(0usize, |idx, (level, (_, odd))| match odd {
+            true if level >= usize::BITS as usize => None,
+            true => Some(idx | 1 << level),
+            false => Some(idx),
+        })
+}
+
+impl MerkleTree {
+    pub fn select_leaves(&self, seed: &[u8], k: usize) -> Vec<(usize, Path)> {
+        select_indices(seed, self.leaves(), k)
+            .into_iter()
+            .filter_map(|idx| Some((idx, self.path(idx).ok()?)))
+            .collect()
+    }
+}
+
+/// Recomputes the indices selected by `seed` in the tree `head` commits to
+/// and checks that `items` and `proofs` open exactly those leaves, in
+/// order. Each proof's directions must be those of its leaf in a tree of
+/// `head.size` leaves closed with `padding`.
+pub fn verify_selection<T: AsRef<[u8]>>(
+    seed: &[u8],
+    k: usize,
+    head: &TreeHead,
+    padding: Padding,
+    items: &[T],
+    proofs: &[Path],
+) -> bool {
+    let leaves = match usize::try_from(head.size) {
+        Ok(leaves) => leaves,
+        Err(_) => return false,
+    };
+    let indices = select_indices(seed, leaves, k);
+    let mut ctx = Sha256::default();
+    items.len() == indices.len()
+        && proofs.len() == indices.len()
+        && indices
+            .iter()
+            .zip(items)
+            .zip(proofs)
+            .all(|((&idx, item), proof)| {
+                padding
+                    .directions(head.size, idx as u64)
+                    .is_some_and(|expected| proof.iter().map(|&(_, odd)| odd).eq(expected))
+                    && verify_proof_in::<Sha256>(&mut ctx, item.as_ref(), head.root, proof)
+                        .is_valid()
+            })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deterministic_and_distinct() {
+        let a = select_indices(b"seed", 16, 5);
+        assert_eq!(a, select_indices(b"seed", 16, 5));
+        assert_ne!(a, select_indices(b"other seed", 16, 5));
+        let mut sorted = a.clone();
+        sorted.sort_unstable();
+        sorted.dedup();
+        assert_eq!(sorted.len(), 5);
+        assert!(a.iter().all(|&idx| idx < 16));
+        assert_eq!(select_indices(b"seed", 4, 10).len(), 4);
+    }
+
+    #[test]
+    fn selection_round_trip() {
+        let data = b"asdfasdfasdfasdfasdfasdfasdfasdf";
+        let tree = MerkleTree::new(data, 8);
+        let selected = tree.select_leaves(b"audit-2024", 3);
+        let items: Vec<&[u8]> = selected
+            .iter()
+            .map(|(idx, _)| &data[idx * 4..idx * 4 + 4])
+            .collect();
+        let proofs: Vec<Path> = selected.into_iter().map(|(_, proof)| proof).collect();
+        assert!(verify_selection(
+            b"audit-2024",
+            3,
+            &tree.head(),
+            Padding::default(),
+            &items,
+            &proofs
+        ));
+    }
+
+    #[test]
+    fn odd_trees() {
+        let items: Vec<[u8; 1]> = (0..5u8).map(|i| [i]).collect();
+        for &padding in [Padding::Promote, Padding::DuplicateLast, Padding::Zero].iter() {
+            let tree = MerkleTree::from_leaves_padded(&items, padding).unwrap();
+            let selected = tree.select_leaves(b"audit", 3);
+            let opened: Vec<[u8; 1]> = selected.iter().map(|&(idx, _)| items[idx]).collect();
+            let mut proofs: Vec<Path> = selected.into_iter().map(|(_, proof)| proof).collect();
+            let head = tree.head();
+            assert!(verify_selection(
+                b"audit", 3, &head, padding, &opened, &proofs
+            ));
+            assert!(!verify_selection(
+                b"other", 3, &head, padding, &opened, &proofs
+            ));
+            proofs.swap(0, 1);
+            assert!(!verify_selection(
+                b"audit", 3, &head, padding, &opened, &proofs
+            ));
+        }
+    }
+
+    #[test]
+    fn rejects_wrong_leaves() {
+        let data = b"asdfjkln12345678";
+        let tree = MerkleTree::new(data, 4);
+        let selected = select_indices(b"seed", 4, 1)[0];
+        let other = (selected + 1) % 4;
+        let item = &data[other * 4..other * 4 + 4];
+        assert!(!verify_selection(
+            b"seed",
+            1,
+            &tree.head(),
+            Padding::default(),
+            &[item],
+            &[tree.path(other).unwrap()]
+        ));
+    }
+}
@@ -0,0 +1,464 @@
+//! Commitments to sets. Members are hashed, and the tree is built over the
+//! distinct leaf hashes in ascending order, so the root depends only on
+//! which items are in the set. Items with the same leaf hash are the same
+//! member: duplicates are dropped, not counted.
+//!
+//! Trees are padded with `Padding::Zero`, so every path has one entry per
+//! level and its direction bits spell out the leaf index. A verifier can
+//! then check that two neighbours are adjacent, which is what a
+//! non-membership proof rests on: the item's hash falls strictly between
+//! them, or before the first leaf or after the last.
+
+use crate::memory::{vec_bytes, HeapSize};
+use crate::{hash_data, hash_pair, Layout, MerkleTree, Padding, Proof, TreeHead};
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+
+pub struct SetCommitment {
+    tree: MerkleTree,
+}
+
+/// A leaf next to an absent item, with its proof.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Neighbour {
+    pub leaf: [u8; 32],
+    pub proof: Proof,
+}
+
+impl Neighbour {
+    fn side(&self) -> ([u8; 32], [u8; 32], &Proof) {
+        (self.leaf, self.leaf, &self.proof)
+    }
+}
+
+/// The leaves either side of an absent item's hash. Only one is present
+/// when the hash sorts before the first leaf or after the last.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NonMembershipProof {
+    pub below: Option<Neighbour>,
+    pub above: Option<Neighbour>,
+}
+
+impl SetCommitment {
+    /// Returns `None` if there are no items.
+    pub fn new<I>(items: I) -> Option<Self>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        let mut hashes: Vec<[u8; 32]> = items
+            .into_iter()
+            .map(|item| hash_data(item.as_ref()))
+            .collect();
+        if hashes.is_empty() {
+            return None;
+        }
+        hashes.sort_unstable();
+        hashes.dedup();
+        Some(SetCommitment {
+            tree: MerkleTree::from_leaf_hashes(hashes, Padding::Zero),
+        })
+    }
+
+    /// Distinct members.
+    pub fn len(&self) -> usize {
+        self.tree.leaves()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    pub fn head(&self) -> TreeHead {
+        self.tree.head()
+    }
+
+    pub fn tree(&self) -> &MerkleTree {
+        &self.tree
+    }
+
+    /// Position of `leaf`, or where it would go.
+    fn search(&self, leaf: &[u8; 32]) -> Result<usize, usize> {
+        let (mut lo, mut hi) = (0, self.len());
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            match self.leaf(mid).cmp(leaf) {
+                Ordering::Less => lo = mid + 1,
+                Ordering::Greater => hi = mid,
+                Ordering::Equal => return Ok(mid),
+            }
+        }
+        Err(lo)
+    }
+
+    fn leaf(&self, idx: usize) -> [u8; 32] {
+        self.tree.node(idx).expect("leaf is in range")
+    }
+
+    fn neighbour(&self, idx: usize) -> Neighbour {
+        Neighbour {
+            leaf: self.leaf(idx),
+            proof: self.tree.leaf_proof(idx).expect("leaf is in range"),
+        }
+    }
+
+    pub fn contains(&self, item: &[u8]) -> bool {
+        self.search(&hash_data(item)).is_ok()
+    }
+
+    pub fn prove_member(&self, item: &[u8]) -> Option<Proof> {
+        let idx = self.search(&hash_data(item)).ok()?;
+        self.tree.leaf_proof(idx).ok()
+    }
+
+    /// Returns `None` if `item` is a member.
+    pub fn prove_non_member(&self, item: &[u8]) -> Option<NonMembershipProof> {
+        let idx = self.search(&hash_data(item)).err()?;
+        Some(NonMembershipProof {
+            below: idx.checked_sub(1).map(|below| self.neighbour(below)),
+            above: (idx < self.len()).then(|| self.neighbour(idx)),
+        })
+    }
+}
+
+/// Checks that `proof` places `leaf` at its stated index in the set with
+/// `head`, reading the index from the path's direction bits.
+fn located(leaf: [u8; 32], proof: &Proof, head: &TreeHead) -> bool {
+    let layout = match usize::try_from(head.size).ok().and_then(Layout::new) {
+        Some(layout) => layout,
+        None => return false,
+    };
+    if proof.index() >= head.size || proof.len() != layout.levels() - 1 {
+        return false;
+    }
+    let mut candidate = leaf;
+    for (level, (sibling, odd)) in proof.path().iter().enumerate() {
+        if *odd != (proof.index() >> level & 1 == 1) {
+            return false;
+        }
+        candidate = if *odd {
+            hash_pair(&candidate, sibling)
+        } else {
+            hash_pair(sibling, &candidate)
+        };
+    }
+    candidate == head.root
+}
+
+pub fn verify_member(item: &[u8], head: &TreeHead, proof: &Proof) -> bool {
+    located(hash_data(item), proof, head)
+}
+
+pub fn verify_non_member(item: &[u8], head: &TreeHead, proof: &NonMembershipProof) -> bool {
+    brackets(
+        &hash_data(item),
+        head,
+        proof.below.as_ref().map(Neighbour::side),
+        proof.above.as_ref().map(Neighbour::side),
+    )
+}
+
+/// Checks that `below` and `above`, each a key, its leaf and the leaf's
+/// proof, are adjacent leaves with keys either side of `key`, or the first
+/// or last leaf when only one is given.
+fn brackets(
+    key: &[u8; 32],
+    head: &TreeHead,
+    below: Option<([u8; 32], [u8; 32], &Proof)>,
+    above: Option<([u8; 32], [u8; 32], &Proof)>,
+) -> bool {
+    let below = match below {
+        Some((k, leaf, proof)) if &k < key && located(leaf, proof, head) => Some(proof.index()),
+        Some(_) => return false,
+        None => None,
+    };
+    let above = match above {
+        Some((k, leaf, proof)) if &k > key && located(leaf, proof, head) => Some(proof.index()),
+        Some(_) => return false,
+        None => None,
+    };
+    match (below, above) {
+        (Some(below), Some(above)) => above == below + 1,
+        (Some(below), None) => below + 1 == head.size,
+        (None, Some(above)) => above == 0,
+        (None, None) => false,
+    }
+}
+
+/// Leaf of a multiset element: `H(key || count_le)`, where `key` is the
+/// element's hash.
+pub fn counted_leaf(key: &[u8; 32], count: u64) -> [u8; 32] {
+    let mut bytes = [0u8; 40];
+    bytes[..32].copy_from_slice(key);
+    bytes[32..].copy_from_slice(&count.to_le_bytes());
+    hash_data(&bytes)
+}
+
+/// A multiset: a set commitment whose leaves also commit to how many times
+/// each element occurs. Elements are ordered by key, so the root depends
+/// only on the elements and their counts. Elements given more than once
+/// have their counts added; a count of zero is an absent element.
+pub struct MultisetCommitment {
+    tree: MerkleTree,
+    /// (key, count) per leaf.
+    entries: Vec<([u8; 32], u64)>,
+}
+
+/// An element's key and count at a leaf, with the leaf's proof.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CountedEntry {
+    pub key: [u8; 32],
+    pub count: u64,
+    pub proof: Proof,
+}
+
+impl CountedEntry {
+    fn side(&self) -> ([u8; 32], [u8; 32], &Proof) {
+        (self.key, counted_leaf(&self.key, self.count), &self.proof)
+    }
+}
+
+/// Evidence of an element's exact count: its leaf if it occurs, or the
+/// elements either side of it if it does not.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CountProof {
+    Present(Proof),
+    Absent {
+        below: Option<CountedEntry>,
+        above: Option<CountedEntry>,
+    },
+}
+
+impl MultisetCommitment {
+    /// Returns `None` if no element has a nonzero count, or if a count
+    /// overflows.
+    pub fn new<I, T>(elements: I) -> Option<Self>
+    where
+        I: IntoIterator<Item = (T, u64)>,
+        T: AsRef<[u8]>,
+    {
+        let mut entries: Vec<([u8; 32], u64)> = elements
+            .into_iter()
+            .map(|(item, count)| (hash_data(item.as_ref()), count))
+            .collect();
+        entries.sort_unstable_by_key(|&(key, _)| key);
+        let mut merged: Vec<([u8; 32], u64)> = Vec::with_capacity(entries.len());
+        for (key, count) in entries {
+            match merged.last_mut() {
+                Some(last) if last.0 == key => last.1 = last.1.checked_add(count)?,
+                _ => merged.push((key, count)),
+            }
+        }
+        merged.retain(|&(_, count)| count > 0);
+        if merged.is_empty() {
+            return None;
+        }
+        let leaves = merged
+            .iter()
+            .map(|(key, count)| counted_leaf(key, *count))
+            .collect();
+        Some(MultisetCommitment {
+            tree: MerkleTree::from_leaf_hashes(leaves, Padding::Zero),
+            entries: merged,
+        })
+    }
+
+    /// One occurrence per item.
+    pub fn from_items<I>(items: I) -> Option<Self>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        MultisetCommitment::new(items.into_iter().map(|item| (item, 1)))
+    }
+
+    /// Distinct elements.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    pub fn head(&self) -> TreeHead {
+        self.tree.head()
+    }
+
+    pub fn tree(&self) -> &MerkleTree {
+        &self.tree
+    }
+
+    pub fn count(&self, item: &[u8]) -> u64 {
+        match self.search(&hash_data(item)) {
+            Ok(idx) => self.entries[idx].1,
+            Err(_) => 0,
+        }
+    }
+
+    fn search(&self, key: &[u8; 32]) -> Result<usize, usize> {
+        self.entries.binary_search_by(|(k, _)| k.cmp(key))
+    }
+
+    fn entry(&self, idx: usize) -> CountedEntry {
+        let (key, count) = self.entries[idx];
+        CountedEntry {
+            key,
+            count,
+            proof: self.tree.leaf_proof(idx).expect("leaf is in range"),
+        }
+    }
+
+    /// Proves `item`'s count, which is `count(item)`.
+    pub fn prove_count(&self, item: &[u8]) -> CountProof {
+        match self.search(&hash_data(item)) {
+            Ok(idx) => CountProof::Present(self.tree.leaf_proof(idx).expect("leaf is in range")),
+            Err(idx) => CountProof::Absent {
+                below: idx.checked_sub(1).map(|below| self.entry(below)),
+                above: (idx < self.len()).then(|| self.entry(idx)),
+            },
+        }
+    }
+}
+
+/// Checks that `item` occurs exactly `count` times in the multiset with
+/// `head`.
+pub fn verify_count(item: &[u8], count: u64, head: &TreeHead, proof: &CountProof) -> bool {
+    let key = hash_data(item);
+    match proof {
+        CountProof::Present(proof) => count > 0 && located(counted_leaf(&key, count), proof, head),
+        CountProof::Absent { below, above } => {
+            count == 0
+                && brackets(
+                    &key,
+                    head,
+                    below.as_ref().map(CountedEntry::side),
+                    above.as_ref().map(CountedEntry::side),
+                )
+        }
+    }
+}
+
+impl HeapSize for SetCommitment {
+    fn heap_size_bytes(&self) -> usize {
+        self.tree.heap_size_bytes()
+    }
+}
+
+impl HeapSize for MultisetCommitment {
+    fn heap_size_bytes(&self) -> usize {
+        self.tree.heap_size_bytes() + vec_bytes(&self.entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn order_independent() {
+        let set = SetCommitment::new(["kiwi", "fig", "pear", "fig", "apple"]).unwrap();
+        let other = SetCommitment::new(["apple", "pear", "kiwi", "fig"]).unwrap();
+        assert_eq!(set.head(), other.head());
+        assert_eq!(set.len(), 4);
+        assert!(SetCommitment::new(Vec::<&str>::new()).is_none());
+        let bigger = SetCommitment::new(["apple", "pear", "kiwi", "fig", "plum"]).unwrap();
+        assert_ne!(set.head(), bigger.head());
+    }
+
+    #[test]
+    fn proves_membership() {
+        let items: Vec<String> = (0..11).map(|i| format!("item-{}", i)).collect();
+        let set = SetCommitment::new(&items).unwrap();
+        let head = set.head();
+        for item in &items {
+            assert!(set.contains(item.as_bytes()));
+            let proof = set.prove_member(item.as_bytes()).unwrap();
+            assert!(verify_member(item.as_bytes(), &head, &proof));
+            assert!(set.prove_non_member(item.as_bytes()).is_none());
+        }
+        let proof = set.prove_member(b"item-3").unwrap();
+        assert!(!verify_member(b"item-4", &head, &proof));
+        let lying = Proof::new(proof.index() ^ 1, proof.path().clone());
+        assert!(!verify_member(b"item-3", &head, &lying));
+    }
+
+    #[test]
+    fn proves_non_membership() {
+        let items: Vec<String> = (0..11).map(|i| format!("item-{}", i)).collect();
+        let set = SetCommitment::new(&items).unwrap();
+        let head = set.head();
+        let mut edges = (false, false);
+        for i in 11..200 {
+            let absent = format!("item-{}", i);
+            let proof = set.prove_non_member(absent.as_bytes()).unwrap();
+            assert!(verify_non_member(absent.as_bytes(), &head, &proof));
+            edges.0 |= proof.below.is_none();
+            edges.1 |= proof.above.is_none();
+            assert!(set.prove_member(absent.as_bytes()).is_none());
+        }
+        assert_eq!(edges, (true, true));
+
+        // Neighbours that are not adjacent prove nothing, here about the
+        // member between them.
+        let proof = NonMembershipProof {
+            below: Some(set.neighbour(2)),
+            above: Some(set.neighbour(4)),
+        };
+        let middle = items
+            .iter()
+            .find(|item| hash_data(item.as_bytes()) == set.leaf(3))
+            .unwrap();
+        assert!(!verify_non_member(middle.as_bytes(), &head, &proof));
+        let member = set.prove_non_member(b"item-200").unwrap();
+        assert!(!verify_non_member(b"item-5", &head, &member));
+    }
+
+    #[test]
+    fn proves_counts() {
+        let stock =
+            MultisetCommitment::new([("bolt", 40), ("nut", 25), ("bolt", 2), ("washer", 0)])
+                .unwrap();
+        let same = MultisetCommitment::new([("nut", 25), ("bolt", 42)]).unwrap();
+        assert_eq!(stock.head(), same.head());
+        assert_eq!(
+            (stock.len(), stock.count(b"bolt"), stock.count(b"washer")),
+            (2, 42, 0)
+        );
+        let fewer = MultisetCommitment::new([("nut", 24), ("bolt", 42)]).unwrap();
+        assert_ne!(stock.head(), fewer.head());
+        assert!(MultisetCommitment::new([("washer", 0)]).is_none());
+        assert!(MultisetCommitment::new([("bolt", u64::MAX), ("bolt", 1)]).is_none());
+
+        let head = stock.head();
+        let proof = stock.prove_count(b"bolt");
+        assert!(verify_count(b"bolt", 42, &head, &proof));
+        assert!(!verify_count(b"bolt", 41, &head, &proof));
+        assert!(!verify_count(b"bolt", 0, &head, &proof));
+        assert!(!verify_count(b"nut", 42, &head, &proof));
+
+        let items: Vec<String> = (0..9).map(|i| format!("sku-{}", i)).collect();
+        let many = MultisetCommitment::from_items(items.iter().chain(&items[..3])).unwrap();
+        let head = many.head();
+        for (i, item) in items.iter().enumerate() {
+            let count = if i < 3 { 2 } else { 1 };
+            let proof = many.prove_count(item.as_bytes());
+            assert!(verify_count(item.as_bytes(), count, &head, &proof));
+        }
+        for i in 9..100 {
+            let absent = format!("sku-{}", i);
+            let proof = many.prove_count(absent.as_bytes());
+            assert!(verify_count(absent.as_bytes(), 0, &head, &proof));
+            assert!(!verify_count(absent.as_bytes(), 1, &head, &proof));
+        }
+
+        // A neighbour with a forged count no longer matches its leaf.
+        let mut forged = many.prove_count(b"sku-100");
+        if let CountProof::Absent { below, above } = &mut forged {
+            if let Some(entry) = below.as_mut().or(above.as_mut()) {
+                entry.count += 1;
+            }
+        }
+        assert!(!verify_count(b"sku-100", 0, &head, &forged));
+    }
+}
@@ -0,0 +1,107 @@
+use crate::{MerkleHasher, MerkleTree};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Shape {
+    pub leaves: usize,
+    pub nodes: usize,
+    pub depth: usize,
+    /// Node count per level, leaves first.
+    pub level_sizes: Vec<usize>,
+    pub perfect: bool,
+}
+
+impl<H: MerkleHasher> MerkleTree<H> {
+    /// Edges between a leaf and the root.
+    pub fn depth(&self) -> usize {
+        self.level_count() - 1
+    }
+
+    pub fn level_count(&self) -> usize {
+        self.layout.levels()
+    }
+
+    pub fn is_perfect(&self) -> bool {
+        self.leaves().is_power_of_two()
+    }
+
+    /// Leaves a tree of this depth can hold.
+    pub fn capacity(&self) -> usize {
+        1 << self.depth()
+    }
+
+    pub fn level_sizes(&self) -> Vec<usize> {
+        self.layout.level_sizes().to_vec()
+    }
+
+    pub fn shape(&self) -> Shape {
+        Shape {
+            leaves: self.leaves(),
+            nodes: self.size(),
+            depth: self.depth(),
+            level_sizes: self.level_sizes(),
+            perfect: self.is_perfect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Padding;
+
+    #[test]
+    fn perfect_shapes() {
+        let tree = MerkleTree::new(b"asdfasdfasdfasdfasdfasdfasdfasdf", 8);
+        assert_eq!(
+            tree.shape(),
+            Shape {
+                leaves: 8,
+                nodes: 15,
+                depth: 3,
+                level_sizes: vec![8, 4, 2, 1],
+                perfect: true,
+            }
+        );
+        assert_eq!(tree.capacity(), 8);
+        assert_eq!(tree.level_count(), 4);
+
+        let single = MerkleTree::new(b"a", 1);
+        assert_eq!(single.depth(), 0);
+        assert_eq!(single.capacity(), 1);
+        assert_eq!(single.level_sizes(), vec![1]);
+    }
+
+    #[test]
+    fn odd_shapes() {
+        let items = ["a", "b", "c", "d", "e", "f"];
+        for &padding in [Padding::Promote, Padding::DuplicateLast, Padding::Zero].iter() {
+            let five = MerkleTree::from_leaves_padded(&items[..5], padding).unwrap();
+            assert_eq!(
+                five.shape(),
+                Shape {
+                    leaves: 5,
+                    nodes: 11,
+                    depth: 3,
+                    level_sizes: vec![5, 3, 2, 1],
+                    perfect: false,
+                }
+            );
+            assert_eq!(five.capacity(), 8);
+
+            let six = MerkleTree::from_leaves_padded(items, padding).unwrap();
+            assert_eq!(six.level_sizes(), vec![6, 3, 2, 1]);
+            assert_eq!((six.size(), six.level_count()), (12, 4));
+            assert!(!six.is_perfect());
+            for tree in [&five, &six] {
+                assert_eq!(tree.level_sizes(), tree.layout().level_sizes());
+                for idx in 0..tree.leaves() {
+                    let len = tree.path(idx).unwrap().len();
+                    assert!(len <= tree.depth());
+                    if padding != Padding::Promote {
+                        assert_eq!(len, tree.depth());
+                    }
+                }
+            }
+        }
+    }
+}
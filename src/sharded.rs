@@ -0,0 +1,166 @@
+//! Tree construction fed by several producer threads at once. Each
+//! producer appends to a shard of its own, and `finalize` lays the shards
+//! out one after another by shard id, each in the order its leaves were
+//! appended. The tree then depends only on what went into each shard, not
+//! on how the threads interleaved, so no ordering queue is needed between
+//! the producers and the builder.
+//!
+//! Leaves are hashed by the appending thread before its shard is locked;
+//! the lock only covers pushing the hash. Producers sharing a shard are
+//! ordered by who takes the lock first, so give each producer its own
+//! shard when the tree must be reproducible.
+
+use crate::{MerkleHasher, MerkleTree, Padding};
+use sha2::Sha256;
+use std::marker::PhantomData;
+use std::sync::Mutex;
+
+pub struct ShardedBuilder<H: MerkleHasher = Sha256> {
+    shards: Vec<Mutex<Vec<[u8; 32]>>>,
+    padding: Padding,
+    hasher: PhantomData<H>,
+}
+
+/// Where an appended leaf went: its shard, and its sequence number there.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ShardPosition {
+    pub shard: usize,
+    pub sequence: usize,
+}
+
+impl ShardedBuilder {
+    pub fn new(shards: usize) -> Self {
+        ShardedBuilder::build(shards, Padding::default())
+    }
+}
+
+impl<H: MerkleHasher> ShardedBuilder<H> {
+    /// Like `new`, hashing with `H` and closing odd levels with `padding`.
+    pub fn build(shards: usize, padding: Padding) -> Self {
+        assert!(shards > 0, "a builder needs a shard");
+        ShardedBuilder {
+            shards: (0..shards).map(|_| Mutex::new(Vec::new())).collect(),
+            padding,
+            hasher: PhantomData,
+        }
+    }
+
+    pub fn shards(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Leaves appended to `shard` so far, or `None` if there is no such
+    /// shard.
+    pub fn shard_len(&self, shard: usize) -> Option<usize> {
+        Some(self.shards.get(shard)?.lock().unwrap().len())
+    }
+
+    /// Appends a leaf over `item` to `shard`, or returns `None` if there is no
+    /// such shard.
+    pub fn append(&self, shard: usize, item: &[u8]) -> Option<ShardPosition> {
+        let slot = self.shards.get(shard)?;
+        let leaf = H::hash_data(item);
+        let mut leaves = slot.lock().unwrap();
+        leaves.push(leaf);
+        Some(ShardPosition {
+            shard,
+            sequence: leaves.len() - 1,
+        })
+    }
+
+    /// Appends every item to `shard` in order under one lock, returning the
+    /// number appended, or `None` with nothing appended if there is no such
+    /// shard.
+    pub fn append_all<I>(&self, shard: usize, items: I) -> Option<usize>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        let slot = self.shards.get(shard)?;
+        let mut ctx = H::Context::default();
+        let hashes: Vec<[u8; 32]> = items
+            .into_iter()
+            .map(|item| H::hash_data_with(&mut ctx, item.as_ref()))
+            .collect();
+        slot.lock().unwrap().extend_from_slice(&hashes);
+        Some(hashes.len())
+    }
+
+    /// Leaf index that `position` will have in the finalized tree, given
+    /// the shards as they stand.
+    pub fn leaf_index(&self, position: ShardPosition) -> Option<usize> {
+        if position.sequence >= self.shard_len(position.shard)? {
+            return None;
+        }
+        let before: usize = (0..position.shard)
+            .map(|shard| self.shard_len(shard).unwrap())
+            .sum();
+        Some(before + position.sequence)
+    }
+
+    /// The tree over every shard in shard order, or `None` if nothing was
+    /// appended.
+    pub fn finalize(self) -> Option<MerkleTree<H>> {
+        let mut leaves = Vec::new();
+        for shard in self.shards {
+            leaves.extend(shard.into_inner().unwrap());
+        }
+        if leaves.is_empty() {
+            return None;
+        }
+        Some(MerkleTree::from_leaf_hashes(leaves, self.padding))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn merges_shards_in_order() {
+        let items: Vec<Vec<u8>> = (0..4u8)
+            .flat_map(|shard| (0..50u8).map(move |i| vec![shard, i]))
+            .collect();
+        let expected = MerkleTree::from_leaves(&items).unwrap();
+        for _ in 0..3 {
+            let builder = ShardedBuilder::new(4);
+            thread::scope(|scope| {
+                // Shards are started in reverse so threads finish out of order.
+                for shard in (0..4).rev() {
+                    let builder = &builder;
+                    let items = &items[50 * shard..50 * shard + 50];
+                    scope.spawn(move || {
+                        for (i, item) in items.iter().enumerate() {
+                            let position = builder.append(shard, item).unwrap();
+                            assert_eq!(position, ShardPosition { shard, sequence: i });
+                        }
+                    });
+                }
+            });
+            let position = ShardPosition {
+                shard: 2,
+                sequence: 7,
+            };
+            assert_eq!(builder.leaf_index(position), Some(107));
+            assert_eq!(builder.finalize().unwrap().root(), expected.root());
+        }
+    }
+
+    #[test]
+    fn skips_empty_shards() {
+        let builder: ShardedBuilder = ShardedBuilder::build(3, Padding::Zero);
+        assert_eq!(builder.append_all(2, ["c", "d"]), Some(2));
+        assert_eq!(builder.append(0, b"a").unwrap().sequence, 0);
+        assert!(builder.append(3, b"x").is_none());
+        let position = ShardPosition {
+            shard: 1,
+            sequence: 0,
+        };
+        assert_eq!(builder.leaf_index(position), None);
+        let tree = builder.finalize().unwrap();
+        let expected = MerkleTree::from_leaves_padded(["a", "c", "d"], Padding::Zero).unwrap();
+        assert_eq!(tree.root(), expected.root());
+        assert!(ShardedBuilder::new(2).finalize().is_none());
+    }
+}
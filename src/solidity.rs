@@ -0,0 +1,85 @@
+//! Solidity source for an on-chain verifier matching this crate's proofs:
+//! SHA-256 leaves and nodes, with each path entry's direction bit deciding
+//! whether the running hash goes first.
+
+/// Library source exposing `verify(item, root, siblings, directions)`, or
+/// `None` if `name` is not a valid Solidity identifier. Direction bit `i` of
+/// `directions` is set when the running hash is hashed first at level `i`;
+/// see `calldata`.
+pub fn verifier(name: &str) -> Option<String> {
+    let mut chars = name.chars();
+    let valid_start = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == '$');
+    if !valid_start || !chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$') {
+        return None;
+    }
+    Some(format!(
+        r#"// SPDX-License-Identifier: MIT
+pragma solidity ^0.8.0;
+
+// Generated by the merkle crate.
+library {name} {{
+    function verify(
+        bytes memory item,
+        bytes32 root,
+        bytes32[] calldata siblings,
+        uint256 directions
+    ) internal pure returns (bool) {{
+        require(siblings.length <= 256, "proof too long");
+        bytes32 candidate = sha256(item);
+        for (uint256 i = 0; i < siblings.length; i++) {{
+            if ((directions >> i) & 1 == 1) {{
+                candidate = sha256(abi.encodePacked(candidate, siblings[i]));
+            }} else {{
+                candidate = sha256(abi.encodePacked(siblings[i], candidate));
+            }}
+        }}
+        return candidate == root;
+    }}
+}}
+"#,
+        name = name
+    ))
+}
+
+/// Splits a path into the `siblings` and big-endian `directions` arguments
+/// of the generated `verify`. Returns `None` for paths deeper than 256.
+pub fn calldata(path: &[([u8; 32], bool)]) -> Option<(Vec<[u8; 32]>, [u8; 32])> {
+    if path.len() > 256 {
+        return None;
+    }
+    let mut directions = [0u8; 32];
+    for (level, (_, odd)) in path.iter().enumerate() {
+        if *odd {
+            directions[31 - level / 8] |= 1 << (level % 8);
+        }
+    }
+    Some((path.iter().map(|(hash, _)| *hash).collect(), directions))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MerkleTree;
+
+    #[test]
+    fn generates_library() {
+        let source = verifier("AirdropProof").unwrap();
+        assert!(source.contains("library AirdropProof {"));
+        assert!(source.contains("sha256(abi.encodePacked(candidate, siblings[i]))"));
+        assert!(verifier("1bad").is_none());
+        assert!(verifier("bad-name").is_none());
+    }
+
+    #[test]
+    fn packs_directions() {
+        let data = b"asdfasdfasdfasdfasdfasdfasdfasdf";
+        let tree = MerkleTree::new(data, 8);
+        let path = tree.path(5).unwrap();
+        let (siblings, directions) = calldata(&path).unwrap();
+        assert_eq!(siblings.len(), 3);
+        assert_eq!(directions[31], 0b101);
+        assert!(directions[..31].iter().all(|&b| b == 0));
+    }
+}
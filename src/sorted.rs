@@ -0,0 +1,91 @@
+//! Trees over unordered sets. Leaves are put in a canonical order before
+//! the tree is built, so parties holding the same items in any order build
+//! the same root. Items are ordered by a caller's key, with ties broken by
+//! leaf hash, or by leaf hash alone.
+//!
+//! Each constructor also returns the order it chose: `order[i]` is the
+//! input position of the item at leaf `i`. That position is also the
+//! leaf's id, so `proof_by_id(pos)` proves the item given at `pos`.
+
+use crate::{MerkleHasher, MerkleTree, Padding};
+use alloc::vec::Vec;
+
+impl MerkleTree {
+    /// Like `from_leaves`, with the leaves sorted by hash.
+    pub fn from_leaves_sorted<T: AsRef<[u8]>>(items: &[T]) -> Option<(Self, Vec<usize>)> {
+        MerkleTree::build_sorted_by_key(items, Padding::default(), |_| ())
+    }
+
+    /// Like `from_leaves`, with the leaves sorted by `key`.
+    pub fn from_leaves_sorted_by_key<T, K, F>(items: &[T], key: F) -> Option<(Self, Vec<usize>)>
+    where
+        T: AsRef<[u8]>,
+        K: Ord,
+        F: FnMut(&T) -> K,
+    {
+        MerkleTree::build_sorted_by_key(items, Padding::default(), key)
+    }
+}
+
+impl<H: MerkleHasher> MerkleTree<H> {
+    pub fn build_sorted_by_key<T, K, F>(
+        items: &[T],
+        padding: Padding,
+        mut key: F,
+    ) -> Option<(Self, Vec<usize>)>
+    where
+        T: AsRef<[u8]>,
+        K: Ord,
+        F: FnMut(&T) -> K,
+    {
+        if items.is_empty() {
+            return None;
+        }
+        let mut ctx = H::Context::default();
+        let mut keyed: Vec<(K, [u8; 32], usize)> = items
+            .iter()
+            .enumerate()
+            .map(|(pos, item)| (key(item), H::hash_data_with(&mut ctx, item.as_ref()), pos))
+            .collect();
+        // Equal keys and hashes mean equal items, so the input position left
+        // as the last tie-break does not change the root.
+        keyed.sort_unstable_by(|a, b| (&a.0, &a.1, a.2).cmp(&(&b.0, &b.1, b.2)));
+        let order: Vec<usize> = keyed.iter().map(|&(_, _, pos)| pos).collect();
+        let hashes = keyed.into_iter().map(|(_, hash, _)| hash).collect();
+        let tree = MerkleTree::from_leaf_hashes(hashes, padding)
+            .with_ids(order.iter().map(|&pos| pos as u64).collect())
+            .expect("the order is a permutation");
+        Some((tree, order))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::verify_proof;
+
+    #[test]
+    fn same_root_in_any_order() {
+        let items = ["pear", "fig", "apple", "kiwi", "fig"];
+        let shuffled = ["fig", "kiwi", "fig", "pear", "apple"];
+        let (tree, order) = MerkleTree::from_leaves_sorted(&items).unwrap();
+        let (other, _) = MerkleTree::from_leaves_sorted(&shuffled).unwrap();
+        assert_eq!(tree.root(), other.root());
+        for (leaf, &pos) in order.iter().enumerate() {
+            let path = tree.proof(items[pos].as_bytes(), leaf).unwrap();
+            assert!(verify_proof(items[pos].as_bytes(), tree.root(), &path).is_valid());
+        }
+
+        let (by_len, order) =
+            MerkleTree::from_leaves_sorted_by_key(&items, |item| item.len()).unwrap();
+        let sorted: Vec<&str> = order.iter().map(|&pos| items[pos]).collect();
+        assert_eq!(sorted[..2], ["fig", "fig"]);
+        assert_eq!(sorted[4], "apple");
+        let (again, _) =
+            MerkleTree::from_leaves_sorted_by_key(&shuffled, |item| item.len()).unwrap();
+        assert_eq!(by_len.root(), again.root());
+        assert_ne!(by_len.root(), tree.root());
+
+        assert!(MerkleTree::from_leaves_sorted::<&str>(&[]).is_none());
+    }
+}
@@ -0,0 +1,635 @@
+//! Sparse Merkle trees over a 256-bit keyspace, for authenticated key-value
+//! stores that must also prove a key absent. Every key has a fixed leaf, so
+//! a proof that the leaf is empty is a non-membership proof.
+//!
+//! A leaf holds `hash_data(value)`, or zero when the key is absent. Parents
+//! hash like the dense tree's, with `hash_pair` taking the right child (key
+//! bit 1) first. Bit `h` of a key, counted from the least significant bit of
+//! its big-endian value, picks the child between heights `h` and `h + 1`.
+//!
+//! Nearly every subtree is empty, and the hash of an empty subtree depends
+//! only on its height, so the tree stores only non-empty nodes and proofs
+//! leave out empty siblings behind a bitmap.
+//!
+//! A [`SnapshotChunk`] exports every entry under one subtree with the
+//! subtree's path to the root. The importer recomputes the subtree from
+//! the entries, so a chunk that leaves out a key, or adds one, does not
+//! verify; [`SnapshotImport::finish`] then checks that the chunks together
+//! give the root, which a missing chunk does not.
+//!
+//! Absence proofs are cached until the next insert, which changes every
+//! one of them. A cuckoo filter of the present keys decides which queries
+//! may use the cache: a key the filter rules out is certainly absent, so a
+//! repeated query for it returns the cached proof without walking the
+//! tree. A key the filter might hold is looked up and proven afresh.
+
+use crate::cuckoo::CuckooFilter;
+use crate::format::{Version, CURRENT};
+use crate::memory::{btree_bytes, table_bytes, vec_bytes, HeapSize};
+use crate::{hash_data, hash_pair};
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryInto;
+use std::sync::{Mutex, MutexGuard, OnceLock, PoisonError};
+
+/// Key bits, and so the height of the root.
+pub const DEPTH: usize = 256;
+
+/// Leaf of an absent key.
+const EMPTY: [u8; 32] = [0; 32];
+
+/// Most absence proofs cached at once; a full cache starts over.
+const CACHED_ABSENCES: usize = 4096;
+
+static DEFAULTS: OnceLock<Vec<[u8; 32]>> = OnceLock::new();
+
+/// `defaults()[h]` is the root of an empty subtree of height `h`, as in
+/// `<Sha256 as ConstHasher>::ZERO_HASHES`. They are hashed at run time, as
+/// evaluating the constant adds seconds to every build.
+fn defaults() -> &'static [[u8; 32]] {
+    DEFAULTS.get_or_init(|| {
+        let mut defaults = vec![EMPTY];
+        for height in 0..DEPTH {
+            let below = defaults[height];
+            defaults.push(hash_pair(&below, &below));
+        }
+        defaults
+    })
+}
+
+fn bit(bytes: &[u8; 32], height: usize) -> bool {
+    bytes[31 - height / 8] >> (height % 8) & 1 == 1
+}
+
+/// The key with its lowest `height` bits cleared: the prefix naming the
+/// node at `height` above it.
+fn prefix(key: &[u8; 32], height: usize) -> [u8; 32] {
+    let mut prefix = *key;
+    for byte in prefix.iter_mut().rev().take(height / 8) {
+        *byte = 0;
+    }
+    if height < DEPTH {
+        prefix[31 - height / 8] &= !0 << (height % 8);
+    }
+    prefix
+}
+
+fn parent(key: &[u8; 32], height: usize, node: &[u8; 32], sibling: &[u8; 32]) -> [u8; 32] {
+    if bit(key, height) {
+        hash_pair(node, sibling)
+    } else {
+        hash_pair(sibling, node)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct SparseMerkleTree {
+    values: BTreeMap<[u8; 32], Vec<u8>>,
+    /// Non-empty nodes by height and prefix.
+    nodes: HashMap<(u16, [u8; 32]), [u8; 32]>,
+    /// `hash_data` of every present key.
+    present: CuckooFilter,
+    /// Proofs for keys `present` rules out, at the current root.
+    absent: Mutex<HashMap<[u8; 32], SparseProof>>,
+}
+
+impl Clone for SparseMerkleTree {
+    fn clone(&self) -> Self {
+        SparseMerkleTree {
+            values: self.values.clone(),
+            nodes: self.nodes.clone(),
+            present: self.present.clone(),
+            absent: Mutex::new(self.absences().clone()),
+        }
+    }
+}
+
+impl SparseMerkleTree {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of keys present.
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    pub fn root(&self) -> [u8; 32] {
+        self.node(DEPTH, &[0; 32])
+    }
+
+    pub fn get(&self, key: &[u8; 32]) -> Option<&[u8]> {
+        self.values.get(key).map(Vec::as_slice)
+    }
+
+    /// Sets the value under `key` and returns the one it replaces.
+    pub fn insert(&mut self, key: [u8; 32], value: Vec<u8>) -> Option<Vec<u8>> {
+        let mut node = hash_data(&value);
+        for height in 0..DEPTH {
+            self.nodes
+                .insert((height as u16, prefix(&key, height)), node);
+            let sibling = self.node(height, &prefix(&sibling_key(&key, height), height));
+            node = parent(&key, height, &node, &sibling);
+        }
+        self.nodes.insert((DEPTH as u16, [0; 32]), node);
+        self.absences().clear();
+        let replaced = self.values.insert(key, value);
+        if replaced.is_none() && !self.present.insert_hash(&hash_data(&key)) {
+            self.present = key_filter(self.values.keys());
+        }
+        replaced
+    }
+
+    fn absences(&self) -> MutexGuard<'_, HashMap<[u8; 32], SparseProof>> {
+        self.absent.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn node(&self, height: usize, prefix: &[u8; 32]) -> [u8; 32] {
+        self.nodes
+            .get(&(height as u16, *prefix))
+            .copied()
+            .unwrap_or(defaults()[height])
+    }
+
+    fn proof(&self, key: &[u8; 32]) -> SparseProof {
+        let mut bitmap = [0; 32];
+        let mut siblings = Vec::new();
+        for height in 0..DEPTH {
+            let sibling = self.node(height, &prefix(&sibling_key(key, height), height));
+            if sibling != defaults()[height] {
+                bitmap[height / 8] |= 1 << (height % 8);
+                siblings.push(sibling);
+            }
+        }
+        SparseProof { bitmap, siblings }
+    }
+
+    /// Proof of the value under `key`; `None` if the key is absent.
+    pub fn proof_of_inclusion(&self, key: &[u8; 32]) -> Option<SparseProof> {
+        self.values.get(key).map(|_| self.proof(key))
+    }
+
+    /// Proof that `key` is absent; `None` if it is present. Asking again
+    /// before the next insert is answered from the cache, unless the key
+    /// filter cannot rule the key out.
+    pub fn proof_of_non_inclusion(&self, key: &[u8; 32]) -> Option<SparseProof> {
+        if self.present.might_contain_hash(&hash_data(key)) {
+            return match self.values.get(key) {
+                Some(_) => None,
+                None => Some(self.proof(key)),
+            };
+        }
+        if let Some(proof) = self.absences().get(key) {
+            return Some(proof.clone());
+        }
+        let proof = self.proof(key);
+        let mut absences = self.absences();
+        if absences.len() >= CACHED_ABSENCES {
+            absences.clear();
+        }
+        absences.insert(*key, proof.clone());
+        Some(proof)
+    }
+}
+
+/// Filter of `keys` with room for as many again.
+fn key_filter<'a, I>(keys: I) -> CuckooFilter
+where
+    I: ExactSizeIterator<Item = &'a [u8; 32]> + Clone,
+{
+    let mut capacity = 2 * keys.len();
+    loop {
+        let mut filter = CuckooFilter::with_capacity(capacity);
+        if keys.clone().all(|key| filter.insert_hash(&hash_data(key))) {
+            return filter;
+        }
+        capacity *= 2;
+    }
+}
+
+impl SparseMerkleTree {
+    /// The entries in chunks of one subtree each, `chunk_bits` key bits
+    /// below the root, in key order. Empty subtrees have no chunk.
+    pub fn snapshot(&self, chunk_bits: u8) -> Vec<SnapshotChunk> {
+        let height = DEPTH.saturating_sub(chunk_bits as usize);
+        let mut chunks: Vec<SnapshotChunk> = Vec::new();
+        for (key, value) in &self.values {
+            let under = prefix(key, height);
+            match chunks.last_mut() {
+                Some(chunk) if chunk.prefix == under => {}
+                _ => chunks.push(SnapshotChunk {
+                    height: height as u16,
+                    prefix: under,
+                    siblings: (height..DEPTH)
+                        .map(|h| self.node(h, &prefix(&sibling_key(&under, h), h)))
+                        .collect(),
+                    entries: Vec::new(),
+                }),
+            }
+            let chunk = chunks.last_mut().expect("pushed above");
+            chunk.entries.push((*key, value.clone()));
+        }
+        chunks
+    }
+}
+
+/// Every entry under the subtree at `height` whose keys start with
+/// `prefix`, with the subtree's siblings from `height` up to the root.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotChunk {
+    pub height: u16,
+    pub prefix: [u8; 32],
+    pub siblings: Vec<[u8; 32]>,
+    /// In ascending key order.
+    pub entries: Vec<([u8; 32], Vec<u8>)>,
+}
+
+/// Root of the subtree at `height` holding exactly `entries`, which are
+/// sorted and share the subtree's prefix.
+fn subtree_root(entries: &[([u8; 32], Vec<u8>)], height: usize) -> [u8; 32] {
+    match entries {
+        [] => defaults()[height],
+        [(_, value)] if height == 0 => hash_data(value),
+        _ => {
+            let split = entries.partition_point(|(key, _)| !bit(key, height - 1));
+            let zero = subtree_root(&entries[..split], height - 1);
+            let one = subtree_root(&entries[split..], height - 1);
+            hash_pair(&one, &zero)
+        }
+    }
+}
+
+impl SnapshotChunk {
+    /// Checks that the entries are all of the subtree's, and that the
+    /// subtree is under `root`.
+    pub fn verify(&self, root: [u8; 32]) -> bool {
+        let height = self.height as usize;
+        if height > DEPTH
+            || prefix(&self.prefix, height) != self.prefix
+            || self.siblings.len() != DEPTH - height
+            || self.entries.is_empty()
+            || self.entries.windows(2).any(|pair| pair[0].0 >= pair[1].0)
+            || self
+                .entries
+                .iter()
+                .any(|(key, _)| prefix(key, height) != self.prefix)
+        {
+            return false;
+        }
+        let mut node = subtree_root(&self.entries, height);
+        for (h, sibling) in (height..DEPTH).zip(self.siblings.iter()) {
+            node = parent(&self.prefix, h, &node, sibling);
+        }
+        node == root
+    }
+
+    /// Format version (1 byte), height (u16 LE), the prefix, the siblings,
+    /// the entry count (u32 LE), then each key with its value's length
+    /// (u32 LE) and the value.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = vec![CURRENT.byte()];
+        out.extend_from_slice(&self.height.to_le_bytes());
+        out.extend_from_slice(&self.prefix);
+        for sibling in &self.siblings {
+            out.extend_from_slice(sibling);
+        }
+        out.extend_from_slice(&(self.entries.len() as u32).to_le_bytes());
+        for (key, value) in &self.entries {
+            out.extend_from_slice(key);
+            out.extend_from_slice(&(value.len() as u32).to_le_bytes());
+            out.extend_from_slice(value);
+        }
+        out
+    }
+
+    /// The number of siblings follows from the height. Returns `None` for
+    /// malformed or trailing bytes; the contents are checked by `verify`.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        fn take<'a>(bytes: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+            if bytes.len() < len {
+                return None;
+            }
+            let (head, rest) = bytes.split_at(len);
+            *bytes = rest;
+            Some(head)
+        }
+        let mut bytes = bytes;
+        if Version::from_byte(*take(&mut bytes, 1)?.first()?)? != CURRENT {
+            return None;
+        }
+        let height = u16::from_le_bytes(take(&mut bytes, 2)?.try_into().unwrap());
+        let prefix = take(&mut bytes, 32)?.try_into().unwrap();
+        let depth = DEPTH.checked_sub(height as usize)?;
+        let siblings = take(&mut bytes, 32 * depth)?
+            .chunks(32)
+            .map(|sibling| sibling.try_into().unwrap())
+            .collect();
+        let count = u32::from_le_bytes(take(&mut bytes, 4)?.try_into().unwrap());
+        let mut entries = Vec::new();
+        for _ in 0..count {
+            let key = take(&mut bytes, 32)?.try_into().unwrap();
+            let len = u32::from_le_bytes(take(&mut bytes, 4)?.try_into().unwrap());
+            entries.push((key, take(&mut bytes, len as usize)?.to_vec()));
+        }
+        if !bytes.is_empty() {
+            return None;
+        }
+        Some(SnapshotChunk {
+            height,
+            prefix,
+            siblings,
+            entries,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotError {
+    /// The chunk does not verify against the root.
+    BadChunk,
+    /// The chunks accepted do not add up to the root.
+    Incomplete,
+}
+
+/// A tree rebuilt chunk by chunk from a snapshot of the tree at `root`.
+#[derive(Debug, Clone)]
+pub struct SnapshotImport {
+    root: [u8; 32],
+    tree: SparseMerkleTree,
+}
+
+impl SnapshotImport {
+    pub fn new(root: [u8; 32]) -> Self {
+        SnapshotImport {
+            root,
+            tree: SparseMerkleTree::new(),
+        }
+    }
+
+    /// Adds the chunk's entries if it verifies. Chunks may come in any
+    /// order.
+    pub fn accept(&mut self, chunk: &SnapshotChunk) -> Result<(), SnapshotError> {
+        if !chunk.verify(self.root) {
+            return Err(SnapshotError::BadChunk);
+        }
+        for (key, value) in &chunk.entries {
+            self.tree.insert(*key, value.clone());
+        }
+        Ok(())
+    }
+
+    /// Keys imported so far.
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    /// The imported tree, if every chunk of the snapshot was accepted.
+    pub fn finish(self) -> Result<SparseMerkleTree, SnapshotError> {
+        if self.tree.root() != self.root {
+            return Err(SnapshotError::Incomplete);
+        }
+        Ok(self.tree)
+    }
+}
+
+/// The key whose leaf is the sibling of `key`'s subtree at `height`.
+fn sibling_key(key: &[u8; 32], height: usize) -> [u8; 32] {
+    let mut sibling = *key;
+    sibling[31 - height / 8] ^= 1 << (height % 8);
+    sibling
+}
+
+/// The siblings of a leaf from the bottom up, without the empty ones.
+/// Bit `h` of `bitmap`, least significant bit first, is set where the
+/// sibling at height `h` is present in `siblings`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "borsh",
+    derive(borsh::BorshSerialize, borsh::BorshDeserialize)
+)]
+pub struct SparseProof {
+    bitmap: [u8; 32],
+    siblings: Vec<[u8; 32]>,
+}
+
+impl SparseProof {
+    pub fn bitmap(&self) -> &[u8; 32] {
+        &self.bitmap
+    }
+
+    pub fn siblings(&self) -> &[[u8; 32]] {
+        &self.siblings
+    }
+
+    /// Checks that, in the tree with this root, `key` maps to `value`, or is
+    /// absent when `value` is `None`.
+    pub fn verify(&self, root: [u8; 32], key: &[u8; 32], value: Option<&[u8]>) -> bool {
+        let present: usize = self.bitmap.iter().map(|b| b.count_ones() as usize).sum();
+        if present != self.siblings.len() {
+            return false;
+        }
+        let mut siblings = self.siblings.iter();
+        let mut node = value.map_or(EMPTY, hash_data);
+        for height in 0..DEPTH {
+            let sibling = if self.bitmap[height / 8] >> (height % 8) & 1 == 1 {
+                *siblings.next().expect("counted against the bitmap")
+            } else {
+                defaults()[height]
+            };
+            node = parent(key, height, &node, &sibling);
+        }
+        node == root
+    }
+}
+
+/// Bytes held by the empty-subtree hashes every sparse tree shares, once
+/// the first tree has used them. `heap_size_bytes` leaves them out, as they
+/// are allocated once per process rather than per tree.
+pub fn defaults_heap_size_bytes() -> usize {
+    DEFAULTS.get().map_or(0, vec_bytes)
+}
+
+impl HeapSize for SparseMerkleTree {
+    fn heap_size_bytes(&self) -> usize {
+        let values: usize = self.values.values().map(Vec::capacity).sum();
+        let absent = self.absences();
+        let proofs: usize = absent
+            .values()
+            .map(|proof| vec_bytes(&proof.siblings))
+            .sum();
+        btree_bytes::<[u8; 32], Vec<u8>>(self.values.len())
+            + values
+            + table_bytes(&self.nodes)
+            + self.present.heap_size_bytes()
+            + table_bytes(&absent)
+            + proofs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(byte: u8, last: u8) -> [u8; 32] {
+        let mut key = [byte; 32];
+        key[31] = last;
+        key
+    }
+
+    #[test]
+    fn proves_presence_and_absence() {
+        let mut tree = SparseMerkleTree::new();
+        assert_eq!(tree.root(), defaults()[DEPTH]);
+        let empty_root = tree.root();
+
+        // The first two keys differ only in their lowest bit.
+        let keys = [key(7, 0), key(7, 1), key(0x80, 0), key(0, 0)];
+        for (i, key) in keys.iter().enumerate() {
+            assert_eq!(tree.insert(*key, vec![i as u8]), None);
+        }
+        assert_eq!(tree.len(), 4);
+        assert_eq!(tree.get(&keys[2]), Some(&[2][..]));
+        let root = tree.root();
+        assert_ne!(root, empty_root);
+
+        for (i, key) in keys.iter().enumerate() {
+            let proof = tree.proof_of_inclusion(key).unwrap();
+            assert!(proof.siblings().len() <= 3);
+            assert!(proof.verify(root, key, Some(&[i as u8])));
+            assert!(!proof.verify(root, key, Some(&[0xff])));
+            assert!(!proof.verify(root, key, None));
+            assert!(tree.proof_of_non_inclusion(key).is_none());
+        }
+
+        let absent = key(7, 2);
+        assert_eq!(tree.get(&absent), None);
+        assert!(tree.proof_of_inclusion(&absent).is_none());
+        let proof = tree.proof_of_non_inclusion(&absent).unwrap();
+        assert!(proof.verify(root, &absent, None));
+        assert!(!proof.verify(root, &absent, Some(&[0])));
+        assert!(!proof.verify(empty_root, &absent, None));
+
+        // A proof for one key does not carry over to its neighbour.
+        let first = tree.proof_of_inclusion(&keys[0]).unwrap();
+        assert!(!first.verify(root, &keys[1], Some(&[0])));
+        let mut short = first.clone();
+        short.siblings.pop();
+        assert!(!short.verify(root, &keys[0], Some(&[0])));
+
+        assert_eq!(tree.insert(keys[1], vec![9]), Some(vec![1]));
+        assert_ne!(tree.root(), root);
+        assert!(tree.proof_of_inclusion(&keys[1]).unwrap().verify(
+            tree.root(),
+            &keys[1],
+            Some(&[9])
+        ));
+    }
+
+    #[test]
+    fn caches_absences() {
+        let mut tree = SparseMerkleTree::new();
+        let keys: Vec<[u8; 32]> = (0..100u8).map(|i| hash_data(&[i])).collect();
+        for key in &keys {
+            tree.insert(*key, key.to_vec());
+        }
+        assert!(keys
+            .iter()
+            .all(|key| tree.proof_of_non_inclusion(key).is_none()));
+
+        let absent = key(1, 1);
+        let proof = tree.proof_of_non_inclusion(&absent).unwrap();
+        assert_eq!(tree.absences().get(&absent), Some(&proof));
+        assert_eq!(tree.proof_of_non_inclusion(&absent), Some(proof));
+
+        // A key the filter cannot rule out is proven from the tree.
+        let false_positive = (0u32..)
+            .map(|i| hash_data(&i.to_be_bytes()))
+            .find(|key| !keys.contains(key) && tree.present.might_contain_hash(&hash_data(key)))
+            .unwrap();
+        let proof = tree.proof_of_non_inclusion(&false_positive).unwrap();
+        assert!(proof.verify(tree.root(), &false_positive, None));
+        assert!(tree.absences().get(&false_positive).is_none());
+
+        // Inserting changes every absence proof.
+        tree.insert(key(2, 2), vec![2]);
+        assert!(tree.absences().is_empty());
+        let proof = tree.proof_of_non_inclusion(&absent).unwrap();
+        assert!(proof.verify(tree.root(), &absent, None));
+        assert!(tree.clone().proof_of_non_inclusion(&key(2, 2)).is_none());
+    }
+
+    #[test]
+    fn root_does_not_depend_on_insertion_order() {
+        let keys: Vec<[u8; 32]> = (0..20u8).map(|i| hash_data(&[i])).collect();
+        let mut forward = SparseMerkleTree::new();
+        let mut backward = SparseMerkleTree::new();
+        for key in &keys {
+            forward.insert(*key, key.to_vec());
+        }
+        for key in keys.iter().rev() {
+            backward.insert(*key, key.to_vec());
+        }
+        assert_eq!(forward.root(), backward.root());
+    }
+
+    #[test]
+    fn imports_complete_snapshots() {
+        let mut tree = SparseMerkleTree::new();
+        for i in 0..40u8 {
+            tree.insert(hash_data(&[i]), vec![i; i as usize % 5]);
+        }
+        let root = tree.root();
+        for bits in [0, 1, 3, 8] {
+            let chunks = tree.snapshot(bits);
+            assert!(chunks.len() <= 1 << bits);
+            let mut import = SnapshotImport::new(root);
+            for chunk in chunks.iter().rev() {
+                let decoded = SnapshotChunk::from_bytes(&chunk.to_bytes()).unwrap();
+                assert_eq!(&decoded, chunk);
+                import.accept(&decoded).unwrap();
+            }
+            assert_eq!(import.len(), 40);
+            assert_eq!(import.finish().unwrap().root(), root);
+        }
+
+        let chunks = tree.snapshot(2);
+        let mut import = SnapshotImport::new(root);
+        for chunk in &chunks[1..] {
+            import.accept(chunk).unwrap();
+        }
+        assert_eq!(import.finish().err(), Some(SnapshotError::Incomplete));
+
+        // A chunk missing a key, or with one added, does not verify.
+        let mut short = chunks[0].clone();
+        short.entries.pop();
+        assert!(!short.verify(root));
+        let mut padded = chunks[0].clone();
+        let (mut extra, _) = padded.entries[0].clone();
+        extra[31] ^= 1;
+        padded.entries.push((extra, vec![]));
+        padded.entries.sort();
+        assert!(!padded.verify(root));
+        let mut moved = chunks[0].clone();
+        moved.prefix = chunks[1].prefix;
+        assert!(!moved.verify(root));
+        assert_eq!(
+            SnapshotImport::new(root).accept(&short),
+            Err(SnapshotError::BadChunk)
+        );
+
+        let bytes = chunks[0].to_bytes();
+        assert!(SnapshotChunk::from_bytes(&bytes[..bytes.len() - 1]).is_none());
+        assert!(SnapshotChunk::from_bytes(&[bytes.clone(), vec![0]].concat()).is_none());
+        assert!(SparseMerkleTree::new().snapshot(4).is_empty());
+        let empty = SnapshotImport::new(SparseMerkleTree::new().root());
+        assert!(empty.finish().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,414 @@
+//! A read-only proof store: the node hashes of a finished tree in one flat
+//! buffer, for servers that only answer proof requests. The store borrows
+//! its bytes, so they can come straight from a memory-mapped file.
+//!
+//! Layout: format version, padding byte, six zero bytes, leaf count (u64
+//! LE), every node hash in node index order, then optional extension fields
+//! (see [`crate::ext`]).
+//!
+//! With the `borsh` feature, [`MerkleTreeView`] serves the same queries
+//! straight from a Borsh tree export.
+//!
+//! [`MerkleTree::snapshot`] writes a store for reloading the whole tree: it
+//! adds a fingerprint of the hasher and, last, a SHA-256 checksum of every
+//! byte before it, both as non-critical extensions so that a snapshot is
+//! also a valid store. Labels and leaf ids are not saved; a reloaded tree
+//! has the identity ids of a fresh one.
+
+use crate::ext;
+use crate::format::{Version, CURRENT};
+use crate::{MerkleHasher, MerkleTree, Node, Padding, Path};
+use sha2::{Digest, Sha256};
+use std::convert::TryInto;
+use std::io;
+
+const HEADER: usize = 16;
+
+/// Extension carrying `H::hash_data(b"merkle-hasher")`, which tells hashers
+/// apart without a registry of names. Tags from 0x7f00 are the crate's.
+pub const HASHER_TAG: u16 = 0x7f00;
+/// Extension carrying the SHA-256 of the snapshot up to this field.
+pub const CHECKSUM_TAG: u16 = 0x7f01;
+
+fn fingerprint<H: MerkleHasher>() -> [u8; 32] {
+    H::hash_data(b"merkle-hasher")
+}
+
+impl<H: MerkleHasher> MerkleTree<H> {
+    pub fn to_proof_store(&self) -> Vec<u8> {
+        self.to_proof_store_with(&[]).unwrap()
+    }
+
+    /// Appends `extensions` as (tag, value) fields after the node hashes.
+    /// Returns `None` if a value is longer than `u32::MAX` bytes.
+    pub fn to_proof_store_with(&self, extensions: &[(u16, &[u8])]) -> Option<Vec<u8>> {
+        let mut out = Vec::with_capacity(HEADER + 32 * self.size());
+        out.push(CURRENT.byte());
+        out.push(self.padding().byte());
+        out.extend_from_slice(&[0; 6]);
+        out.extend_from_slice(&(self.leaves() as u64).to_le_bytes());
+        for node in &self.nodes {
+            out.extend_from_slice(&node.value);
+        }
+        for (tag, value) in extensions {
+            ext::write_field(&mut out, *tag, value)?;
+        }
+        Some(out)
+    }
+}
+
+impl<H: MerkleHasher> MerkleTree<H> {
+    /// The tree as a checksummed store; see the module docs.
+    pub fn snapshot(&self) -> Vec<u8> {
+        let mut out = self
+            .to_proof_store_with(&[(HASHER_TAG, &fingerprint::<H>())])
+            .unwrap();
+        let checksum: [u8; 32] = Sha256::digest(&out).into();
+        ext::write_field(&mut out, CHECKSUM_TAG, &checksum).unwrap();
+        out
+    }
+
+    /// Reloads a snapshot taken with the same hasher. The checksum is
+    /// checked but nothing is rehashed, so load only snapshots you wrote.
+    pub fn from_snapshot(bytes: &[u8]) -> Option<Self> {
+        let store = ProofStore::from_bytes(bytes)?;
+        if store.extension(HASHER_TAG) != Some(&fingerprint::<H>()[..]) {
+            return None;
+        }
+        // The checksum field is the last 38 bytes: tag, length and hash.
+        let (body, field) = bytes.split_at(bytes.len().checked_sub(38)?);
+        let mut expected = Vec::new();
+        ext::write_field(&mut expected, CHECKSUM_TAG, &Sha256::digest(body)).unwrap();
+        if field != &expected[..] {
+            return None;
+        }
+        let nodes = store
+            .nodes
+            .chunks_exact(32)
+            .map(|hash| Node::new(hash.try_into().unwrap()))
+            .collect();
+        Some(MerkleTree::from_nodes_padded(
+            nodes,
+            store.leaves,
+            store.padding,
+        ))
+    }
+
+    pub fn save<P: AsRef<std::path::Path>>(&self, path: P) -> io::Result<()> {
+        std::fs::write(path, self.snapshot())
+    }
+
+    pub fn load<P: AsRef<std::path::Path>>(path: P) -> io::Result<Self> {
+        let bytes = std::fs::read(path)?;
+        MerkleTree::from_snapshot(&bytes)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "not a valid tree snapshot"))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ProofStore<'a> {
+    nodes: &'a [u8],
+    leaves: usize,
+    padding: Padding,
+    extensions: &'a [u8],
+}
+
+/// Nodes in a tree over `leaves` leaves, or `None` on overflow.
+fn node_count(leaves: usize) -> Option<usize> {
+    let mut count = leaves;
+    let mut len = leaves;
+    while len > 1 {
+        len = len.div_ceil(2);
+        count = count.checked_add(len)?;
+    }
+    Some(count)
+}
+
+impl<'a> ProofStore<'a> {
+    /// Checks the header, length and extension framing only; node hashes
+    /// are served as stored. The crate defines [`HASHER_TAG`] and
+    /// [`CHECKSUM_TAG`], both non-critical and read only by
+    /// `from_snapshot`; any critical tag is rejected.
+    pub fn from_bytes(bytes: &'a [u8]) -> Option<Self> {
+        if bytes.len() < HEADER
+            || Version::from_byte(bytes[0]) != Some(Version::V1)
+            || bytes[2..8] != [0; 6]
+        {
+            return None;
+        }
+        let padding = Padding::from_byte(bytes[1])?;
+        let leaves = u64::from_le_bytes(bytes[8..16].try_into().unwrap());
+        let leaves: usize = leaves.try_into().ok()?;
+        if leaves == 0 {
+            return None;
+        }
+        let size = node_count(leaves)?.checked_mul(32)?;
+        let nodes = bytes.get(HEADER..HEADER.checked_add(size)?)?;
+        let extensions = &bytes[HEADER + size..];
+        if !ext::check(extensions, &[]) {
+            return None;
+        }
+        Some(ProofStore {
+            nodes,
+            leaves,
+            padding,
+            extensions,
+        })
+    }
+
+    pub fn extension(&self, tag: u16) -> Option<&'a [u8]> {
+        ext::find(self.extensions, tag)
+    }
+
+    pub fn leaves(&self) -> usize {
+        self.leaves
+    }
+
+    fn node(&self, idx: usize) -> [u8; 32] {
+        self.nodes[32 * idx..32 * idx + 32].try_into().unwrap()
+    }
+
+    pub fn root(&self) -> [u8; 32] {
+        self.node(self.nodes.len() / 32 - 1)
+    }
+
+    pub fn leaf(&self, idx: usize) -> Option<[u8; 32]> {
+        if idx < self.leaves {
+            Some(self.node(idx))
+        } else {
+            None
+        }
+    }
+
+    /// Same path as `MerkleTree::path`, read with one lookup per level.
+    pub fn path(&self, idx: usize) -> Option<Path> {
+        if idx >= self.leaves {
+            return None;
+        }
+        let mut path = Vec::new();
+        let (mut start, mut len, mut offset) = (0, self.leaves, idx);
+        while len > 1 {
+            let sibling = if offset ^ 1 < len {
+                Some(self.node(start + (offset ^ 1)))
+            } else {
+                self.padding.sibling(&self.node(start + offset))
+            };
+            if let Some(sibling) = sibling {
+                path.push((sibling, offset & 1 == 1));
+            }
+            start += len;
+            len = len.div_ceil(2);
+            offset /= 2;
+        }
+        Some(path)
+    }
+}
+
+/// Roots and proofs read in place from a Borsh-encoded `MerkleTree`. Only
+/// the header and node hashes are looked at; unlike deserializing, opening
+/// a view does not rehash the tree, so only open exports you wrote.
+#[cfg(feature = "borsh")]
+#[derive(Debug, Clone, Copy)]
+pub struct MerkleTreeView<'a> {
+    store: ProofStore<'a>,
+}
+
+#[cfg(feature = "borsh")]
+impl<'a> MerkleTreeView<'a> {
+    pub fn open(bytes: &'a [u8]) -> Option<Self> {
+        // Version byte, leaves (u64 LE), node count (u32 LE), node hashes,
+        // then labels and ids, followed by a padding byte when the
+        // leaf count is not a power of two.
+        if bytes.len() < 13 || Version::from_byte(bytes[0]) != Some(Version::V1) {
+            return None;
+        }
+        let leaves: usize = u64::from_le_bytes(bytes[1..9].try_into().unwrap())
+            .try_into()
+            .ok()?;
+        let count: usize = u32::from_le_bytes(bytes[9..13].try_into().unwrap())
+            .try_into()
+            .ok()?;
+        if leaves == 0 || node_count(leaves) != Some(count) {
+            return None;
+        }
+        let end = count.checked_mul(32)?.checked_add(13)?;
+        let nodes = bytes.get(13..end)?;
+        let padding = if leaves.is_power_of_two() {
+            Padding::default()
+        } else {
+            Padding::from_byte(*bytes.get(trailer(bytes, end)?)?)?
+        };
+        Some(MerkleTreeView {
+            store: ProofStore {
+                nodes,
+                leaves,
+                padding,
+                extensions: &[],
+            },
+        })
+    }
+
+    pub fn leaves(&self) -> usize {
+        self.store.leaves()
+    }
+
+    pub fn root(&self) -> [u8; 32] {
+        self.store.root()
+    }
+
+    pub fn leaf(&self, idx: usize) -> Option<[u8; 32]> {
+        self.store.leaf(idx)
+    }
+
+    pub fn path(&self, idx: usize) -> Option<Path> {
+        self.store.path(idx)
+    }
+}
+
+/// Offset of the padding byte, skipping the labels and ids that start at
+/// `at`.
+#[cfg(feature = "borsh")]
+fn trailer(bytes: &[u8], mut at: usize) -> Option<usize> {
+    let u32_at = |at: &mut usize| -> Option<usize> {
+        let len = u32::from_le_bytes(bytes.get(*at..at.checked_add(4)?)?.try_into().unwrap());
+        *at += 4;
+        len.try_into().ok()
+    };
+    for _ in 0..u32_at(&mut at)? {
+        at = at.checked_add(8)?;
+        let len = u32_at(&mut at)?;
+        at = at.checked_add(len)?;
+    }
+    let ids = u32_at(&mut at)?;
+    at.checked_add(ids.checked_mul(8)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::verify_proof;
+
+    #[test]
+    fn serves_tree_paths() {
+        let tree = MerkleTree::new(b"asdfjkln12345678zxcvqwer09876543", 8);
+        let bytes = tree.to_proof_store();
+        let store = ProofStore::from_bytes(&bytes).unwrap();
+        assert_eq!((store.leaves(), store.root()), (8, tree.root()));
+        for idx in 0..8 {
+            assert_eq!(store.path(idx), tree.path(idx).ok());
+        }
+        assert!(verify_proof(b"jkln", store.root(), &store.path(1).unwrap()).is_valid());
+        assert!(store.path(8).is_none());
+        assert!(store.leaf(8).is_none());
+
+        let single = MerkleTree::new(b"solo", 1).to_proof_store();
+        assert_eq!(
+            ProofStore::from_bytes(&single).unwrap().path(0),
+            Some(vec![])
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_stores() {
+        let bytes = MerkleTree::new(b"asdfjkln12345678", 4).to_proof_store();
+        assert!(ProofStore::from_bytes(&bytes[..bytes.len() - 1]).is_none());
+        let mut bad = bytes.clone();
+        bad[0] = 0;
+        assert!(ProofStore::from_bytes(&bad).is_none());
+        let mut bad = bytes.clone();
+        bad[1] = 3;
+        assert!(ProofStore::from_bytes(&bad).is_none());
+        let mut bad = bytes;
+        bad[8] = 5;
+        assert!(ProofStore::from_bytes(&bad).is_none());
+    }
+
+    #[test]
+    fn carries_extensions() {
+        let tree = MerkleTree::new(b"asdfjkln12345678", 4);
+        let bytes = tree
+            .to_proof_store_with(&[(1, b"producer"), (2, b"")])
+            .unwrap();
+        let store = ProofStore::from_bytes(&bytes).unwrap();
+        assert_eq!(store.root(), tree.root());
+        assert_eq!(store.extension(1), Some(&b"producer"[..]));
+        assert_eq!(store.extension(3), None);
+
+        let critical = tree
+            .to_proof_store_with(&[(ext::CRITICAL | 1, b"")])
+            .unwrap();
+        assert!(ProofStore::from_bytes(&critical).is_none());
+    }
+
+    #[test]
+    #[cfg(feature = "borsh")]
+    fn views_borsh_exports() {
+        let tree = MerkleTree::new(b"asdfjkln12345678zxcvqwer09876543", 8);
+        let bytes = borsh::to_vec(&tree).unwrap();
+        let view = MerkleTreeView::open(&bytes).unwrap();
+        assert_eq!((view.leaves(), view.root()), (8, tree.root()));
+        for idx in 0..8 {
+            assert_eq!(view.path(idx), tree.path(idx).ok());
+        }
+        assert!(MerkleTreeView::open(&bytes[..100]).is_none());
+
+        let items = ["a", "b", "c", "d", "e"];
+        let mut tree = MerkleTree::from_leaves_padded(items, Padding::Zero).unwrap();
+        assert!(tree.set_label(2, "c"));
+        let bytes = borsh::to_vec(&tree).unwrap();
+        let view = MerkleTreeView::open(&bytes).unwrap();
+        assert_eq!(view.root(), tree.root());
+        for idx in 0..5 {
+            assert_eq!(view.path(idx), tree.path(idx).ok());
+        }
+    }
+
+    #[test]
+    fn reloads_snapshots() {
+        let items = ["a", "b", "c", "d", "e"];
+        let tree = MerkleTree::from_leaves_padded(items, Padding::Zero).unwrap();
+        let bytes = tree.snapshot();
+        let loaded: MerkleTree = MerkleTree::from_snapshot(&bytes).unwrap();
+        assert_eq!(
+            (loaded.root(), loaded.padding()),
+            (tree.root(), Padding::Zero)
+        );
+        for idx in 0..5 {
+            assert_eq!(loaded.path(idx), tree.path(idx));
+        }
+        assert_eq!(loaded.leaf_by_id(4), Some(4));
+        // A snapshot is also a store, served in place without copying.
+        let store = ProofStore::from_bytes(&bytes).unwrap();
+        assert_eq!(store.path(3), tree.path(3).ok());
+
+        let mut corrupt = bytes.clone();
+        corrupt[HEADER + 40] ^= 1;
+        assert!(MerkleTree::<sha2::Sha256>::from_snapshot(&corrupt).is_none());
+        assert!(MerkleTree::<sha2::Sha256>::from_snapshot(&bytes[..bytes.len() - 1]).is_none());
+        assert!(MerkleTree::<sha2::Sha256>::from_snapshot(&tree.to_proof_store()).is_none());
+        assert!(MerkleTree::<sha2::Sha512Trunc256>::from_snapshot(&bytes).is_none());
+
+        let path = std::env::temp_dir().join(format!("merkle-snapshot-{}", std::process::id()));
+        tree.save(&path).unwrap();
+        let loaded: MerkleTree = MerkleTree::load(&path).unwrap();
+        assert_eq!(loaded.root(), tree.root());
+        std::fs::write(&path, &corrupt).unwrap();
+        let err = MerkleTree::<sha2::Sha256>::load(&path).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn serves_odd_trees() {
+        for &padding in [Padding::Promote, Padding::DuplicateLast, Padding::Zero].iter() {
+            let tree =
+                MerkleTree::from_leaves_padded(["a", "b", "c", "d", "e", "f"], padding).unwrap();
+            let bytes = tree.to_proof_store();
+            let store = ProofStore::from_bytes(&bytes).unwrap();
+            assert_eq!((store.leaves(), store.root()), (6, tree.root()));
+            for idx in 0..6 {
+                assert_eq!(store.path(idx), tree.path(idx).ok());
+            }
+        }
+    }
+}
@@ -0,0 +1,325 @@
+//! Roots of inputs too large to hold in memory. [`MerkleTreeBuilder`] hashes
+//! fixed-size chunks as bytes arrive and keeps, per level, only the node
+//! still waiting for its sibling, so memory is one chunk plus one hash per
+//! level. The result is the head of the tree `from_leaves_padded` would
+//! build over the same chunks, the last of which may be short.
+//!
+//! Between chunks the builder's state is its [`Frontier`], the right edge
+//! of the tree so far: the root of each perfect subtree the leaves split
+//! into, one hash per set bit of the leaf count. A frontier hands a build
+//! off to another appender with `frontier` and `from_frontier`, and closes
+//! off to the same head the builder would, so it can be checked against a
+//! published root.
+
+use crate::format::{Version, CURRENT};
+use crate::{MerkleHasher, Padding, TreeHead};
+use sha2::Sha256;
+use std::convert::TryInto;
+use std::io::{self, Read, Write};
+use std::marker::PhantomData;
+
+pub struct MerkleTreeBuilder<H: MerkleHasher = Sha256> {
+    chunk_size: usize,
+    padding: Padding,
+    chunk: Vec<u8>,
+    leaves: u64,
+    /// `frontier[h]` is the last node of level `h` while it has no sibling.
+    frontier: Vec<Option<[u8; 32]>>,
+    ctx: H::Context,
+    hasher: PhantomData<H>,
+}
+
+impl MerkleTreeBuilder {
+    pub fn new(chunk_size: usize) -> Self {
+        MerkleTreeBuilder::build(chunk_size, Padding::default())
+    }
+
+    /// Head of the tree over `reader`'s bytes in `chunk_size` chunks, or
+    /// `None` if it is empty.
+    pub fn from_reader<R: Read>(mut reader: R, chunk_size: usize) -> io::Result<Option<TreeHead>> {
+        let mut builder = MerkleTreeBuilder::new(chunk_size);
+        io::copy(&mut reader, &mut builder)?;
+        Ok(builder.finalize())
+    }
+}
+
+impl<H: MerkleHasher> MerkleTreeBuilder<H> {
+    /// Like `new`, hashing with `H` and closing odd levels with `padding`.
+    pub fn build(chunk_size: usize, padding: Padding) -> Self {
+        assert!(chunk_size > 0, "chunks must not be empty");
+        MerkleTreeBuilder {
+            chunk_size,
+            padding,
+            chunk: Vec::with_capacity(chunk_size),
+            leaves: 0,
+            frontier: Vec::new(),
+            ctx: H::Context::default(),
+            hasher: PhantomData,
+        }
+    }
+
+    /// Chunks hashed so far.
+    pub fn leaves(&self) -> u64 {
+        self.leaves
+    }
+
+    pub fn write(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            let take = (self.chunk_size - self.chunk.len()).min(data.len());
+            self.chunk.extend_from_slice(&data[..take]);
+            data = &data[take..];
+            if self.chunk.len() == self.chunk_size {
+                self.push_chunk();
+            }
+        }
+    }
+
+    fn push_chunk(&mut self) {
+        let mut node = H::hash_data_with(&mut self.ctx, &self.chunk);
+        self.chunk.clear();
+        self.leaves += 1;
+        for slot in self.frontier.iter_mut() {
+            match slot.take() {
+                Some(even) => node = H::hash_pair_with(&mut self.ctx, &node, &even),
+                None => {
+                    *slot = Some(node);
+                    return;
+                }
+            }
+        }
+        self.frontier.push(Some(node));
+    }
+
+    /// Hashes the short last chunk, if any, and closes off the tree.
+    /// Returns `None` if nothing was written.
+    pub fn finalize(mut self) -> Option<TreeHead> {
+        if !self.chunk.is_empty() {
+            self.push_chunk();
+        }
+        let root = close::<H>(&mut self.ctx, self.padding, self.leaves, &self.frontier)?;
+        Some(TreeHead {
+            size: self.leaves,
+            root,
+        })
+    }
+
+    /// The tree so far, or `None` while part of a chunk is buffered, as the
+    /// frontier only holds whole leaves.
+    pub fn frontier(&self) -> Option<Frontier> {
+        if !self.chunk.is_empty() {
+            return None;
+        }
+        Some(Frontier {
+            leaves: self.leaves,
+            nodes: self.frontier.iter().flatten().copied().collect(),
+        })
+    }
+
+    /// A builder that continues from `frontier`, as the builder it came
+    /// from would. The chunk size and padding are not part of the frontier
+    /// and must be the ones it was built with.
+    pub fn from_frontier(frontier: &Frontier, chunk_size: usize, padding: Padding) -> Self {
+        let mut builder = MerkleTreeBuilder::build(chunk_size, padding);
+        builder.leaves = frontier.leaves;
+        builder.frontier = frontier.slots();
+        builder
+    }
+}
+
+/// Root of a tree of `leaves` leaves from its frontier slots, lowest level
+/// first.
+fn close<H: MerkleHasher>(
+    ctx: &mut H::Context,
+    padding: Padding,
+    leaves: u64,
+    frontier: &[Option<[u8; 32]>],
+) -> Option<[u8; 32]> {
+    let mut len = leaves;
+    let mut carry: Option<[u8; 32]> = None;
+    for waiting in frontier {
+        if len == 1 {
+            carry = carry.or(*waiting);
+            break;
+        }
+        // The last nodes of this level in offset order: the one waiting
+        // for a sibling and the one closed off from below.
+        carry = match (*waiting, carry) {
+            (Some(even), Some(odd)) => Some(H::hash_pair_with(ctx, &odd, &even)),
+            (Some(lone), None) | (None, Some(lone)) => Some(padding.parent::<H>(ctx, &lone)),
+            (None, None) => None,
+        };
+        len = len.div_ceil(2);
+    }
+    carry
+}
+
+/// The right edge of a tree being appended to: for each set bit `h` of
+/// the leaf count, lowest first, the root of the perfect subtree of `2^h`
+/// leaves that bit stands for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frontier {
+    leaves: u64,
+    nodes: Vec<[u8; 32]>,
+}
+
+impl Frontier {
+    /// Returns `None` unless there is one node per set bit of `leaves`.
+    pub fn new(leaves: u64, nodes: Vec<[u8; 32]>) -> Option<Self> {
+        if nodes.len() != leaves.count_ones() as usize {
+            return None;
+        }
+        Some(Frontier { leaves, nodes })
+    }
+
+    pub fn leaves(&self) -> u64 {
+        self.leaves
+    }
+
+    pub fn nodes(&self) -> &[[u8; 32]] {
+        &self.nodes
+    }
+
+    fn slots(&self) -> Vec<Option<[u8; 32]>> {
+        let levels = 64 - self.leaves.leading_zeros() as usize;
+        let mut nodes = self.nodes.iter().copied();
+        (0..levels)
+            .map(|h| {
+                if self.leaves >> h & 1 == 1 {
+                    nodes.next()
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Head of the tree the frontier closes off to, or `None` if it is
+    /// empty.
+    pub fn head(&self) -> Option<TreeHead> {
+        self.head_with::<Sha256>(Padding::default())
+    }
+
+    pub fn head_with<H: MerkleHasher>(&self, padding: Padding) -> Option<TreeHead> {
+        let mut ctx = H::Context::default();
+        let root = close::<H>(&mut ctx, padding, self.leaves, &self.slots())?;
+        Some(TreeHead {
+            size: self.leaves,
+            root,
+        })
+    }
+
+    /// Checks that the frontier is that of the tree `head` commits to.
+    pub fn verify(&self, head: &TreeHead) -> bool {
+        self.verify_with::<Sha256>(head, Padding::default())
+    }
+
+    pub fn verify_with<H: MerkleHasher>(&self, head: &TreeHead, padding: Padding) -> bool {
+        self.head_with::<H>(padding).as_ref() == Some(head)
+    }
+
+    /// Format version (1 byte), leaf count (u64 LE), then the nodes.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(9 + 32 * self.nodes.len());
+        out.push(CURRENT.byte());
+        out.extend_from_slice(&self.leaves.to_le_bytes());
+        for node in &self.nodes {
+            out.extend_from_slice(node);
+        }
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 9 || Version::from_byte(bytes[0])? != CURRENT {
+            return None;
+        }
+        let leaves = u64::from_le_bytes(bytes[1..9].try_into().unwrap());
+        let rest = &bytes[9..];
+        if rest.len() & 31 != 0 {
+            return None;
+        }
+        let nodes = rest
+            .chunks(32)
+            .map(|node| node.try_into().unwrap())
+            .collect();
+        Frontier::new(leaves, nodes)
+    }
+}
+
+impl<H: MerkleHasher> Write for MerkleTreeBuilder<H> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        MerkleTreeBuilder::write(self, buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MerkleTree;
+
+    #[test]
+    fn matches_in_memory_build() {
+        let data: Vec<u8> = (0..200u8).collect();
+        for &padding in [Padding::Promote, Padding::DuplicateLast, Padding::Zero].iter() {
+            for len in [1, 7, 8, 9, 63, 64, 65, 200] {
+                let expected = MerkleTree::from_leaves_padded(data[..len].chunks(8), padding)
+                    .unwrap()
+                    .head();
+                let mut builder: MerkleTreeBuilder = MerkleTreeBuilder::build(8, padding);
+                for piece in data[..len].chunks(5) {
+                    builder.write(piece);
+                }
+                assert_eq!(builder.finalize(), Some(expected));
+            }
+        }
+
+        let head = MerkleTreeBuilder::from_reader(&data[..], 16).unwrap();
+        assert_eq!(
+            head,
+            Some(MerkleTree::from_leaves(data.chunks(16)).unwrap().head())
+        );
+        assert_eq!(MerkleTreeBuilder::from_reader(&[][..], 16).unwrap(), None);
+    }
+
+    #[test]
+    fn hands_off_frontiers() {
+        let data: Vec<u8> = (0..200u8).collect();
+        for &padding in [Padding::Promote, Padding::DuplicateLast, Padding::Zero].iter() {
+            for split in [1, 7, 8, 13, 16] {
+                let expected = MerkleTree::from_leaves_padded(data.chunks(8), padding)
+                    .unwrap()
+                    .head();
+                let mut first: MerkleTreeBuilder = MerkleTreeBuilder::build(8, padding);
+                first.write(&data[..8 * split - 3]);
+                assert!(first.frontier().is_none());
+                first.write(&data[8 * split - 3..8 * split]);
+                let frontier = first.frontier().unwrap();
+                assert_eq!(frontier.nodes().len(), split.count_ones() as usize);
+
+                let so_far = MerkleTree::from_leaves_padded(data[..8 * split].chunks(8), padding)
+                    .unwrap()
+                    .head();
+                assert!(frontier.verify_with::<Sha256>(&so_far, padding));
+                assert!(!frontier.verify_with::<Sha256>(&expected, padding));
+
+                let bytes = frontier.to_bytes();
+                let received = Frontier::from_bytes(&bytes).unwrap();
+                let mut second: MerkleTreeBuilder =
+                    MerkleTreeBuilder::from_frontier(&received, 8, padding);
+                second.write(&data[8 * split..]);
+                assert_eq!(second.finalize(), Some(expected));
+            }
+        }
+
+        let frontier = MerkleTreeBuilder::new(8).frontier().unwrap();
+        assert_eq!(frontier.head(), None);
+        assert!(Frontier::new(3, vec![[0; 32]]).is_none());
+        let bytes = Frontier::new(3, vec![[1; 32], [2; 32]]).unwrap().to_bytes();
+        assert!(Frontier::from_bytes(&bytes[..bytes.len() - 32]).is_none());
+        assert!(Frontier::from_bytes(&bytes[..bytes.len() - 1]).is_none());
+    }
+}
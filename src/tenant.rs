@@ -0,0 +1,264 @@
+//! Per-tenant trees behind one registry root. Each tenant publishes its own
+//! tree, subject to a leaf quota; the registry commits to every tenant's
+//! current head in a tree ordered by tenant id.
+
+use crate::{hash_pair, verify_proof, MerkleTree, Padding, Path, TreeHead};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuotaExceeded {
+    pub limit: usize,
+    pub requested: usize,
+}
+
+/// Proof that `head` is the registered head of `tenant`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TenantProof {
+    pub tenant: Vec<u8>,
+    pub head: TreeHead,
+    pub path: Path,
+}
+
+/// Registry leaf: `H(len_le || tenant || size_le || root)`.
+fn entry(tenant: &[u8], head: &TreeHead) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update((tenant.len() as u64).to_le_bytes());
+    hasher.update(tenant);
+    hasher.update(head.size.to_le_bytes());
+    hasher.update(head.root);
+    hasher.finalize().into()
+}
+
+impl TenantProof {
+    pub fn verify(&self, registry_root: [u8; 32]) -> bool {
+        let mut candidate = entry(&self.tenant, &self.head);
+        for (hash, odd) in &self.path {
+            candidate = if *odd {
+                hash_pair(&candidate, hash)
+            } else {
+                hash_pair(hash, &candidate)
+            };
+        }
+        candidate == registry_root
+    }
+
+    /// Checks both the registration and an item's inclusion in the tenant's
+    /// tree.
+    pub fn verify_item(
+        &self,
+        item: &[u8],
+        path: &[([u8; 32], bool)],
+        registry_root: [u8; 32],
+    ) -> bool {
+        self.verify(registry_root) && verify_proof(item, self.head.root, path).is_valid()
+    }
+}
+
+struct Tenant {
+    quota: usize,
+    tree: Option<MerkleTree>,
+}
+
+pub struct TenantRegistry {
+    default_quota: usize,
+    max_tenants: usize,
+    tenants: BTreeMap<Vec<u8>, Tenant>,
+    /// Registry tree over the tenants' entries, rebuilt after changes.
+    index: Option<MerkleTree>,
+}
+
+impl TenantRegistry {
+    /// `default_quota` caps the leaves of a new tenant's tree, and
+    /// `max_tenants` the number of tenants.
+    pub fn new(default_quota: usize, max_tenants: usize) -> Self {
+        TenantRegistry {
+            default_quota,
+            max_tenants,
+            tenants: BTreeMap::new(),
+            index: None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.tenants.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tenants.is_empty()
+    }
+
+    fn tenant(&mut self, tenant: &[u8]) -> Result<&mut Tenant, QuotaExceeded> {
+        if !self.tenants.contains_key(tenant) && self.tenants.len() >= self.max_tenants {
+            return Err(QuotaExceeded {
+                limit: self.max_tenants,
+                requested: self.tenants.len() + 1,
+            });
+        }
+        let quota = self.default_quota;
+        Ok(self
+            .tenants
+            .entry(tenant.to_vec())
+            .or_insert(Tenant { quota, tree: None }))
+    }
+
+    /// Sets a tenant's leaf quota. Fails if its current tree is already
+    /// larger.
+    pub fn set_quota(&mut self, tenant: &[u8], quota: usize) -> Result<(), QuotaExceeded> {
+        let tenant = self.tenant(tenant)?;
+        let leaves = tenant.tree.as_ref().map_or(0, MerkleTree::leaves);
+        if leaves > quota {
+            return Err(QuotaExceeded {
+                limit: quota,
+                requested: leaves,
+            });
+        }
+        tenant.quota = quota;
+        Ok(())
+    }
+
+    pub fn quota(&self, tenant: &[u8]) -> usize {
+        self.tenants
+            .get(tenant)
+            .map_or(self.default_quota, |tenant| tenant.quota)
+    }
+
+    /// Replaces a tenant's tree and returns its new head. A rejected tree
+    /// leaves the registry unchanged.
+    pub fn publish(&mut self, tenant: &[u8], tree: MerkleTree) -> Result<TreeHead, QuotaExceeded> {
+        let quota = self.quota(tenant);
+        if tree.leaves() > quota {
+            return Err(QuotaExceeded {
+                limit: quota,
+                requested: tree.leaves(),
+            });
+        }
+        let entry = self.tenant(tenant)?;
+        let head = tree.head();
+        entry.tree = Some(tree);
+        self.index = None;
+        Ok(head)
+    }
+
+    pub fn remove(&mut self, tenant: &[u8]) -> Option<MerkleTree> {
+        let removed = self.tenants.remove(tenant)?;
+        self.index = None;
+        removed.tree
+    }
+
+    pub fn tree(&self, tenant: &[u8]) -> Option<&MerkleTree> {
+        self.tenants.get(tenant)?.tree.as_ref()
+    }
+
+    pub fn head(&self, tenant: &[u8]) -> Option<TreeHead> {
+        self.tree(tenant).map(MerkleTree::head)
+    }
+
+    /// Tenants with a published tree, in id order.
+    fn heads(&self) -> impl Iterator<Item = (&Vec<u8>, TreeHead)> {
+        self.tenants
+            .iter()
+            .filter_map(|(id, tenant)| Some((id, tenant.tree.as_ref()?.head())))
+    }
+
+    /// Rebuilds the registry tree if tenants changed since the last call.
+    fn index(&mut self) -> Option<&MerkleTree> {
+        if self.index.is_none() {
+            let entries: Vec<[u8; 32]> = self.heads().map(|(id, head)| entry(id, &head)).collect();
+            if entries.is_empty() {
+                return None;
+            }
+            self.index = Some(MerkleTree::from_leaf_hashes(entries, Padding::default()));
+        }
+        self.index.as_ref()
+    }
+
+    /// Root over every tenant's current head, or `None` before any tenant
+    /// has published.
+    pub fn root(&mut self) -> Option<[u8; 32]> {
+        self.index().map(MerkleTree::root)
+    }
+
+    pub fn prove(&mut self, tenant: &[u8]) -> Option<TenantProof> {
+        let position = self.heads().position(|(id, _)| id.as_slice() == tenant)?;
+        let head = self.head(tenant)?;
+        let path = self.index()?.path(position).ok()?;
+        Some(TenantProof {
+            tenant: tenant.to_vec(),
+            head,
+            path,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn enforces_quotas() {
+        let mut registry = TenantRegistry::new(4, 2);
+        let big = MerkleTree::new(b"asdfasdfasdfasdf", 8);
+        assert_eq!(
+            registry.publish(b"acme", big),
+            Err(QuotaExceeded {
+                limit: 4,
+                requested: 8
+            })
+        );
+        // Rejected publishes do not take up tenant slots.
+        for tenant in [&b"hooli"[..], b"umbrella", b"wonka"] {
+            let big = MerkleTree::new(b"asdfasdfasdfasdf", 8);
+            assert!(registry.publish(tenant, big).is_err());
+        }
+        assert_eq!(registry.len(), 0);
+        registry.set_quota(b"acme", 8).unwrap();
+        assert!(registry
+            .publish(b"acme", MerkleTree::new(b"asdfasdfasdfasdf", 8))
+            .is_ok());
+        assert!(registry.set_quota(b"acme", 4).is_err());
+
+        registry
+            .publish(b"globex", MerkleTree::new(b"abcd", 4))
+            .unwrap();
+        assert_eq!(
+            registry.publish(b"initech", MerkleTree::new(b"ab", 2)),
+            Err(QuotaExceeded {
+                limit: 2,
+                requested: 3
+            })
+        );
+    }
+
+    #[test]
+    fn proves_tenant_heads() {
+        let mut registry = TenantRegistry::new(16, 16);
+        assert_eq!(registry.root(), None);
+        registry
+            .publish(b"acme", MerkleTree::new(b"abcd", 4))
+            .unwrap();
+        registry
+            .publish(b"globex", MerkleTree::new(b"wxyz", 4))
+            .unwrap();
+        registry
+            .publish(b"initech", MerkleTree::new(b"12", 2))
+            .unwrap();
+        let root = registry.root().unwrap();
+
+        let proof = registry.prove(b"globex").unwrap();
+        let path = registry.tree(b"globex").unwrap().path(2).unwrap();
+        assert!(proof.verify(root));
+        assert!(proof.verify_item(b"y", &path, root));
+        assert!(!proof.verify_item(b"x", &path, root));
+
+        let mut forged = proof.clone();
+        forged.tenant = b"acme".to_vec();
+        assert!(!forged.verify(root));
+
+        registry
+            .publish(b"globex", MerkleTree::new(b"wxyq", 4))
+            .unwrap();
+        assert!(!proof.verify(registry.root().unwrap()));
+        assert!(registry.prove(b"hooli").is_none());
+    }
+}
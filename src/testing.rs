@@ -0,0 +1,140 @@
+//! Deliberately broken proofs, for testing how an application handles
+//! proofs that fail. [`FaultInjector`] corrupts valid proofs in a chosen
+//! way, with the position and bits picked by draws from its seed, so the
+//! same seed gives the same corruptions on every run. Each draw is
+//! `H("merkle-fault" || seed_le || counter_le)`.
+//!
+//! A corrupted proof fails to verify except in one case: swapping the
+//! direction of an entry whose sibling equals the node it is paired with,
+//! as under `Padding::DuplicateLast`, leaves the hash unchanged.
+
+use crate::{Path, Proof};
+use alloc::vec::Vec;
+use core::convert::TryInto;
+use sha2::{Digest, Sha256};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Corruption {
+    /// Flips one bit of one sibling hash.
+    FlipSibling,
+    /// Inverts one entry's direction bit.
+    SwapDirection,
+    /// Drops one or more entries from the root end of the path.
+    Truncate,
+}
+
+impl Corruption {
+    pub const ALL: [Corruption; 3] = [
+        Corruption::FlipSibling,
+        Corruption::SwapDirection,
+        Corruption::Truncate,
+    ];
+}
+
+pub struct FaultInjector {
+    seed: u64,
+    counter: u64,
+}
+
+impl FaultInjector {
+    pub fn new(seed: u64) -> Self {
+        FaultInjector { seed, counter: 0 }
+    }
+
+    /// A draw in `0..n`, which must not be zero.
+    fn draw(&mut self, n: usize) -> usize {
+        let mut hasher = Sha256::new();
+        hasher.update(b"merkle-fault");
+        hasher.update(self.seed.to_le_bytes());
+        hasher.update(self.counter.to_le_bytes());
+        self.counter += 1;
+        let digest: [u8; 32] = hasher.finalize().into();
+        (u64::from_le_bytes(digest[..8].try_into().unwrap()) % n as u64) as usize
+    }
+
+    /// A copy of `path` with `corruption` applied, or `None` if the path is
+    /// empty and so has nothing to corrupt.
+    pub fn corrupt_path(
+        &mut self,
+        path: &[([u8; 32], bool)],
+        corruption: Corruption,
+    ) -> Option<Path> {
+        if path.is_empty() {
+            return None;
+        }
+        let mut path: Vec<([u8; 32], bool)> = path.to_vec();
+        match corruption {
+            Corruption::FlipSibling => {
+                let entry = self.draw(path.len());
+                let bit = self.draw(256);
+                path[entry].0[bit / 8] ^= 1 << (bit % 8);
+            }
+            Corruption::SwapDirection => {
+                let entry = self.draw(path.len());
+                path[entry].1 = !path[entry].1;
+            }
+            Corruption::Truncate => {
+                let keep = self.draw(path.len());
+                path.truncate(keep);
+            }
+        }
+        Some(path)
+    }
+
+    /// Like `corrupt_path`, keeping the proof's index.
+    pub fn corrupt(&mut self, proof: &Proof, corruption: Corruption) -> Option<Proof> {
+        let path = self.corrupt_path(proof.path(), corruption)?;
+        Some(Proof::new(proof.index(), path))
+    }
+
+    /// Applies a corruption drawn from the seed, returning which.
+    pub fn corrupt_any(&mut self, proof: &Proof) -> Option<(Corruption, Proof)> {
+        let corruption = Corruption::ALL[self.draw(Corruption::ALL.len())];
+        Some((corruption, self.corrupt(proof, corruption)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{verify_proof, MerkleTree};
+
+    #[test]
+    fn corrupted_proofs_fail() {
+        let data = b"asdfjkln12345678zxcvqwer09876543";
+        let tree = MerkleTree::new(data, 8);
+        let mut faults = FaultInjector::new(7);
+        for idx in 0..8 {
+            let item = &data[4 * idx..4 * idx + 4];
+            let proof = tree.leaf_proof(idx).unwrap();
+            for &corruption in Corruption::ALL.iter() {
+                let bad = faults.corrupt(&proof, corruption).unwrap();
+                assert_eq!(bad.index(), proof.index());
+                assert!(!bad.verify(item, &tree.head()).is_valid());
+            }
+            let (_, bad) = faults.corrupt_any(&proof).unwrap();
+            assert!(!bad.verify(item, &tree.head()).is_valid());
+        }
+
+        let truncated = faults
+            .corrupt_path(&tree.path(3).unwrap(), Corruption::Truncate)
+            .unwrap();
+        assert!(truncated.len() < 3);
+        assert!(!verify_proof(b"qwer", tree.root(), &truncated).is_valid());
+        assert!(faults.corrupt_path(&[], Corruption::FlipSibling).is_none());
+    }
+
+    #[test]
+    fn seeded() {
+        let tree = MerkleTree::new(b"asdfjkln12345678zxcvqwer09876543", 8);
+        let proof = tree.leaf_proof(5).unwrap();
+        let run = |seed| {
+            let mut faults = FaultInjector::new(seed);
+            (0..4)
+                .map(|_| faults.corrupt_any(&proof).unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(run(1), run(1));
+        assert_ne!(run(1), run(2));
+    }
+}
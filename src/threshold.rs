@@ -0,0 +1,370 @@
+//! Experimental: trees with escrowed subtrees. Selected node hashes are
+//! withheld from the published tree, along with everything below them, and
+//! secret-shared among escrow parties. The root and every other node stay
+//! public, so leaves whose paths avoid the sealed nodes prove as usual, while
+//! a path that needs a sealed sibling can only be assembled once `threshold`
+//! parties hand over their shares.
+//!
+//! Leaves inside a sealed subtree cannot be proven from the published tree
+//! at all; unsealing only reveals the subtree's root hash.
+//!
+//! The sharing scheme is pluggable through [`SecretSharing`]; [`Shamir`] is
+//! byte-wise Shamir sharing over GF(256).
+
+use crate::memory::{btree_bytes, vec_bytes, HeapSize};
+use crate::{hash_pair, Error, Layout, MerkleTree, Padding, Path};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// One party's share of a secret. `x` is the party's evaluation point and is
+/// never zero.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Share {
+    pub x: u8,
+    pub y: [u8; 32],
+}
+
+pub trait SecretSharing {
+    /// Splits `secret` into `parties` shares, any `threshold` of which
+    /// recover it. Called with `1 <= threshold <= parties`.
+    fn split(&mut self, secret: &[u8; 32], threshold: u8, parties: u8) -> Vec<Share>;
+
+    /// Recovers a secret, or returns `None` if the shares are malformed.
+    /// Too few shares may yield a wrong secret rather than `None`.
+    fn combine(&self, shares: &[Share]) -> Option<[u8; 32]>;
+}
+
+/// Shamir sharing of each byte over GF(256), with party `i` at `x = i + 1`.
+/// Polynomial coefficients are drawn from `H(seed || counter)`, so the seed
+/// must be secret, random and never reused.
+pub struct Shamir {
+    seed: [u8; 32],
+    counter: u64,
+}
+
+impl Shamir {
+    pub fn new(seed: [u8; 32]) -> Self {
+        Shamir { seed, counter: 0 }
+    }
+
+    fn coefficients(&mut self) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update(self.seed);
+        hasher.update(self.counter.to_le_bytes());
+        self.counter += 1;
+        hasher.finalize().into()
+    }
+}
+
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0;
+    while b != 0 {
+        if b & 1 == 1 {
+            product ^= a;
+        }
+        let carry = a & 0x80 != 0;
+        a <<= 1;
+        if carry {
+            a ^= 0x1b;
+        }
+        b >>= 1;
+    }
+    product
+}
+
+/// `a^254`, the multiplicative inverse of a non-zero `a`.
+fn gf_inv(a: u8) -> u8 {
+    let mut result = 1;
+    for _ in 0..254 {
+        result = gf_mul(result, a);
+    }
+    result
+}
+
+impl SecretSharing for Shamir {
+    fn split(&mut self, secret: &[u8; 32], threshold: u8, parties: u8) -> Vec<Share> {
+        let coefficients: Vec<[u8; 32]> = (1..threshold).map(|_| self.coefficients()).collect();
+        (1..=parties)
+            .map(|x| {
+                let mut y = *secret;
+                let mut power = 1;
+                for coefficient in &coefficients {
+                    power = gf_mul(power, x);
+                    for (byte, c) in y.iter_mut().zip(coefficient) {
+                        *byte ^= gf_mul(*c, power);
+                    }
+                }
+                Share { x, y }
+            })
+            .collect()
+    }
+
+    fn combine(&self, shares: &[Share]) -> Option<[u8; 32]> {
+        let points: BTreeSet<u8> = shares.iter().map(|share| share.x).collect();
+        if shares.is_empty() || points.len() != shares.len() || points.contains(&0) {
+            return None;
+        }
+        let mut secret = [0; 32];
+        for share in shares {
+            // Lagrange basis polynomial for this share, evaluated at zero.
+            let mut basis = 1;
+            for other in shares.iter().filter(|other| other.x != share.x) {
+                basis = gf_mul(basis, gf_mul(other.x, gf_inv(other.x ^ share.x)));
+            }
+            for (byte, y) in secret.iter_mut().zip(&share.y) {
+                *byte ^= gf_mul(*y, basis);
+            }
+        }
+        Some(secret)
+    }
+}
+
+/// What one escrow party holds: its share of each sealed node.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Escrow {
+    pub shares: BTreeMap<usize, Share>,
+}
+
+/// The published side of a tree with sealed subtrees.
+#[derive(Debug, Clone)]
+pub struct ThresholdTree {
+    /// `None` for sealed nodes and their descendants.
+    nodes: Vec<Option<[u8; 32]>>,
+    layout: Layout,
+    padding: Padding,
+    sealed: BTreeSet<usize>,
+    threshold: u8,
+}
+
+impl ThresholdTree {
+    /// Seals the subtrees under `sealed` and splits each sealed hash among
+    /// `parties` escrows. Returns `None` if the threshold is not in
+    /// `1..=parties`, or a sealed node is the root, out of range, or below
+    /// another sealed node.
+    pub fn new<S: SecretSharing>(
+        tree: &MerkleTree,
+        sealed: &[usize],
+        threshold: u8,
+        parties: u8,
+        backend: &mut S,
+    ) -> Option<(ThresholdTree, Vec<Escrow>)> {
+        let layout = tree.layout().clone();
+        let sealed: BTreeSet<usize> = sealed.iter().copied().collect();
+        if threshold == 0 || threshold > parties || sealed.iter().any(|&node| node >= layout.root())
+        {
+            return None;
+        }
+
+        // Parents come after their children, so walk down from the root.
+        let mut hidden = vec![false; layout.nodes()];
+        for node in (0..layout.nodes()).rev() {
+            let below_sealed = layout.parent(node).is_some_and(|parent| hidden[parent]);
+            if sealed.contains(&node) && below_sealed {
+                return None;
+            }
+            hidden[node] = below_sealed || sealed.contains(&node);
+        }
+
+        let mut escrows = vec![
+            Escrow {
+                shares: BTreeMap::new(),
+            };
+            parties as usize
+        ];
+        for &node in &sealed {
+            let shares = backend.split(&tree.nodes[node].value, threshold, parties);
+            for (escrow, share) in escrows.iter_mut().zip(shares) {
+                escrow.shares.insert(node, share);
+            }
+        }
+        let nodes = tree
+            .nodes
+            .iter()
+            .zip(&hidden)
+            .map(|(node, &hidden)| if hidden { None } else { Some(node.value) })
+            .collect();
+        let published = ThresholdTree {
+            nodes,
+            layout,
+            padding: tree.padding(),
+            sealed,
+            threshold,
+        };
+        Some((published, escrows))
+    }
+
+    pub fn root(&self) -> [u8; 32] {
+        self.nodes[self.layout.root()].expect("the root is never sealed")
+    }
+
+    pub fn leaves(&self) -> usize {
+        self.layout.leaves()
+    }
+
+    pub fn threshold(&self) -> u8 {
+        self.threshold
+    }
+
+    pub fn sealed(&self) -> impl Iterator<Item = usize> + '_ {
+        self.sealed.iter().copied()
+    }
+
+    /// Path for leaf `idx` from the published nodes alone. Fails with
+    /// `Sealed` naming the first sealed node the path needs.
+    pub fn path(&self, idx: usize) -> Result<Path, Error> {
+        self.walk(idx, |_| None)
+    }
+
+    /// Path for leaf `idx`, recovering sealed siblings from `escrows` with
+    /// `backend`. A recovered hash is only used if it rehashes to the
+    /// published parent, so too few or corrupted shares fail with `Sealed`.
+    pub fn unseal_path<S: SecretSharing>(
+        &self,
+        idx: usize,
+        escrows: &[&Escrow],
+        backend: &S,
+    ) -> Result<Path, Error> {
+        self.walk(idx, |node| {
+            let shares: Vec<Share> = escrows
+                .iter()
+                .filter_map(|escrow| escrow.shares.get(&node).copied())
+                .collect();
+            backend.combine(&shares)
+        })
+    }
+
+    fn walk<F>(&self, idx: usize, recover: F) -> Result<Path, Error>
+    where
+        F: Fn(usize) -> Option<[u8; 32]>,
+    {
+        if idx >= self.layout.nodes() {
+            return Err(Error::IndexOutOfRange {
+                index: idx,
+                size: self.layout.nodes(),
+            });
+        }
+        if idx >= self.leaves() {
+            return Err(Error::NotALeaf(idx));
+        }
+        let sealed_above = |mut node| {
+            while !self.sealed.contains(&node) {
+                node = self
+                    .layout
+                    .parent(node)
+                    .expect("hidden nodes are below a sealed one");
+            }
+            node
+        };
+        let mut candidate = self.nodes[idx].ok_or_else(|| Error::Sealed(sealed_above(idx)))?;
+        let mut path = Vec::new();
+        let mut cidx = idx;
+        while cidx != self.layout.root() {
+            let (_, offset) = self.layout.level_offset(cidx).expect("node is in range");
+            let odd = offset & 1 == 1;
+            let parent = self
+                .layout
+                .parent(cidx)
+                .expect("only the root has no parent");
+            let sibling = match self.layout.sibling(cidx) {
+                // The parent is public, so a hidden sibling is itself sealed.
+                Some(sibling) => Some(match self.nodes[sibling] {
+                    Some(hash) => hash,
+                    None => recover(sibling)
+                        .filter(|hash| {
+                            let rehashed = if odd {
+                                hash_pair(&candidate, hash)
+                            } else {
+                                hash_pair(hash, &candidate)
+                            };
+                            Some(rehashed) == self.nodes[parent]
+                        })
+                        .ok_or(Error::Sealed(sibling))?,
+                }),
+                None => self.padding.sibling(&candidate),
+            };
+            if let Some(sibling) = sibling {
+                path.push((sibling, odd));
+            }
+            candidate = self.nodes[parent].expect("ancestors of a public node are public");
+            cidx = parent;
+        }
+        Ok(path)
+    }
+}
+
+impl HeapSize for ThresholdTree {
+    fn heap_size_bytes(&self) -> usize {
+        vec_bytes(&self.nodes)
+            + self.layout.heap_size_bytes()
+            + btree_bytes::<usize, ()>(self.sealed.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::verify_proof;
+
+    #[test]
+    fn shamir_recovers_from_any_threshold() {
+        let mut shamir = Shamir::new([7; 32]);
+        let secret = crate::hash_data(b"secret");
+        let shares = shamir.split(&secret, 3, 5);
+        assert_eq!(shares.len(), 5);
+        for skip in 0..5 {
+            let subset: Vec<Share> = (0..5)
+                .filter(|&i| i != skip && i != (skip + 1) % 5)
+                .map(|i| shares[i])
+                .collect();
+            assert_eq!(shamir.combine(&subset), Some(secret));
+        }
+        assert_ne!(shamir.combine(&shares[..2]), Some(secret));
+        assert_eq!(shamir.combine(&[shares[0], shares[0]]), None);
+        assert_eq!(shamir.split(&secret, 1, 2)[1].y, secret);
+    }
+
+    #[test]
+    fn sealed_siblings_need_escrow() {
+        let tree = MerkleTree::new(b"asdfjkln12345678zxcvqwer09876543", 8);
+        let mut shamir = Shamir::new([1; 32]);
+        let (published, escrows) = ThresholdTree::new(&tree, &[9], 2, 3, &mut shamir).unwrap();
+        assert_eq!(published.root(), tree.root());
+        assert_eq!(escrows.len(), 3);
+
+        assert_eq!(published.path(5), tree.path(5));
+        assert_eq!(published.path(0), Err(Error::Sealed(9)));
+        assert_eq!(published.path(2), Err(Error::Sealed(9)));
+
+        let path = published
+            .unseal_path(0, &[&escrows[0], &escrows[2]], &shamir)
+            .unwrap();
+        assert_eq!(Ok(path.clone()), tree.path(0));
+        assert!(verify_proof(b"asdf", published.root(), &path).is_valid());
+        assert_eq!(
+            published.unseal_path(0, &[&escrows[1]], &shamir),
+            Err(Error::Sealed(9))
+        );
+        assert_eq!(
+            published.unseal_path(2, &[&escrows[0], &escrows[1]], &shamir),
+            Err(Error::Sealed(9))
+        );
+
+        let mut corrupted = escrows[1].clone();
+        corrupted.shares.get_mut(&9).unwrap().y[0] ^= 1;
+        assert_eq!(
+            published.unseal_path(0, &[&escrows[0], &corrupted], &shamir),
+            Err(Error::Sealed(9))
+        );
+    }
+
+    #[test]
+    fn rejects_bad_seals() {
+        let tree = MerkleTree::new(b"asdfjkln12345678", 4);
+        let mut shamir = Shamir::new([2; 32]);
+        assert!(ThresholdTree::new(&tree, &[6], 1, 1, &mut shamir).is_none());
+        assert!(ThresholdTree::new(&tree, &[4, 0], 1, 1, &mut shamir).is_none());
+        assert!(ThresholdTree::new(&tree, &[7], 1, 1, &mut shamir).is_none());
+        assert!(ThresholdTree::new(&tree, &[4], 3, 2, &mut shamir).is_none());
+        assert!(ThresholdTree::new(&tree, &[4], 0, 2, &mut shamir).is_none());
+    }
+}
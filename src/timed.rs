@@ -0,0 +1,158 @@
+//! Inclusion proofs that expire. A [`SignedHead`] is a tree head with the
+//! time it was issued, signed by the log, and a [`TimedProof`] carries one
+//! alongside a leaf's proof. Verification checks the signature and rejects
+//! heads older than the verifier's [`FreshnessPolicy`] allows.
+//!
+//! `TreeHead` itself is left unchanged, since head logs commit to its hash.
+//! Signing is pluggable through [`HeadSigner`] and [`HeadVerifier`]; the
+//! signed message is `H(head hash || issued_at_le)`, with `issued_at` in
+//! seconds since the Unix epoch.
+
+use crate::{hash_data, Proof, TreeHead};
+use std::time::Duration;
+
+pub trait HeadSigner {
+    fn sign(&self, message: &[u8; 32]) -> Vec<u8>;
+}
+
+pub trait HeadVerifier {
+    fn verify(&self, message: &[u8; 32], signature: &[u8]) -> bool;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignedHead {
+    pub head: TreeHead,
+    pub issued_at: u64,
+    pub signature: Vec<u8>,
+}
+
+impl SignedHead {
+    pub fn sign<S: HeadSigner>(head: TreeHead, issued_at: u64, signer: &S) -> Self {
+        let mut signed = SignedHead {
+            head,
+            issued_at,
+            signature: Vec::new(),
+        };
+        signed.signature = signer.sign(&signed.message());
+        signed
+    }
+
+    pub fn message(&self) -> [u8; 32] {
+        let mut bytes = [0u8; 40];
+        bytes[..32].copy_from_slice(&self.head.hash());
+        bytes[32..].copy_from_slice(&self.issued_at.to_le_bytes());
+        hash_data(&bytes)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FreshnessPolicy {
+    /// Oldest head accepted.
+    pub max_age: Duration,
+    /// How far ahead of the verifier's clock a head may be dated.
+    pub max_skew: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimedProofError {
+    BadSignature,
+    Stale,
+    FromFuture,
+    BadProof,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimedProof {
+    pub head: SignedHead,
+    pub proof: Proof,
+}
+
+impl TimedProof {
+    /// Checks the proof for `item` at `now`, in seconds since the Unix
+    /// epoch.
+    pub fn verify<V: HeadVerifier>(
+        &self,
+        item: &[u8],
+        verifier: &V,
+        policy: &FreshnessPolicy,
+        now: u64,
+    ) -> Result<(), TimedProofError> {
+        let head = &self.head;
+        if !verifier.verify(&head.message(), &head.signature) {
+            return Err(TimedProofError::BadSignature);
+        }
+        if head.issued_at > now.saturating_add(policy.max_skew.as_secs()) {
+            return Err(TimedProofError::FromFuture);
+        }
+        if now.saturating_sub(head.issued_at) > policy.max_age.as_secs() {
+            return Err(TimedProofError::Stale);
+        }
+        if !self.proof.verify(item, &head.head).is_valid() {
+            return Err(TimedProofError::BadProof);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MerkleTree;
+
+    /// Keyed hash standing in for a real signature scheme.
+    struct Keyed([u8; 32]);
+
+    impl HeadSigner for Keyed {
+        fn sign(&self, message: &[u8; 32]) -> Vec<u8> {
+            hash_data(&[self.0, *message].concat()).to_vec()
+        }
+    }
+
+    impl HeadVerifier for Keyed {
+        fn verify(&self, message: &[u8; 32], signature: &[u8]) -> bool {
+            self.sign(message) == signature
+        }
+    }
+
+    #[test]
+    fn enforces_head_age() {
+        let tree = MerkleTree::new(b"asdfjkln12345678", 4);
+        let key = Keyed([7; 32]);
+        let timed = TimedProof {
+            head: SignedHead::sign(tree.head(), 1_000, &key),
+            proof: tree.leaf_proof(1).unwrap(),
+        };
+        let policy = FreshnessPolicy {
+            max_age: Duration::from_secs(60),
+            max_skew: Duration::from_secs(5),
+        };
+
+        assert_eq!(timed.verify(b"jkln", &key, &policy, 1_000), Ok(()));
+        assert_eq!(timed.verify(b"jkln", &key, &policy, 1_060), Ok(()));
+        assert_eq!(timed.verify(b"jkln", &key, &policy, 995), Ok(()));
+        assert_eq!(
+            timed.verify(b"jkln", &key, &policy, 1_061),
+            Err(TimedProofError::Stale)
+        );
+        assert_eq!(
+            timed.verify(b"jkln", &key, &policy, 994),
+            Err(TimedProofError::FromFuture)
+        );
+        assert_eq!(
+            timed.verify(b"asdf", &key, &policy, 1_000),
+            Err(TimedProofError::BadProof)
+        );
+        assert_eq!(
+            timed.verify(b"jkln", &Keyed([8; 32]), &policy, 1_000),
+            Err(TimedProofError::BadSignature)
+        );
+
+        // Redating the head breaks the signature.
+        let mut redated = timed.clone();
+        redated.head.issued_at = 2_000;
+        assert_eq!(
+            redated.verify(b"jkln", &key, &policy, 2_000),
+            Err(TimedProofError::BadSignature)
+        );
+    }
+}
@@ -0,0 +1,155 @@
+//! Erasing leaves while keeping the tree verifiable. `erase` replaces a
+//! leaf's hash with a tombstone, `H("merkle-tombstone" || old leaf hash)`,
+//! so the tree no longer holds anything derived from the item but its old
+//! leaf hash, and that only inside the tombstone. The [`DeletionProof`] it
+//! returns shows that the leaf held the old value under the old root, that
+//! it holds the tombstone for that value under the new root, and, as both
+//! roots are reached through the same siblings, that no other leaf changed.
+//! The one sibling that may differ is a leaf's own duplicate under
+//! `Padding::DuplicateLast`, which the proof's shape identifies.
+
+use crate::{bounded, Error, MerkleHasher, MerkleTree, Padding, Path};
+use alloc::vec;
+use alloc::vec::Vec;
+use sha2::Sha256;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "borsh",
+    derive(borsh::BorshSerialize, borsh::BorshDeserialize)
+)]
+pub struct DeletionProof {
+    pub index: u64,
+    pub leaves: u64,
+    pub padding: Padding,
+    pub old_root: [u8; 32],
+    pub new_root: [u8; 32],
+    /// The erased leaf's hash, which the tombstone commits to.
+    pub old_leaf: [u8; 32],
+    /// Path of the leaf before the erasure.
+    pub path: Path,
+}
+
+/// The tombstone for a leaf that hashed to `old_leaf`.
+pub fn tombstone<H: MerkleHasher>(old_leaf: &[u8; 32]) -> [u8; 32] {
+    let mut bytes = Vec::with_capacity(16 + 32);
+    bytes.extend_from_slice(b"merkle-tombstone");
+    bytes.extend_from_slice(old_leaf);
+    H::hash_data(&bytes)
+}
+
+impl<H: MerkleHasher> MerkleTree<H> {
+    /// Replaces leaf `idx` with its tombstone and returns the proof of the
+    /// deletion. Erasing a tombstone again tombstones it anew.
+    pub fn erase(&mut self, idx: usize) -> Result<DeletionProof, Error> {
+        let path = self.path(idx)?;
+        let old_root = self.root();
+        let old_leaf = self.nodes[idx].value;
+        let tombstone = tombstone::<H>(&old_leaf);
+        let new_root = self.set_leaf_hashes(&mut H::Context::default(), vec![(idx, tombstone)]);
+        Ok(DeletionProof {
+            index: idx as u64,
+            leaves: self.leaves() as u64,
+            padding: self.padding(),
+            old_root,
+            new_root,
+            old_leaf,
+            path,
+        })
+    }
+}
+
+impl DeletionProof {
+    pub fn verify(&self) -> bool {
+        self.verify_with::<Sha256>()
+    }
+
+    /// Checks the old leaf under the old root and its tombstone under the
+    /// new root, through the same siblings.
+    pub fn verify_with<H: MerkleHasher>(&self) -> bool {
+        if self.index >= self.leaves || self.path.len() > bounded::MAX_DEPTH {
+            return false;
+        }
+        let mut ctx = H::Context::default();
+        let mut old = self.old_leaf;
+        let mut new = tombstone::<H>(&self.old_leaf);
+        let mut entries = self.path.iter();
+        let (mut len, mut offset) = (self.leaves, self.index);
+        while len > 1 {
+            let lone = offset ^ 1 >= len;
+            if !(lone && self.padding == Padding::Promote) {
+                let (sibling, odd) = match entries.next() {
+                    Some(entry) => entry,
+                    None => return false,
+                };
+                if *odd != (offset & 1 == 1) {
+                    return false;
+                }
+                // A duplicated node is its own sibling, before and after.
+                let duplicated = lone && self.padding == Padding::DuplicateLast;
+                if duplicated && *sibling != old {
+                    return false;
+                }
+                let (old_sibling, new_sibling) = if duplicated {
+                    (old, new)
+                } else {
+                    (*sibling, *sibling)
+                };
+                let pair = |ctx: &mut H::Context, node: &[u8; 32], sibling: &[u8; 32]| {
+                    if *odd {
+                        H::hash_pair_with(ctx, node, sibling)
+                    } else {
+                        H::hash_pair_with(ctx, sibling, node)
+                    }
+                };
+                old = pair(&mut ctx, &old, &old_sibling);
+                new = pair(&mut ctx, &new, &new_sibling);
+            }
+            len = len.div_ceil(2);
+            offset /= 2;
+        }
+        entries.next().is_none() && old == self.old_root && new == self.new_root
+    }
+
+    /// Like `verify`, also checking that the erased leaf held `item`.
+    pub fn verify_item(&self, item: &[u8]) -> bool {
+        Sha256::hash_data(item) == self.old_leaf && self.verify()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{verify_proof, Padding};
+
+    #[test]
+    fn proves_erasures() {
+        let items = ["alice", "bob", "carol", "dave", "erin"];
+        for &padding in [Padding::Promote, Padding::DuplicateLast, Padding::Zero].iter() {
+            let mut tree = MerkleTree::from_leaves_padded(items, padding).unwrap();
+            let before = tree.root();
+            let proof = tree.erase(4).unwrap();
+            assert_eq!((proof.old_root, proof.new_root), (before, tree.root()));
+            assert_ne!(before, tree.root());
+            assert!(proof.verify());
+            assert!(proof.verify_item(b"erin"));
+            assert!(!proof.verify_item(b"mallory"));
+            // Other leaves still verify under the new root.
+            let path = tree.path(1).unwrap();
+            assert!(verify_proof(b"bob", tree.root(), &path).is_valid());
+            assert_eq!(tree.node(4), Ok(tombstone::<Sha256>(&proof.old_leaf)));
+        }
+
+        let mut tree = MerkleTree::from_leaves(items).unwrap();
+        let mut proof = tree.erase(1).unwrap();
+        let mut wrong_shape = proof.clone();
+        wrong_shape.leaves = 2;
+        assert!(!wrong_shape.verify());
+        // Changing another leaf together with the erasure does not verify.
+        let mut other = MerkleTree::from_leaves(["alice", "bob", "CAROL", "dave", "erin"]).unwrap();
+        proof.new_root = other.erase(1).unwrap().new_root;
+        assert!(!proof.verify());
+        assert_eq!(tree.erase(5), Err(Error::NotALeaf(5)));
+    }
+}
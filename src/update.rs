@@ -0,0 +1,121 @@
+//! Changing leaves in place. Only the ancestors of a changed leaf are
+//! rehashed, and ancestors shared by several changed leaves once, so an
+//! update costs one hash per level rather than a rebuild.
+
+use crate::{Error, MerkleHasher, MerkleTree};
+use alloc::vec::Vec;
+
+impl<H: MerkleHasher> MerkleTree<H> {
+    /// Replaces leaf `idx` with `data` and returns the new root.
+    pub fn update(&mut self, idx: usize, data: &[u8]) -> Result<[u8; 32], Error> {
+        self.update_many([(idx, data)])
+    }
+
+    /// Applies every `(idx, data)` update, the last one winning where an
+    /// index repeats, and returns the new root. Nothing changes unless all
+    /// indices are leaves.
+    pub fn update_many<I, T>(&mut self, updates: I) -> Result<[u8; 32], Error>
+    where
+        I: IntoIterator<Item = (usize, T)>,
+        T: AsRef<[u8]>,
+    {
+        let updates: Vec<(usize, T)> = updates.into_iter().collect();
+        if let Some(&(idx, _)) = updates.iter().find(|(idx, _)| *idx >= self.leaves()) {
+            return Err(Error::NotALeaf(idx));
+        }
+        let mut ctx = H::Context::default();
+        let leaves = updates
+            .iter()
+            .map(|(idx, data)| (*idx, H::hash_data_with(&mut ctx, data.as_ref())))
+            .collect();
+        Ok(self.set_leaf_hashes(&mut ctx, leaves))
+    }
+
+    /// Sets each `(idx, leaf hash)`, the last one winning, and rehashes the
+    /// ancestors. Every index must be a leaf.
+    pub(crate) fn set_leaf_hashes(
+        &mut self,
+        ctx: &mut H::Context,
+        leaves: Vec<(usize, [u8; 32])>,
+    ) -> [u8; 32] {
+        let mut dirty: Vec<usize> = Vec::with_capacity(leaves.len());
+        for (idx, leaf) in leaves {
+            self.reposition(idx, self.nodes[idx].value, leaf);
+            self.nodes[idx].value = leaf;
+            dirty.push(idx);
+        }
+        dirty.sort_unstable();
+        dirty.dedup();
+
+        // Node indices grow level by level, so parents of sorted nodes come
+        // out sorted and shared ones are adjacent.
+        let root = self.layout.root();
+        while dirty.first().is_some_and(|&node| node != root) {
+            for node in dirty.iter_mut() {
+                *node = self
+                    .layout
+                    .parent(*node)
+                    .expect("only the root has no parent");
+            }
+            dirty.dedup();
+            for &node in &dirty {
+                let hash = match self.layout.children(node).expect("parents have children") {
+                    (even, Some(odd)) => {
+                        H::hash_pair_with(ctx, &self.nodes[odd].value, &self.nodes[even].value)
+                    }
+                    (lone, None) => self.padding.parent::<H>(ctx, &self.nodes[lone].value),
+                };
+                self.nodes[node].value = hash;
+            }
+        }
+        self.root()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{verify_proof, Error, MerkleTree, Padding};
+
+    #[test]
+    fn matches_rebuild() {
+        let mut items: Vec<Vec<u8>> = (0..7u8).map(|i| vec![i]).collect();
+        for &padding in [Padding::Promote, Padding::DuplicateLast, Padding::Zero].iter() {
+            let mut tree = MerkleTree::from_leaves_padded(&items, padding).unwrap();
+            items[6] = b"six".to_vec();
+            let root = tree.update(6, b"six").unwrap();
+            let rebuilt = MerkleTree::from_leaves_padded(&items, padding).unwrap();
+            assert_eq!((root, tree.root()), (rebuilt.root(), rebuilt.root()));
+
+            items[1] = b"one".to_vec();
+            items[2] = b"two".to_vec();
+            let root = tree
+                .update_many(vec![(2, &b"x"[..]), (1, b"one"), (2, b"two")])
+                .unwrap();
+            let rebuilt = MerkleTree::from_leaves_padded(&items, padding).unwrap();
+            assert_eq!(root, rebuilt.root());
+            for (idx, item) in items.iter().enumerate() {
+                assert_eq!(tree.path(idx), rebuilt.path(idx));
+                assert!(verify_proof(item, root, &tree.path(idx).unwrap()).is_valid());
+            }
+            items = (0..7u8).map(|i| vec![i]).collect();
+        }
+    }
+
+    #[test]
+    fn rejects_non_leaves_without_changes() {
+        let mut tree = MerkleTree::new(b"asdfjkln12345678", 4);
+        let root = tree.root();
+        assert_eq!(
+            tree.update_many(vec![(0, &b"new"[..]), (4, b"bad")]),
+            Err(Error::NotALeaf(4))
+        );
+        assert_eq!(tree.root(), root);
+        assert_eq!(tree.update(9, b"bad"), Err(Error::NotALeaf(9)));
+
+        let mut single = MerkleTree::from_leaves(["a"]).unwrap();
+        assert_eq!(
+            single.update(0, b"b"),
+            Ok(MerkleTree::from_leaves(["b"]).unwrap().root())
+        );
+    }
+}
@@ -0,0 +1,227 @@
+use crate::hash_data;
+use crate::memory::{vec_bytes, HeapSize};
+use sha2::{Digest, Sha256};
+
+/// A tree whose nodes carry the total weight beneath them. Each node is
+/// committed as `H(hash || weight_le)`, and siblings are hashed in leaf
+/// order, so a proof binds the weight of everything to the left of a leaf.
+/// A node alone at the end of its level is paired with an empty sibling of
+/// zero hash and zero weight, as `Padding::Zero` does, so any leaf count
+/// works and every proof has one sibling per level.
+pub struct WeightedTree {
+    levels: Vec<Vec<([u8; 32], u64)>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "borsh",
+    derive(borsh::BorshSerialize, borsh::BorshDeserialize)
+)]
+pub struct WeightedProof {
+    pub index: usize,
+    pub weight: u64,
+    pub siblings: Vec<([u8; 32], u64)>,
+}
+
+/// Stand-in sibling for a node alone on its level.
+const EMPTY: ([u8; 32], u64) = ([0; 32], 0);
+
+fn commit(hash: &[u8; 32], weight: u64) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(hash);
+    hasher.update(weight.to_le_bytes());
+    hasher.finalize().into()
+}
+
+fn parent(left: &([u8; 32], u64), right: &([u8; 32], u64)) -> Option<([u8; 32], u64)> {
+    let mut hasher = Sha256::new();
+    hasher.update(commit(&left.0, left.1));
+    hasher.update(commit(&right.0, right.1));
+    Some((hasher.finalize().into(), left.1.checked_add(right.1)?))
+}
+
+impl WeightedTree {
+    /// Returns `None` if there are no items or the total weight overflows a
+    /// `u64`.
+    pub fn new<I, T>(items: I) -> Option<Self>
+    where
+        I: IntoIterator<Item = (T, u64)>,
+        T: AsRef<[u8]>,
+    {
+        let leaves: Vec<([u8; 32], u64)> = items
+            .into_iter()
+            .map(|(item, weight)| (hash_data(item.as_ref()), weight))
+            .collect();
+        if leaves.is_empty() {
+            return None;
+        }
+        let mut levels = vec![leaves];
+        while levels[levels.len() - 1].len() > 1 {
+            let next = levels[levels.len() - 1]
+                .chunks(2)
+                .map(|pair| parent(&pair[0], pair.get(1).unwrap_or(&EMPTY)))
+                .collect::<Option<Vec<_>>>()?;
+            levels.push(next);
+        }
+        Some(WeightedTree { levels })
+    }
+
+    pub fn leaves(&self) -> usize {
+        self.levels[0].len()
+    }
+
+    pub fn total_weight(&self) -> u64 {
+        self.levels[self.levels.len() - 1][0].1
+    }
+
+    pub fn root(&self) -> [u8; 32] {
+        let (hash, weight) = self.levels[self.levels.len() - 1][0];
+        commit(&hash, weight)
+    }
+
+    /// Index of the first leaf whose cumulative weight exceeds `target`.
+    pub fn find(&self, target: u64) -> Option<usize> {
+        if target >= self.total_weight() {
+            return None;
+        }
+        let mut remaining = target;
+        let mut offset = 0;
+        // Only a sibling with weight is taken, so an empty one never is.
+        for level in self.levels.iter().rev().skip(1) {
+            offset *= 2;
+            let left = level[offset].1;
+            if remaining >= left {
+                remaining -= left;
+                offset += 1;
+            }
+        }
+        Some(offset)
+    }
+
+    pub fn proof(&self, idx: usize) -> Option<WeightedProof> {
+        let weight = self.levels[0].get(idx)?.1;
+        let mut offset = idx;
+        let mut siblings = Vec::new();
+        for level in &self.levels[..self.levels.len() - 1] {
+            siblings.push(level.get(offset ^ 1).copied().unwrap_or(EMPTY));
+            offset /= 2;
+        }
+        Some(WeightedProof {
+            index: idx,
+            weight,
+            siblings,
+        })
+    }
+
+    pub fn prefix_proof(&self, target: u64) -> Option<WeightedProof> {
+        self.find(target).and_then(|idx| self.proof(idx))
+    }
+}
+
+impl WeightedProof {
+    /// Recomputes the committed root and the cumulative weight before the
+    /// leaf, or `None` if the weights overflow.
+    fn reconstruct(&self, item: &[u8]) -> Option<([u8; 32], u64)> {
+        let mut node = (hash_data(item), self.weight);
+        let mut before = 0u64;
+        let mut offset = self.index;
+        for sibling in &self.siblings {
+            node = if offset & 1 == 1 {
+                before = before.checked_add(sibling.1)?;
+                parent(sibling, &node)?
+            } else {
+                parent(&node, sibling)?
+            };
+            offset /= 2;
+        }
+        if offset != 0 {
+            return None;
+        }
+        Some((commit(&node.0, node.1), before))
+    }
+
+    pub fn verify(&self, item: &[u8], root: [u8; 32]) -> bool {
+        self.reconstruct(item)
+            .is_some_and(|(computed, _)| computed == root)
+    }
+
+    /// Checks that the proven leaf is the first one whose cumulative weight
+    /// exceeds `target`.
+    pub fn verify_prefix(&self, item: &[u8], root: [u8; 32], target: u64) -> bool {
+        match self.reconstruct(item) {
+            Some((computed, before)) => {
+                computed == root && before <= target && target - before < self.weight
+            }
+            None => false,
+        }
+    }
+}
+
+impl HeapSize for WeightedTree {
+    fn heap_size_bytes(&self) -> usize {
+        vec_bytes(&self.levels) + self.levels.iter().map(vec_bytes).sum::<usize>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tree() -> WeightedTree {
+        WeightedTree::new(vec![("a", 5), ("b", 0), ("c", 3), ("d", 2)]).unwrap()
+    }
+
+    #[test]
+    fn finds_by_cumulative_weight() {
+        let tree = tree();
+        assert_eq!(tree.total_weight(), 10);
+        assert_eq!(tree.find(0), Some(0));
+        assert_eq!(tree.find(4), Some(0));
+        assert_eq!(tree.find(5), Some(2));
+        assert_eq!(tree.find(7), Some(2));
+        assert_eq!(tree.find(8), Some(3));
+        assert_eq!(tree.find(10), None);
+    }
+
+    #[test]
+    fn prefix_proofs() {
+        let tree = tree();
+        let proof = tree.prefix_proof(6).unwrap();
+        assert_eq!(proof.index, 2);
+        assert!(proof.verify(b"c", tree.root()));
+        assert!(proof.verify_prefix(b"c", tree.root(), 6));
+        assert!(!proof.verify_prefix(b"c", tree.root(), 8));
+        assert!(!proof.verify_prefix(b"c", tree.root(), 4));
+
+        let mut forged = proof.clone();
+        forged.siblings[0].1 += 1;
+        assert!(!forged.verify_prefix(b"c", tree.root(), 6));
+    }
+
+    #[test]
+    fn any_leaf_count() {
+        let weights = [4u64, 0, 1, 7, 2, 0, 3];
+        for len in 1..=weights.len() {
+            let items: Vec<([u8; 1], u64)> = (0..len).map(|i| ([i as u8], weights[i])).collect();
+            let tree = WeightedTree::new(items.iter().copied()).unwrap();
+            assert_eq!(tree.leaves(), len);
+            assert_eq!(tree.total_weight(), weights[..len].iter().sum::<u64>());
+            for (idx, (item, _)) in items.iter().enumerate() {
+                assert!(tree.proof(idx).unwrap().verify(item, tree.root()));
+            }
+            for target in 0..tree.total_weight() {
+                let proof = tree.prefix_proof(target).unwrap();
+                let before: u64 = weights[..proof.index].iter().sum();
+                assert!(before <= target && target < before + weights[proof.index]);
+                assert!(proof.verify_prefix(&items[proof.index].0, tree.root(), target));
+            }
+            assert!(tree.proof(len).is_none());
+        }
+    }
+
+    #[test]
+    fn rejects_bad_shapes() {
+        assert!(WeightedTree::new(Vec::<(&str, u64)>::new()).is_none());
+        assert!(WeightedTree::new(vec![("a", u64::MAX), ("b", 1)]).is_none());
+    }
+}
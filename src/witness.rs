@@ -0,0 +1,305 @@
+//! Client for witness co-signing of [`MerkleLog`](crate::MerkleLog) heads.
+//! Each witness is sent the new head with a consistency proof from the last
+//! size it saw; a witness that accepts the proof signs the head and the
+//! client gathers those cosignatures into a [`Checkpoint`] once enough
+//! witnesses have signed.
+//!
+//! Requests are plain HTTP/1.1 `POST`s (no TLS) with a text body:
+//!
+//! ```text
+//! old <size>
+//! new <size>
+//! root <hex root>
+//! <hex consistency proof hash, one per line>
+//! ```
+//!
+//! A witness answers `200` with its signature of `TreeHead::hash` in hex;
+//! any other status is a refusal.
+
+use crate::canonical::parse_hash;
+use crate::http;
+use crate::{HeadVerifier, TreeHead};
+use std::io;
+use std::time::Duration;
+
+pub struct Witness<V> {
+    pub name: String,
+    /// `http://host:port/path`.
+    pub url: String,
+    pub verifier: V,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cosignature {
+    pub witness: String,
+    pub signature: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Checkpoint {
+    pub head: TreeHead,
+    pub cosignatures: Vec<Cosignature>,
+}
+
+impl Checkpoint {
+    /// Checks that at least `quorum` of `witnesses` have validly signed.
+    pub fn verify<V: HeadVerifier>(&self, witnesses: &[Witness<V>], quorum: usize) -> bool {
+        let message = self.head.hash();
+        let signed = witnesses
+            .iter()
+            .filter(|witness| {
+                self.cosignatures.iter().any(|cosig| {
+                    cosig.witness == witness.name
+                        && witness.verifier.verify(&message, &cosig.signature)
+                })
+            })
+            .count();
+        signed >= quorum
+    }
+}
+
+/// Fewer than the quorum signed. `checkpoint` holds the cosignatures that
+/// were collected.
+#[derive(Debug)]
+pub struct NoQuorum {
+    pub checkpoint: Checkpoint,
+    pub failures: Vec<(String, io::Error)>,
+}
+
+pub struct WitnessClient<V> {
+    witnesses: Vec<Witness<V>>,
+    quorum: usize,
+    timeout: Duration,
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn unhex(text: &str) -> Option<Vec<u8>> {
+    if text.len() & 1 == 1 {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Request body for a witness.
+pub fn encode_request(old_size: u64, head: &TreeHead, proof: &[[u8; 32]]) -> String {
+    let mut body = format!(
+        "old {}\nnew {}\nroot {}\n",
+        old_size,
+        head.size,
+        hex(&head.root)
+    );
+    for hash in proof {
+        body.push_str(&hex(hash));
+        body.push('\n');
+    }
+    body
+}
+
+/// Parses a request body into the old size, the new head and the proof.
+pub fn decode_request(body: &str) -> Option<(u64, TreeHead, Vec<[u8; 32]>)> {
+    let mut lines = body.lines();
+    let mut field = |name: &str| lines.next()?.strip_prefix(name)?.strip_prefix(' ');
+    let old = field("old")?.parse().ok()?;
+    let size = field("new")?.parse().ok()?;
+    let root = parse_hash(field("root")?)?;
+    let proof = lines.map(parse_hash).collect::<Option<Vec<_>>>()?;
+    Some((old, TreeHead { size, root }, proof))
+}
+
+impl<V: HeadVerifier> WitnessClient<V> {
+    pub fn new(witnesses: Vec<Witness<V>>, quorum: usize) -> Self {
+        WitnessClient {
+            witnesses,
+            quorum,
+            timeout: Duration::from_secs(10),
+        }
+    }
+
+    /// Connect, read and write timeout for each witness; 10 seconds by
+    /// default.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Asks every witness to cosign `head`, proving it consistent with the
+    /// head of `old_size` leaves. Signatures that do not verify count as
+    /// failures.
+    pub fn cosign(
+        &self,
+        old_size: u64,
+        head: &TreeHead,
+        proof: &[[u8; 32]],
+    ) -> Result<Checkpoint, NoQuorum> {
+        let body = encode_request(old_size, head, proof);
+        let message = head.hash();
+        let mut checkpoint = Checkpoint {
+            head: *head,
+            cosignatures: Vec::new(),
+        };
+        let mut failures = Vec::new();
+        for witness in &self.witnesses {
+            let signed =
+                http::post(&witness.url, "text/plain", &body, self.timeout).and_then(|response| {
+                    let signature = unhex(response.trim())
+                        .ok_or_else(|| invalid("signature is not hex".to_string()))?;
+                    if !witness.verifier.verify(&message, &signature) {
+                        return Err(invalid("signature does not verify".to_string()));
+                    }
+                    Ok(signature)
+                });
+            match signed {
+                Ok(signature) => checkpoint.cosignatures.push(Cosignature {
+                    witness: witness.name.clone(),
+                    signature,
+                }),
+                Err(err) => failures.push((witness.name.clone(), err)),
+            }
+        }
+        if checkpoint.cosignatures.len() >= self.quorum {
+            Ok(checkpoint)
+        } else {
+            Err(NoQuorum {
+                checkpoint,
+                failures,
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{hash_data, verify_consistency, MerkleLog};
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+
+    struct Keyed([u8; 32]);
+
+    impl Keyed {
+        fn sign(&self, message: &[u8; 32]) -> Vec<u8> {
+            hash_data(&[self.0, *message].concat()).to_vec()
+        }
+    }
+
+    impl HeadVerifier for Keyed {
+        fn verify(&self, message: &[u8; 32], signature: &[u8]) -> bool {
+            self.sign(message) == signature
+        }
+    }
+
+    /// A witness that knows `old` and answers one request, refusing
+    /// inconsistent heads with 409 and everything when `broken`.
+    fn serve(key: [u8; 32], old: TreeHead, broken: bool) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/add-checkpoint", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if let Some(value) = line.strip_prefix("Content-Length: ") {
+                    length = value.trim().parse().unwrap();
+                }
+                if line == "\r\n" {
+                    break;
+                }
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            let (old_size, head, proof) =
+                decode_request(std::str::from_utf8(&body).unwrap()).unwrap();
+            let consistent = old_size == old.size
+                && verify_consistency(old.size, head.size, old.root, head.root, &proof);
+            let response = match (broken, consistent) {
+                (true, _) => "HTTP/1.1 500 Internal Server Error\r\n\r\n".to_string(),
+                (false, false) => "HTTP/1.1 409 Conflict\r\n\r\n".to_string(),
+                (false, true) => format!(
+                    "HTTP/1.1 200 OK\r\n\r\n{}\n",
+                    hex(&Keyed(key).sign(&head.hash()))
+                ),
+            };
+            (&stream).write_all(response.as_bytes()).unwrap();
+        });
+        url
+    }
+
+    fn witnesses(old: TreeHead, broken: [bool; 3]) -> Vec<Witness<Keyed>> {
+        (0..3u8)
+            .map(|i| Witness {
+                name: format!("w{}", i),
+                url: serve([i; 32], old, broken[i as usize]),
+                verifier: Keyed([i; 32]),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn collects_a_quorum() {
+        let mut log = MerkleLog::new();
+        for i in 0..7u8 {
+            log.append(&[i]);
+        }
+        let old = TreeHead {
+            size: 3,
+            root: log.root_at(3).unwrap(),
+        };
+        let new = TreeHead {
+            size: 7,
+            root: log.root(),
+        };
+        let proof = log.consistency_proof(3, 7).unwrap();
+
+        let client = WitnessClient::new(witnesses(old, [false, true, false]), 2);
+        let checkpoint = client.cosign(3, &new, &proof).unwrap();
+        assert_eq!(checkpoint.head, new);
+        assert_eq!(checkpoint.cosignatures.len(), 2);
+        let verifiers: Vec<Witness<Keyed>> = (0..3u8)
+            .map(|i| Witness {
+                name: format!("w{}", i),
+                url: String::new(),
+                verifier: Keyed([i; 32]),
+            })
+            .collect();
+        assert!(checkpoint.verify(&verifiers, 2));
+        assert!(!checkpoint.verify(&verifiers, 3));
+
+        let client = WitnessClient::new(witnesses(old, [false, true, false]), 3);
+        let err = client.cosign(3, &new, &proof).unwrap_err();
+        assert_eq!(err.checkpoint.cosignatures.len(), 2);
+        assert_eq!(err.failures.len(), 1);
+        assert_eq!(err.failures[0].0, "w1");
+
+        // A proof against the wrong old head is refused by every witness.
+        let client = WitnessClient::new(witnesses(old, [false; 3]), 1);
+        let err = client.cosign(3, &new, &proof[1..]).unwrap_err();
+        assert_eq!(err.failures.len(), 3);
+    }
+
+    #[test]
+    fn request_round_trip() {
+        let head = TreeHead {
+            size: 9,
+            root: [0xab; 32],
+        };
+        let body = encode_request(4, &head, &[[1; 32], [2; 32]]);
+        assert_eq!(
+            decode_request(&body),
+            Some((4, head, vec![[1; 32], [2; 32]]))
+        );
+        assert_eq!(decode_request("old 4\nnew 9\n"), None);
+        assert_eq!(unhex("0aff"), Some(vec![0x0a, 0xff]));
+        assert_eq!(unhex("0af"), None);
+    }
+}
@@ -0,0 +1,217 @@
+//! Inclusion proofs carried in an X.509 extension, for certificate
+//! transparency style workflows on private CAs. The extension value is
+//!
+//! ```text
+//! MerkleInclusion ::= SEQUENCE {
+//!     treeHead TreeHead,
+//!     proof    MerkleProof
+//! }
+//! ```
+//!
+//! using the types from the `der` module, under an OID chosen by the caller.
+
+use crate::der::{self, read_any, read_tlv, write_tlv, BOOLEAN, OCTET_STRING, SEQUENCE};
+use crate::Path;
+
+const OBJECT_IDENTIFIER: u8 = 0x06;
+
+fn encode_oid(arcs: &[u64]) -> Option<Vec<u8>> {
+    let (first, second, rest) = match arcs {
+        [first @ 0..=1, second @ 0..=39, rest @ ..] | [first @ 2, second, rest @ ..] => {
+            (*first, *second, rest)
+        }
+        _ => return None,
+    };
+    let mut out = Vec::new();
+    for arc in
+        std::iter::once(first.checked_mul(40)?.checked_add(second)?).chain(rest.iter().copied())
+    {
+        let mut groups = vec![(arc & 0x7f) as u8];
+        let mut rest = arc >> 7;
+        while rest > 0 {
+            groups.push(0x80 | (rest & 0x7f) as u8);
+            rest >>= 7;
+        }
+        out.extend(groups.iter().rev());
+    }
+    Some(out)
+}
+
+/// DER `Extension` embedding the tree head and proof. Returns `None` for an
+/// invalid OID.
+pub fn inclusion_extension(
+    oid: &[u64],
+    critical: bool,
+    tree_size: u64,
+    root: &[u8; 32],
+    path: &[([u8; 32], bool)],
+) -> Option<Vec<u8>> {
+    let mut inclusion = der::encode_tree_head(tree_size, root);
+    inclusion.extend(der::encode_proof(path));
+    let mut value = Vec::with_capacity(inclusion.len() + 4);
+    write_tlv(&mut value, SEQUENCE, &inclusion);
+
+    let mut extension = Vec::with_capacity(value.len() + 32);
+    write_tlv(&mut extension, OBJECT_IDENTIFIER, &encode_oid(oid)?);
+    if critical {
+        write_tlv(&mut extension, BOOLEAN, &[0xff]);
+    }
+    write_tlv(&mut extension, OCTET_STRING, &value);
+    let mut out = Vec::with_capacity(extension.len() + 4);
+    write_tlv(&mut out, SEQUENCE, &extension);
+    Some(out)
+}
+
+/// Constructed values the search descends through. Extensions sit four
+/// deep in a certificate: Certificate, TBSCertificate, the `[3]` wrapper and
+/// the extension list.
+const MAX_NESTING: usize = 8;
+
+/// Searches `der` (a certificate, TBSCertificate or bare extension list) for
+/// an extension with `oid` and decodes the inclusion proof it carries.
+/// Gives up on input nested deeper than any certificate.
+pub fn find_inclusion(der: &[u8], oid: &[u64]) -> Option<(u64, [u8; 32], Path)> {
+    let oid = encode_oid(oid)?;
+    find_extension_value(der, &oid, 0).and_then(decode_inclusion)
+}
+
+fn find_extension_value<'a>(mut input: &'a [u8], oid: &[u8], depth: usize) -> Option<&'a [u8]> {
+    if depth > MAX_NESTING {
+        return None;
+    }
+    while !input.is_empty() {
+        let (tag, value, rest) = read_any(input)?;
+        if tag & 0x20 != 0 {
+            if let Some(found) = extension_value(tag, value, oid)
+                .or_else(|| find_extension_value(value, oid, depth + 1))
+            {
+                return Some(found);
+            }
+        }
+        input = rest;
+    }
+    None
+}
+
+fn extension_value<'a>(tag: u8, value: &'a [u8], oid: &[u8]) -> Option<&'a [u8]> {
+    if tag != SEQUENCE {
+        return None;
+    }
+    let (id, rest) = read_tlv(value, OBJECT_IDENTIFIER)?;
+    if id != oid {
+        return None;
+    }
+    let rest = match read_tlv(rest, BOOLEAN) {
+        Some(([0xff], rest)) => rest,
+        Some(_) => return None,
+        None => rest,
+    };
+    match read_tlv(rest, OCTET_STRING)? {
+        (value, []) => Some(value),
+        _ => None,
+    }
+}
+
+fn decode_inclusion(value: &[u8]) -> Option<(u64, [u8; 32], Path)> {
+    let (inclusion, rest) = read_tlv(value, SEQUENCE)?;
+    if !rest.is_empty() {
+        return None;
+    }
+    let (_, _, proof) = read_any(inclusion)?;
+    let head = &inclusion[..inclusion.len() - proof.len()];
+    let (size, root) = der::decode_tree_head(head)?;
+    Some((size, root, der::decode_proof(proof)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{verify_proof, MerkleTree};
+
+    const OID: &[u64] = &[1, 3, 6, 1, 4, 1, 55555, 1, 1];
+
+    #[test]
+    fn oid_encoding() {
+        assert_eq!(
+            encode_oid(&[1, 2, 840, 113549]),
+            Some(vec![0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d])
+        );
+        assert_eq!(encode_oid(&[2, 999]), Some(vec![0x88, 0x37]));
+        assert!(encode_oid(&[1, 40]).is_none());
+        assert!(encode_oid(&[3]).is_none());
+    }
+
+    #[test]
+    fn embed_and_extract() {
+        let data = b"asdfjkln12345678";
+        let tree = MerkleTree::new(data, 4);
+        let path = tree.path(1).unwrap();
+        let extension = inclusion_extension(OID, false, 4, &tree.root(), &path).unwrap();
+
+        // Wrap it like the extensions field of a TBSCertificate.
+        let mut other = Vec::new();
+        write_tlv(
+            &mut other,
+            OBJECT_IDENTIFIER,
+            &encode_oid(&[2, 5, 29, 19]).unwrap(),
+        );
+        write_tlv(&mut other, OCTET_STRING, &[0x30, 0x00]);
+        let mut list = Vec::new();
+        write_tlv(&mut list, SEQUENCE, &other);
+        list.extend(&extension);
+        let mut extensions = Vec::new();
+        write_tlv(&mut extensions, SEQUENCE, &list);
+        let mut tagged = Vec::new();
+        write_tlv(&mut tagged, 0xa3, &extensions);
+
+        let (size, root, found) = find_inclusion(&tagged, OID).unwrap();
+        assert_eq!((size, root), (4, tree.root()));
+        assert!(verify_proof(b"jkln", root, &found).is_valid());
+        assert!(find_inclusion(&tagged, &[1, 3, 6, 1, 4, 1, 55555, 1, 2]).is_none());
+    }
+
+    #[test]
+    fn limits_nesting() {
+        let extension = inclusion_extension(OID, false, 1, &[0; 32], &[]).unwrap();
+        let nest = |levels: usize| {
+            (0..levels).fold(extension.clone(), |inner, _| {
+                let mut outer = Vec::new();
+                write_tlv(&mut outer, SEQUENCE, &inner);
+                outer
+            })
+        };
+        assert!(find_inclusion(&nest(MAX_NESTING), OID).is_some());
+        assert!(find_inclusion(&nest(MAX_NESTING + 1), OID).is_none());
+
+        // Far deeper than the stack would allow without the limit. Headers
+        // are sized from the inside out so the nesting is well formed.
+        let header = |len: usize| {
+            let mut out = vec![SEQUENCE];
+            if len < 0x80 {
+                out.push(len as u8);
+            } else {
+                let bytes = len.to_be_bytes();
+                let skip = bytes.iter().take_while(|&&b| b == 0).count();
+                out.push(0x80 | (bytes.len() - skip) as u8);
+                out.extend_from_slice(&bytes[skip..]);
+            }
+            out
+        };
+        let mut headers = Vec::new();
+        let mut len = extension.len();
+        for _ in 0..100_000 {
+            let next = header(len);
+            len += next.len();
+            headers.push(next);
+        }
+        let mut deep: Vec<u8> = headers.into_iter().rev().flatten().collect();
+        deep.extend_from_slice(&extension);
+        assert!(find_inclusion(&deep, OID).is_none());
+    }
+
+    #[test]
+    fn critical_flag() {
+        let extension = inclusion_extension(OID, true, 1, &[0; 32], &[]).unwrap();
+        assert_eq!(find_inclusion(&extension, OID), Some((1, [0; 32], vec![])));
+    }
+}